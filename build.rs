@@ -0,0 +1,65 @@
+use std::{env, fs, path::Path, process::Command};
+
+/// Static files of the frontend, embedded in the binary
+const ASSETS_DIR: &str = "src/day_23";
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // vendored protoc so the build doesn't depend on a system-wide install
+    std::env::set_var("PROTOC", protoc_bin_vendored::protoc_bin_path()?);
+
+    // new migrations must trigger a rebuild of the embedded migrator
+    println!("cargo:rerun-if-changed=migrations");
+
+    tonic_build::configure()
+        .build_client(false)
+        .compile_protos(&["proto/quotes.proto"], &["proto"])?;
+
+    embed_assets()?;
+    git_commit();
+
+    Ok(())
+}
+
+/// Sets `GIT_COMMIT` to the commit being built, `unknown` outside a checkout
+fn git_commit() {
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs");
+
+    let commit = Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|commit| commit.trim().to_string())
+        .unwrap_or_else(|| "unknown".to_string());
+    println!("cargo:rustc-env=GIT_COMMIT={}", commit);
+}
+
+/// Writes the `EMBEDDED` list of `(name, content)` pairs included by `src/assets.rs`
+fn embed_assets() -> Result<(), Box<dyn std::error::Error>> {
+    println!("cargo:rerun-if-changed={}", ASSETS_DIR);
+
+    let dir = Path::new(&env::var("CARGO_MANIFEST_DIR")?).join(ASSETS_DIR);
+    let mut files = fs::read_dir(&dir)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    files.retain(|path| path.is_file());
+    files.sort();
+
+    let entries = files
+        .iter()
+        .map(|path| {
+            let name = path.file_name().unwrap().to_string_lossy();
+            format!("    ({:?}, include_bytes!({:?})),\n", name, path)
+        })
+        .collect::<String>();
+    fs::write(
+        Path::new(&env::var("OUT_DIR")?).join("assets.rs"),
+        format!(
+            "pub static EMBEDDED: &[(&str, &[u8])] = &[\n{}];\n",
+            entries
+        ),
+    )?;
+    Ok(())
+}
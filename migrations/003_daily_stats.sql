@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS daily_stats (
+    day DATE PRIMARY KEY,
+    requests BIGINT NOT NULL DEFAULT 0,
+    quotes_created BIGINT NOT NULL DEFAULT 0,
+    games_finished BIGINT NOT NULL DEFAULT 0,
+    milk_withdrawn BIGINT NOT NULL DEFAULT 0
+);
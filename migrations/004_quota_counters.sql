@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS quota_counters (
+    api_key TEXT NOT NULL,
+    day DATE NOT NULL,
+    created BIGINT NOT NULL DEFAULT 0,
+    PRIMARY KEY (api_key, day)
+);
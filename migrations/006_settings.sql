@@ -0,0 +1,9 @@
+-- every change is a new version, so that the history of a setting is kept
+CREATE TABLE IF NOT EXISTS settings (
+    key TEXT NOT NULL,
+    version INT NOT NULL,
+    value JSONB NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    deleted_at TIMESTAMPTZ,
+    PRIMARY KEY (key, version)
+);
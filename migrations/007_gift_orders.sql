@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS gift_orders (
+    id UUID PRIMARY KEY,
+    priority INT NOT NULL,
+    payload JSONB NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS gift_orders_next_idx ON gift_orders (priority DESC, created_at);
@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS quote_likes (
+    quote_id UUID NOT NULL REFERENCES quotes (id) ON DELETE CASCADE,
+    client_id TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (quote_id, client_id)
+);
@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS quote_comments (
+    id UUID PRIMARY KEY,
+    quote_id UUID NOT NULL REFERENCES quotes (id) ON DELETE CASCADE,
+    author TEXT NOT NULL,
+    body TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS quote_comments_thread_idx ON quote_comments (quote_id, created_at);
@@ -0,0 +1,3 @@
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+
+CREATE INDEX IF NOT EXISTS quotes_author_trgm_idx ON quotes USING GIN (author gin_trgm_ops);
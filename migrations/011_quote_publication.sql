@@ -0,0 +1,4 @@
+-- set while a quote is scheduled, cleared once its publication has been announced
+ALTER TABLE quotes ADD COLUMN IF NOT EXISTS publish_at TIMESTAMPTZ;
+
+CREATE INDEX IF NOT EXISTS quotes_scheduled_idx ON quotes (publish_at) WHERE publish_at IS NOT NULL;
@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS pagination_tokens (
+    token TEXT PRIMARY KEY,
+    page BIGINT NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS pagination_tokens_expiry_idx ON pagination_tokens (expires_at);
@@ -0,0 +1,3 @@
+-- set when moderation holds a quote for review, hidden until an admin approves it
+ALTER TABLE quotes ADD COLUMN IF NOT EXISTS pending_review BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE quotes ADD COLUMN IF NOT EXISTS review_reason TEXT;
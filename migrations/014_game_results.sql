@@ -0,0 +1,10 @@
+-- day 12 games archived by a reset, finished or abandoned
+CREATE TABLE IF NOT EXISTS game_results (
+    id BIGSERIAL PRIMARY KEY,
+    outcome TEXT NOT NULL,
+    moves INT NOT NULL,
+    board TEXT NOT NULL,
+    finished_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS game_results_finished_idx ON game_results (finished_at DESC);
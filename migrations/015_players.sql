@@ -0,0 +1,23 @@
+-- registered day 12 players and their Elo ratings
+CREATE TABLE IF NOT EXISTS players (
+    name TEXT PRIMARY KEY,
+    -- session token, sent back as the player cookie
+    token UUID NOT NULL UNIQUE,
+    rating INT NOT NULL DEFAULT 1200,
+    games INT NOT NULL DEFAULT 0,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS players_rating_idx ON players (rating DESC);
+
+CREATE TABLE IF NOT EXISTS rating_changes (
+    id BIGSERIAL PRIMARY KEY,
+    player TEXT NOT NULL REFERENCES players (name) ON DELETE CASCADE,
+    opponent TEXT NOT NULL,
+    outcome TEXT NOT NULL,
+    rating INT NOT NULL,
+    delta INT NOT NULL,
+    played_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS rating_changes_player_idx ON rating_changes (player, played_at DESC);
@@ -0,0 +1,9 @@
+-- uploaded files, their content held in a large object
+CREATE TABLE IF NOT EXISTS uploads (
+    id UUID PRIMARY KEY,
+    -- route the file was uploaded to, e.g. lockfile
+    kind TEXT NOT NULL,
+    object OID NOT NULL,
+    size BIGINT NOT NULL,
+    uploaded_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
@@ -0,0 +1,6 @@
+-- the id breaks ties between rows with the same timestamp, so the indexes cover it too
+CREATE INDEX IF NOT EXISTS quotes_created_idx ON quotes (created_at, id);
+DROP INDEX IF EXISTS quote_comments_thread_idx;
+CREATE INDEX IF NOT EXISTS quote_comments_thread_idx ON quote_comments (quote_id, created_at, id);
+DROP INDEX IF EXISTS gift_orders_next_idx;
+CREATE INDEX IF NOT EXISTS gift_orders_next_idx ON gift_orders (priority DESC, created_at, id);
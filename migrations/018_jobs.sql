@@ -0,0 +1,18 @@
+-- work accepted by a request and done later by the job workers
+CREATE TABLE IF NOT EXISTS jobs (
+    id UUID PRIMARY KEY,
+    -- what to do with the payload, e.g. lockfile_graph
+    kind TEXT NOT NULL,
+    payload JSONB NOT NULL,
+    -- queued, running, succeeded or failed
+    status TEXT NOT NULL DEFAULT 'queued',
+    attempts INT NOT NULL DEFAULT 0,
+    content_type TEXT,
+    result TEXT,
+    error TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    started_at TIMESTAMPTZ,
+    finished_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS jobs_queued_idx ON jobs (created_at, id) WHERE status IN ('queued', 'running');
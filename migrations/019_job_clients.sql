@@ -0,0 +1,5 @@
+-- address of the client a job was queued for, bounding the unfinished jobs of each client
+ALTER TABLE jobs ADD COLUMN IF NOT EXISTS client TEXT;
+
+CREATE INDEX IF NOT EXISTS jobs_client_idx ON jobs (client) WHERE status IN ('queued', 'running');
+CREATE INDEX IF NOT EXISTS jobs_finished_idx ON jobs (finished_at) WHERE finished_at IS NOT NULL;
@@ -0,0 +1,3 @@
+-- likes are given by registered players only, the ones keyed on an API key or a session cookie
+-- can't be told apart from made up ones
+DELETE FROM quote_likes WHERE client_id NOT LIKE 'player:%';
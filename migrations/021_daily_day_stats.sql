@@ -0,0 +1,7 @@
+-- requests of each challenge day, the routes outside of the days only count in daily_stats
+CREATE TABLE IF NOT EXISTS daily_day_stats (
+    day DATE NOT NULL,
+    challenge_day SMALLINT NOT NULL,
+    requests BIGINT NOT NULL DEFAULT 0,
+    PRIMARY KEY (day, challenge_day)
+);
@@ -0,0 +1,391 @@
+//! Minimal admin interface, rendered server-side and driven by htmx
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    middleware,
+    response::{Html, IntoResponse},
+    routing::{get, post},
+    Form, Router,
+};
+use uuid::Uuid;
+
+use crate::{
+    auth::{require_admin, Auth},
+    caching::conditional,
+    day_12::{Board, BoardState},
+    day_19::{DbState, NewQuote, Quote},
+    day_23::escape_string,
+    day_9::RateLimiterState,
+    i18n::Language,
+    links::LinkBuilder,
+    theme::Theme,
+};
+
+const REFRESH: &str = "every 2s";
+
+#[derive(Clone)]
+pub struct AdminState {
+    pub quotes: DbState,
+    pub games: BoardState,
+    pub milk: RateLimiterState,
+}
+
+/// Routes of the admin UI, meant to be nested under `/admin/ui`
+pub fn admin_router(state: AdminState, auth: Auth) -> Router {
+    Router::new()
+        .route("/", get(page))
+        .route("/quotes", get(quotes))
+        .route("/quotes/:id", get(quote).put(update).delete(remove))
+        .route("/quotes/:id/edit", get(edit))
+        .route("/board", get(board))
+        .route("/board/reset", post(reset_board))
+        .route("/bucket", get(bucket))
+        .with_state(state)
+        // fragments polled by the page mostly revalidate to a 304
+        .layer(middleware::from_fn(conditional))
+        .layer(middleware::from_fn_with_state(auth, require_admin))
+}
+
+pub async fn page(links: LinkBuilder) -> impl IntoResponse {
+    Html(format!(
+        "<html><head><title>Admin</title><script src=\"https://unpkg.com/htmx.org@2.0.4\"></script></head>\
+         <body><main>\
+         <h1>Quotes</h1><div hx-get=\"{quotes}\" hx-trigger=\"load\"></div>\
+         <h1>Board</h1><div id=\"board-panel\" hx-get=\"{board}\" hx-trigger=\"load, {refresh}\"></div>\
+         <h1>Milk</h1><div hx-get=\"{bucket}\" hx-trigger=\"load, {refresh}\"></div>\
+         </main></body></html>",
+        quotes = links.href("/quotes"),
+        board = links.href("/board"),
+        bucket = links.href("/bucket"),
+        refresh = REFRESH,
+    ))
+}
+
+fn quote_row(quote: &Quote, links: &LinkBuilder) -> String {
+    let path = links.href(&format!("/quotes/{}", quote.id));
+    format!(
+        "<tr><td>{}</td><td>{}</td><td>{}</td><td>\
+         <button hx-get=\"{path}/edit\">Edit</button>\
+         <button hx-delete=\"{path}\" hx-confirm=\"Delete this quote?\">Delete</button>\
+         </td></tr>",
+        escape_string(&quote.author),
+        escape_string(&quote.quote),
+        quote.version,
+        path = path,
+    )
+}
+
+pub async fn quotes(State(state): State<AdminState>, links: LinkBuilder) -> impl IntoResponse {
+    let quotes = match state.quotes.repository.all_quotes().await {
+        Ok(q) => q,
+        _ => return Err((StatusCode::INTERNAL_SERVER_ERROR, "".to_string())),
+    };
+
+    let rows = quotes
+        .iter()
+        .map(|q| quote_row(q, &links))
+        .collect::<String>();
+
+    // every action replaces the row it was triggered from
+    Ok(Html(format!(
+        "<table id=\"quotes\"><thead><tr><th>Author</th><th>Quote</th><th>Version</th><th></th></tr></thead>\
+         <tbody hx-target=\"closest tr\" hx-swap=\"outerHTML\">{}</tbody></table>",
+        rows
+    )))
+}
+
+pub async fn quote(
+    Path(id): Path<Uuid>,
+    State(state): State<AdminState>,
+    links: LinkBuilder,
+) -> impl IntoResponse {
+    match state.quotes.repository.get(id).await {
+        Ok(q) => Ok(Html(quote_row(&q, &links))),
+        _ => Err((StatusCode::NOT_FOUND, "".to_string())),
+    }
+}
+
+pub async fn edit(
+    Path(id): Path<Uuid>,
+    State(state): State<AdminState>,
+    links: LinkBuilder,
+) -> impl IntoResponse {
+    let quote = match state.quotes.repository.get(id).await {
+        Ok(q) => q,
+        _ => return Err((StatusCode::NOT_FOUND, "".to_string())),
+    };
+
+    let path = links.href(&format!("/quotes/{}", quote.id));
+    Ok(Html(format!(
+        "<tr><td><input name=\"author\" value=\"{}\"></td><td><input name=\"quote\" value=\"{}\"></td><td>{}</td><td>\
+         <button hx-put=\"{path}\" hx-include=\"closest tr\">Save</button>\
+         <button hx-get=\"{path}\">Cancel</button>\
+         </td></tr>",
+        escape_string(&quote.author),
+        escape_string(&quote.quote),
+        quote.version,
+        path = path,
+    )))
+}
+
+pub async fn update(
+    Path(id): Path<Uuid>,
+    State(state): State<AdminState>,
+    links: LinkBuilder,
+    Form(new_quote): Form<NewQuote>,
+) -> impl IntoResponse {
+    match state.quotes.repository.update(id, new_quote).await {
+        Ok(q) => Ok(Html(quote_row(&q, &links))),
+        _ => Err((StatusCode::NOT_FOUND, "".to_string())),
+    }
+}
+
+pub async fn remove(Path(id): Path<Uuid>, State(state): State<AdminState>) -> impl IntoResponse {
+    // an empty body makes htmx drop the row
+    match state.quotes.repository.delete(id, false).await {
+        Ok(_) => Ok(Html("")),
+        _ => Err((StatusCode::NOT_FOUND, "".to_string())),
+    }
+}
+
+fn board_panel(board: &Board, links: &LinkBuilder) -> String {
+    format!(
+        "{}<button hx-post=\"{}\" hx-target=\"#board-panel\">Reset</button>",
+        board.to_html(Language::default(), &Theme::configured()),
+        links.href("/board/reset")
+    )
+}
+
+pub async fn board(State(state): State<AdminState>, links: LinkBuilder) -> impl IntoResponse {
+    Html(board_panel(&*state.games.board.lock().await, &links))
+}
+
+/// Same reset as `/12/reset`, the game archived and both boards emptied
+pub async fn reset_board(State(state): State<AdminState>, links: LinkBuilder) -> impl IntoResponse {
+    state.games.clear().await;
+    Html(board_panel(&*state.games.board.lock().await, &links))
+}
+
+pub async fn bucket(State(state): State<AdminState>) -> impl IntoResponse {
+    let limiter = state.milk.limiter.lock().await;
+    Html(format!(
+        "<p id=\"bucket\">Milk available: {} / {}</p>",
+        limiter.balance(),
+        limiter.max()
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{
+        board_feed::BoardFeed,
+        config::Config,
+        day_12::{arc_board, arc_random_board, place, MockGameResultRepository},
+        day_19::{state_tokens, MockQuoteRepository, QuoteStatus, PAGE_SIZE},
+        moderation::WordListModerator,
+        players::MockPlayerRepository,
+        test_support::box_future,
+    };
+    use axum::{
+        body::Body,
+        http::{header, Request},
+    };
+    use chrono::Utc;
+    use http_body_util::BodyExt;
+    use mockall::predicate::eq;
+    use tower::ServiceExt;
+
+    fn board_state(results: MockGameResultRepository) -> BoardState {
+        BoardState {
+            board: arc_board(),
+            random_board: arc_random_board(),
+            results: Arc::new(results),
+            feed: BoardFeed::default(),
+            players: Arc::new(MockPlayerRepository::new()),
+        }
+    }
+
+    fn create_test_app(repository: MockQuoteRepository, config: Config) -> Router {
+        create_test_app_with(
+            repository,
+            config,
+            board_state(MockGameResultRepository::new()),
+        )
+    }
+
+    fn create_test_app_with(
+        repository: MockQuoteRepository,
+        config: Config,
+        games: BoardState,
+    ) -> Router {
+        let state = AdminState {
+            quotes: DbState {
+                repository: Arc::new(repository),
+                tokens: state_tokens(),
+                moderator: Arc::new(WordListModerator::default()),
+                page_size: PAGE_SIZE,
+                players: Arc::new(MockPlayerRepository::new()),
+            },
+            games,
+            milk: RateLimiterState::default(),
+        };
+        Router::new().nest("/admin/ui", admin_router(state, Auth::new(&config)))
+    }
+
+    fn request(method: &str, uri: &str) -> Request<Body> {
+        Request::builder()
+            .method(method)
+            .uri(uri)
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    async fn body(response: axum::response::Response) -> String {
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        String::from_utf8(bytes.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_requires_token() {
+        let config = Config {
+            admin_token: Some("elf".to_string()),
+            ..Config::default()
+        };
+        let app = create_test_app(MockQuoteRepository::new(), config);
+
+        let response = app
+            .clone()
+            .oneshot(request("GET", "/admin/ui/bucket"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/admin/ui/bucket")
+                    .header(header::AUTHORIZATION, "Bearer elf")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(body(response).await.contains("Milk available: 5 / 5"));
+    }
+
+    #[tokio::test]
+    async fn test_page_links() {
+        let app = create_test_app(MockQuoteRepository::new(), Config::default());
+        let response = app.oneshot(request("GET", "/admin/ui")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let html = body(response).await;
+        assert!(html.contains("hx-get=\"/admin/ui/quotes\""));
+        assert!(html.contains("hx-get=\"/admin/ui/bucket\""));
+    }
+
+    #[tokio::test]
+    async fn test_quotes_are_escaped() {
+        let mut mock = MockQuoteRepository::new();
+        mock.expect_all_quotes().returning(|| {
+            box_future(Ok(vec![Quote {
+                id: Uuid::new_v4(),
+                author: "<b>Santa</b>".to_string(),
+                quote: "Ho ho ho".to_string(),
+                created_at: Utc::now(),
+                version: 1,
+                likes: 0,
+                publish_at: None,
+                status: QuoteStatus::Published,
+            }]))
+        });
+
+        let app = create_test_app(mock, Config::default());
+        let response = app
+            .oneshot(request("GET", "/admin/ui/quotes"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let html = body(response).await;
+        assert!(html.contains("&lt;b&gt;Santa&lt;&#x2F;b&gt;"));
+        assert!(html.contains("hx-delete=\"/admin/ui/quotes/"));
+    }
+
+    #[tokio::test]
+    async fn test_update_quote() {
+        let id = Uuid::new_v4();
+        let mut mock = MockQuoteRepository::new();
+        mock.expect_update()
+            .with(
+                eq(id),
+                eq(NewQuote {
+                    author: "Santa".to_string(),
+                    quote: "Ho".to_string(),
+                    publish_at: None,
+                }),
+            )
+            .returning(move |id, new_quote| {
+                box_future(Ok(Quote {
+                    id,
+                    author: new_quote.author,
+                    quote: new_quote.quote,
+                    created_at: Utc::now(),
+                    version: 2,
+                    likes: 0,
+                    publish_at: None,
+                    status: QuoteStatus::Published,
+                }))
+            });
+
+        let app = create_test_app(mock, Config::default());
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("PUT")
+                    .uri(format!("/admin/ui/quotes/{}", id))
+                    .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+                    .body(Body::from("author=Santa&quote=Ho"))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(body(response).await.contains("<td>2</td>"));
+    }
+
+    #[tokio::test]
+    async fn test_reset_board() {
+        let mut results = MockGameResultRepository::new();
+        results
+            .expect_archive()
+            .times(1)
+            .returning(|_| box_future(Ok(())));
+        let games = board_state(results);
+        let placed = Router::new()
+            .route("/12/place/:team/:column", post(place))
+            .with_state(games.clone())
+            .oneshot(request("POST", "/12/place/cookie/1"))
+            .await
+            .unwrap();
+        assert_eq!(placed.status(), StatusCode::OK);
+        let app =
+            create_test_app_with(MockQuoteRepository::new(), Config::default(), games.clone());
+        let response = app
+            .oneshot(request("POST", "/admin/ui/board/reset"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let html = body(response).await;
+        assert!(html.starts_with("<div id=\"board\">"));
+        assert!(html.contains("hx-post=\"/admin/ui/board/reset\""));
+        // the game on the board was archived, as `/12/reset` does
+        assert!(!html.contains('🍪'));
+    }
+}
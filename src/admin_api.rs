@@ -0,0 +1,242 @@
+//! JSON admin API over the in-memory state, meant to be nested under `/admin`
+
+use axum::{
+    extract::State,
+    middleware,
+    routing::{get, post},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{
+    app_error::AppError,
+    auth::{require_admin, Auth},
+    maintenance::{self, MAINTENANCE},
+    settings::{reload, SettingsState},
+    snapshot::{Snapshot, VolatileState},
+};
+
+#[derive(Clone)]
+pub struct AdminApiState {
+    pub volatile: VolatileState,
+    pub settings: SettingsState,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Maintenance {
+    pub enabled: bool,
+}
+
+#[derive(Serialize)]
+pub struct StateDump {
+    maintenance: bool,
+    #[serde(flatten)]
+    state: Snapshot,
+}
+
+/// Routes of the admin API, every one of them requiring the admin role
+pub fn admin_api_router(state: AdminApiState, auth: Auth) -> Router {
+    Router::new()
+        .route("/state", get(dump))
+        .route("/reset", post(reset))
+        .route("/maintenance", get(maintenance).put(set_maintenance))
+        .with_state(state)
+        .layer(middleware::from_fn_with_state(auth, require_admin))
+}
+
+async fn state_dump(state: &AdminApiState) -> StateDump {
+    StateDump {
+        maintenance: state.settings.cache.flag(MAINTENANCE),
+        state: state.volatile.capture().await,
+    }
+}
+
+/// Boards, milk bucket and list tokens as they are now
+pub async fn dump(State(state): State<AdminApiState>) -> Json<StateDump> {
+    Json(state_dump(&state).await)
+}
+
+/// Resets the day 9 bucket, the day 12 boards and the day 19 list tokens at once
+pub async fn reset(State(state): State<AdminApiState>) -> Json<StateDump> {
+    state.volatile.reset().await;
+    Json(state_dump(&state).await)
+}
+
+pub async fn maintenance(State(state): State<AdminApiState>) -> Json<Maintenance> {
+    Json(Maintenance {
+        enabled: state.settings.cache.flag(MAINTENANCE),
+    })
+}
+
+/// Sets the maintenance flag in the settings, so that every instance follows it
+pub async fn set_maintenance(
+    State(state): State<AdminApiState>,
+    Json(Maintenance { enabled }): Json<Maintenance>,
+) -> Result<Json<Maintenance>, AppError> {
+    let repository = state.settings.repository.as_ref();
+    if let Err(e) = repository
+        .put(maintenance::setting_key(), json!(enabled))
+        .await
+    {
+        tracing::error!("failed to set maintenance mode: {}", e);
+        return Err(AppError::Internal);
+    }
+    reload(repository, &state.settings.cache).await;
+    Ok(Json(Maintenance {
+        enabled: state.settings.cache.flag(MAINTENANCE),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{
+        board_feed::BoardFeed,
+        config::Config,
+        day_12::{arc_board, arc_random_board, BoardState, MockGameResultRepository},
+        day_19::{state_tokens, DbState, MockQuoteRepository, PAGE_SIZE},
+        day_9::RateLimiterState,
+        moderation::WordListModerator,
+        players::MockPlayerRepository,
+        settings::{MockSettingsRepository, Setting, Settings},
+        test_support::box_future,
+    };
+    use axum::{
+        body::Body,
+        http::{header, Request, StatusCode},
+        response::Response,
+    };
+    use chrono::Utc;
+    use http_body_util::BodyExt;
+    use serde_json::Value;
+    use sqlx::types::Json as Jsonb;
+    use tower::ServiceExt;
+
+    fn setting(key: &str, value: Value) -> Setting {
+        Setting {
+            key: key.to_string(),
+            version: 1,
+            value: Jsonb(value),
+            updated_at: Utc::now(),
+            deleted_at: None,
+        }
+    }
+
+    fn create_test_app(settings: MockSettingsRepository) -> (Router, VolatileState) {
+        let volatile = VolatileState {
+            games: BoardState {
+                board: arc_board(),
+                random_board: arc_random_board(),
+                results: Arc::new(MockGameResultRepository::new()),
+                feed: BoardFeed::default(),
+                players: Arc::new(MockPlayerRepository::new()),
+            },
+            milk: RateLimiterState::default(),
+            quotes: DbState {
+                repository: Arc::new(MockQuoteRepository::new()),
+                tokens: state_tokens(),
+                moderator: Arc::new(WordListModerator::default()),
+                page_size: PAGE_SIZE,
+                players: Arc::new(MockPlayerRepository::new()),
+            },
+        };
+        let state = AdminApiState {
+            volatile: volatile.clone(),
+            settings: SettingsState {
+                repository: Arc::new(settings),
+                cache: Settings::new(),
+            },
+        };
+        let config = Config {
+            admin_token: Some("elf".to_string()),
+            ..Config::default()
+        };
+        let app = Router::new().nest("/admin", admin_api_router(state, Auth::new(&config)));
+        (app, volatile)
+    }
+
+    async fn send(app: &Router, method: &str, uri: &str, body: Option<Value>) -> Response {
+        let mut request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(header::AUTHORIZATION, "Bearer elf");
+        if body.is_some() {
+            request = request.header(header::CONTENT_TYPE, "application/json");
+        }
+        let body = body.map_or_else(Body::empty, |b| Body::from(b.to_string()));
+        app.clone()
+            .oneshot(request.body(body).unwrap())
+            .await
+            .unwrap()
+    }
+
+    async fn json(response: Response) -> Value {
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_requires_admin() {
+        let (app, _) = create_test_app(MockSettingsRepository::new());
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/admin/state")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_reset() {
+        let (app, volatile) = create_test_app(MockSettingsRepository::new());
+        assert!(volatile.milk.limiter.lock().await.try_acquire(3));
+        volatile.quotes.tokens.issue(2).await.unwrap();
+
+        let response = send(&app, "GET", "/admin/state", None).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let state = json(response).await;
+        assert_eq!(state["milk"], 2);
+        assert_eq!(state["tokens"].as_object().unwrap().len(), 1);
+        assert_eq!(state["maintenance"], false);
+
+        let response = send(&app, "POST", "/admin/reset", None).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let state = json(response).await;
+        assert_eq!(state["milk"], 5);
+        assert!(state["tokens"].as_object().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_maintenance() {
+        let mut mock = MockSettingsRepository::new();
+        mock.expect_put()
+            .withf(|key, value| key == "flags.maintenance" && value == &json!(true))
+            .returning(|key, value| box_future(Ok(setting(&key, value))));
+        mock.expect_current()
+            .returning(|| box_future(Ok(vec![setting("flags.maintenance", json!(true))])));
+        let (app, _) = create_test_app(mock);
+
+        let response = send(&app, "GET", "/admin/maintenance", None).await;
+        assert_eq!(json(response).await, json!({ "enabled": false }));
+
+        let response = send(
+            &app,
+            "PUT",
+            "/admin/maintenance",
+            Some(json!({ "enabled": true })),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(json(response).await, json!({ "enabled": true }));
+
+        let response = send(&app, "GET", "/admin/state", None).await;
+        assert_eq!(json(response).await["maintenance"], true);
+    }
+}
@@ -0,0 +1,720 @@
+//! The whole service, its router along with the background tasks and shutdown hooks, built
+//! from a database pool and the configuration
+
+use std::{collections::HashMap, sync::Arc};
+
+use axum::{
+    http::Method,
+    middleware,
+    routing::{delete, get, post, put},
+    Extension, Router,
+};
+use chrono::Utc;
+use shuttle_runtime::CustomError;
+use sqlx::PgPool;
+use tokio::sync::broadcast;
+
+use crate::{
+    admin::{admin_router, AdminState},
+    admin_api::{admin_api_router, AdminApiState},
+    app_state::AppState,
+    assets,
+    auth::{require_role, Auth, Role},
+    authors::{self, AuthorIndex, AuthorsState},
+    board_feed::{board_events, BoardFeed},
+    caching,
+    client_ip::TrustedProxies,
+    clock::SystemClock,
+    coalesce,
+    comments::{self, CommentState},
+    compression::compression_layer,
+    config::Config,
+    countdown::{self, CountdownState},
+    day_1::*,
+    day_11::*,
+    day_12::*,
+    day_16::*,
+    day_19::*,
+    day_2::*,
+    day_23::*,
+    day_24::{self, QueueState},
+    day_5::*,
+    day_9::*,
+    day_minus_1::*,
+    days::{self, EnabledDays},
+    errors::{errors_router, track_errors, ErrorLog},
+    events::{self, EventsState},
+    fixtures::{self, state_fixture_loader, FixtureState},
+    geo,
+    grpc::grpc_router,
+    health::{self, HealthState},
+    instrument,
+    jobs::{self, JobHandler, JobState, JobWorker},
+    keys, maintenance,
+    migrations::{self, MigrationMode, MigrationState},
+    moderation,
+    openapi::{self, ApiDoc},
+    outbox::{self, BroadcastSink, EventSink, OutboxDispatcher},
+    password, players,
+    preflight::{preflight, BudgetRegistry},
+    progress::{self, ProgressState},
+    prometheus,
+    quota::{self, QuotaState},
+    quote_form,
+    rate_limit::{self, ClientRateLimit},
+    room::{self, RoomRegistry},
+    schemas, self_check,
+    settings::{self, settings_router, SettingsState, SETTINGS},
+    shutdown::{self, GracefulService, ShuttleGraceful},
+    slo::{self, SloTracker},
+    snapshot::{self, SnapshotSaver, VolatileState},
+    stats::{self, StatsState},
+    tasks::Supervisor,
+    token_metrics,
+    tokens::{self, state_token_store, TokenBackend},
+    trace::trace_layer,
+    uploads,
+    validation::{validate_json, SchemaRegistry},
+    versioning::versioned,
+    MIGRATOR,
+};
+
+const EVENTS_CAPACITY: usize = 1024;
+const JOB_WORKERS: usize = 2;
+
+/// Migrates the database, checks the configuration and spawns the background tasks before
+/// assembling the router
+pub async fn build(pool: PgPool, config: Config) -> ShuttleGraceful {
+    let started_at = Utc::now();
+    // before any board is built
+    config.board.install();
+
+    let migration_state =
+        MigrationState::new(migrations::state_migration_runner(pool.clone(), &MIGRATOR));
+    match config.migration_mode {
+        MigrationMode::Startup => migration_state.migrate().await.map_err(CustomError::msg)?,
+        MigrationMode::Background => {
+            let migrations = migration_state.clone();
+            tokio::spawn(async move { migrations.migrate().await });
+        }
+        MigrationMode::Manual => {
+            tracing::info!("migrations wait for POST /admin/migrations")
+        }
+    }
+
+    // fail fast rather than at the first request hitting a broken dependency
+    if let Err(errors) = self_check::run(&pool, &MIGRATOR, &config).await {
+        let report = errors
+            .iter()
+            .map(|e| format!("- {}", e))
+            .collect::<Vec<_>>()
+            .join("\n");
+        return Err(CustomError::msg(format!("Startup self-check failed:\n{}", report)).into());
+    }
+
+    let mut tasks = Supervisor::new();
+
+    let metrics = prometheus::install().map_err(CustomError::msg)?;
+    let upkeep_metrics = metrics.clone();
+    tasks.spawn("metrics upkeep", move || {
+        prometheus::upkeep(upkeep_metrics.clone())
+    });
+
+    // outbox events are fanned out in-process to whoever subscribes
+    let (events, _) = broadcast::channel(EVENTS_CAPACITY);
+    let author_index = Arc::new(AuthorIndex::default());
+    let sinks: Vec<Arc<dyn EventSink>> = vec![
+        Arc::new(BroadcastSink::new(events.clone())),
+        author_index.clone(),
+    ];
+    let events_state = EventsState { events };
+    let outbox_pool = pool.clone();
+    let shutdown_sinks = sinks.clone();
+    tasks.spawn("outbox", move || {
+        OutboxDispatcher::new(outbox_pool.clone(), sinks.clone()).run()
+    });
+    let sweep_pool = pool.clone();
+    tasks.spawn("outbox sweep", move || outbox::sweep(sweep_pool.clone()));
+
+    let job_state = JobState {
+        repository: jobs::state_job_repository(pool.clone()),
+    };
+    let job_handlers = Arc::new(HashMap::from([(
+        LOCKFILE_GRAPH_JOB,
+        render_graph_job as JobHandler,
+    )]));
+    for _ in 0..JOB_WORKERS {
+        let job_repository = job_state.repository.clone();
+        let job_handlers = job_handlers.clone();
+        tasks.spawn("jobs", move || {
+            JobWorker::new(job_repository.clone(), job_handlers.clone()).run()
+        });
+    }
+    let job_repository = job_state.repository.clone();
+    tasks.spawn("job cleanup", move || jobs::cleanup(job_repository.clone()));
+
+    let stats_state = StatsState {
+        repository: stats::state_stats_repository(pool.clone()),
+    };
+    let stats_repository = stats_state.repository.clone();
+    tasks.spawn("stats rollup", move || {
+        stats::rollup(stats_repository.clone())
+    });
+
+    let quota_state = QuotaState {
+        repository: quota::state_quota_repository(pool.clone()),
+        daily_limit: config.quote_daily_quota,
+        auth: Auth::new(&config),
+    };
+    let quota_repository = quota_state.repository.clone();
+    tasks.spawn("quota cleanup", move || {
+        quota::cleanup(quota_repository.clone())
+    });
+
+    // settings are in place before the states that read them are built
+    let settings_state = SettingsState {
+        repository: settings::state_settings_repository(pool.clone()),
+        cache: SETTINGS.clone(),
+    };
+    settings::reload(settings_state.repository.as_ref(), &settings_state.cache).await;
+    let settings_pool = pool.clone();
+    let settings_repository = settings_state.repository.clone();
+    tasks.spawn("settings listener", move || {
+        settings::listen(
+            settings_pool.clone(),
+            settings_repository.clone(),
+            SETTINGS.clone(),
+        )
+    });
+
+    let db_state = DbState {
+        repository: InstrumentedQuoteRepository::wrap(
+            state_repository(pool.clone()),
+            config.slow_query,
+        ),
+        tokens: state_token_store(config.pagination_tokens, pool.clone()),
+        moderator: moderation::state_moderator(&config),
+        page_size: config.quotes_page_size,
+        players: players::state_players(pool.clone()),
+    };
+    if config.pagination_tokens == TokenBackend::Postgres {
+        let token_store = db_state.tokens.clone();
+        tasks.spawn("token cleanup", move || {
+            tokens::cleanup(token_store.clone())
+        });
+    }
+
+    let authors_state = AuthorsState {
+        index: author_index,
+        repository: db_state.repository.clone(),
+    };
+    if let Err(e) = authors_state
+        .index
+        .rebuild(authors_state.repository.as_ref())
+        .await
+    {
+        tracing::warn!("failed to build the authors index: {}", e);
+    }
+
+    let publisher = db_state.repository.clone();
+    tasks.spawn("quote publisher", move || {
+        publish_scheduled(publisher.clone())
+    });
+
+    let rate_limiter_state = RateLimiterState::new(config.milk_bucket);
+
+    let milk_state = rate_limiter_state.clone();
+    tasks.spawn("milk settings", move || follow_settings(milk_state.clone()));
+
+    let board_state = BoardState {
+        board: arc_board(),
+        random_board: arc_random_board(),
+        results: state_game_results(pool.clone()),
+        feed: BoardFeed::default(),
+        players: db_state.players.clone(),
+    };
+
+    let volatile_state = VolatileState {
+        games: board_state.clone(),
+        milk: rate_limiter_state.clone(),
+        quotes: db_state.clone(),
+    };
+    if config.persist_state {
+        match snapshot::take(&pool).await {
+            Ok(Some(s)) => volatile_state.restore(s).await,
+            Ok(None) => {}
+            Err(e) => tracing::warn!("failed to restore the state snapshot: {}", e),
+        }
+    }
+
+    let bulk_delete_state = BulkDeleteState {
+        repository: db_state.repository.clone(),
+        max_rows: config.bulk_delete_max,
+    };
+
+    let gift_state = GiftState {
+        // loaded by the self-check already
+        keys: keys::state_keys(&config).expect("day 16 keys"),
+        leeway: config.jwt_leeway.as_secs(),
+        limits: config.gift_limits,
+        secure_cookies: config.secure_cookies,
+    };
+    token_metrics::TOKENS.register_kids(gift_state.keys.key_ids());
+
+    let schema_registry = SchemaRegistry::new(
+        [
+            crate::day_9::schemas(),
+            crate::day_16::schemas(),
+            crate::day_19::schemas(),
+            day_24::schemas(),
+            comments::schemas(),
+            players::schemas(),
+        ]
+        .into_iter()
+        .flatten(),
+    )
+    .map_err(CustomError::msg)?;
+
+    let budget_registry = BudgetRegistry::new(
+        [
+            crate::day_5::budgets(),
+            crate::day_16::budgets(),
+            crate::day_19::budgets(),
+            crate::day_23::budgets(),
+        ]
+        .into_iter()
+        .flatten(),
+    )
+    .with_limits(&config.body_limits);
+
+    let api_doc = ApiDoc::new(
+        [
+            crate::day_minus_1::operations(),
+            crate::day_1::operations(),
+            crate::day_2::operations(),
+            crate::day_5::operations(),
+            crate::day_9::operations(),
+            crate::day_11::operations(),
+            crate::day_12::operations(),
+            crate::day_16::operations(),
+            crate::day_19::operations(),
+            crate::day_23::operations(),
+        ]
+        .into_iter()
+        .flatten(),
+    );
+
+    let slo_tracker = SloTracker::new(
+        config.slo_budgets.clone(),
+        config.slo_default,
+        config.slo_window,
+    );
+
+    let health_state = HealthState {
+        database: health::state_probe(pool.clone()),
+        migrations: migration_state.status.clone(),
+        draining: shutdown::draining(),
+    };
+
+    let admin_state = AdminState {
+        quotes: db_state.clone(),
+        games: board_state.clone(),
+        milk: rate_limiter_state.clone(),
+    };
+
+    let enabled_days = EnabledDays::new(config.disabled_days.clone());
+
+    let admin_api_state = AdminApiState {
+        volatile: volatile_state.clone(),
+        settings: settings_state.clone(),
+    };
+
+    let mut place_route = post(place);
+    if let Some(interval) = config.place_interval {
+        let place_limit = ClientRateLimit::every(interval);
+        let sweep = place_limit.clone();
+        tasks.spawn("place limit sweep", move || sweep.clone().sweep());
+        place_route = place_route.route_layer(middleware::from_fn_with_state(
+            place_limit,
+            rate_limit::limit_clients,
+        ));
+    }
+
+    // signing arbitrary gifts is only meant for local runs
+    let dev_routes = if config.dev_tokens {
+        Router::new().route("/16/dev/mint", post(mint))
+    } else {
+        Router::new()
+    };
+
+    let error_log = ErrorLog::new();
+    let auth = Auth::new(&config);
+    let reader = middleware::from_fn_with_state(auth.require(Role::Reader), require_role);
+    let editor = middleware::from_fn_with_state(auth.require(Role::Editor), require_role);
+    let admin = middleware::from_fn_with_state(auth.require(Role::Admin), require_role);
+    // the responses of these routes are the same for every client
+    let single_flight = middleware::from_fn(coalesce::single_flight);
+
+    // fixtures would mix with real data, they are only meant for local runs
+    let fixture_routes = if config.seed_fixtures {
+        Router::new()
+            .route(
+                "/admin/seed",
+                post(fixtures::seed).route_layer(admin.clone()),
+            )
+            .with_state(FixtureState {
+                loader: state_fixture_loader(pool.clone()),
+            })
+    } else {
+        Router::new()
+    };
+
+    let upload_repository = config
+        .store_uploads
+        .then(|| uploads::state_upload_repository(pool.clone(), config.upload_quota_bytes));
+    if let Some(repository) = upload_repository.clone() {
+        let retention = config.upload_retention;
+        tasks.spawn("upload cleanup", move || {
+            uploads::cleanup(repository.clone(), retention)
+        });
+    }
+
+    let app_state = AppState {
+        milk: rate_limiter_state,
+        games: board_state,
+        gifts: gift_state,
+        bulk_delete: bulk_delete_state,
+        events: events_state,
+        authors: authors_state,
+        quotes: db_state.clone(),
+        restore: RestoreState {
+            repository: db_state.repository.clone(),
+            uploads: upload_repository.clone(),
+        },
+        slo: slo_tracker.clone(),
+        migrations: migration_state,
+        countdown: CountdownState {
+            clock: Arc::new(SystemClock),
+        },
+        scenes: SceneRegistry::new(),
+        lockfiles: LockfileState {
+            uploads: upload_repository,
+            jobs: Some(job_state.repository.clone()),
+        },
+        jobs: job_state,
+        queue: QueueState {
+            repository: day_24::state_queue_repository(pool.clone()),
+        },
+        comments: CommentState {
+            repository: comments::state_comment_repository(pool.clone()),
+        },
+        rooms: RoomRegistry::new(),
+        stats: stats_state.clone(),
+    };
+
+    // every route takes its state out of the app state, whatever their order
+    let routes: Router = Router::new()
+        .route("/", get(hello_bird))
+        .route("/-1/seek", get(seek))
+        .route("/1/slice", post(slice_names))
+        .route("/2/dest", get(dest_v4))
+        .route("/2/key", get(key_v4))
+        .route("/2/v6/dest", get(dest_v6))
+        .route("/2/v6/key", get(key_v6))
+        .route(
+            "/5/manifest",
+            post(manifest).route_layer(budget_registry.body_limit(Method::POST, "/5/manifest")),
+        )
+        .route("/9/milk", post(milk))
+        .route("/9/refill", post(refill).route_layer(admin.clone()))
+        .route("/11/red_pixels", post(red_pixels))
+        .route("/12/board", get(board).route_layer(single_flight.clone()))
+        .route("/12/board/events", get(board_events))
+        .route("/12/board/diff", get(diff))
+        .route("/12/random-board", get(random))
+        .route("/12/reset", post(reset).route_layer(admin.clone()))
+        .route("/12/history", get(history))
+        .route(
+            "/12/random-board/state",
+            get(random_state)
+                .put(rewind_random)
+                .route_layer(admin.clone()),
+        )
+        .route("/12/export", get(export))
+        .route("/12/import", post(import).route_layer(admin.clone()))
+        .route("/12/players", post(players::register))
+        .route(
+            "/12/leaderboard",
+            get(players::leaderboard).route_layer(single_flight.clone()),
+        )
+        .route("/12/place/:team/:column", place_route)
+        .route(
+            "/16/wrap",
+            post(wrap).route_layer(budget_registry.body_limit(Method::POST, "/16/wrap")),
+        )
+        .route("/16/unwrap", get(unwrap))
+        .route("/16/decode", post(decode))
+        .route("/16/seal", post(seal))
+        .route("/16/open", post(open))
+        .route(
+            "/16/introspect",
+            post(introspect).route_layer(admin.clone()),
+        )
+        .merge(dev_routes)
+        .merge(fixture_routes)
+        .route("/19/quotes", delete(bulk_delete).route_layer(admin.clone()))
+        .route(
+            "/19/events",
+            get(events::events).route_layer(reader.clone()),
+        )
+        .route(
+            "/19/authors",
+            get(authors::authors)
+                .route_layer(single_flight.clone())
+                .route_layer(reader.clone()),
+        )
+        .route(
+            "/admin/authors/rebuild",
+            post(authors::rebuild).route_layer(admin.clone()),
+        )
+        .route("/19/reset", post(reset_quotes).route_layer(admin.clone()))
+        .route("/19/cite/:id", get(cite).route_layer(reader.clone()))
+        .route(
+            "/19/cite/:id/like",
+            post(like).delete(unlike).route_layer(reader.clone()),
+        )
+        .route(
+            "/19/top",
+            get(top)
+                .route_layer(single_flight.clone())
+                .route_layer(reader.clone()),
+        )
+        .route(
+            "/19/authors/suggest",
+            get(suggest_authors).route_layer(reader.clone()),
+        )
+        .route(
+            "/19/draft",
+            post(draft)
+                .route_layer(middleware::from_fn_with_state(
+                    quota_state.clone(),
+                    quota::enforce_quote_quota,
+                ))
+                .route_layer(editor.clone()),
+        )
+        .route(
+            "/23/quote-form",
+            get(quote_form::quote_form).merge(
+                post(quote_form::submit_quote)
+                    .route_layer(middleware::from_fn_with_state(
+                        quota_state.clone(),
+                        quota::enforce_quote_quota,
+                    ))
+                    .route_layer(editor.clone()),
+            ),
+        )
+        .route("/19/remove/:id", delete(remove).route_layer(editor.clone()))
+        .route("/19/undo/:id", put(undo).route_layer(editor.clone()))
+        .route(
+            "/19/list",
+            get(list).head(list_totals).route_layer(reader.clone()),
+        )
+        .route("/19/count", get(count).route_layer(reader.clone()))
+        .route("/admin/backup", post(backup).route_layer(admin.clone()))
+        .route(
+            "/admin/queries",
+            get(instrument::timings).route_layer(admin.clone()),
+        )
+        .route(
+            "/admin/coalescing",
+            get(coalesce::counts).route_layer(admin.clone()),
+        )
+        .route(
+            "/admin/tokens",
+            get(token_metrics::counts).route_layer(admin.clone()),
+        )
+        .route(
+            "/admin/moderation",
+            get(moderation::pending).route_layer(admin.clone()),
+        )
+        .route(
+            "/admin/moderation/:id/approve",
+            post(moderation::approve).route_layer(admin.clone()),
+        )
+        .route(
+            "/admin/moderation/:id/reject",
+            post(moderation::reject).route_layer(admin.clone()),
+        )
+        .route("/admin/restore", post(restore).route_layer(admin.clone()))
+        .route(
+            "/admin/restore/:upload_id",
+            get(restored_archive).route_layer(admin.clone()),
+        )
+        .route("/admin/slo", get(slo::report).route_layer(admin.clone()))
+        .route(
+            "/admin/migrations",
+            get(migrations::status)
+                .post(migrations::trigger)
+                .route_layer(admin),
+        )
+        .nest(
+            "/assets",
+            Router::new()
+                .route("/:name", get(assets::serve))
+                .layer(middleware::from_fn(caching::conditional)),
+        )
+        .nest(
+            "/schemas",
+            Router::new()
+                .route("/:file", get(schemas::serve))
+                .layer(middleware::from_fn(caching::conditional)),
+        )
+        .route("/countdown", get(countdown::get_countdown))
+        .route("/23", get(assets::page))
+        .route("/23/star", get(star))
+        .route("/23/present/:color", get(present))
+        .route("/23/ornament/:state/:number", get(ornament))
+        .route("/23/scene", get(scene))
+        .route(
+            "/23/lockfile",
+            post(lockfile).route_layer(budget_registry.body_limit(Method::POST, "/23/lockfile")),
+        )
+        .route("/23/lockfile/:upload_id", get(stored_lockfile))
+        .route("/jobs/:id", get(jobs::status))
+        .route("/jobs/:id/result", get(jobs::result))
+        .route("/24/enqueue", post(day_24::enqueue))
+        .route("/24/dequeue", post(day_24::dequeue))
+        .route("/24/peek", get(day_24::peek))
+        .route(
+            "/19/cite/:id/comments",
+            get(comments::thread).route_layer(reader),
+        )
+        .route(
+            "/19/cite/:id/comments",
+            post(comments::comment)
+                .route_layer(middleware::from_fn_with_state(
+                    quota_state,
+                    quota::enforce_quote_quota,
+                ))
+                .route_layer(editor.clone()),
+        )
+        .route(
+            "/19/comments/:comment_id",
+            delete(comments::remove_comment).route_layer(editor),
+        )
+        .route("/geo/coords", get(geo::coords))
+        .route("/geo/timezone", post(geo::timezone))
+        .route("/validate/nice", post(password::nice))
+        .route("/validate/game", post(password::game))
+        .route("/ws/room/:name", get(room::join))
+        .route("/ws/room/:name/participants", get(room::participants))
+        .route("/stats/daily", get(stats::daily))
+        .with_state(app_state)
+        .nest("/admin/ui", admin_router(admin_state, auth.clone()))
+        .nest("/admin", admin_api_router(admin_api_state, auth.clone()))
+        .nest(
+            "/admin/errors",
+            errors_router(error_log.clone(), auth.clone()),
+        )
+        .nest(
+            "/admin/settings",
+            settings_router(settings_state, auth.clone()),
+        )
+        .route_layer(middleware::from_fn_with_state(
+            enabled_days.clone(),
+            days::gate,
+        ));
+
+    let progress_state = ProgressState {
+        days: Arc::new(progress::probe(&routes, progress::DAYS).await),
+        started_at,
+        quotes: db_state.repository.clone(),
+        games: state_game_results(pool.clone()),
+    };
+    let router = versioned(routes)
+        .merge(
+            Router::new()
+                .route("/meta/progress", get(progress::progress))
+                .with_state(progress_state),
+        )
+        .merge(
+            Router::new()
+                .route("/days", get(days::days))
+                .with_state(enabled_days),
+        )
+        .merge(
+            Router::new()
+                .route("/openapi.json", get(openapi::spec))
+                .with_state(api_doc)
+                .route("/docs", get(openapi::docs)),
+        )
+        .merge(
+            Router::new()
+                .route("/metrics", get(prometheus::render))
+                .with_state(metrics),
+        )
+        .merge(
+            Router::new()
+                .route("/health", get(health::health))
+                .route("/ready", get(health::ready))
+                .with_state(health_state),
+        )
+        .layer(middleware::from_fn_with_state(
+            schema_registry,
+            validate_json,
+        ))
+        // before validation, which reads the bodies it checks
+        .layer(middleware::from_fn_with_state(
+            budget_registry.clone(),
+            preflight,
+        ))
+        // gRPC bodies are framed protobuf, cut at the budget of the REST route writing quotes
+        .merge(
+            grpc_router(db_state, &auth)
+                .layer(budget_registry.body_limit(Method::POST, "/19/draft")),
+        )
+        .layer(middleware::from_fn_with_state(
+            SETTINGS.clone(),
+            maintenance::guard,
+        ))
+        .layer(compression_layer())
+        // inside the request id span, so that every log line carries the id
+        .layer(trace_layer())
+        .layer(middleware::from_fn_with_state(error_log, track_errors))
+        .layer(middleware::from_fn(stats::count_requests))
+        .layer(middleware::from_fn(prometheus::track_requests))
+        .layer(middleware::from_fn_with_state(
+            slo_tracker,
+            slo::track_latency,
+        ));
+    // outside of everything else, so that a flooding client costs as little as possible
+    let router = match config.client_quota {
+        Some(quota) => {
+            let client_limit = ClientRateLimit::new(quota);
+            let sweep = client_limit.clone();
+            tasks.spawn("client limit sweep", move || sweep.clone().sweep());
+            router.layer(middleware::from_fn_with_state(
+                client_limit,
+                rate_limit::limit_clients,
+            ))
+        }
+        None => router,
+    };
+    let router = router.layer(Extension(TrustedProxies::new(
+        config.trusted_proxies.clone(),
+    )));
+
+    // background tasks stop first, so that nothing changes the state flushed after them
+    let mut service = GracefulService::new(router)
+        .on_shutdown(tasks)
+        .on_shutdown(OutboxDispatcher::new(pool.clone(), shutdown_sinks))
+        .on_shutdown(stats_state);
+    if config.persist_state {
+        service = service.on_shutdown(SnapshotSaver {
+            pool,
+            state: volatile_state,
+        });
+    }
+
+    Ok(service)
+}
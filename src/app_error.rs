@@ -0,0 +1,145 @@
+//! Errors of the handlers, answered with their status and the JSON body of validation errors
+
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+
+use crate::{errors::ErrorKind, multipart::UploadError, validation::ValidationErrors};
+
+#[derive(Debug)]
+pub enum AppError {
+    /// `RowNotFound` is answered as a 404, anything else as a 500 with the cause logged
+    Database(sqlx::Error),
+    /// Malformed or inconsistent request
+    BadRequest(String),
+    /// Well-formed request that can't be carried out, such as a quote refused by moderation
+    Unprocessable(String),
+    NotFound,
+    NotAcceptable,
+    Unauthorized,
+    RateLimited,
+    /// Body over the limit of its route, in bytes
+    TooLarge(u64),
+    /// Token turned away with the status of the route, counted in the error log under its kind
+    Jwt(StatusCode, &'static str),
+    Upload(UploadError),
+    /// Failure whose cause was logged where it happened
+    Internal,
+    /// Status without a variant of its own, such as the 418 of day 23
+    Status(StatusCode),
+}
+
+impl AppError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            AppError::Database(sqlx::Error::RowNotFound) | AppError::NotFound => {
+                StatusCode::NOT_FOUND
+            }
+            AppError::Database(_) | AppError::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+            AppError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            AppError::Unprocessable(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            AppError::NotAcceptable => StatusCode::NOT_ACCEPTABLE,
+            AppError::Unauthorized => StatusCode::UNAUTHORIZED,
+            AppError::RateLimited => StatusCode::TOO_MANY_REQUESTS,
+            AppError::TooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            AppError::Jwt(status, _) | AppError::Status(status) => *status,
+            AppError::Upload(e) => e.status(),
+        }
+    }
+
+    fn message(&self) -> String {
+        match self {
+            AppError::BadRequest(message) | AppError::Unprocessable(message) => message.clone(),
+            AppError::Jwt(_, kind) => format!("token {}", kind.replace('_', " ")),
+            AppError::TooLarge(limit) => format!("body over {} bytes", limit),
+            AppError::Upload(UploadError::Malformed) => "malformed upload".to_string(),
+            AppError::Upload(UploadError::Missing) => "missing upload".to_string(),
+            AppError::Upload(UploadError::TooLarge) => "upload too large".to_string(),
+            AppError::Upload(UploadError::UnsupportedType) => "unsupported upload type".to_string(),
+            // the cause of a database error stays in the logs
+            _ => self
+                .status()
+                .canonical_reason()
+                .unwrap_or_default()
+                .to_ascii_lowercase(),
+        }
+    }
+}
+
+impl From<sqlx::Error> for AppError {
+    fn from(e: sqlx::Error) -> Self {
+        AppError::Database(e)
+    }
+}
+
+impl From<UploadError> for AppError {
+    fn from(e: UploadError) -> Self {
+        AppError::Upload(e)
+    }
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let status = self.status();
+        if let AppError::Database(e) = &self {
+            if status.is_server_error() {
+                tracing::error!("database error: {}", e);
+            }
+        }
+        let body = Json(ValidationErrors::whole(self.message()));
+        match self {
+            AppError::Jwt(_, kind) => (status, Extension(ErrorKind(kind)), body).into_response(),
+            _ => (status, body).into_response(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body_util::BodyExt;
+    use serde_json::{json, Value};
+
+    async fn respond(error: AppError) -> (StatusCode, Value) {
+        let response = error.into_response();
+        let status = response.status();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_into_response() {
+        assert_eq!(
+            respond(AppError::Database(sqlx::Error::RowNotFound)).await,
+            (
+                StatusCode::NOT_FOUND,
+                json!({ "errors": [{ "path": "", "message": "not found" }] })
+            )
+        );
+        let (status, body) = respond(AppError::Database(sqlx::Error::PoolTimedOut)).await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(body["errors"][0]["message"], "internal server error");
+        let (status, body) = respond(AppError::Unprocessable("too naughty".to_string())).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body["errors"][0]["message"], "too naughty");
+        let (status, _) = respond(AppError::Upload(UploadError::TooLarge)).await;
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+        let (status, _) = respond(AppError::Status(StatusCode::IM_A_TEAPOT)).await;
+        assert_eq!(status, StatusCode::IM_A_TEAPOT);
+    }
+
+    #[tokio::test]
+    async fn test_jwt_kind() {
+        let response = AppError::Jwt(StatusCode::UNAUTHORIZED, "signature_failed").into_response();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            response.extensions().get::<ErrorKind>(),
+            Some(&ErrorKind("signature_failed"))
+        );
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let body = serde_json::from_slice::<Value>(&body).unwrap();
+        assert_eq!(body["errors"][0]["message"], "token signature failed");
+    }
+}
@@ -0,0 +1,45 @@
+//! State of the whole router. Handlers keep extracting the part they need, e.g.
+//! `State<BoardState>`, through the `FromRef` implementations derived here.
+
+use axum::extract::FromRef;
+
+use crate::{
+    authors::AuthorsState,
+    comments::CommentState,
+    countdown::CountdownState,
+    day_12::BoardState,
+    day_16::GiftState,
+    day_19::{BulkDeleteState, DbState, RestoreState},
+    day_23::{LockfileState, SceneRegistry},
+    day_24::QueueState,
+    day_9::RateLimiterState,
+    events::EventsState,
+    jobs::JobState,
+    migrations::MigrationState,
+    room::RoomRegistry,
+    slo::SloTracker,
+    stats::StatsState,
+};
+
+/// Each part has a type of its own, which is what `FromRef` picks it by
+#[derive(Clone, FromRef)]
+pub struct AppState {
+    pub milk: RateLimiterState,
+    pub games: BoardState,
+    pub gifts: GiftState,
+    pub bulk_delete: BulkDeleteState,
+    pub events: EventsState,
+    pub authors: AuthorsState,
+    pub quotes: DbState,
+    pub restore: RestoreState,
+    pub slo: SloTracker,
+    pub migrations: MigrationState,
+    pub countdown: CountdownState,
+    pub scenes: SceneRegistry,
+    pub lockfiles: LockfileState,
+    pub jobs: JobState,
+    pub queue: QueueState,
+    pub comments: CommentState,
+    pub rooms: RoomRegistry,
+    pub stats: StatsState,
+}
@@ -0,0 +1,122 @@
+//! Frontend files embedded at build time, linked with their content hash so that they can be cached for good
+
+use std::{collections::HashMap, sync::LazyLock};
+
+use axum::{
+    extract::Path,
+    http::{header, StatusCode},
+    response::{IntoResponse, Redirect, Response},
+};
+
+use crate::caching::fingerprint;
+
+include!(concat!(env!("OUT_DIR"), "/assets.rs"));
+
+/// Embedded assets by file name
+static ASSETS: LazyLock<HashMap<&'static str, Asset>> = LazyLock::new(|| {
+    EMBEDDED
+        .iter()
+        .map(|(name, content)| {
+            let asset = Asset {
+                content,
+                fingerprint: fingerprint(content),
+                mime: mime(name),
+            };
+            (*name, asset)
+        })
+        .collect()
+});
+
+struct Asset {
+    content: &'static [u8],
+    fingerprint: String,
+    mime: &'static str,
+}
+
+fn mime(name: &str) -> &'static str {
+    match name.rsplit_once('.').map(|(_, extension)| extension) {
+        Some("html") => "text/html; charset=utf-8",
+        Some("css") => "text/css; charset=utf-8",
+        Some("js") => "text/javascript; charset=utf-8",
+        Some("svg") => "image/svg+xml",
+        Some("png") => "image/png",
+        _ => "application/octet-stream",
+    }
+}
+
+/// URL of an asset, changing along with its content, `None` for assets that weren't embedded
+pub fn asset_url(name: &str) -> Option<String> {
+    ASSETS
+        .get(name)
+        .map(|asset| format!("/assets/{}?v={}", name, asset.fingerprint))
+}
+
+/// Serves an embedded asset, the caching middleware making fingerprinted requests immutable
+pub async fn serve(Path(name): Path<String>) -> Response {
+    match ASSETS.get(name.as_str()) {
+        Some(asset) => ([(header::CONTENT_TYPE, asset.mime)], asset.content).into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+/// Stable entry point of the day 23 page, sending to its current version
+pub async fn page() -> Redirect {
+    // embedded by the build script along with every file of the directory
+    Redirect::temporary(&asset_url("23.html").unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::caching::conditional;
+    use axum::{body::Body, http::Request, middleware, routing::get, Router};
+    use tower::ServiceExt;
+
+    fn create_test_app() -> Router {
+        Router::new().route("/23", get(page)).nest(
+            "/assets",
+            Router::new()
+                .route("/:name", get(serve))
+                .layer(middleware::from_fn(conditional)),
+        )
+    }
+
+    async fn get_uri(uri: &str) -> Response {
+        create_test_app()
+            .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_fingerprinted_page() {
+        let response = get_uri("/23").await;
+        assert_eq!(response.status(), StatusCode::TEMPORARY_REDIRECT);
+        let location = response.headers()[header::LOCATION].to_str().unwrap();
+        assert_eq!(Some(location.to_string()), asset_url("23.html"));
+
+        let response = get_uri(location).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            "text/html; charset=utf-8"
+        );
+        assert!(response.headers()[header::CACHE_CONTROL]
+            .to_str()
+            .unwrap()
+            .contains("immutable"));
+
+        // the logical name still works, revalidated on every use
+        let response = get_uri("/assets/23.html").await;
+        assert_eq!(response.headers()[header::CACHE_CONTROL], "no-cache");
+    }
+
+    #[tokio::test]
+    async fn test_unknown_asset() {
+        assert_eq!(asset_url("missing.css"), None);
+        assert_eq!(
+            get_uri("/assets/missing.css").await.status(),
+            StatusCode::NOT_FOUND
+        );
+    }
+}
@@ -0,0 +1,354 @@
+use core::str::FromStr;
+use std::collections::HashMap;
+
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderMap, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use serde::{Deserialize, Serialize};
+
+use crate::{config::Config, quota::API_KEY_HEADER};
+
+/// Permissions granted to a credential, each role includes the ones below it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    Reader,
+    Editor,
+    Admin,
+}
+
+impl FromStr for Role {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "reader" => Ok(Role::Reader),
+            "editor" => Ok(Role::Editor),
+            "admin" => Ok(Role::Admin),
+            other => Err(format!("unknown role {}", other)),
+        }
+    }
+}
+
+/// Claims of the JWTs accepted as credentials
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Claims {
+    pub role: Role,
+    pub exp: u64,
+}
+
+/// Credentials accepted by the service and the role each of them grants
+#[derive(Clone, Debug)]
+pub struct Auth {
+    token: Option<String>,
+    api_keys: HashMap<String, Role>,
+    jwt_secret: Option<String>,
+    enforce_roles: bool,
+    production: bool,
+}
+
+/// Role required by a group of routes
+#[derive(Clone, Debug)]
+pub struct Gate {
+    auth: Auth,
+    role: Role,
+}
+
+impl Auth {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            token: config.admin_token.clone(),
+            api_keys: config.api_keys.clone(),
+            jwt_secret: config.auth_jwt_secret.clone(),
+            enforce_roles: config.enforce_roles,
+            production: config.production,
+        }
+    }
+
+    pub fn require(&self, role: Role) -> Gate {
+        Gate {
+            auth: self.clone(),
+            role,
+        }
+    }
+
+    /// The `x-api-key` of the request when it's one of the configured keys
+    pub fn known_api_key(&self, headers: &HeaderMap) -> Option<String> {
+        let presented = headers.get(API_KEY_HEADER)?.to_str().ok()?;
+        self.api_keys
+            .keys()
+            .any(|key| constant_time_eq(presented, key))
+            .then(|| presented.to_string())
+    }
+
+    fn has_credentials(&self) -> bool {
+        self.token.is_some() || !self.api_keys.is_empty() || self.jwt_secret.is_some()
+    }
+
+    /// Role of the credential sent with the request, if it is a known one
+    fn role(&self, headers: &HeaderMap) -> Option<Role> {
+        let presented = presented_token(headers).or_else(|| {
+            headers
+                .get(API_KEY_HEADER)
+                .and_then(|v| v.to_str().ok())
+                .map(str::to_string)
+        })?;
+
+        if self
+            .token
+            .as_ref()
+            .is_some_and(|t| constant_time_eq(&presented, t))
+        {
+            return Some(Role::Admin);
+        }
+
+        if let Some(role) = self
+            .api_keys
+            .iter()
+            .find(|(key, _)| constant_time_eq(&presented, key))
+            .map(|(_, role)| *role)
+        {
+            return Some(role);
+        }
+
+        let secret = self.jwt_secret.as_ref()?;
+        jsonwebtoken::decode::<Claims>(
+            &presented,
+            &DecodingKey::from_secret(secret.as_bytes()),
+            &Validation::new(Algorithm::HS256),
+        )
+        .ok()
+        .map(|data| data.claims.role)
+    }
+
+    fn allows(&self, headers: &HeaderMap) -> bool {
+        match self.role(headers) {
+            Some(role) => role == Role::Admin,
+            // without credentials the admin routes are open on local runs only
+            None => !self.has_credentials() && !self.production,
+        }
+    }
+
+    fn check(&self, headers: &HeaderMap, required: Role) -> Result<(), StatusCode> {
+        // admin routes are guarded the same as the admin UI, whether roles are enforced or not
+        if required == Role::Admin {
+            return match (self.allows(headers), self.role(headers)) {
+                (true, _) => Ok(()),
+                (false, Some(_)) => Err(StatusCode::FORBIDDEN),
+                (false, None) => Err(StatusCode::UNAUTHORIZED),
+            };
+        }
+
+        // the challenge routes stay public unless roles are enforced
+        if !self.enforce_roles {
+            return Ok(());
+        }
+
+        match self.role(headers) {
+            Some(role) if role >= required => Ok(()),
+            Some(_) => Err(StatusCode::FORBIDDEN),
+            None => Err(StatusCode::UNAUTHORIZED),
+        }
+    }
+}
+
+impl Gate {
+    /// Checks credentials sent outside of `require_role`, such as the metadata of a gRPC call
+    pub fn check(&self, headers: &HeaderMap) -> Result<(), StatusCode> {
+        self.auth.check(headers, self.role)
+    }
+}
+
+/// Token from a bearer header, or the password of basic credentials so that browsers can prompt for it
+fn presented_token(headers: &HeaderMap) -> Option<String> {
+    let value = headers.get(header::AUTHORIZATION)?.to_str().ok()?;
+    let (scheme, credentials) = value.split_once(' ')?;
+
+    match scheme.to_ascii_lowercase().as_str() {
+        "bearer" => Some(credentials.trim().to_string()),
+        "basic" => {
+            let decoded = String::from_utf8(STANDARD.decode(credentials.trim()).ok()?).ok()?;
+            decoded
+                .split_once(':')
+                .map(|(_, password)| password.to_string())
+        }
+        _ => None,
+    }
+}
+
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |acc, (x, y)| acc | (x ^ y))
+            == 0
+}
+
+fn unauthorized() -> Response {
+    (
+        StatusCode::UNAUTHORIZED,
+        [(header::WWW_AUTHENTICATE, "Basic realm=\"admin\"")],
+        "".to_string(),
+    )
+        .into_response()
+}
+
+pub async fn require_admin(State(auth): State<Auth>, request: Request, next: Next) -> Response {
+    if auth.allows(request.headers()) {
+        return next.run(request).await;
+    }
+
+    unauthorized()
+}
+
+pub async fn require_role(State(gate): State<Gate>, request: Request, next: Next) -> Response {
+    match gate.auth.check(request.headers(), gate.role) {
+        Ok(()) => next.run(request).await,
+        Err(StatusCode::UNAUTHORIZED) => unauthorized(),
+        Err(status) => (status, "".to_string()).into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+    use jsonwebtoken::{EncodingKey, Header};
+
+    fn auth(token: Option<&str>, production: bool) -> Auth {
+        Auth {
+            token: token.map(str::to_string),
+            api_keys: HashMap::new(),
+            jwt_secret: None,
+            enforce_roles: false,
+            production,
+        }
+    }
+
+    fn enforced() -> Auth {
+        Auth {
+            api_keys: HashMap::from([
+                ("reader-key".to_string(), Role::Reader),
+                ("editor-key".to_string(), Role::Editor),
+            ]),
+            jwt_secret: Some("jwt-secret".to_string()),
+            enforce_roles: true,
+            ..auth(Some("elf"), true)
+        }
+    }
+
+    fn headers(authorization: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::AUTHORIZATION,
+            HeaderValue::from_str(authorization).unwrap(),
+        );
+        headers
+    }
+
+    #[test]
+    fn test_bearer_token() {
+        let auth = auth(Some("elf"), true);
+        assert!(auth.allows(&headers("Bearer elf")));
+        assert!(!auth.allows(&headers("Bearer orc")));
+        assert!(!auth.allows(&HeaderMap::new()));
+    }
+
+    #[test]
+    fn test_basic_credentials() {
+        let auth = auth(Some("elf"), true);
+        let credentials = STANDARD.encode("santa:elf");
+        assert!(auth.allows(&headers(&format!("Basic {}", credentials))));
+        assert!(!auth.allows(&headers("Basic not-base64")));
+    }
+
+    #[test]
+    fn test_without_token() {
+        assert!(auth(None, false).allows(&HeaderMap::new()));
+        assert!(!auth(None, true).allows(&headers("Bearer anything")));
+    }
+
+    #[test]
+    fn test_roles_from_api_keys() {
+        let auth = enforced();
+        let mut reader = HeaderMap::new();
+        reader.insert(API_KEY_HEADER, HeaderValue::from_static("reader-key"));
+
+        assert_eq!(auth.check(&reader, Role::Reader), Ok(()));
+        assert_eq!(
+            auth.check(&reader, Role::Editor),
+            Err(StatusCode::FORBIDDEN)
+        );
+        assert_eq!(
+            auth.check(&headers("Bearer editor-key"), Role::Editor),
+            Ok(())
+        );
+        assert_eq!(auth.check(&headers("Bearer elf"), Role::Admin), Ok(()));
+        assert_eq!(
+            auth.check(&HeaderMap::new(), Role::Reader),
+            Err(StatusCode::UNAUTHORIZED)
+        );
+        assert!(!auth.allows(&headers("Bearer editor-key")));
+    }
+
+    #[test]
+    fn test_roles_from_jwt() {
+        let auth = enforced();
+        let jwt = jsonwebtoken::encode(
+            &Header::default(),
+            &Claims {
+                role: Role::Editor,
+                exp: u64::MAX / 2,
+            },
+            &EncodingKey::from_secret(b"jwt-secret"),
+        )
+        .unwrap();
+
+        let headers = headers(&format!("Bearer {}", jwt));
+        assert_eq!(auth.check(&headers, Role::Editor), Ok(()));
+        assert_eq!(
+            auth.check(&headers, Role::Admin),
+            Err(StatusCode::FORBIDDEN)
+        );
+    }
+
+    #[test]
+    fn test_roles_not_enforced() {
+        let auth = Auth {
+            enforce_roles: false,
+            ..enforced()
+        };
+        assert_eq!(auth.check(&HeaderMap::new(), Role::Editor), Ok(()));
+    }
+
+    #[test]
+    fn test_admin_role_always_checked() {
+        let auth = Auth {
+            enforce_roles: false,
+            ..enforced()
+        };
+        assert_eq!(
+            auth.check(&HeaderMap::new(), Role::Admin),
+            Err(StatusCode::UNAUTHORIZED)
+        );
+        assert_eq!(
+            auth.check(&headers("Bearer editor-key"), Role::Admin),
+            Err(StatusCode::FORBIDDEN)
+        );
+        assert_eq!(auth.check(&headers("Bearer elf"), Role::Admin), Ok(()));
+        // a deployment without credentials has no admin at all
+        assert_eq!(
+            auth(None, true).check(&HeaderMap::new(), Role::Admin),
+            Err(StatusCode::UNAUTHORIZED)
+        );
+        assert_eq!(
+            auth(None, false).check(&HeaderMap::new(), Role::Admin),
+            Ok(())
+        );
+    }
+}
@@ -0,0 +1,291 @@
+//! `GET /19/authors`, a read model of the published quotes per author kept up to date from the outbox
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, Mutex, RwLock},
+};
+
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    day_19::{Quote, QuoteRepository, QuoteStatus},
+    outbox::{self, EventSink, OutboxEvent},
+};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuthorCount {
+    pub author: String,
+    pub count: i64,
+}
+
+#[derive(Default)]
+struct Index {
+    /// Author of every published quote, so that updates and deletions know what to decrement
+    authors: HashMap<Uuid, String>,
+    counts: BTreeMap<String, i64>,
+}
+
+impl Index {
+    fn remove(&mut self, id: Uuid) {
+        let Some(author) = self.authors.remove(&id) else {
+            return;
+        };
+        if let Some(count) = self.counts.get_mut(&author) {
+            *count -= 1;
+            if *count == 0 {
+                self.counts.remove(&author);
+            }
+        }
+    }
+
+    /// Takes the quote as it is after a change, applying the same snapshot twice is harmless
+    fn apply(&mut self, quote: &Quote) {
+        self.remove(quote.id);
+        if quote.status == QuoteStatus::Published {
+            self.authors.insert(quote.id, quote.author.clone());
+            *self.counts.entry(quote.author.clone()).or_default() += 1;
+        }
+    }
+
+    fn apply_event(&mut self, event: &OutboxEvent) -> Result<(), String> {
+        match event.topic.as_str() {
+            outbox::QUOTES_RESET => *self = Index::default(),
+            outbox::QUOTE_DELETED => {
+                let quote: Quote =
+                    serde_json::from_value(event.payload.0.clone()).map_err(|e| e.to_string())?;
+                self.remove(quote.id);
+            }
+            topic if topic.starts_with("quote.") => {
+                let quote: Quote =
+                    serde_json::from_value(event.payload.0.clone()).map_err(|e| e.to_string())?;
+                self.apply(&quote);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+/// Quote counts per author, updated incrementally by the outbox events
+#[derive(Default)]
+pub struct AuthorIndex {
+    index: RwLock<Index>,
+    /// Events delivered while a rebuild reads the quotes, which its count may not include
+    missed: Mutex<Option<Vec<OutboxEvent>>>,
+    /// One rebuild at a time, each of them collects the events it misses
+    rebuilding: tokio::sync::Mutex<()>,
+}
+
+impl AuthorIndex {
+    pub fn authors(&self) -> Vec<AuthorCount> {
+        self.index
+            .read()
+            .unwrap()
+            .counts
+            .iter()
+            .map(|(author, count)| AuthorCount {
+                author: author.clone(),
+                count: *count,
+            })
+            .collect()
+    }
+
+    /// Replaces the index with a fresh count of the quotes table, for a cold start or a missed
+    /// event. The events delivered meanwhile are replayed over the count, in their order, so
+    /// that the outbox may keep delivering during a rebuild.
+    pub async fn rebuild(&self, repository: &dyn QuoteRepository) -> Result<usize, sqlx::Error> {
+        let _rebuilding = self.rebuilding.lock().await;
+        *self.missed.lock().unwrap() = Some(vec![]);
+        let quotes = match repository.all_quotes().await {
+            Ok(quotes) => quotes,
+            Err(e) => {
+                *self.missed.lock().unwrap() = None;
+                return Err(e);
+            }
+        };
+        let mut index = Index::default();
+        for quote in &quotes {
+            index.apply(quote);
+        }
+
+        // no event is delivered between the replay and the swap
+        let mut current = self.index.write().unwrap();
+        for event in self.missed.lock().unwrap().take().unwrap_or_default() {
+            // it was applied to the current index already, a failure was reported then
+            let _ = index.apply_event(&event);
+        }
+        let authors = index.counts.len();
+        *current = index;
+        Ok(authors)
+    }
+}
+
+#[async_trait::async_trait]
+impl EventSink for AuthorIndex {
+    async fn deliver(&self, event: &OutboxEvent) -> Result<(), String> {
+        let mut index = self.index.write().unwrap();
+        if let Some(missed) = self.missed.lock().unwrap().as_mut() {
+            missed.push(event.clone());
+        }
+        index.apply_event(event)
+    }
+}
+
+#[derive(Clone)]
+pub struct AuthorsState {
+    pub index: Arc<AuthorIndex>,
+    pub repository: Arc<dyn QuoteRepository>,
+}
+
+pub async fn authors(State(state): State<AuthorsState>) -> impl IntoResponse {
+    Json(state.index.authors())
+}
+
+pub async fn rebuild(State(state): State<AuthorsState>) -> impl IntoResponse {
+    match state.index.rebuild(state.repository.as_ref()).await {
+        Ok(_) => Ok(Json(state.index.authors())),
+        _ => Err((StatusCode::INTERNAL_SERVER_ERROR, "".to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use serde_json::json;
+    use sqlx::types::Json;
+
+    use super::*;
+    use crate::{day_19::MockQuoteRepository, test_support::box_future};
+
+    fn quote(id: Uuid, author: &str, status: QuoteStatus) -> Quote {
+        Quote {
+            id,
+            author: author.to_string(),
+            quote: "Ho ho ho".to_string(),
+            created_at: Utc::now(),
+            version: 1,
+            likes: 0,
+            publish_at: None,
+            status,
+        }
+    }
+
+    fn event(topic: &str, payload: impl Serialize) -> OutboxEvent {
+        OutboxEvent {
+            id: 1,
+            topic: topic.to_string(),
+            payload: Json(serde_json::to_value(payload).unwrap()),
+            attempts: 0,
+        }
+    }
+
+    fn count(author: &str, count: i64) -> AuthorCount {
+        AuthorCount {
+            author: author.to_string(),
+            count,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_incremental_updates() {
+        let index = AuthorIndex::default();
+        let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+
+        for e in [
+            event(
+                outbox::QUOTE_CREATED,
+                quote(a, "Santa", QuoteStatus::Published),
+            ),
+            // delivered at least once, a replay doesn't count twice
+            event(
+                outbox::QUOTE_CREATED,
+                quote(a, "Santa", QuoteStatus::Published),
+            ),
+            event(outbox::QUOTE_CREATED, quote(b, "Santa", QuoteStatus::Draft)),
+        ] {
+            index.deliver(&e).await.unwrap();
+        }
+        assert_eq!(index.authors(), [count("Santa", 1)]);
+
+        for e in [
+            event(
+                outbox::QUOTE_PUBLISHED,
+                quote(b, "Santa", QuoteStatus::Published),
+            ),
+            event(
+                outbox::QUOTE_UPDATED,
+                quote(a, "Rudolph", QuoteStatus::Published),
+            ),
+        ] {
+            index.deliver(&e).await.unwrap();
+        }
+        assert_eq!(index.authors(), [count("Rudolph", 1), count("Santa", 1)]);
+
+        index
+            .deliver(&event(
+                outbox::QUOTE_DELETED,
+                quote(a, "Rudolph", QuoteStatus::Published),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(index.authors(), [count("Santa", 1)]);
+
+        index
+            .deliver(&event(outbox::QUOTES_RESET, json!(null)))
+            .await
+            .unwrap();
+        assert!(index.authors().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_rebuild() {
+        let mut mock = MockQuoteRepository::new();
+        mock.expect_all_quotes().returning(|| {
+            box_future(Ok(vec![
+                quote(Uuid::new_v4(), "Santa", QuoteStatus::Published),
+                quote(Uuid::new_v4(), "Santa", QuoteStatus::Published),
+                quote(Uuid::new_v4(), "Grinch", QuoteStatus::PendingReview),
+            ]))
+        });
+
+        let index = AuthorIndex::default();
+        assert_eq!(index.rebuild(&mock).await.unwrap(), 1);
+        assert_eq!(index.authors(), [count("Santa", 2)]);
+    }
+
+    #[tokio::test]
+    async fn test_rebuild_replays_events_delivered_meanwhile() {
+        let index = Arc::new(AuthorIndex::default());
+        let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+        let mut mock = MockQuoteRepository::new();
+        let delivering = index.clone();
+        // the quotes are read before the dispatcher delivers the creation of b
+        mock.expect_all_quotes().returning(move || {
+            let index = delivering.clone();
+            Box::pin(async move {
+                let created = event(
+                    outbox::QUOTE_CREATED,
+                    quote(b, "Rudolph", QuoteStatus::Published),
+                );
+                index.deliver(&created).await.unwrap();
+                Ok(vec![quote(a, "Santa", QuoteStatus::Published)])
+            })
+        });
+
+        assert_eq!(index.rebuild(&mock).await.unwrap(), 2);
+        assert_eq!(index.authors(), [count("Rudolph", 1), count("Santa", 1)]);
+
+        // back to applying the events only
+        assert!(index.missed.lock().unwrap().is_none());
+        index
+            .deliver(&event(
+                outbox::QUOTE_DELETED,
+                quote(b, "Rudolph", QuoteStatus::Published),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(index.authors(), [count("Santa", 1)]);
+    }
+}
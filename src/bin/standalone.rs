@@ -0,0 +1,118 @@
+//! The service without the Shuttle runtime, e.g. for local and Docker runs:
+//! `APP_ENV=development DATABASE_URL=postgres://... cargo run --features standalone --bin standalone`
+//!
+//! The configuration is read from the environment only. `BIND_ADDR` defaults to `0.0.0.0:8000`.
+//! The production checks are on unless `APP_ENV=development`, so that a container started
+//! without credentials keeps its admin routes closed. With `--tls-cert cert.pem --tls-key
+//! key.pem`, HTTPS is served instead and the day 16 gift cookies are marked `Secure`.
+
+use std::{net::SocketAddr, path::PathBuf};
+
+use axum_server::tls_rustls::RustlsConfig;
+use sqlx::PgPool;
+use tokio::net::TcpListener;
+use tracing_subscriber::EnvFilter;
+
+use shuttlings_cch24::{app, config::Config};
+
+const DEFAULT_BIND_ADDR: &str = "0.0.0.0:8000";
+const USAGE: &str = "usage: standalone [--tls-cert <cert.pem> --tls-key <key.pem>]";
+
+/// PEM files of the certificate chain and of its private key
+#[derive(Debug, PartialEq)]
+struct Tls {
+    cert: PathBuf,
+    key: PathBuf,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Option<Tls>, String> {
+    let (mut cert, mut key) = (None, None);
+    while let Some(arg) = args.next() {
+        let target = match arg.as_str() {
+            "--tls-cert" => &mut cert,
+            "--tls-key" => &mut key,
+            _ => return Err(format!("unknown argument {}\n{}", arg, USAGE)),
+        };
+        let path = args
+            .next()
+            .ok_or_else(|| format!("{} needs a path\n{}", arg, USAGE))?;
+        *target = Some(PathBuf::from(path));
+    }
+    match (cert, key) {
+        (Some(cert), Some(key)) => Ok(Some(Tls { cert, key })),
+        (None, None) => Ok(None),
+        _ => Err(format!("--tls-cert and --tls-key go together\n{}", USAGE)),
+    }
+}
+
+/// Only an explicit development environment relaxes the production checks
+fn production(app_env: Option<&str>) -> bool {
+    app_env != Some("development")
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")),
+        )
+        .init();
+
+    let tls = parse_args(std::env::args().skip(1))?;
+    let database_url = std::env::var("DATABASE_URL").map_err(|_| "DATABASE_URL is not set")?;
+    let production = production(std::env::var("APP_ENV").ok().as_deref());
+    let mut config = Config::load(production, |k| std::env::var(k).ok());
+    // browsers only send `Secure` cookies back over HTTPS
+    config.secure_cookies |= tls.is_some();
+
+    let pool = PgPool::connect(&database_url).await?;
+    let service = app::build(pool, config).await?;
+
+    let addr = std::env::var("BIND_ADDR").unwrap_or_else(|_| DEFAULT_BIND_ADDR.to_string());
+    match tls {
+        Some(Tls { cert, key }) => {
+            let addr: SocketAddr = addr.parse()?;
+            let tls = RustlsConfig::from_pem_file(cert, key).await?;
+            tracing::info!("listening on https://{}", addr);
+            service.serve_tls(addr, tls).await?;
+        }
+        None => {
+            let listener = TcpListener::bind(&addr).await?;
+            tracing::info!("listening on http://{}", addr);
+            service.serve(listener).await?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Result<Option<Tls>, String> {
+        parse_args(args.iter().map(|a| a.to_string()))
+    }
+
+    #[test]
+    fn test_parse_args() {
+        assert_eq!(args(&[]), Ok(None));
+        assert_eq!(
+            args(&["--tls-key", "key.pem", "--tls-cert", "cert.pem"]),
+            Ok(Some(Tls {
+                cert: PathBuf::from("cert.pem"),
+                key: PathBuf::from("key.pem"),
+            }))
+        );
+        assert!(args(&["--tls-cert", "cert.pem"]).is_err());
+        assert!(args(&["--tls-cert"]).is_err());
+        assert!(args(&["--port", "80"]).is_err());
+    }
+
+    #[test]
+    fn test_production_by_default() {
+        assert!(production(None));
+        assert!(production(Some("production")));
+        assert!(production(Some("dev")));
+        assert!(!production(Some("development")));
+    }
+}
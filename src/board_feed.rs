@@ -0,0 +1,148 @@
+//! `GET /12/board/events`, the day 12 board as Server-Sent Events, along with who is watching
+
+use core::convert::Infallible;
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use axum::{
+    extract::State,
+    response::{
+        sse::{Event, KeepAlive},
+        Sse,
+    },
+};
+use serde_json::json;
+use tokio::sync::broadcast;
+use tokio_stream::{once, wrappers::BroadcastStream, Stream, StreamExt};
+
+use crate::{
+    day_12::{Board, BoardState},
+    links::Links,
+    shutdown::until_draining,
+};
+
+const FEED_CAPACITY: usize = 64;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FeedEvent {
+    /// JSON view of the board after a change
+    Board(String),
+    Spectators(usize),
+}
+
+impl FeedEvent {
+    fn into_sse(self) -> Event {
+        match self {
+            FeedEvent::Board(json) => Event::default().event("board").data(json),
+            FeedEvent::Spectators(n) => Event::default()
+                .event("spectators")
+                .data(json!({ "spectators": n }).to_string()),
+        }
+    }
+}
+
+/// Changes of the game board, fanned out to its spectators
+#[derive(Clone)]
+pub struct BoardFeed {
+    events: broadcast::Sender<FeedEvent>,
+    spectators: Arc<AtomicUsize>,
+}
+
+impl Default for BoardFeed {
+    fn default() -> Self {
+        Self {
+            events: broadcast::channel(FEED_CAPACITY).0,
+            spectators: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+}
+
+impl BoardFeed {
+    pub fn spectators(&self) -> usize {
+        self.spectators.load(Ordering::Relaxed)
+    }
+
+    pub fn publish(&self, board: &Board) {
+        // nobody watching is not an error
+        let _ = self.events.send(FeedEvent::Board(
+            board.to_json(Links::new(), self.spectators()),
+        ));
+    }
+
+    fn join(&self) -> Spectator {
+        let n = self.spectators.fetch_add(1, Ordering::Relaxed) + 1;
+        let _ = self.events.send(FeedEvent::Spectators(n));
+        Spectator { feed: self.clone() }
+    }
+}
+
+/// Presence of a connected spectator, which expires with its stream when the connection drops
+struct Spectator {
+    feed: BoardFeed,
+}
+
+impl Drop for Spectator {
+    fn drop(&mut self) {
+        let n = self.feed.spectators.fetch_sub(1, Ordering::Relaxed) - 1;
+        let _ = self.feed.events.send(FeedEvent::Spectators(n));
+    }
+}
+
+pub async fn board_events(
+    State(state): State<BoardState>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let updates = BroadcastStream::new(state.feed.events.subscribe());
+    let spectator = state.feed.join();
+    let current = {
+        let board = state.board.lock().await;
+        FeedEvent::Board(board.to_json(Links::new(), state.feed.spectators()))
+    };
+
+    // spectators that fall behind skip to the next change, every event carries the whole board
+    let stream = once(current)
+        .chain(updates.filter_map(Result::ok))
+        .map(move |event| {
+            let _present = &spectator;
+            Ok(event.into_sse())
+        });
+    Sse::new(until_draining(stream)).keep_alive(KeepAlive::default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_presence() {
+        let feed = BoardFeed::default();
+        let mut events = feed.events.subscribe();
+
+        let first = feed.join();
+        let second = feed.join();
+        assert_eq!(feed.spectators(), 2);
+        drop(first);
+        assert_eq!(feed.spectators(), 1);
+        drop(second);
+        assert_eq!(feed.spectators(), 0);
+
+        let counts: Vec<_> = std::iter::from_fn(|| events.try_recv().ok()).collect();
+        assert_eq!(counts, [1, 2, 1, 0].map(FeedEvent::Spectators).to_vec());
+    }
+
+    #[tokio::test]
+    async fn test_publish_includes_spectators() {
+        let feed = BoardFeed::default();
+        let mut events = feed.events.subscribe();
+        let _spectator = feed.join();
+        events.recv().await.unwrap();
+
+        feed.publish(&Board::new());
+        let FeedEvent::Board(json) = events.recv().await.unwrap() else {
+            panic!("expected a board");
+        };
+        let view: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(view["spectators"], 1);
+    }
+}
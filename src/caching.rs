@@ -0,0 +1,214 @@
+//! Validators and cache headers for pages and assets, so that polling clients mostly get `304`s
+
+use std::time::SystemTime;
+
+use axum::{
+    body::{to_bytes, Body, HttpBody},
+    extract::Request,
+    http::{header, HeaderMap, HeaderValue, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use sha2::{Digest, Sha256};
+
+/// Bodies above this size, or of a size not known upfront, are passed through without a validator
+const MAX_BUFFERED: usize = 8 * 1024 * 1024;
+/// Query parameter carrying the content hash of a fingerprinted asset
+const FINGERPRINT_PARAM: &str = "v";
+const IMMUTABLE: &str = "public, max-age=31536000, immutable";
+const REVALIDATE: &str = "no-cache";
+
+/// Content hash used both as entity tag and as asset fingerprint
+pub fn fingerprint(content: &[u8]) -> String {
+    Sha256::digest(content)
+        .iter()
+        .take(8)
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+fn fingerprint_of(request: &Request) -> Option<String> {
+    request.uri().query()?.split('&').find_map(|pair| {
+        pair.strip_prefix(FINGERPRINT_PARAM)?
+            .strip_prefix('=')
+            .map(str::to_string)
+    })
+}
+
+/// Whether the client already holds the representation, `If-None-Match` taking precedence
+fn is_fresh(headers: &HeaderMap, etag: &str, last_modified: Option<&HeaderValue>) -> bool {
+    if let Some(tags) = headers.get(header::IF_NONE_MATCH) {
+        return tags.to_str().is_ok_and(|tags| {
+            tags.split(',')
+                .map(|t| t.trim().trim_start_matches("W/"))
+                .any(|t| t == "*" || t == etag)
+        });
+    }
+
+    let parse = |v: &HeaderValue| -> Option<SystemTime> {
+        httpdate::parse_http_date(v.to_str().ok()?).ok()
+    };
+    match (
+        headers.get(header::IF_MODIFIED_SINCE).and_then(parse),
+        last_modified.and_then(parse),
+    ) {
+        (Some(since), Some(modified)) => modified <= since,
+        _ => false,
+    }
+}
+
+/// Adds an `ETag` and a `Cache-Control` to successful GET responses and answers `304` to conditional requests.
+/// Assets requested with `?v=<fingerprint>` matching their content are cached for good.
+pub async fn conditional(request: Request, next: Next) -> Response {
+    // HEAD responses have no body to hash
+    if request.method() != Method::GET {
+        return next.run(request).await;
+    }
+
+    let headers = request.headers().clone();
+    let fingerprint_requested = fingerprint_of(&request);
+    let response = next.run(request).await;
+
+    // a streamed body is sent as it comes rather than buffered to be hashed
+    let buffered = response
+        .body()
+        .size_hint()
+        .exact()
+        .is_some_and(|len| len <= MAX_BUFFERED as u64);
+    if response.status() != StatusCode::OK || !buffered {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let bytes = match to_bytes(body, MAX_BUFFERED).await {
+        Ok(b) => b,
+        _ => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    };
+
+    let hash = fingerprint(&bytes);
+    let etag = format!("\"{}\"", hash);
+    let cache_control = match fingerprint_requested {
+        Some(v) if v == hash => IMMUTABLE,
+        _ => REVALIDATE,
+    };
+
+    parts
+        .headers
+        .insert(header::ETAG, HeaderValue::from_str(&etag).unwrap());
+    parts
+        .headers
+        .entry(header::CACHE_CONTROL)
+        .or_insert(HeaderValue::from_static(cache_control));
+
+    if is_fresh(&headers, &etag, parts.headers.get(header::LAST_MODIFIED)) {
+        parts.status = StatusCode::NOT_MODIFIED;
+        parts.headers.remove(header::CONTENT_LENGTH);
+        parts.headers.remove(header::CONTENT_TYPE);
+        return Response::from_parts(parts, Body::empty());
+    }
+
+    Response::from_parts(parts, Body::from(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Bytes, middleware, routing::get, Router};
+    use http_body_util::BodyExt;
+    use tower::ServiceExt;
+
+    const CONTENT: &str = "<p>Milk available: 5 / 5</p>";
+
+    fn create_test_app() -> Router {
+        Router::new()
+            .route(
+                "/bucket",
+                get(|| async {
+                    (
+                        [(header::LAST_MODIFIED, "Wed, 25 Dec 2024 00:00:00 GMT")],
+                        CONTENT,
+                    )
+                }),
+            )
+            .route(
+                "/stream",
+                get(|| async {
+                    let chunks =
+                        [CONTENT, CONTENT].map(|c| Ok::<_, std::io::Error>(Bytes::from(c)));
+                    Body::from_stream(tokio_stream::iter(chunks))
+                }),
+            )
+            .route("/large", get(|| async { vec![b'a'; MAX_BUFFERED + 1] }))
+            .layer(middleware::from_fn(conditional))
+    }
+
+    async fn get_with(uri: &str, name: header::HeaderName, value: &str) -> Response {
+        create_test_app()
+            .oneshot(
+                Request::builder()
+                    .uri(uri)
+                    .header(name, value)
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_if_none_match() {
+        let etag = format!("\"{}\"", fingerprint(CONTENT.as_bytes()));
+
+        let response = get_with("/bucket", header::IF_NONE_MATCH, "\"other\"").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::ETAG], etag.as_str());
+        assert_eq!(response.headers()[header::CACHE_CONTROL], REVALIDATE);
+
+        let response = get_with("/bucket", header::IF_NONE_MATCH, &format!("W/{}", etag)).await;
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+    }
+
+    #[tokio::test]
+    async fn test_if_modified_since() {
+        let response = get_with(
+            "/bucket",
+            header::IF_MODIFIED_SINCE,
+            "Thu, 26 Dec 2024 00:00:00 GMT",
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+
+        let response = get_with(
+            "/bucket",
+            header::IF_MODIFIED_SINCE,
+            "Tue, 24 Dec 2024 00:00:00 GMT",
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_fingerprinted_asset() {
+        let uri = format!("/bucket?v={}", fingerprint(CONTENT.as_bytes()));
+        let response = get_with(&uri, header::ACCEPT, "*/*").await;
+        assert_eq!(response.headers()[header::CACHE_CONTROL], IMMUTABLE);
+
+        let response = get_with("/bucket?v=stale", header::ACCEPT, "*/*").await;
+        assert_eq!(response.headers()[header::CACHE_CONTROL], REVALIDATE);
+    }
+
+    #[tokio::test]
+    async fn test_unbuffered_bodies_pass_through() {
+        let response = get_with("/stream", header::ACCEPT, "*/*").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(!response.headers().contains_key(header::ETAG));
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, format!("{}{}", CONTENT, CONTENT));
+
+        let response = get_with("/large", header::ACCEPT, "*/*").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(!response.headers().contains_key(header::ETAG));
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body.len(), MAX_BUFFERED + 1);
+    }
+}
@@ -0,0 +1,153 @@
+//! Citation formats of `/19/cite/:id`, for bots and docs embedding quotes
+
+use serde::Deserialize;
+
+use crate::{day_19::Quote, day_23::escape_string};
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CitationFormat {
+    Text,
+    Markdown,
+    Bibtex,
+    Html,
+}
+
+impl CitationFormat {
+    pub fn mime(&self) -> &'static str {
+        match self {
+            CitationFormat::Text => "text/plain; charset=utf-8",
+            CitationFormat::Markdown => "text/markdown; charset=utf-8",
+            CitationFormat::Bibtex => "application/x-bibtex; charset=utf-8",
+            CitationFormat::Html => "text/html; charset=utf-8",
+        }
+    }
+
+    pub fn render(&self, quote: &Quote) -> String {
+        match self {
+            CitationFormat::Text => text(quote),
+            CitationFormat::Markdown => markdown(quote),
+            CitationFormat::Bibtex => bibtex(quote),
+            CitationFormat::Html => html(quote),
+        }
+    }
+}
+
+fn text(quote: &Quote) -> String {
+    format!(
+        "\"{}\" - {}, {}",
+        quote.quote,
+        quote.author,
+        quote.created_at.format("%Y")
+    )
+}
+
+fn markdown(quote: &Quote) -> String {
+    // every line stays inside the block quote
+    let body = escape_markdown(&quote.quote)
+        .lines()
+        .map(|line| format!("> {}", line))
+        .collect::<Vec<_>>()
+        .join("\n");
+    format!("{}\n>\n> — {}\n", body, escape_markdown(&quote.author))
+}
+
+fn escape_markdown(s: &str) -> String {
+    s.chars().fold(String::new(), |mut escaped, c| {
+        if "\\`*_{}[]<>()#+-.!|~".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+        escaped
+    })
+}
+
+fn bibtex(quote: &Quote) -> String {
+    format!(
+        "@misc{{quote-{},\n  author = {{{}}},\n  title = {{{}}},\n  year = {{{}}},\n  howpublished = {{\\url{{/19/cite/{}}}}}\n}}\n",
+        quote.id.simple(),
+        escape_bibtex(&quote.author),
+        escape_bibtex(&quote.quote),
+        quote.created_at.format("%Y"),
+        quote.id
+    )
+}
+
+fn escape_bibtex(s: &str) -> String {
+    s.chars()
+        .map(|c| match c {
+            '\\' => "\\textbackslash{}".to_string(),
+            '~' => "\\textasciitilde{}".to_string(),
+            '^' => "\\textasciicircum{}".to_string(),
+            '{' | '}' | '&' | '%' | '$' | '#' | '_' => format!("\\{}", c),
+            c => c.to_string(),
+        })
+        .collect()
+}
+
+fn html(quote: &Quote) -> String {
+    format!(
+        "<blockquote class=\"quote\"><p>{}</p><footer>— <cite>{}</cite></footer></blockquote>",
+        escape_string(&quote.quote),
+        escape_string(&quote.author)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::day_19::QuoteStatus;
+
+    fn quote(author: &str, text: &str) -> Quote {
+        Quote {
+            id: Uuid::nil(),
+            author: author.to_string(),
+            quote: text.to_string(),
+            created_at: Utc.with_ymd_and_hms(2024, 12, 19, 0, 0, 0).unwrap(),
+            version: 1,
+            likes: 0,
+            publish_at: None,
+            status: QuoteStatus::Published,
+        }
+    }
+
+    #[test]
+    fn test_text() {
+        assert_eq!(
+            CitationFormat::Text.render(&quote("Santa", "Ho ho ho")),
+            "\"Ho ho ho\" - Santa, 2024"
+        );
+    }
+
+    #[test]
+    fn test_markdown() {
+        assert_eq!(
+            CitationFormat::Markdown.render(&quote("*Santa*", "# Ho\nho [ho](x)")),
+            "> \\# Ho\n> ho \\[ho\\]\\(x\\)\n>\n> — \\*Santa\\*\n"
+        );
+    }
+
+    #[test]
+    fn test_bibtex() {
+        assert_eq!(
+            CitationFormat::Bibtex.render(&quote("Santa & co", "100% {jolly} \\o/")),
+            "@misc{quote-00000000000000000000000000000000,\n  \
+             author = {Santa \\& co},\n  \
+             title = {100\\% \\{jolly\\} \\textbackslash{}o/},\n  \
+             year = {2024},\n  \
+             howpublished = {\\url{/19/cite/00000000-0000-0000-0000-000000000000}}\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_html() {
+        assert_eq!(
+            CitationFormat::Html.render(&quote("Santa", "<script>alert('ho')</script>")),
+            "<blockquote class=\"quote\"><p>&lt;script&gt;alert(&#x27;ho&#x27;)&lt;&#x2F;script&gt;</p>\
+             <footer>— <cite>Santa</cite></footer></blockquote>"
+        );
+    }
+}
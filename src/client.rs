@@ -0,0 +1,527 @@
+//! Typed async client for the HTTP API, sharing its DTOs with the server
+
+use core::fmt;
+
+use reqwest::{header, Client, RequestBuilder, Response, StatusCode};
+use serde::de::DeserializeOwned;
+use uuid::Uuid;
+
+use crate::{
+    day_12::{BoardView, Team},
+    day_19::{NewQuote, Quote, Quotes},
+    day_9::{Bucket, Milk},
+    quota::API_KEY_HEADER,
+};
+
+#[derive(Debug)]
+pub enum ClientError {
+    /// The request could not be sent or the response could not be decoded
+    Http(reqwest::Error),
+    /// The server answered with an unexpected status code
+    Status(StatusCode),
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::Http(e) => write!(f, "request failed: {}", e),
+            ClientError::Status(s) => write!(f, "unexpected status: {}", s),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {}
+
+impl From<reqwest::Error> for ClientError {
+    fn from(value: reqwest::Error) -> Self {
+        Self::Http(value)
+    }
+}
+
+/// Connection details shared by every client
+#[derive(Clone)]
+struct Base {
+    http: Client,
+    url: String,
+    /// Sent as bearer credentials, e.g. the admin token or a JWT
+    token: Option<String>,
+    api_key: Option<String>,
+}
+
+impl Base {
+    fn new(url: impl Into<String>) -> Self {
+        Self {
+            http: Client::new(),
+            url: url.into().trim_end_matches('/').to_string(),
+            token: None,
+            api_key: None,
+        }
+    }
+
+    fn with_token(self, token: String) -> Self {
+        Self {
+            token: Some(token),
+            ..self
+        }
+    }
+
+    fn with_api_key(self, key: String) -> Self {
+        Self {
+            api_key: Some(key),
+            ..self
+        }
+    }
+
+    fn request(&self, method: reqwest::Method, path: &str) -> RequestBuilder {
+        let mut req = self.http.request(method, format!("{}{}", self.url, path));
+        if let Some(token) = &self.token {
+            req = req.bearer_auth(token);
+        }
+        if let Some(key) = &self.api_key {
+            req = req.header(API_KEY_HEADER, key);
+        }
+        req
+    }
+}
+
+fn expect(response: Response, status: StatusCode) -> Result<Response, ClientError> {
+    match response.status() {
+        s if s == status => Ok(response),
+        s => Err(ClientError::Status(s)),
+    }
+}
+
+async fn json<T: DeserializeOwned>(response: Response) -> Result<T, ClientError> {
+    Ok(response.json::<T>().await?)
+}
+
+/// Client for the day 19 quote endpoints
+#[derive(Clone)]
+pub struct QuotesClient {
+    base: Base,
+}
+
+impl QuotesClient {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            base: Base::new(url),
+        }
+    }
+
+    /// Sends `token` as bearer credentials, the routes gated by a role answer 401 without any
+    pub fn with_token(self, token: impl Into<String>) -> Self {
+        Self {
+            base: self.base.with_token(token.into()),
+        }
+    }
+
+    /// Sends `key` as `x-api-key`
+    pub fn with_api_key(self, key: impl Into<String>) -> Self {
+        Self {
+            base: self.base.with_api_key(key.into()),
+        }
+    }
+
+    pub async fn cite(&self, id: Uuid) -> Result<Quote, ClientError> {
+        let res = self
+            .base
+            .request(reqwest::Method::GET, &format!("/19/cite/{}", id))
+            .send()
+            .await?;
+        json(expect(res, StatusCode::OK)?).await
+    }
+
+    pub async fn draft(&self, new_quote: &NewQuote) -> Result<Quote, ClientError> {
+        let res = self
+            .base
+            .request(reqwest::Method::POST, "/19/draft")
+            .json(new_quote)
+            .send()
+            .await?;
+        json(expect(res, StatusCode::CREATED)?).await
+    }
+
+    pub async fn remove(&self, id: Uuid) -> Result<Quote, ClientError> {
+        let res = self
+            .base
+            .request(reqwest::Method::DELETE, &format!("/19/remove/{}", id))
+            .send()
+            .await?;
+        json(expect(res, StatusCode::OK)?).await
+    }
+
+    pub async fn undo(&self, id: Uuid, new_quote: &NewQuote) -> Result<Quote, ClientError> {
+        let res = self
+            .base
+            .request(reqwest::Method::PUT, &format!("/19/undo/{}", id))
+            .json(new_quote)
+            .send()
+            .await?;
+        json(expect(res, StatusCode::OK)?).await
+    }
+
+    /// Fetches the first page, or the one pointed by a previously returned token
+    pub async fn list(&self, token: Option<&str>) -> Result<Quotes, ClientError> {
+        let mut req = self.base.request(reqwest::Method::GET, "/19/list");
+        if let Some(t) = token {
+            req = req.query(&[("token", t)]);
+        }
+        let res = req
+            .header(header::ACCEPT, "application/json")
+            .send()
+            .await?;
+        json(expect(res, StatusCode::OK)?).await
+    }
+
+    pub async fn reset(&self) -> Result<(), ClientError> {
+        let res = self
+            .base
+            .request(reqwest::Method::POST, "/19/reset")
+            .send()
+            .await?;
+        expect(res, StatusCode::OK).map(|_| ())
+    }
+}
+
+/// Result of a move on the day 12 board
+#[derive(Debug)]
+pub enum Placement {
+    Placed(BoardView),
+    /// The game is over or the column is full, the board is left as is
+    Rejected(BoardView),
+}
+
+/// Client for the day 12 board endpoints
+#[derive(Clone)]
+pub struct BoardClient {
+    base: Base,
+}
+
+impl BoardClient {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            base: Base::new(url),
+        }
+    }
+
+    /// Sends `token` as bearer credentials, the routes gated by a role answer 401 without any
+    pub fn with_token(self, token: impl Into<String>) -> Self {
+        Self {
+            base: self.base.with_token(token.into()),
+        }
+    }
+
+    /// Sends `key` as `x-api-key`
+    pub fn with_api_key(self, key: impl Into<String>) -> Self {
+        Self {
+            base: self.base.with_api_key(key.into()),
+        }
+    }
+
+    async fn board_request(
+        &self,
+        method: reqwest::Method,
+        path: &str,
+    ) -> Result<(StatusCode, BoardView), ClientError> {
+        let res = self
+            .base
+            .request(method, path)
+            .header(header::ACCEPT, "application/json")
+            .send()
+            .await?;
+        match res.status() {
+            s @ (StatusCode::OK | StatusCode::SERVICE_UNAVAILABLE) => Ok((s, json(res).await?)),
+            s => Err(ClientError::Status(s)),
+        }
+    }
+
+    pub async fn board(&self) -> Result<BoardView, ClientError> {
+        self.board_request(reqwest::Method::GET, "/12/board")
+            .await
+            .map(|(_, b)| b)
+    }
+
+    pub async fn random_board(&self) -> Result<BoardView, ClientError> {
+        self.board_request(reqwest::Method::GET, "/12/random-board")
+            .await
+            .map(|(_, b)| b)
+    }
+
+    pub async fn reset(&self) -> Result<BoardView, ClientError> {
+        self.board_request(reqwest::Method::POST, "/12/reset")
+            .await
+            .map(|(_, b)| b)
+    }
+
+    pub async fn place(&self, team: Team, column: usize) -> Result<Placement, ClientError> {
+        let team = match team {
+            Team::Cookie => "cookie",
+            Team::Milk => "milk",
+        };
+        let path = format!("/12/place/{}/{}", team, column);
+        match self.board_request(reqwest::Method::POST, &path).await? {
+            (StatusCode::OK, b) => Ok(Placement::Placed(b)),
+            (_, b) => Ok(Placement::Rejected(b)),
+        }
+    }
+}
+
+/// Client for the day 9 milk endpoints
+#[derive(Clone)]
+pub struct MilkClient {
+    base: Base,
+}
+
+impl MilkClient {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            base: Base::new(url),
+        }
+    }
+
+    /// Sends `token` as bearer credentials, the routes gated by a role answer 401 without any
+    pub fn with_token(self, token: impl Into<String>) -> Self {
+        Self {
+            base: self.base.with_token(token.into()),
+        }
+    }
+
+    /// Sends `key` as `x-api-key`
+    pub fn with_api_key(self, key: impl Into<String>) -> Self {
+        Self {
+            base: self.base.with_api_key(key.into()),
+        }
+    }
+
+    /// Withdraws milk without any conversion, fails with 429 once the bucket is empty
+    pub async fn withdraw(&self) -> Result<(), ClientError> {
+        let res = self
+            .base
+            .request(reqwest::Method::POST, "/9/milk")
+            .send()
+            .await?;
+        expect(res, StatusCode::OK).map(|_| ())
+    }
+
+    pub async fn convert(&self, milk: &Milk) -> Result<Milk, ClientError> {
+        let res = self
+            .base
+            .request(reqwest::Method::POST, "/9/milk")
+            .json(milk)
+            .send()
+            .await?;
+        json(expect(res, StatusCode::OK)?).await
+    }
+
+    pub async fn refill(&self) -> Result<Bucket, ClientError> {
+        let res = self
+            .base
+            .request(reqwest::Method::POST, "/9/refill")
+            .send()
+            .await?;
+        json(expect(res, StatusCode::OK)?).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::{
+        auth::{require_role, Auth, Role},
+        board_feed::BoardFeed,
+        config::Config,
+        day_12::{
+            arc_board, arc_random_board, board, place, reset, BoardState, MockGameResultRepository,
+        },
+        day_19::{cite, draft, remove, state_tokens, DbState, MockQuoteRepository, QuoteStatus},
+        day_9::{milk, refill, RateLimiterState},
+        moderation::WordListModerator,
+        players::MockPlayerRepository,
+        test_support::box_future,
+    };
+    use axum::{
+        middleware,
+        routing::{delete, get, post},
+        Router,
+    };
+    use chrono::Utc;
+
+    async fn serve() -> String {
+        let mut results = MockGameResultRepository::new();
+        results.expect_archive().returning(|_| box_future(Ok(())));
+
+        let router = Router::new()
+            .route("/9/milk", post(milk))
+            .route("/9/refill", post(refill))
+            .with_state(RateLimiterState::default())
+            .route("/12/board", get(board))
+            .route("/12/reset", post(reset))
+            .route("/12/place/:team/:column", post(place))
+            .with_state(BoardState {
+                board: arc_board(),
+                random_board: arc_random_board(),
+                results: Arc::new(results),
+                feed: BoardFeed::default(),
+                players: Arc::new(MockPlayerRepository::new()),
+            });
+
+        listen(router).await
+    }
+
+    async fn listen(router: Router) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+        format!("http://{}", addr)
+    }
+
+    fn quote(id: Uuid, new_quote: NewQuote) -> Quote {
+        Quote {
+            id,
+            author: new_quote.author,
+            quote: new_quote.quote,
+            created_at: Utc::now(),
+            version: 1,
+            likes: 0,
+            publish_at: None,
+            status: QuoteStatus::Published,
+        }
+    }
+
+    /// Day 19 routes over a mocked repository, drafting needs an editor and removing an admin
+    async fn serve_quotes(repository: MockQuoteRepository) -> String {
+        let auth = Auth::new(&Config {
+            admin_token: Some("elf".to_string()),
+            api_keys: HashMap::from([("santa".to_string(), Role::Editor)]),
+            enforce_roles: true,
+            ..Config::default()
+        });
+        let editor = middleware::from_fn_with_state(auth.require(Role::Editor), require_role);
+        let admin = middleware::from_fn_with_state(auth.require(Role::Admin), require_role);
+
+        let router = Router::new()
+            .route("/19/cite/:id", get(cite))
+            .route("/19/draft", post(draft).route_layer(editor))
+            .route("/19/remove/:id", delete(remove).route_layer(admin))
+            .with_state(DbState {
+                repository: Arc::new(repository),
+                tokens: state_tokens(),
+                moderator: Arc::new(WordListModerator::default()),
+                page_size: 3,
+                players: Arc::new(MockPlayerRepository::new()),
+            });
+        listen(router).await
+    }
+
+    #[tokio::test]
+    async fn test_board_client() {
+        let client = BoardClient::new(serve().await);
+
+        let board = client.reset().await.unwrap();
+        assert_eq!(board.winner, None);
+
+        match client.place(Team::Cookie, 1).await.unwrap() {
+            Placement::Placed(b) => assert_eq!(b.tiles[3][1], "cookie"),
+            p => panic!("unexpected placement {:?}", p),
+        }
+
+        assert_eq!(client.board().await.unwrap().tiles[3][1], "cookie");
+    }
+
+    #[tokio::test]
+    async fn test_board_client_invalid_column() {
+        let client = BoardClient::new(serve().await);
+
+        match client.place(Team::Milk, 9).await {
+            Err(ClientError::Status(s)) => assert_eq!(s, StatusCode::BAD_REQUEST),
+            r => panic!("unexpected result {:?}", r),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_milk_client() {
+        let client = MilkClient::new(serve().await);
+
+        match client.convert(&Milk::Gallons(1.0)).await.unwrap() {
+            Milk::Liters(l) => assert!((l - 3.78541).abs() < 1e-4),
+            m => panic!("unexpected conversion {:?}", m),
+        }
+
+        // the bucket starts with 5 tokens, one was used above
+        for _ in 0..4 {
+            client.withdraw().await.unwrap();
+        }
+        match client.withdraw().await {
+            Err(ClientError::Status(s)) => assert_eq!(s, StatusCode::TOO_MANY_REQUESTS),
+            r => panic!("unexpected result {:?}", r),
+        }
+
+        assert_eq!(client.refill().await.unwrap().level, 5);
+        client.withdraw().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_quotes_client() {
+        let id = Uuid::new_v4();
+        let santa = NewQuote {
+            author: "Santa".to_string(),
+            quote: "Ho ho ho".to_string(),
+            publish_at: None,
+        };
+        let mut mock = MockQuoteRepository::new();
+        mock.expect_create()
+            .returning(move |q| box_future(Ok(quote(id, q))));
+        let cited = santa.clone();
+        mock.expect_get()
+            .returning(move |id| box_future(Ok(quote(id, cited.clone()))));
+        let url = serve_quotes(mock).await;
+
+        let client = QuotesClient::new(url).with_api_key("santa");
+        let drafted = client.draft(&santa).await.unwrap();
+        assert_eq!(drafted.id, id);
+        assert_eq!(drafted.author, "Santa");
+        assert_eq!(client.cite(id).await.unwrap().quote, "Ho ho ho");
+    }
+
+    #[tokio::test]
+    async fn test_quotes_client_credentials() {
+        let id = Uuid::new_v4();
+        let mut mock = MockQuoteRepository::new();
+        mock.expect_delete().times(1).returning(|id, _| {
+            box_future(Ok(quote(
+                id,
+                NewQuote {
+                    author: "Grinch".to_string(),
+                    quote: "Bah".to_string(),
+                    publish_at: None,
+                },
+            )))
+        });
+        let url = serve_quotes(mock).await;
+
+        match QuotesClient::new(url.clone()).remove(id).await {
+            Err(ClientError::Status(s)) => assert_eq!(s, StatusCode::UNAUTHORIZED),
+            r => panic!("unexpected result {:?}", r),
+        }
+        // an editor key isn't enough to remove quotes
+        match QuotesClient::new(url.clone())
+            .with_api_key("santa")
+            .remove(id)
+            .await
+        {
+            Err(ClientError::Status(s)) => assert_eq!(s, StatusCode::FORBIDDEN),
+            r => panic!("unexpected result {:?}", r),
+        }
+
+        let removed = QuotesClient::new(url)
+            .with_token("elf")
+            .remove(id)
+            .await
+            .unwrap();
+        assert_eq!(removed.id, id);
+    }
+}
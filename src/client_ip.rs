@@ -0,0 +1,257 @@
+//! Address of the client, looked up past the proxies trusted to report it
+
+use core::{
+    net::{IpAddr, SocketAddr},
+    str::FromStr,
+};
+use std::sync::Arc;
+
+use axum::{
+    async_trait,
+    extract::{ConnectInfo, FromRequestParts},
+    http::{request::Parts, HeaderMap, StatusCode},
+};
+
+/// An address or a CIDR block, e.g. `10.0.0.0/8`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IpRange {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl FromStr for IpRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, prefix) = match s.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (s, None),
+        };
+        let addr = addr
+            .parse::<IpAddr>()
+            .map_err(|_| format!("invalid address {}", s))?;
+        let max = match addr {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+        let prefix = match prefix {
+            Some(p) => p
+                .parse::<u8>()
+                .ok()
+                .filter(|p| *p <= max)
+                .ok_or_else(|| format!("invalid prefix {}", s))?,
+            None => max,
+        };
+        Ok(Self { addr, prefix })
+    }
+}
+
+impl IpRange {
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        // IPv4 clients of a dual stack listener show up as mapped IPv6 addresses
+        let ip = match ip {
+            IpAddr::V6(v6) => v6.to_canonical(),
+            v4 => v4,
+        };
+        match (self.addr, ip) {
+            (IpAddr::V4(range), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(range) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(range), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(range) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Proxies whose forwarding headers are believed, added to the requests as an extension
+#[derive(Debug, Clone, Default)]
+pub struct TrustedProxies(Arc<Vec<IpRange>>);
+
+impl TrustedProxies {
+    pub fn new(ranges: Vec<IpRange>) -> Self {
+        Self(Arc::new(ranges))
+    }
+
+    fn trusts(&self, ip: IpAddr) -> bool {
+        self.0.iter().any(|r| r.contains(ip))
+    }
+}
+
+/// `for=` parameters of the `Forwarded` headers, nearest proxy last
+fn forwarded(headers: &HeaderMap) -> Option<Vec<IpAddr>> {
+    let values = headers.get_all("forwarded").iter().collect::<Vec<_>>();
+    if values.is_empty() {
+        return None;
+    }
+
+    let hops = values
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .filter_map(|hop| {
+            hop.split(';')
+                .filter_map(|pair| pair.trim().split_once('='))
+                .find(|(name, _)| name.eq_ignore_ascii_case("for"))
+                .and_then(|(_, node)| node_ip(node))
+        })
+        .collect();
+    Some(hops)
+}
+
+/// Address of a `Forwarded` node, e.g. `192.0.2.1`, `"192.0.2.1:80"` or `"[2001:db8::1]:80"`
+fn node_ip(node: &str) -> Option<IpAddr> {
+    let node = node.trim().trim_matches('"');
+    if let Some(v6) = node.strip_prefix('[') {
+        return v6.split(']').next()?.parse().ok();
+    }
+    node.parse()
+        .ok()
+        .or_else(|| node.parse::<SocketAddr>().ok().map(|a| a.ip()))
+}
+
+/// Addresses of `X-Forwarded-For`, nearest proxy last
+fn x_forwarded_for(headers: &HeaderMap) -> Vec<IpAddr> {
+    headers
+        .get_all("x-forwarded-for")
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .filter_map(|ip| ip.trim().parse().ok())
+        .collect()
+}
+
+/// Walks the chain back from the peer, the client being the first address not trusted.
+/// `Forwarded` is preferred, `X-Forwarded-For` is only read without it.
+pub fn resolve(peer: IpAddr, headers: &HeaderMap, trusted: &TrustedProxies) -> IpAddr {
+    if !trusted.trusts(peer) {
+        return peer;
+    }
+
+    let hops = forwarded(headers).unwrap_or_else(|| x_forwarded_for(headers));
+    let mut client = peer;
+    for hop in hops.into_iter().rev() {
+        client = hop;
+        if !trusted.trusts(hop) {
+            break;
+        }
+    }
+    client
+}
+
+/// Address of the client, for limits and records kept per client
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ClientIp(pub IpAddr);
+
+/// Fails when the service is served without connection info
+#[async_trait]
+impl<S> FromRequestParts<S> for ClientIp
+where
+    S: Send + Sync,
+{
+    type Rejection = (StatusCode, String);
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let Some(ConnectInfo(peer)) = parts.extensions.get::<ConnectInfo<SocketAddr>>() else {
+            tracing::warn!("no connection info, the client address is unknown");
+            return Err((StatusCode::INTERNAL_SERVER_ERROR, "".to_string()));
+        };
+        let trusted = parts
+            .extensions
+            .get::<TrustedProxies>()
+            .cloned()
+            .unwrap_or_default();
+        Ok(Self(resolve(peer.ip(), &parts.headers, &trusted)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.append(*name, HeaderValue::from_static(value));
+        }
+        headers
+    }
+
+    #[test]
+    fn test_ranges() {
+        let private = "10.0.0.0/8".parse::<IpRange>().unwrap();
+        assert!(private.contains(ip("10.1.2.3")));
+        assert!(private.contains(ip("::ffff:10.1.2.3")));
+        assert!(!private.contains(ip("11.0.0.1")));
+        assert!("::1".parse::<IpRange>().unwrap().contains(ip("::1")));
+        assert!("0.0.0.0/0"
+            .parse::<IpRange>()
+            .unwrap()
+            .contains(ip("8.8.8.8")));
+        assert!("10.0.0.0/33".parse::<IpRange>().is_err());
+        assert!("proxy".parse::<IpRange>().is_err());
+    }
+
+    #[test]
+    fn test_resolve() {
+        let trusted = TrustedProxies::new(vec!["10.0.0.0/8".parse().unwrap()]);
+        let xff = headers(&[("x-forwarded-for", "203.0.113.9, 198.51.100.7, 10.0.0.2")]);
+
+        // headers from an untrusted peer are ignored
+        assert_eq!(
+            resolve(ip("198.51.100.1"), &xff, &trusted),
+            ip("198.51.100.1")
+        );
+        // the address the last trusted proxy saw, not what the client claims
+        assert_eq!(resolve(ip("10.0.0.1"), &xff, &trusted), ip("198.51.100.7"));
+        assert_eq!(
+            resolve(ip("10.0.0.1"), &HeaderMap::new(), &trusted),
+            ip("10.0.0.1")
+        );
+
+        let forwarded = headers(&[
+            ("forwarded", "for=\"[2001:db8::1]:4711\";proto=https"),
+            ("forwarded", "For=10.0.0.3"),
+            ("x-forwarded-for", "192.0.2.60"),
+        ]);
+        assert_eq!(
+            resolve(ip("10.0.0.1"), &forwarded, &trusted),
+            ip("2001:db8::1")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_extractor() {
+        let mut request = axum::http::Request::builder()
+            .header("x-forwarded-for", "203.0.113.9")
+            .body(())
+            .unwrap();
+        request
+            .extensions_mut()
+            .insert(ConnectInfo(SocketAddr::from(([10, 0, 0, 1], 443))));
+        let (mut parts, _) = request.into_parts();
+        assert_eq!(
+            ClientIp::from_request_parts(&mut parts, &()).await,
+            Ok(ClientIp(ip("10.0.0.1")))
+        );
+
+        parts
+            .extensions
+            .insert(TrustedProxies::new(vec!["10.0.0.1".parse().unwrap()]));
+        assert_eq!(
+            ClientIp::from_request_parts(&mut parts, &()).await,
+            Ok(ClientIp(ip("203.0.113.9")))
+        );
+
+        parts.extensions.clear();
+        assert!(ClientIp::from_request_parts(&mut parts, &()).await.is_err());
+    }
+}
@@ -0,0 +1,238 @@
+//! Single flight for expensive reads: identical requests arriving while one is being answered
+//! wait for its response instead of running the handler again
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{LazyLock, Mutex},
+};
+
+use axum::{
+    body::{to_bytes, Body, Bytes},
+    extract::{MatchedPath, OriginalUri, Request},
+    http::{header, HeaderMap, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+
+use crate::versioning::unversioned;
+
+/// Responses larger than this aren't worth sharing, they are answered as a 500 instead
+const MAX_BODY_SIZE: usize = 4 * 1024 * 1024;
+
+/// Requests in flight and the counters of every coalesced route
+pub static FLIGHTS: LazyLock<Flights> = LazyLock::new(Flights::default);
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FlightCount {
+    /// Requests that ran the handler
+    pub executed: u64,
+    /// Requests answered with the response of another one
+    pub coalesced: u64,
+}
+
+/// Response kept whole so that every waiting request gets a copy
+#[derive(Clone)]
+struct Shared {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes,
+}
+
+type Flight = watch::Receiver<Option<Shared>>;
+
+#[derive(Default)]
+pub struct Flights {
+    in_flight: Mutex<HashMap<String, Flight>>,
+    counts: Mutex<BTreeMap<String, FlightCount>>,
+}
+
+/// Forgets the flight once its leader is done, even when the leader is cancelled
+struct Landing<'a> {
+    flights: &'a Flights,
+    key: String,
+}
+
+impl Drop for Landing<'_> {
+    fn drop(&mut self) {
+        self.flights.in_flight.lock().unwrap().remove(&self.key);
+    }
+}
+
+impl From<Shared> for Response {
+    fn from(shared: Shared) -> Self {
+        let mut response = Response::new(Body::from(shared.body));
+        *response.status_mut() = shared.status;
+        *response.headers_mut() = shared.headers;
+        response
+    }
+}
+
+impl Flights {
+    fn count(&self, route: &str, coalesced: bool) {
+        let mut counts = self.counts.lock().unwrap();
+        let count = counts.entry(route.to_string()).or_default();
+        match coalesced {
+            true => count.coalesced += 1,
+            false => count.executed += 1,
+        }
+    }
+
+    pub fn snapshot(&self) -> BTreeMap<String, FlightCount> {
+        self.counts.lock().unwrap().clone()
+    }
+
+    async fn run(&self, route: &str, key: String, request: Request, next: Next) -> Response {
+        let joined = self.in_flight.lock().unwrap().get(&key).cloned();
+        if let Some(mut flight) = joined {
+            // a leader that went away leaves nothing to wait for, the request then runs on its own
+            if let Ok(shared) = flight.wait_for(Option::is_some).await {
+                let shared = shared.clone().unwrap();
+                self.count(route, true);
+                return shared.into();
+            }
+            self.count(route, false);
+            return next.run(request).await;
+        }
+
+        let (sender, flight) = watch::channel(None);
+        self.in_flight.lock().unwrap().insert(key.clone(), flight);
+        let _landing = Landing { flights: self, key };
+        self.count(route, false);
+
+        let (parts, body) = next.run(request).await.into_parts();
+        let Ok(body) = to_bytes(body, MAX_BODY_SIZE).await else {
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        };
+        let shared = Shared {
+            status: parts.status,
+            headers: parts.headers,
+            body,
+        };
+        sender.send_replace(Some(shared.clone()));
+        shared.into()
+    }
+}
+
+/// What makes two requests identical: the full URI and the headers the handlers negotiate on
+fn flight_key(request: &Request) -> String {
+    let uri = request
+        .extensions()
+        .get::<OriginalUri>()
+        .map(|original| &original.0)
+        .unwrap_or(request.uri());
+    let header = |name| {
+        request
+            .headers()
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default()
+    };
+    format!(
+        "{} {}\n{}\n{}",
+        request.method(),
+        uri,
+        header(header::ACCEPT),
+        header(header::ACCEPT_LANGUAGE)
+    )
+}
+
+/// Middleware of the routes whose responses are the same for every client
+pub async fn single_flight(request: Request, next: Next) -> Response {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|p| unversioned(p.as_str()).to_string())
+        .unwrap_or_else(|| request.uri().path().to_string());
+    let key = flight_key(&request);
+    FLIGHTS.run(&route, key, request, next).await
+}
+
+pub async fn counts() -> impl IntoResponse {
+    Json(FLIGHTS.snapshot())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use super::*;
+    use axum::{extract::State, middleware, routing::get, Router};
+    use http_body_util::BodyExt;
+    use tower::ServiceExt;
+
+    async fn slow_count(State(calls): State<Arc<AtomicUsize>>) -> String {
+        let call = calls.fetch_add(1, Ordering::SeqCst) + 1;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        call.to_string()
+    }
+
+    fn create_test_app(calls: Arc<AtomicUsize>) -> Router {
+        Router::new()
+            .route(
+                "/coalesce/slow",
+                get(slow_count).route_layer(middleware::from_fn(single_flight)),
+            )
+            .with_state(calls)
+    }
+
+    async fn get_body(app: Router, accept: &str) -> String {
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/coalesce/slow")
+                    .header(header::ACCEPT, accept)
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        String::from_utf8(bytes.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_requests_share_a_response() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let app = create_test_app(calls.clone());
+
+        let bodies = get_concurrently(&app, ["*/*", "*/*", "*/*"]).await;
+        assert_eq!(bodies, ["1", "1", "1"]);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert_eq!(
+            FLIGHTS.snapshot()["/coalesce/slow"],
+            FlightCount {
+                executed: 1,
+                coalesced: 2
+            }
+        );
+
+        // once answered, the next request runs the handler again
+        assert_eq!(get_body(app.clone(), "*/*").await, "2");
+        // and a different representation is never shared
+        let bodies = get_concurrently(&app, ["text/plain", "application/json", "text/plain"]).await;
+        assert_eq!(calls.load(Ordering::SeqCst), 4);
+        assert_eq!(bodies[0], bodies[2]);
+        assert_ne!(bodies[0], bodies[1]);
+    }
+
+    async fn get_concurrently(app: &Router, accepts: [&'static str; 3]) -> Vec<String> {
+        let handles = accepts
+            .into_iter()
+            .map(|accept| tokio::spawn(get_body(app.clone(), accept)))
+            .collect::<Vec<_>>();
+        let mut bodies = Vec::new();
+        for handle in handles {
+            bodies.push(handle.await.unwrap());
+        }
+        bodies
+    }
+}
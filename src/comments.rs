@@ -0,0 +1,334 @@
+//! Comment threads on quotes, removed along with their quote
+
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, Query, State},
+    http::{Method, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use chrono::{DateTime, Utc};
+#[cfg(test)]
+use mockall::automock;
+use serde::{Deserialize, Serialize};
+use sqlx::{query, query_as, FromRow, PgPool};
+use uuid::Uuid;
+
+use crate::{ordering, validation::RouteSchema};
+
+const PAGE_SIZE: i64 = 10;
+
+#[derive(Clone)]
+pub struct CommentState {
+    pub repository: Arc<dyn CommentRepository>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Comment {
+    pub id: Uuid,
+    pub quote_id: Uuid,
+    pub author: String,
+    pub body: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NewComment {
+    pub author: String,
+    pub body: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Comments {
+    pub comments: Vec<Comment>,
+    pub page: i64,
+    pub next_page: Option<i64>,
+}
+
+#[derive(Deserialize)]
+pub struct Page {
+    page: Option<i64>,
+}
+
+pub fn schemas() -> Vec<RouteSchema> {
+    vec![RouteSchema::new(
+        Method::POST,
+        "/19/cite/:id/comments",
+        serde_json::json!({
+            "type": "object",
+            "required": ["author", "body"],
+            "properties": {
+                "author": { "type": "string", "minLength": 1 },
+                "body": { "type": "string", "minLength": 1, "maxLength": 2000 }
+            }
+        }),
+    )]
+}
+
+#[async_trait::async_trait]
+#[cfg_attr(test, automock)]
+pub trait CommentRepository: Send + Sync + 'static {
+    /// `RowNotFound` when the quote doesn't exist
+    async fn create(&self, quote_id: Uuid, new_comment: NewComment)
+        -> Result<Comment, sqlx::Error>;
+    /// Oldest first, fetches one comment more than asked to tell if there's a next page
+    async fn thread(
+        &self,
+        quote_id: Uuid,
+        offset: i64,
+        limit: i64,
+    ) -> Result<Vec<Comment>, sqlx::Error>;
+    async fn delete(&self, id: Uuid) -> Result<u64, sqlx::Error>;
+}
+
+pub struct PostgresCommentRepository {
+    pool: PgPool,
+}
+
+impl PostgresCommentRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl CommentRepository for PostgresCommentRepository {
+    async fn create(
+        &self,
+        quote_id: Uuid,
+        new_comment: NewComment,
+    ) -> Result<Comment, sqlx::Error> {
+        query_as::<_, Comment>(
+            "INSERT INTO quote_comments (id, quote_id, author, body)
+             SELECT $1, id, $3, $4 FROM quotes WHERE id = $2 RETURNING *",
+        )
+        .bind(Uuid::new_v4())
+        .bind(quote_id)
+        .bind(new_comment.author)
+        .bind(new_comment.body)
+        .fetch_one(&self.pool)
+        .await
+    }
+
+    async fn thread(
+        &self,
+        quote_id: Uuid,
+        offset: i64,
+        limit: i64,
+    ) -> Result<Vec<Comment>, sqlx::Error> {
+        query_as::<_, Comment>(&format!(
+            "SELECT * FROM quote_comments WHERE quote_id = $1
+             ORDER BY {} OFFSET $2 LIMIT $3",
+            ordering::COMMENTS
+        ))
+        .bind(quote_id)
+        .bind(offset)
+        .bind(limit + 1)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    async fn delete(&self, id: Uuid) -> Result<u64, sqlx::Error> {
+        query("DELETE FROM quote_comments WHERE id = $1")
+            .bind(id)
+            .execute(&self.pool)
+            .await
+            .map(|r| r.rows_affected())
+    }
+}
+
+pub async fn comment(
+    Path(quote_id): Path<Uuid>,
+    State(state): State<CommentState>,
+    Json(new_comment): Json<NewComment>,
+) -> impl IntoResponse {
+    match state.repository.create(quote_id, new_comment).await {
+        Ok(c) => Ok((StatusCode::CREATED, Json(c))),
+        Err(sqlx::Error::RowNotFound) => Err((StatusCode::NOT_FOUND, "".to_string())),
+        _ => Err((StatusCode::INTERNAL_SERVER_ERROR, "".to_string())),
+    }
+}
+
+pub async fn thread(
+    Path(quote_id): Path<Uuid>,
+    State(state): State<CommentState>,
+    Query(Page { page }): Query<Page>,
+) -> impl IntoResponse {
+    let page = page.unwrap_or(1);
+    if page < 1 {
+        return Err((StatusCode::BAD_REQUEST, "".to_string()));
+    }
+
+    match state
+        .repository
+        .thread(quote_id, (page - 1) * PAGE_SIZE, PAGE_SIZE)
+        .await
+    {
+        Ok(mut comments) => {
+            let next_page = if comments.len() as i64 > PAGE_SIZE {
+                comments.truncate(PAGE_SIZE as usize);
+                Some(page + 1)
+            } else {
+                None
+            };
+            Ok((
+                StatusCode::OK,
+                Json(Comments {
+                    comments,
+                    page,
+                    next_page,
+                }),
+            ))
+        }
+        _ => Err((StatusCode::INTERNAL_SERVER_ERROR, "".to_string())),
+    }
+}
+
+pub async fn remove_comment(
+    Path(id): Path<Uuid>,
+    State(state): State<CommentState>,
+) -> impl IntoResponse {
+    match state.repository.delete(id).await {
+        Ok(0) => StatusCode::NOT_FOUND,
+        Ok(_) => StatusCode::NO_CONTENT,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+pub fn state_comment_repository(pool: PgPool) -> Arc<dyn CommentRepository> {
+    Arc::new(PostgresCommentRepository::new(pool))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::box_future;
+    use axum::{
+        body::Body,
+        http::Request,
+        routing::{delete, get},
+        Router,
+    };
+    use http_body_util::BodyExt;
+    use mockall::predicate::eq;
+    use tower::ServiceExt;
+
+    fn create_test_app(repository: MockCommentRepository) -> Router {
+        Router::new()
+            .route("/19/cite/:id/comments", get(thread).post(comment))
+            .route("/19/comments/:comment_id", delete(remove_comment))
+            .with_state(CommentState {
+                repository: Arc::new(repository),
+            })
+    }
+
+    fn comment_on(quote_id: Uuid, body: &str) -> Comment {
+        Comment {
+            id: Uuid::new_v4(),
+            quote_id,
+            author: "Elf".to_string(),
+            body: body.to_string(),
+            created_at: Utc::now(),
+        }
+    }
+
+    fn post_comment(quote_id: Uuid) -> Request<Body> {
+        Request::builder()
+            .method("POST")
+            .uri(format!("/19/cite/{}/comments", quote_id))
+            .header("content-type", "application/json")
+            .body(Body::from(r#"{"author":"Elf","body":"So true"}"#))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_comment() {
+        let mut mock = MockCommentRepository::new();
+        let quote_id = Uuid::new_v4();
+        mock.expect_create()
+            .with(
+                eq(quote_id),
+                eq(NewComment {
+                    author: "Elf".to_string(),
+                    body: "So true".to_string(),
+                }),
+            )
+            .returning(|id, c| box_future(Ok(comment_on(id, &c.body))));
+
+        let response = create_test_app(mock)
+            .oneshot(post_comment(quote_id))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let created: Comment = serde_json::from_slice(&body).unwrap();
+        assert_eq!(created.quote_id, quote_id);
+    }
+
+    #[tokio::test]
+    async fn test_comment_missing_quote() {
+        let mut mock = MockCommentRepository::new();
+        mock.expect_create()
+            .returning(|_, _| box_future(Err(sqlx::Error::RowNotFound)));
+
+        let response = create_test_app(mock)
+            .oneshot(post_comment(Uuid::new_v4()))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_thread_pages() {
+        let mut mock = MockCommentRepository::new();
+        let quote_id = Uuid::new_v4();
+        mock.expect_thread()
+            .with(eq(quote_id), eq(PAGE_SIZE), eq(PAGE_SIZE))
+            .returning(|id, _, limit| {
+                box_future(Ok((0..=limit)
+                    .map(|n| comment_on(id, &n.to_string()))
+                    .collect()))
+            });
+
+        let response = create_test_app(mock)
+            .oneshot(
+                Request::builder()
+                    .uri(format!("/19/cite/{}/comments?page=2", quote_id))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let comments: Comments = serde_json::from_slice(&body).unwrap();
+        assert_eq!(comments.comments.len() as i64, PAGE_SIZE);
+        assert_eq!(comments.page, 2);
+        assert_eq!(comments.next_page, Some(3));
+    }
+
+    #[tokio::test]
+    async fn test_remove_comment() {
+        let mut mock = MockCommentRepository::new();
+        let mut deleted = vec![0, 1];
+        mock.expect_delete()
+            .returning(move |_| box_future(Ok(deleted.pop().unwrap())));
+        let app = create_test_app(mock);
+
+        let request = || {
+            Request::builder()
+                .method("DELETE")
+                .uri(format!("/19/comments/{}", Uuid::new_v4()))
+                .body(Body::empty())
+                .unwrap()
+        };
+        let response = app.clone().oneshot(request()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+        let response = app.oneshot(request()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,112 @@
+//! Gzip or brotli compression of the responses worth it, as the client accepts them
+
+use axum::{
+    body::HttpBody,
+    http::{header, Response},
+};
+use tower_http::compression::{
+    predicate::{And, Predicate},
+    CompressionLayer, DefaultPredicate,
+};
+
+/// Plain text shorter than this is sent as is, e.g. the day 2 addresses or the day 9 answers
+const MIN_PLAIN_TEXT: u64 = 1024;
+
+/// Leaves out plain-text bodies too short to gain anything, every other type is compressed
+/// from the default size on
+#[derive(Debug, Clone, Copy)]
+pub struct NotTinyText;
+
+impl Predicate for NotTinyText {
+    fn should_compress<B>(&self, response: &Response<B>) -> bool
+    where
+        B: HttpBody,
+    {
+        let plain_text = response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|t| t.to_str().ok())
+            .is_some_and(|t| t.starts_with("text/plain"));
+        if !plain_text {
+            return true;
+        }
+
+        let length = response
+            .headers()
+            .get(header::CONTENT_LENGTH)
+            .and_then(|l| l.to_str().ok())
+            .and_then(|l| l.parse().ok())
+            .or_else(|| response.body().size_hint().exact());
+        // a streamed body has no known size, it's likely to be a long one
+        length.is_none_or(|l| l >= MIN_PLAIN_TEXT)
+    }
+}
+
+pub type CompressionPredicate = And<DefaultPredicate, NotTinyText>;
+
+/// The default predicate already skips images, gRPC, event streams and tiny bodies
+pub fn compression_layer() -> CompressionLayer<CompressionPredicate> {
+    CompressionLayer::new()
+        .no_deflate()
+        .no_zstd()
+        .compress_when(DefaultPredicate::new().and(NotTinyText))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, http::Request, routing::get, Json, Router};
+    use serde_json::{json, Value};
+    use tower::ServiceExt;
+
+    fn create_test_app() -> Router {
+        Router::new()
+            .route("/short", get(|| async { "Milk withdrawn\n" }))
+            .route("/long", get(|| async { "Milk withdrawn\n".repeat(100) }))
+            .route(
+                "/json",
+                get(|| async { Json(json!({ "quotes": vec!["Ho ho ho"; 10] })) }),
+            )
+            .layer(compression_layer())
+    }
+
+    async fn encoding(uri: &str, accept: &str) -> Option<String> {
+        let response = create_test_app()
+            .oneshot(
+                Request::builder()
+                    .uri(uri)
+                    .header(header::ACCEPT_ENCODING, accept)
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        response
+            .headers()
+            .get(header::CONTENT_ENCODING)
+            .map(|e| e.to_str().unwrap().to_string())
+    }
+
+    #[tokio::test]
+    async fn test_compression() {
+        assert_eq!(encoding("/json", "gzip").await.as_deref(), Some("gzip"));
+        assert_eq!(encoding("/json", "br").await.as_deref(), Some("br"));
+        assert_eq!(encoding("/long", "gzip").await.as_deref(), Some("gzip"));
+        assert_eq!(encoding("/short", "gzip").await, None);
+        assert_eq!(encoding("/json", "identity").await, None);
+        assert_eq!(encoding("/json", "deflate").await, None);
+    }
+
+    #[tokio::test]
+    async fn test_uncompressed_body() {
+        let response = create_test_app()
+            .oneshot(Request::builder().uri("/json").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let json: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["quotes"][0], "Ho ho ho");
+    }
+}
@@ -0,0 +1,567 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    path::PathBuf,
+    time::Duration,
+};
+
+use crate::{
+    auth::Role,
+    client_ip::IpRange,
+    day_12::BoardConfig,
+    day_16::{GiftLimits, SUPER_SECRET},
+    day_19::PAGE_SIZE,
+    day_9::BucketConfig,
+    keys::KeyBackend,
+    migrations::MigrationMode,
+    rate_limit::ClientQuota,
+    tokens::TokenBackend,
+};
+
+/// Settings resolved once at startup, from Shuttle secrets or any other key/value source
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Whether the service runs in a Shuttle deployment, as opposed to a local run
+    pub production: bool,
+    /// HMAC secret used to sign day 16 gifts
+    pub gift_secret: String,
+    /// Where the day 16 keys come from, `memory`, `file` or `kms`
+    pub key_backend: KeyBackend,
+    /// Gift secret of the `file` key backend
+    pub gift_secret_file: Option<PathBuf>,
+    /// RSA public key of `/16/decode` for the `file` key backend, the bundled one when unset
+    pub decode_key_file: Option<PathBuf>,
+    /// Key of the `kms` backend
+    pub kms_key_id: Option<String>,
+    /// Clock skew tolerated when checking the expiry of day 16 gifts
+    pub jwt_leeway: Duration,
+    /// Size, nesting and key count allowed in the claims of a gift
+    pub gift_limits: GiftLimits,
+    /// Whether day 16 gift cookies are marked `Secure`, on when the service is reached over HTTPS
+    pub secure_cookies: bool,
+    /// Whether `/16/dev/mint` is routed, never in a Shuttle deployment
+    pub dev_tokens: bool,
+    /// Quotes each API key, or client address without a known key, can create per day
+    pub quote_daily_quota: i64,
+    /// Bearer token granting access to the admin UI
+    pub admin_token: Option<String>,
+    /// Whether boards, the milk bucket and list tokens survive redeploys
+    pub persist_state: bool,
+    /// API keys and the role they grant, from `key:role` pairs separated by commas
+    pub api_keys: HashMap<String, Role>,
+    /// HMAC secret of the JWTs carrying a `role` claim
+    pub auth_jwt_secret: Option<String>,
+    /// Whether the quote and game routes require a role, off to keep the challenge routes public
+    pub enforce_roles: bool,
+    /// Where `/19/list` continuation tokens are kept, `memory` or `postgres`
+    pub pagination_tokens: TokenBackend,
+    /// Repository calls taking longer than this are logged
+    pub slow_query: Duration,
+    /// Words rejecting a quote, separated by commas
+    pub moderation_denylist: Vec<String>,
+    /// External moderation API, only used with the `http-moderation` feature
+    pub moderation_url: Option<String>,
+    /// Time given to the moderation API before accepting the quote anyway
+    pub moderation_timeout: Duration,
+    /// Quotes a single bulk delete may remove
+    pub bulk_delete_max: u64,
+    /// Minimum time between two `/12/place` moves of a client, off by default for the challenge validator
+    pub place_interval: Option<Duration>,
+    /// Whether `/23/lockfile` uploads are kept in Postgres, to be rendered again later
+    pub store_uploads: bool,
+    /// How long stored uploads are kept
+    pub upload_retention: Duration,
+    /// Bytes all the stored uploads together may take up, uploads over it are rendered only
+    pub upload_quota_bytes: i64,
+    /// When schema migrations run, `startup`, `background` or `manual`
+    pub migration_mode: MigrationMode,
+    /// Proxies whose `Forwarded` and `X-Forwarded-For` headers are believed, addresses or CIDR
+    /// blocks separated by commas
+    pub trusted_proxies: Vec<IpRange>,
+    /// Whether `/admin/seed` is routed, never in a Shuttle deployment
+    pub seed_fixtures: bool,
+    /// Latency budget of the routes without one of their own
+    pub slo_default: Duration,
+    /// Latency budgets by route template, from `route=ms` pairs separated by commas
+    pub slo_budgets: HashMap<String, Duration>,
+    /// How far back `/admin/slo` looks
+    pub slo_window: Duration,
+    /// Quotes per page of `/19/list`, unless the `quotes.page_size` setting is set
+    pub quotes_page_size: i64,
+    /// Shape of the day 9 milk bucket, unless the `milk.*` settings are set
+    pub milk_bucket: BucketConfig,
+    /// Shape of the day 12 boards
+    pub board: BoardConfig,
+    /// Body sizes allowed by route template, over the budgets of the modules, from `route=bytes`
+    /// pairs separated by commas
+    pub body_limits: HashMap<String, u64>,
+    /// Days whose routes aren't served, separated by commas
+    pub disabled_days: BTreeSet<i8>,
+    /// Requests per second and burst allowed to each client address, unlimited by default for
+    /// the challenge validator
+    pub client_quota: Option<ClientQuota>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            production: false,
+            gift_secret: SUPER_SECRET.to_string(),
+            key_backend: KeyBackend::Memory,
+            gift_secret_file: None,
+            decode_key_file: None,
+            kms_key_id: None,
+            // the jsonwebtoken default
+            jwt_leeway: Duration::from_secs(60),
+            gift_limits: GiftLimits::default(),
+            secure_cookies: false,
+            dev_tokens: false,
+            quote_daily_quota: 1000,
+            admin_token: None,
+            persist_state: false,
+            api_keys: HashMap::new(),
+            auth_jwt_secret: None,
+            enforce_roles: false,
+            pagination_tokens: TokenBackend::Memory,
+            slow_query: Duration::from_millis(200),
+            moderation_denylist: vec![],
+            moderation_url: None,
+            moderation_timeout: Duration::from_millis(500),
+            bulk_delete_max: 100,
+            place_interval: None,
+            store_uploads: false,
+            upload_retention: Duration::from_secs(24 * 60 * 60),
+            upload_quota_bytes: 256 * 1024 * 1024,
+            migration_mode: MigrationMode::Startup,
+            trusted_proxies: vec![],
+            seed_fixtures: false,
+            slo_default: Duration::from_millis(500),
+            slo_budgets: HashMap::new(),
+            slo_window: Duration::from_secs(300),
+            quotes_page_size: PAGE_SIZE,
+            milk_bucket: BucketConfig::default(),
+            board: BoardConfig::default(),
+            body_limits: HashMap::new(),
+            disabled_days: BTreeSet::new(),
+            client_quota: None,
+        }
+    }
+}
+
+impl Config {
+    /// Builds the configuration from a lookup function, falling back to the defaults for missing keys
+    pub fn load(production: bool, lookup: impl Fn(&str) -> Option<String>) -> Self {
+        let default = Self::default();
+        Self {
+            production,
+            gift_secret: lookup("GIFT_SECRET").unwrap_or(default.gift_secret),
+            key_backend: lookup("KEY_BACKEND")
+                .and_then(|b| {
+                    b.parse()
+                        .inspect_err(|e| tracing::warn!("ignoring KEY_BACKEND: {}", e))
+                        .ok()
+                })
+                .unwrap_or(default.key_backend),
+            gift_secret_file: lookup("GIFT_SECRET_FILE")
+                .filter(|p| !p.is_empty())
+                .map(PathBuf::from),
+            decode_key_file: lookup("DECODE_PUBLIC_KEY_FILE")
+                .filter(|p| !p.is_empty())
+                .map(PathBuf::from),
+            kms_key_id: lookup("KMS_KEY_ID").filter(|k| !k.is_empty()),
+            jwt_leeway: lookup("JWT_LEEWAY_SECS")
+                .and_then(|s| s.parse().ok())
+                .map(Duration::from_secs)
+                .unwrap_or(default.jwt_leeway),
+            gift_limits: GiftLimits {
+                max_bytes: lookup("GIFT_MAX_BYTES")
+                    .and_then(|n| n.parse().ok())
+                    .unwrap_or(default.gift_limits.max_bytes),
+                max_depth: lookup("GIFT_MAX_DEPTH")
+                    .and_then(|n| n.parse().ok())
+                    .unwrap_or(default.gift_limits.max_depth),
+                max_keys: lookup("GIFT_MAX_KEYS")
+                    .and_then(|n| n.parse().ok())
+                    .unwrap_or(default.gift_limits.max_keys),
+            },
+            secure_cookies: lookup("SECURE_COOKIES")
+                .map(|v| v == "true")
+                .unwrap_or(default.secure_cookies),
+            dev_tokens: match lookup("DEV_TOKENS").is_some_and(|v| v == "true") {
+                true if production => {
+                    tracing::warn!("ignoring DEV_TOKENS in production");
+                    false
+                }
+                enabled => enabled,
+            },
+            quote_daily_quota: lookup("QUOTE_DAILY_QUOTA")
+                .and_then(|q| q.parse().ok())
+                .unwrap_or(default.quote_daily_quota),
+            admin_token: lookup("ADMIN_TOKEN").filter(|t| !t.is_empty()),
+            persist_state: lookup("PERSIST_STATE")
+                .map(|v| v == "true")
+                .unwrap_or(default.persist_state),
+            api_keys: lookup("API_KEYS")
+                .map(|keys| parse_api_keys(&keys))
+                .unwrap_or(default.api_keys),
+            auth_jwt_secret: lookup("AUTH_JWT_SECRET").filter(|s| !s.is_empty()),
+            enforce_roles: lookup("ENFORCE_ROLES")
+                .map(|v| v == "true")
+                .unwrap_or(default.enforce_roles),
+            pagination_tokens: lookup("PAGINATION_TOKENS")
+                .and_then(|b| {
+                    b.parse()
+                        .inspect_err(|e| tracing::warn!("ignoring PAGINATION_TOKENS: {}", e))
+                        .ok()
+                })
+                .unwrap_or(default.pagination_tokens),
+            slow_query: lookup("SLOW_QUERY_MS")
+                .and_then(|ms| ms.parse().ok())
+                .map(Duration::from_millis)
+                .unwrap_or(default.slow_query),
+            moderation_denylist: lookup("MODERATION_DENYLIST")
+                .map(|words| {
+                    words
+                        .split(',')
+                        .map(|w| w.trim().to_string())
+                        .filter(|w| !w.is_empty())
+                        .collect()
+                })
+                .unwrap_or(default.moderation_denylist),
+            moderation_url: lookup("MODERATION_URL").filter(|u| !u.is_empty()),
+            moderation_timeout: lookup("MODERATION_TIMEOUT_MS")
+                .and_then(|ms| ms.parse().ok())
+                .map(Duration::from_millis)
+                .unwrap_or(default.moderation_timeout),
+            bulk_delete_max: lookup("BULK_DELETE_MAX")
+                .and_then(|n| n.parse().ok())
+                .unwrap_or(default.bulk_delete_max),
+            place_interval: lookup("PLACE_INTERVAL_MS")
+                .and_then(|ms| ms.parse().ok())
+                .filter(|ms| *ms > 0)
+                .map(Duration::from_millis),
+            store_uploads: lookup("STORE_UPLOADS")
+                .map(|v| v == "true")
+                .unwrap_or(default.store_uploads),
+            upload_retention: lookup("UPLOAD_RETENTION_HOURS")
+                .and_then(|h| h.parse::<u64>().ok())
+                .filter(|h| *h > 0)
+                .map(|h| Duration::from_secs(h * 60 * 60))
+                .unwrap_or(default.upload_retention),
+            upload_quota_bytes: lookup("UPLOAD_QUOTA_BYTES")
+                .and_then(|n| n.parse().ok())
+                .filter(|n| *n >= 0)
+                .unwrap_or(default.upload_quota_bytes),
+            migration_mode: lookup("MIGRATION_MODE")
+                .and_then(|m| {
+                    m.parse()
+                        .inspect_err(|e| tracing::warn!("ignoring MIGRATION_MODE: {}", e))
+                        .ok()
+                })
+                .unwrap_or(default.migration_mode),
+            trusted_proxies: lookup("TRUSTED_PROXIES")
+                .map(|proxies| parse_trusted_proxies(&proxies))
+                .unwrap_or(default.trusted_proxies),
+            seed_fixtures: match lookup("SEED_FIXTURES").is_some_and(|v| v == "true") {
+                true if production => {
+                    tracing::warn!("ignoring SEED_FIXTURES in production");
+                    false
+                }
+                enabled => enabled,
+            },
+            slo_default: lookup("SLO_DEFAULT_MS")
+                .and_then(|ms| ms.parse().ok())
+                .map(Duration::from_millis)
+                .unwrap_or(default.slo_default),
+            slo_budgets: lookup("SLO_BUDGETS")
+                .map(|budgets| parse_slo_budgets(&budgets))
+                .unwrap_or(default.slo_budgets),
+            slo_window: lookup("SLO_WINDOW_SECS")
+                .and_then(|s| s.parse().ok())
+                .filter(|s| *s > 0)
+                .map(Duration::from_secs)
+                .unwrap_or(default.slo_window),
+            quotes_page_size: lookup("QUOTES_PAGE_SIZE")
+                .and_then(|n| n.parse().ok())
+                .filter(|n| *n > 0)
+                .unwrap_or(default.quotes_page_size),
+            milk_bucket: BucketConfig {
+                initial: lookup("MILK_INITIAL")
+                    .and_then(|n| n.parse().ok())
+                    .unwrap_or(default.milk_bucket.initial),
+                max: lookup("MILK_MAX")
+                    .and_then(|n| n.parse().ok())
+                    .filter(|n| *n > 0)
+                    .unwrap_or(default.milk_bucket.max),
+                refill_amount: lookup("MILK_REFILL_AMOUNT")
+                    .and_then(|n| n.parse().ok())
+                    .filter(|n| *n > 0)
+                    .unwrap_or(default.milk_bucket.refill_amount),
+                refill_interval: lookup("MILK_REFILL_INTERVAL_SECS")
+                    .and_then(|s| s.parse().ok())
+                    .filter(|s| *s > 0)
+                    .map(Duration::from_secs)
+                    .unwrap_or(default.milk_bucket.refill_interval),
+            },
+            board: BoardConfig {
+                size: lookup("BOARD_SIZE")
+                    .and_then(|n| n.parse().ok())
+                    .filter(|n| *n > 0)
+                    .unwrap_or(default.board.size),
+            },
+            body_limits: lookup("BODY_LIMITS")
+                .map(|limits| parse_body_limits(&limits))
+                .unwrap_or(default.body_limits),
+            disabled_days: lookup("DISABLED_DAYS")
+                .map(|days| parse_days(&days))
+                .unwrap_or(default.disabled_days),
+            client_quota: lookup("CLIENT_RATE_PER_SEC")
+                .and_then(|n| n.parse().ok())
+                .filter(|n| *n > 0)
+                .map(|per_second| ClientQuota {
+                    per_second,
+                    burst: lookup("CLIENT_RATE_BURST")
+                        .and_then(|n| n.parse().ok())
+                        .filter(|n| *n > 0)
+                        .unwrap_or(per_second),
+                }),
+        }
+    }
+}
+
+fn parse_api_keys(keys: &str) -> HashMap<String, Role> {
+    keys.split(',')
+        .filter(|pair| !pair.trim().is_empty())
+        .filter_map(|pair| {
+            let parsed = pair
+                .split_once(':')
+                .ok_or_else(|| "missing role".to_string())
+                .and_then(|(key, role)| Ok((key.trim().to_string(), role.parse::<Role>()?)));
+            if let Err(e) = &parsed {
+                tracing::warn!("ignoring API key entry: {}", e);
+            }
+            parsed.ok()
+        })
+        .collect()
+}
+
+fn parse_trusted_proxies(proxies: &str) -> Vec<IpRange> {
+    proxies
+        .split(',')
+        .map(str::trim)
+        .filter(|p| !p.is_empty())
+        .filter_map(|p| {
+            p.parse()
+                .inspect_err(|e| tracing::warn!("ignoring trusted proxy: {}", e))
+                .ok()
+        })
+        .collect()
+}
+
+fn parse_slo_budgets(budgets: &str) -> HashMap<String, Duration> {
+    budgets
+        .split(',')
+        .filter(|pair| !pair.trim().is_empty())
+        .filter_map(|pair| {
+            let parsed = pair
+                .split_once('=')
+                .ok_or_else(|| "missing budget".to_string())
+                .and_then(|(route, ms)| {
+                    let ms = ms
+                        .trim()
+                        .parse()
+                        .map_err(|_| format!("bad budget {}", ms))?;
+                    Ok((route.trim().to_string(), Duration::from_millis(ms)))
+                });
+            if let Err(e) = &parsed {
+                tracing::warn!("ignoring SLO budget entry: {}", e);
+            }
+            parsed.ok()
+        })
+        .collect()
+}
+
+fn parse_body_limits(limits: &str) -> HashMap<String, u64> {
+    limits
+        .split(',')
+        .filter(|pair| !pair.trim().is_empty())
+        .filter_map(|pair| {
+            let parsed = pair
+                .split_once('=')
+                .ok_or_else(|| "missing limit".to_string())
+                .and_then(|(route, bytes)| {
+                    let bytes = bytes
+                        .trim()
+                        .parse()
+                        .ok()
+                        .filter(|b| *b > 0)
+                        .ok_or_else(|| format!("bad limit {}", bytes))?;
+                    Ok((route.trim().to_string(), bytes))
+                });
+            if let Err(e) = &parsed {
+                tracing::warn!("ignoring body limit entry: {}", e);
+            }
+            parsed.ok()
+        })
+        .collect()
+}
+
+fn parse_days(days: &str) -> BTreeSet<i8> {
+    days.split(',')
+        .map(str::trim)
+        .filter(|d| !d.is_empty())
+        .filter_map(|d| {
+            d.parse()
+                .inspect_err(|_| tracing::warn!("ignoring disabled day {}", d))
+                .ok()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_defaults() {
+        let config = Config::load(false, |_| None);
+        assert!(!config.production);
+        assert_eq!(config.gift_secret, SUPER_SECRET);
+        assert_eq!(config.key_backend, KeyBackend::Memory);
+        assert_eq!(config.gift_secret_file, None);
+        assert_eq!(config.jwt_leeway, Duration::from_secs(60));
+        assert_eq!(config.gift_limits, GiftLimits::default());
+        assert!(!config.secure_cookies);
+        assert!(!config.dev_tokens);
+        assert_eq!(config.quote_daily_quota, 1000);
+        assert_eq!(config.admin_token, None);
+        assert!(!config.persist_state);
+        assert!(config.api_keys.is_empty());
+        assert!(!config.enforce_roles);
+        assert_eq!(config.pagination_tokens, TokenBackend::Memory);
+        assert_eq!(config.slow_query, Duration::from_millis(200));
+        assert!(config.moderation_denylist.is_empty());
+        assert_eq!(config.moderation_url, None);
+        assert_eq!(config.bulk_delete_max, 100);
+        assert_eq!(config.place_interval, None);
+        assert!(!config.store_uploads);
+        assert_eq!(config.upload_retention, Duration::from_secs(24 * 60 * 60));
+        assert_eq!(config.upload_quota_bytes, 256 * 1024 * 1024);
+        assert_eq!(config.migration_mode, MigrationMode::Startup);
+        assert!(config.trusted_proxies.is_empty());
+        assert!(!config.seed_fixtures);
+        assert_eq!(config.slo_default, Duration::from_millis(500));
+        assert!(config.slo_budgets.is_empty());
+        assert_eq!(config.slo_window, Duration::from_secs(300));
+        assert_eq!(config.quotes_page_size, PAGE_SIZE);
+        assert_eq!(config.milk_bucket, BucketConfig::default());
+        assert_eq!(config.board, BoardConfig::default());
+        assert!(config.body_limits.is_empty());
+        assert!(config.disabled_days.is_empty());
+        assert_eq!(config.client_quota, None);
+    }
+
+    #[test]
+    fn test_load_overrides() {
+        let config = Config::load(true, |k| match k {
+            "GIFT_SECRET" => Some("not-so-secret".to_string()),
+            "JWT_LEEWAY_SECS" => Some("5".to_string()),
+            "KEY_BACKEND" => Some("kms".to_string()),
+            "KMS_KEY_ID" => Some("gift-key".to_string()),
+            "GIFT_MAX_DEPTH" => Some("4".to_string()),
+            "SECURE_COOKIES" => Some("true".to_string()),
+            "DEV_TOKENS" => Some("true".to_string()),
+            "QUOTE_DAILY_QUOTA" => Some("10".to_string()),
+            "ADMIN_TOKEN" => Some("elf".to_string()),
+            "PERSIST_STATE" => Some("true".to_string()),
+            "API_KEYS" => Some("k1:reader, k2:Editor,broken,k3:santa".to_string()),
+            "ENFORCE_ROLES" => Some("true".to_string()),
+            "PAGINATION_TOKENS" => Some("postgres".to_string()),
+            "SLOW_QUERY_MS" => Some("50".to_string()),
+            "MODERATION_DENYLIST" => Some("grinch, ,scrooge".to_string()),
+            "MODERATION_TIMEOUT_MS" => Some("100".to_string()),
+            "BULK_DELETE_MAX" => Some("5".to_string()),
+            "PLACE_INTERVAL_MS" => Some("1000".to_string()),
+            "STORE_UPLOADS" => Some("true".to_string()),
+            "UPLOAD_RETENTION_HOURS" => Some("2".to_string()),
+            "UPLOAD_QUOTA_BYTES" => Some("-1".to_string()),
+            "MIGRATION_MODE" => Some("background".to_string()),
+            "TRUSTED_PROXIES" => Some("10.0.0.0/8, proxy,::1".to_string()),
+            "SEED_FIXTURES" => Some("true".to_string()),
+            "SLO_DEFAULT_MS" => Some("250".to_string()),
+            "SLO_BUDGETS" => {
+                Some("/19/cite/:id=50, /23/lockfile=2000,/9/milk,/1/slice=soon".to_string())
+            }
+            "SLO_WINDOW_SECS" => Some("60".to_string()),
+            "QUOTES_PAGE_SIZE" => Some("10".to_string()),
+            "MILK_MAX" => Some("20".to_string()),
+            "MILK_REFILL_INTERVAL_SECS" => Some("0".to_string()),
+            "BOARD_SIZE" => Some("6".to_string()),
+            "CLIENT_RATE_PER_SEC" => Some("20".to_string()),
+            "DISABLED_DAYS" => Some("19, 23,,christmas".to_string()),
+            "BODY_LIMITS" => Some("/5/manifest=1024,/16/wrap=0, /23/lockfile=2048".to_string()),
+            _ => None,
+        });
+        assert!(config.production);
+        assert_eq!(config.gift_secret, "not-so-secret");
+        assert_eq!(config.jwt_leeway, Duration::from_secs(5));
+        assert_eq!(config.key_backend, KeyBackend::Kms);
+        assert_eq!(config.kms_key_id.as_deref(), Some("gift-key"));
+        assert_eq!(config.gift_limits.max_depth, 4);
+        assert_eq!(config.gift_limits.max_bytes, 2048);
+        assert!(config.secure_cookies);
+        // only honored in local runs
+        assert!(!config.dev_tokens);
+        assert!(
+            Config::load(false, |k| (k == "DEV_TOKENS").then(|| "true".to_string())).dev_tokens
+        );
+        assert_eq!(config.quote_daily_quota, 10);
+        assert_eq!(config.admin_token.as_deref(), Some("elf"));
+        assert!(config.persist_state);
+        assert_eq!(config.api_keys.len(), 2);
+        assert_eq!(config.api_keys["k2"], Role::Editor);
+        assert!(config.enforce_roles);
+        assert_eq!(config.pagination_tokens, TokenBackend::Postgres);
+        assert_eq!(config.slow_query, Duration::from_millis(50));
+        assert_eq!(config.moderation_denylist, ["grinch", "scrooge"]);
+        assert_eq!(config.moderation_timeout, Duration::from_millis(100));
+        assert_eq!(config.bulk_delete_max, 5);
+        assert_eq!(config.place_interval, Some(Duration::from_secs(1)));
+        assert!(config.store_uploads);
+        assert_eq!(config.upload_retention, Duration::from_secs(2 * 60 * 60));
+        assert_eq!(config.upload_quota_bytes, 256 * 1024 * 1024);
+        assert_eq!(config.migration_mode, MigrationMode::Background);
+        assert_eq!(
+            config.trusted_proxies,
+            ["10.0.0.0/8".parse().unwrap(), "::1".parse().unwrap()]
+        );
+        // only honored in local runs
+        assert!(!config.seed_fixtures);
+        assert!(
+            Config::load(false, |k| (k == "SEED_FIXTURES")
+                .then(|| "true".to_string()))
+            .seed_fixtures
+        );
+        assert_eq!(config.slo_default, Duration::from_millis(250));
+        assert_eq!(config.slo_budgets.len(), 2);
+        assert_eq!(
+            config.slo_budgets["/19/cite/:id"],
+            Duration::from_millis(50)
+        );
+        assert_eq!(config.slo_window, Duration::from_secs(60));
+        assert_eq!(config.quotes_page_size, 10);
+        assert_eq!(config.milk_bucket.max, 20);
+        // a bucket that never refills isn't a rate limit
+        assert_eq!(config.milk_bucket.refill_interval, Duration::from_secs(1));
+        assert_eq!(config.board.size, 6);
+        assert_eq!(config.body_limits.len(), 2);
+        assert_eq!(config.body_limits["/23/lockfile"], 2048);
+        assert_eq!(config.disabled_days, BTreeSet::from([19, 23]));
+        assert_eq!(
+            config.client_quota,
+            Some(ClientQuota {
+                per_second: 20,
+                burst: 20
+            })
+        );
+    }
+}
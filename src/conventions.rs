@@ -0,0 +1,163 @@
+//! Serde conventions of the JSON bodies: snake_case names, externally tagged enums, datetimes in
+//! RFC 3339 UTC and lowercase hyphenated UUIDs. Reading is looser than writing, so that payloads
+//! accepted so far keep being accepted.
+//! `room::Event` and `moderation::Verdict` keep the internal and adjacent tags their clients know.
+
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+
+/// Name of the variant, the key of an externally tagged enum or the string of a unit variant
+pub fn tag<T: Serialize>(value: &T) -> Option<String> {
+    match serde_json::to_value(value).ok()? {
+        Value::String(tag) => Some(tag),
+        Value::Object(map) if map.len() == 1 => map.keys().next().cloned(),
+        _ => None,
+    }
+}
+
+/// Unit variant named by `tag`, how enums stored as text are read back
+pub fn from_tag<T: DeserializeOwned>(tag: &str) -> Result<T, String> {
+    serde_json::from_value(Value::String(tag.to_string()))
+        .map_err(|_| format!("unknown variant {}", tag))
+}
+
+/// `DateTime<Utc>` written as RFC 3339 with a `Z`, read with any offset or a space for the `T`
+pub mod datetime {
+    use chrono::{DateTime, SecondsFormat, Utc};
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        value: &DateTime<Utc>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&value.to_rfc3339_opts(SecondsFormat::AutoSi, true))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<DateTime<Utc>, D::Error> {
+        parse(&String::deserialize(deserializer)?)
+    }
+
+    fn parse<E: Error>(value: &str) -> Result<DateTime<Utc>, E> {
+        DateTime::parse_from_rfc3339(value)
+            .map(|d| d.with_timezone(&Utc))
+            .map_err(Error::custom)
+    }
+
+    pub mod option {
+        use super::*;
+
+        pub fn serialize<S: Serializer>(
+            value: &Option<DateTime<Utc>>,
+            serializer: S,
+        ) -> Result<S::Ok, S::Error> {
+            match value {
+                Some(value) => super::serialize(value, serializer),
+                None => serializer.serialize_none(),
+            }
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<Option<DateTime<Utc>>, D::Error> {
+            Option::<String>::deserialize(deserializer)?
+                .map(|value| parse(&value))
+                .transpose()
+        }
+    }
+}
+
+/// `Uuid` written lowercase and hyphenated, read in any case, with or without hyphens or braces
+pub mod uuid {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+    use uuid::Uuid;
+
+    pub fn serialize<S: Serializer>(value: &Uuid, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&value.hyphenated().to_string())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Uuid, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        Uuid::parse_str(&value).map_err(Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{DateTime, TimeZone, Utc};
+    use serde::Deserialize;
+    use serde_json::json;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    #[serde(rename_all = "snake_case")]
+    enum Unit {
+        Liters(f32),
+        PendingReview,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Record {
+        #[serde(with = "super::uuid")]
+        id: ::uuid::Uuid,
+        #[serde(with = "datetime")]
+        at: DateTime<Utc>,
+        #[serde(with = "datetime::option", default)]
+        until: Option<DateTime<Utc>>,
+    }
+
+    #[test]
+    fn test_tags() {
+        assert_eq!(tag(&Unit::Liters(1.0)).as_deref(), Some("liters"));
+        assert_eq!(tag(&Unit::PendingReview).as_deref(), Some("pending_review"));
+        assert_eq!(from_tag::<Unit>("pending_review"), Ok(Unit::PendingReview));
+        assert_eq!(
+            from_tag::<Unit>("PendingReview"),
+            Err("unknown variant PendingReview".to_string())
+        );
+    }
+
+    #[test]
+    fn test_record() {
+        let record = Record {
+            id: ::uuid::Uuid::max(),
+            at: Utc.with_ymd_and_hms(2024, 12, 25, 0, 0, 0).unwrap(),
+            until: None,
+        };
+        let written = serde_json::to_value(&record).unwrap();
+        assert_eq!(
+            written,
+            json!({
+                "id": "ffffffff-ffff-ffff-ffff-ffffffffffff",
+                "at": "2024-12-25T00:00:00Z",
+                "until": null
+            })
+        );
+        // what chrono and uuid wrote on their own reads the same
+        let default = json!({
+            "id": ::uuid::Uuid::max(),
+            "at": record.at,
+        });
+        assert_eq!(default, json!({ "id": written["id"], "at": written["at"] }));
+
+        let read = serde_json::from_value::<Record>(json!({
+            "id": "{FFFFFFFF-FFFF-FFFF-FFFF-FFFFFFFFFFFF}",
+            "at": "2024-12-25 01:00:00+01:00",
+        }))
+        .unwrap();
+        assert_eq!(read, record);
+        let read = serde_json::from_value::<Record>(json!({
+            "id": "FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF",
+            "at": "2024-12-25T00:00:00.000Z",
+            "until": "2024-12-26T00:00:00Z",
+        }))
+        .unwrap();
+        assert_eq!(read.until, Some(record.at + chrono::Days::new(1)));
+        assert!(serde_json::from_value::<Record>(json!({
+            "id": "ffffffff-ffff-ffff-ffff-ffffffffffff",
+            "at": "Christmas",
+        }))
+        .is_err());
+    }
+}
@@ -0,0 +1,212 @@
+//! Time left until the next Christmas, as seen from a timezone
+
+use std::sync::Arc;
+
+use axum::{
+    extract::State,
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{DateTime, Datelike, NaiveDate, TimeZone, Utc};
+use chrono_tz::Tz;
+use serde::Serialize;
+
+use crate::{
+    clock::Clock,
+    negotiate::{Accept, Format},
+    validation::{FromParams, Params, ValidatedQuery},
+};
+
+#[derive(Clone)]
+pub struct CountdownState {
+    pub clock: Arc<dyn Clock>,
+}
+
+/// IANA name of the zone, UTC when missing
+pub struct Zone(Tz);
+
+impl FromParams for Zone {
+    fn from_params(params: &mut Params) -> Option<Self> {
+        Some(Self(params.optional("tz").unwrap_or(Tz::UTC)))
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Countdown {
+    pub tz: String,
+    /// Midnight starting the next December 25, or the current one while it lasts
+    pub christmas: DateTime<Tz>,
+    /// Whether December 25 is already going on, everything left is then zero
+    pub today: bool,
+    pub days: i64,
+    pub hours: i64,
+    pub minutes: i64,
+    pub seconds: i64,
+}
+
+/// Start of December 25 of `year`, the earliest one should the midnight repeat
+fn christmas(tz: Tz, year: i32) -> DateTime<Tz> {
+    let midnight = NaiveDate::from_ymd_opt(year, 12, 25)
+        .unwrap()
+        .and_hms_opt(0, 0, 0)
+        .unwrap();
+    // a zone skipping its midnight starts the day at the same instant as UTC would
+    tz.from_local_datetime(&midnight)
+        .earliest()
+        .unwrap_or_else(|| tz.from_utc_datetime(&midnight))
+}
+
+pub fn countdown(now: DateTime<Utc>, tz: Tz) -> Countdown {
+    let local = now.with_timezone(&tz);
+    let today = local.month() == 12 && local.day() == 25;
+    let christmas = match christmas(tz, local.year()) {
+        c if today || c > local => c,
+        _ => christmas(tz, local.year() + 1),
+    };
+
+    let left = match today {
+        true => 0,
+        false => (christmas.with_timezone(&Utc) - now).num_seconds(),
+    };
+    Countdown {
+        tz: tz.name().to_string(),
+        christmas,
+        today,
+        days: left / 86_400,
+        hours: left % 86_400 / 3_600,
+        minutes: left % 3_600 / 60,
+        seconds: left % 60,
+    }
+}
+
+impl Countdown {
+    /// Fragment of the day 23 page, polling for itself every second
+    fn to_html(&self) -> String {
+        let text = match self.today {
+            true => "It's Christmas!".to_string(),
+            false => format!(
+                "{} days {:02}:{:02}:{:02}",
+                self.days, self.hours, self.minutes, self.seconds
+            ),
+        };
+        // zones such as `Etc/GMT+5` would read as a space in the query
+        format!(
+            r#"<div id="countdown" class="text" hx-get="/countdown?tz={}" hx-trigger="every 1s" hx-swap="outerHTML" hx-headers='{{"Accept": "text/html"}}'>{}</div>"#,
+            self.tz.replace('+', "%2B"),
+            text
+        )
+    }
+}
+
+pub async fn get_countdown(
+    State(state): State<CountdownState>,
+    accept: Accept,
+    ValidatedQuery(Zone(tz)): ValidatedQuery<Zone>,
+) -> Response {
+    let countdown = countdown(state.clock.now(), tz);
+    match accept.negotiate(&[Format::Json, Format::Html]) {
+        Some(Format::Html) => (
+            [(header::CONTENT_TYPE, Format::Html.mime())],
+            countdown.to_html(),
+        )
+            .into_response(),
+        Some(_) => Json(countdown).into_response(),
+        None => StatusCode::NOT_ACCEPTABLE.into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::MockClock;
+    use axum::{body::Body, http::Request, routing::get, Router};
+    use http_body_util::BodyExt;
+    use serde_json::Value;
+    use tower::ServiceExt;
+
+    fn at(instant: &str) -> DateTime<Utc> {
+        instant.parse().unwrap()
+    }
+
+    fn create_test_app(now: DateTime<Utc>) -> Router {
+        let mut clock = MockClock::new();
+        clock.expect_now().return_const(now);
+        Router::new()
+            .route("/countdown", get(get_countdown))
+            .with_state(CountdownState {
+                clock: Arc::new(clock),
+            })
+    }
+
+    async fn get_response(app: Router, uri: &str, accept: &str) -> (StatusCode, String) {
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri(uri)
+                    .header(header::ACCEPT, accept)
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let status = response.status();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[test]
+    fn test_countdown() {
+        let left = countdown(at("2024-12-23T22:58:30Z"), Tz::UTC);
+        assert_eq!(
+            (left.days, left.hours, left.minutes, left.seconds),
+            (1, 1, 1, 30)
+        );
+        assert_eq!(left.christmas.to_rfc3339(), "2024-12-25T00:00:00+00:00");
+
+        // Christmas starts earlier east of UTC
+        let left = countdown(at("2024-12-24T23:30:00Z"), Tz::Europe__Madrid);
+        assert!(left.today);
+        assert_eq!((left.days, left.hours, left.seconds), (0, 0, 0));
+        assert_eq!(left.christmas.to_rfc3339(), "2024-12-25T00:00:00+01:00");
+
+        // once over, the next one is a year away
+        let left = countdown(at("2024-12-26T00:00:00Z"), Tz::UTC);
+        assert!(!left.today);
+        assert_eq!(left.days, 364);
+        assert_eq!(left.christmas.to_rfc3339(), "2025-12-25T00:00:00+00:00");
+    }
+
+    #[tokio::test]
+    async fn test_get_countdown() {
+        let app = create_test_app(at("2024-12-24T12:00:00Z"));
+
+        let (status, body) = get_response(
+            app.clone(),
+            "/countdown?tz=America/New_York",
+            "application/json",
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let json = serde_json::from_str::<Value>(&body).unwrap();
+        assert_eq!(json["tz"], "America/New_York");
+        assert_eq!(json["christmas"], "2024-12-25T00:00:00-05:00");
+        assert_eq!(
+            (json["days"].as_i64(), json["hours"].as_i64()),
+            (Some(0), Some(17))
+        );
+
+        let (_, body) = get_response(app.clone(), "/countdown", "text/html").await;
+        assert!(body.contains("hx-get=\"/countdown?tz=UTC\""));
+        assert!(body.contains(">0 days 12:00:00</div>"));
+        let (_, body) = get_response(app.clone(), "/countdown?tz=Etc/GMT%2B5", "text/html").await;
+        assert!(body.contains("hx-get=\"/countdown?tz=Etc/GMT%2B5\""));
+
+        let (status, body) = get_response(app.clone(), "/countdown?tz=Mars/Olympus", "*/*").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body.contains("\"path\":\"tz\""));
+
+        let (status, _) = get_response(app, "/countdown", "image/png").await;
+        assert_eq!(status, StatusCode::NOT_ACCEPTABLE);
+    }
+}
@@ -0,0 +1,108 @@
+use axum::{
+    extract::Query,
+    http::{Method, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::openapi::Operation;
+
+#[derive(Debug, Default, Deserialize)]
+pub struct Window {
+    offset: Option<usize>,
+    limit: Option<usize>,
+    split: Option<usize>,
+}
+
+/// Names in the window, grouped in chunks of `split` names when given
+fn slice(names: &str, window: &Window) -> Result<Value, StatusCode> {
+    let names = names
+        .lines()
+        .map(str::trim)
+        .filter(|n| !n.is_empty())
+        .skip(window.offset.unwrap_or(0))
+        .take(window.limit.unwrap_or(usize::MAX))
+        .collect::<Vec<&str>>();
+
+    match window.split {
+        None => Ok(json!(names)),
+        Some(0) => Err(StatusCode::BAD_REQUEST),
+        Some(split) => Ok(json!(names.chunks(split).collect::<Vec<_>>())),
+    }
+}
+
+pub fn operations() -> Vec<Operation> {
+    vec![
+        Operation::new(Method::POST, "/1/slice", "Pages through a list of names")
+            .query("offset", "integer", "Names skipped, 0 by default")
+            .query("limit", "integer", "Names returned, all of them by default")
+            .query(
+                "split",
+                "integer",
+                "Size of the chunks the page is split in",
+            )
+            .body("text/plain")
+            .media_response(
+                StatusCode::OK,
+                "application/json",
+                "The names of the page, chunked with `split`",
+            )
+            .response(StatusCode::BAD_REQUEST, "Malformed window"),
+    ]
+}
+
+pub async fn slice_names(Query(window): Query<Window>, body: String) -> impl IntoResponse {
+    match slice(&body, &window) {
+        Ok(names) => Ok((StatusCode::OK, Json(names))),
+        Err(status) => Err((status, "".to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NAMES: &str = "Rudolph\nDasher\n\nDancer\nPrancer\nVixen\n";
+
+    #[test]
+    fn test_window() {
+        let window = Window {
+            offset: Some(1),
+            limit: Some(3),
+            split: None,
+        };
+        assert_eq!(
+            slice(NAMES, &window).unwrap(),
+            json!(["Dasher", "Dancer", "Prancer"])
+        );
+        assert_eq!(
+            slice(NAMES, &Window::default())
+                .unwrap()
+                .as_array()
+                .unwrap()
+                .len(),
+            5
+        );
+    }
+
+    #[test]
+    fn test_split() {
+        let window = Window {
+            offset: Some(3),
+            limit: None,
+            split: Some(2),
+        };
+        assert_eq!(
+            slice(NAMES, &window).unwrap(),
+            json!([["Prancer", "Vixen"]])
+        );
+
+        let window = Window {
+            split: Some(0),
+            ..Window::default()
+        };
+        assert_eq!(slice(NAMES, &window), Err(StatusCode::BAD_REQUEST));
+    }
+}
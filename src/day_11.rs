@@ -0,0 +1,173 @@
+use std::io::Cursor;
+
+use axum::{
+    extract::Multipart,
+    http::{Method, StatusCode},
+};
+use image::{ImageFormat, ImageReader, Limits, RgbImage};
+
+use crate::{
+    multipart::{self, Parts},
+    openapi::Operation,
+};
+
+const MAX_DIMENSION: u32 = 4096;
+const MAX_ALLOC: u64 = 64 * 1024 * 1024;
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+const IMAGE_LIMITS: multipart::Limits = multipart::Limits {
+    field: 2 * 1024 * 1024,
+    total: 2 * 1024 * 1024,
+};
+
+/// Pixels whose red outweighs green and blue together
+fn count_red(image: &RgbImage) -> usize {
+    image
+        .pixels()
+        .filter(|p| {
+            let [r, g, b] = p.0;
+            r as u16 > g as u16 + b as u16
+        })
+        .count()
+}
+
+fn decode(bytes: &[u8]) -> Result<RgbImage, (StatusCode, String)> {
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_DIMENSION);
+    limits.max_image_height = Some(MAX_DIMENSION);
+    limits.max_alloc = Some(MAX_ALLOC);
+
+    let mut reader = ImageReader::with_format(Cursor::new(bytes), ImageFormat::Png);
+    reader.limits(limits);
+    reader
+        .decode()
+        .map(|image| image.to_rgb8())
+        .map_err(|_| (StatusCode::UNPROCESSABLE_ENTITY, "".to_string()))
+}
+
+pub fn operations() -> Vec<Operation> {
+    vec![Operation::new(
+        Method::POST,
+        "/11/red_pixels",
+        "Counts the red pixels of a PNG",
+    )
+    .body("multipart/form-data")
+    .media_response(StatusCode::OK, "text/plain", "The number of red pixels")
+    .response(StatusCode::BAD_REQUEST, "No image part")
+    .response(StatusCode::PAYLOAD_TOO_LARGE, "Image over 2 MiB")
+    .response(StatusCode::UNSUPPORTED_MEDIA_TYPE, "Not a PNG")
+    .response(
+        StatusCode::UNPROCESSABLE_ENTITY,
+        "Corrupted or over 4096x4096",
+    )]
+}
+
+pub async fn red_pixels(multipart: Multipart) -> Result<String, (StatusCode, String)> {
+    let parts = Parts::read(multipart, IMAGE_LIMITS).await?;
+    // only PNGs are supported, whatever the part claims to be
+    let bytes = parts
+        .field_or_first("image")?
+        .expect_type(&["image/png", "application/octet-stream"])?
+        .bytes
+        .clone();
+    if !bytes.starts_with(PNG_SIGNATURE) {
+        return Err((StatusCode::UNSUPPORTED_MEDIA_TYPE, "".to_string()));
+    }
+
+    // decoding up to 4096x4096 pixels is CPU-bound, it runs off the async workers
+    tokio::task::spawn_blocking(move || decode(&bytes).map(|image| count_red(&image).to_string()))
+        .await
+        .map_err(|e| {
+            tracing::warn!("red pixels task: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "".to_string())
+        })?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, http::Request, routing::post, Router};
+    use http_body_util::BodyExt;
+    use image::Rgb;
+    use tower::ServiceExt;
+
+    const BOUNDARY: &str = "decoration";
+
+    fn png() -> Vec<u8> {
+        let mut image = RgbImage::from_pixel(4, 2, Rgb([10, 10, 10]));
+        image.put_pixel(0, 0, Rgb([200, 100, 99]));
+        image.put_pixel(1, 0, Rgb([200, 100, 100]));
+        image.put_pixel(2, 1, Rgb([255, 0, 0]));
+
+        let mut bytes = Cursor::new(Vec::new());
+        image.write_to(&mut bytes, ImageFormat::Png).unwrap();
+        bytes.into_inner()
+    }
+
+    fn upload(content_type: &str, content: &[u8]) -> Request<Body> {
+        let mut body = format!(
+            "--{b}\r\nContent-Disposition: form-data; name=\"image\"; filename=\"tree.png\"\r\nContent-Type: {ct}\r\n\r\n",
+            b = BOUNDARY,
+            ct = content_type
+        )
+        .into_bytes();
+        body.extend_from_slice(content);
+        body.extend_from_slice(format!("\r\n--{}--\r\n", BOUNDARY).as_bytes());
+
+        Request::builder()
+            .method("POST")
+            .uri("/11/red_pixels")
+            .header(
+                "content-type",
+                format!("multipart/form-data; boundary={}", BOUNDARY),
+            )
+            .body(Body::from(body))
+            .unwrap()
+    }
+
+    fn create_test_app() -> Router {
+        Router::new().route("/11/red_pixels", post(red_pixels))
+    }
+
+    #[tokio::test]
+    async fn test_red_pixels() {
+        let response = create_test_app()
+            .oneshot(upload("image/png", &png()))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(&body[..], b"2");
+    }
+
+    #[tokio::test]
+    async fn test_bad_uploads() {
+        let response = create_test_app()
+            .oneshot(upload("image/jpeg", &png()))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+        let response = create_test_app()
+            .oneshot(upload("image/png", b"not an image"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+        let mut truncated = png();
+        truncated.truncate(40);
+        let response = create_test_app()
+            .oneshot(upload("image/png", &truncated))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        let mut oversized = png();
+        oversized.resize(IMAGE_LIMITS.field + 1, 0);
+        let response = create_test_app()
+            .oneshot(upload("image/png", &oversized))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+}
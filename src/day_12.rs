@@ -1,36 +1,352 @@
 use core::{
-    clone::Clone, convert::From, fmt, iter::Iterator, ops::RangeInclusive, option::Option,
-    unreachable, write, writeln,
+    clone::Clone, convert::From, fmt, iter::Iterator, ops::RangeInclusive, option::Option, write,
 };
-use std::sync::Arc;
+use std::sync::{Arc, OnceLock};
 
-use rand::{Rng, SeedableRng};
+use chrono::{DateTime, Utc};
+#[cfg(test)]
+use mockall::automock;
+use rand::{Rng, RngCore, SeedableRng};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use sqlx::{query, query_as, query_scalar, FromRow, PgPool};
 
 use axum::{
-    extract::{Path, State},
-    http::StatusCode,
-    response::IntoResponse,
+    extract::{Path, Query, State},
+    http::{header, Method, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
 };
+use axum_extra::extract::CookieJar;
 use tokio::sync::Mutex;
+use uuid::Uuid;
+
+use crate::{
+    board_feed::BoardFeed,
+    day_23::escape_string,
+    dry_run::{DryRun, Preview},
+    i18n::{Language, Message},
+    links::{LinkBuilder, Links},
+    negotiate::{Accept, Format},
+    openapi::Operation,
+    ordering, outbox,
+    players::{self, PlayerRepository, RatedGame},
+    stats::STATS,
+    theme::{Theme, CLASSIC},
+    validation::{FromParams, Params, ValidatedQuery},
+};
+
+const SVG_CELL_SIZE: usize = 40;
+/// Outline of the cells of the winning line
+const SVG_HIGHLIGHT: &str = " stroke=\"#ffd700\" stroke-width=\"4\"";
+const HISTORY_LIMIT: i64 = 10;
+const MAX_HISTORY_LIMIT: i64 = 100;
+const EXPORT_VERSION: u32 = 1;
+const RANDOM_SEED: u64 = 2024;
+/// Highest draw index a rewind replays up to, a fraction of a second of work
+const MAX_REWIND: u64 = 10_000_000;
 
 #[derive(Clone)]
 pub struct BoardState {
     pub board: Arc<Mutex<Board>>,
     pub random_board: Arc<Mutex<RandomBoard>>,
+    pub results: Arc<dyn GameResultRepository>,
+    pub feed: BoardFeed,
+    pub players: Arc<dyn PlayerRepository>,
+}
+
+impl BoardState {
+    /// Both boards back to empty, the game on the board archived as `/12/reset` does
+    pub async fn clear(&self) {
+        let mut board = self.board.lock().await;
+        let mut random_board = self.random_board.lock().await;
+        archive(self.results.as_ref(), &board).await;
+        *board = Board::new();
+        *random_board = RandomBoard::new();
+        self.feed.publish(&board);
+    }
+}
+
+/// Game archived by a reset
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, FromRow)]
+pub struct GameResult {
+    pub id: i64,
+    /// Winning team, `tie`, or `abandoned` when the game was reset before its end
+    pub outcome: String,
+    pub moves: i32,
+    /// Final board, as emojis
+    pub board: String,
+    pub finished_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct NewGameResult {
+    pub outcome: String,
+    pub moves: i32,
+    pub board: String,
+}
+
+#[async_trait::async_trait]
+#[cfg_attr(test, automock)]
+pub trait GameResultRepository: Send + Sync + 'static {
+    async fn archive(&self, result: NewGameResult) -> Result<(), sqlx::Error>;
+    /// Most recent games first
+    async fn recent(&self, limit: i64) -> Result<Vec<GameResult>, sqlx::Error>;
+    async fn count(&self) -> Result<i64, sqlx::Error>;
+}
+
+pub struct PostgresGameResultRepository {
+    pool: PgPool,
+}
+
+impl PostgresGameResultRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl GameResultRepository for PostgresGameResultRepository {
+    async fn archive(&self, result: NewGameResult) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        let result = query_as::<_, GameResult>(
+            "INSERT INTO game_results (outcome, moves, board) VALUES ($1, $2, $3) RETURNING *",
+        )
+        .bind(result.outcome)
+        .bind(result.moves)
+        .bind(result.board)
+        .fetch_one(&mut *tx)
+        .await?;
+        outbox::enqueue(&mut tx, outbox::GAME_COMPLETED, &result).await?;
+        tx.commit().await
+    }
+
+    async fn recent(&self, limit: i64) -> Result<Vec<GameResult>, sqlx::Error> {
+        query_as::<_, GameResult>(&format!(
+            "SELECT * FROM game_results ORDER BY {} LIMIT $1",
+            ordering::GAME_RESULTS
+        ))
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    async fn count(&self) -> Result<i64, sqlx::Error> {
+        query_scalar::<_, i64>("SELECT COUNT(*) FROM game_results")
+            .fetch_one(&self.pool)
+            .await
+    }
+}
+
+#[derive(Deserialize)]
+pub struct History {
+    limit: Option<i64>,
+}
+
+/// Shape of `/12/random-board`, the challenge's full board when neither is given
+pub struct RandomFill {
+    /// Probability that a playable cell is empty, the tiles then fall to the bottom of their column
+    density: Option<f64>,
+    /// Probability that a tile is a cookie rather than a milk
+    bias: Option<f64>,
+}
+
+impl FromParams for RandomFill {
+    fn from_params(params: &mut Params) -> Option<Self> {
+        let mut probability = |name| {
+            let p = params.optional::<f64>(name);
+            if p.is_some_and(|p| !p.is_finite()) {
+                params.invalid(name, "not a finite number");
+            }
+            p
+        };
+        Some(Self {
+            density: probability("density"),
+            bias: probability("bias"),
+        })
+    }
+}
+
+impl RandomFill {
+    /// Probabilities of an empty cell and of a cookie, clamped between 0 and 1
+    fn probabilities(&self) -> Option<(f64, f64)> {
+        if self.density.is_none() && self.bias.is_none() {
+            return None;
+        }
+        let density = self.density.unwrap_or(0.0);
+        let bias = self.bias.unwrap_or(0.5);
+        Some((density.clamp(0.0, 1.0), bias.clamp(0.0, 1.0)))
+    }
+}
+
+#[derive(Deserialize)]
+pub struct Since {
+    game: Uuid,
+    since: usize,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Board {
+    /// Changes whenever the board starts over, so that diffs aren't applied across games
+    #[serde(default = "Uuid::new_v4")]
+    game: Uuid,
     tiles: Vec<Vec<Tile>>,
     winner: Option<Winner>,
+    /// Moves of the game in order, snapshots from before the history was kept have none
+    #[serde(default)]
+    moves: Vec<Move>,
+    #[serde(default)]
+    seats: Seats,
+    /// Cells that won the game, none on a tie
+    #[serde(default)]
+    line: Option<Line>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Cell {
+    pub row: usize,
+    pub column: usize,
+}
+
+/// A full row, column or diagonal of the same team
+type Line = Vec<Cell>;
+
+/// Registered players of the game, the first one to play a team takes its seat
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct Seats {
+    cookie: Option<String>,
+    milk: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Move {
+    row: usize,
+    column: usize,
+    team: Team,
+}
+
+/// JSON Patch operation (RFC 6902) on the JSON view of the board
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PatchOp {
+    pub op: String,
+    pub path: String,
+    pub value: serde_json::Value,
+}
+
+impl PatchOp {
+    fn replace(path: String, value: serde_json::Value) -> Self {
+        Self {
+            op: "replace".to_string(),
+            path,
+            value,
+        }
+    }
+}
+
+/// Changes since a move, to apply on a board view that was up to date at that move
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BoardDiff {
+    /// Game the moves were played in, the `game` of the next poll
+    pub game: Uuid,
+    /// Moves played so far, the `since` of the next poll
+    pub moves: usize,
+    pub patch: Vec<PatchOp>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExportConfig {
+    pub rows: usize,
+    pub columns: usize,
+}
+
+/// Game as served by `/12/export` and restored by `/12/import`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GameExport {
+    pub version: u32,
+    pub config: ExportConfig,
+    /// Tile names, top row first
+    pub tiles: Vec<Vec<String>>,
+    /// Moves in order, may be empty for a game played before they were recorded
+    pub moves: Vec<Move>,
 }
 
 pub struct RandomBoard {
     board: Board,
-    seed: rand::rngs::StdRng,
+    seed: CountingRng,
+}
+
+/// Seeded generator of the random board, which counts its draws so it can be rewound to one
+struct CountingRng {
+    rng: rand::rngs::StdRng,
+    /// 32 bit words drawn so far
+    draws: u64,
+}
+
+impl CountingRng {
+    fn rewound(draws: u64) -> Self {
+        let mut rng = Self {
+            rng: rand::rngs::StdRng::seed_from_u64(RANDOM_SEED),
+            draws: 0,
+        };
+        for _ in 0..draws {
+            rng.next_u32();
+        }
+        rng
+    }
+}
+
+impl RngCore for CountingRng {
+    fn next_u32(&mut self) -> u32 {
+        self.draws += 1;
+        self.rng.next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.draws += 2;
+        self.rng.next_u64()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.draws += dest.len().div_ceil(4) as u64;
+        self.rng.fill_bytes(dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+/// Position of the random board generator in its seeded sequence
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RngState {
+    pub seed: u64,
+    pub draws: u64,
+}
+
+#[derive(Deserialize)]
+pub struct Rewind {
+    draws: u64,
+}
+
+/// Side of the square of playable cells on the challenge's board
+pub const BOARD_SIZE: usize = 4;
+/// Shape of the boards of the process, set once at startup
+static BOARD_CONFIG: OnceLock<BoardConfig> = OnceLock::new();
+
+/// Shape of every board, a square of playable cells walled on its sides and bottom
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoardConfig {
+    /// Cells of a row or column of play, and of a winning line
+    pub size: usize,
+}
+
+impl Default for BoardConfig {
+    fn default() -> Self {
+        Self { size: BOARD_SIZE }
+    }
 }
-struct BoardConfig {}
 
 #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
@@ -40,7 +356,8 @@ enum Tile {
     Wall,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 enum Winner {
     Team(Team),
     Tie,
@@ -55,28 +372,94 @@ pub enum Team {
 
 impl fmt::Display for Tile {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let s = match self {
-            Tile::Team(t) => match t {
-                Team::Cookie => "🍪",
-                Team::Milk => "🥛",
-            },
-            Tile::Empty => "⬛",
-            Tile::Wall => "⬜",
-        };
-        write!(f, "{}", s)
+        write!(f, "{}", self.symbol(&CLASSIC))
     }
 }
 
 impl fmt::Display for Winner {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let s = match self {
-            Winner::Team(t) => format!("{} wins!", Tile::Team(*t)),
-            Winner::Tie => "No winner.".to_string(),
-        };
-        write!(f, "{}", s)
+        write!(f, "{}", self.banner(Language::English, Some(&CLASSIC)))
     }
 }
 
+impl Tile {
+    fn symbol(&self, theme: &Theme) -> &'static str {
+        match self {
+            Tile::Team(Team::Cookie) => theme.cookie,
+            Tile::Team(Team::Milk) => theme.milk,
+            Tile::Empty => theme.empty,
+            Tile::Wall => theme.wall,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Tile::Team(Team::Cookie) => "cookie",
+            Tile::Team(Team::Milk) => "milk",
+            Tile::Empty => "empty",
+            Tile::Wall => "wall",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "cookie" => Some(Tile::Team(Team::Cookie)),
+            "milk" => Some(Tile::Team(Team::Milk)),
+            "empty" => Some(Tile::Empty),
+            "wall" => Some(Tile::Wall),
+            _ => None,
+        }
+    }
+
+    fn svg_color(&self) -> &'static str {
+        match self {
+            Tile::Team(Team::Cookie) => "#c68642",
+            Tile::Team(Team::Milk) => "#a7d8f0",
+            Tile::Empty => "#000000",
+            Tile::Wall => "#eeeeee",
+        }
+    }
+}
+
+impl Winner {
+    /// Result announcement, naming the team by its symbol in the theme or by its name
+    fn banner(&self, language: Language, theme: Option<&Theme>) -> String {
+        match (self, theme) {
+            (Winner::Team(t), Some(theme)) => {
+                Message::Wins(Tile::Team(*t).symbol(theme)).text(language)
+            }
+            (Winner::Team(t), None) => Message::Wins(Tile::Team(*t).name()).text(language),
+            (Winner::Tie, _) => Message::NoWinner.text(language),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Winner::Team(t) => Tile::Team(*t).name(),
+            Winner::Tie => "tie",
+        }
+    }
+}
+
+/// JSON representation of the board
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct BoardView {
+    /// Game shown, the `game` of a first `/12/board/diff` poll
+    #[serde(default)]
+    pub game: Uuid,
+    pub tiles: Vec<Vec<String>>,
+    pub winner: Option<String>,
+    /// Moves that are still possible, one per team and free column
+    #[serde(default)]
+    pub links: Links,
+    /// Clients following `/12/board/events`
+    #[serde(default)]
+    pub spectators: usize,
+    /// Cells that won the game, empty while it goes on or on a tie
+    #[serde(default)]
+    pub winning_cells: Vec<Cell>,
+}
+
 impl From<Team> for Tile {
     fn from(value: Team) -> Self {
         match value {
@@ -86,26 +469,36 @@ impl From<Team> for Tile {
     }
 }
 
-impl From<Tile> for Winner {
-    fn from(value: Tile) -> Self {
-        match value {
-            Tile::Team(t) => Self::Team(t),
-            // winner should be printed only if it's cookie or milk
-            _ => unreachable!(),
+impl BoardConfig {
+    /// Makes this the shape of the boards of the process, before the first one is built
+    pub fn install(self) {
+        if BOARD_CONFIG.set(self).is_err() {
+            tracing::warn!("ignoring the board shape, one is already installed");
         }
     }
-}
 
-impl BoardConfig {
-    pub const ROWS: usize = 5;
-    pub const COLUMNS: usize = 6;
+    fn current() -> Self {
+        *BOARD_CONFIG.get_or_init(Self::default)
+    }
+
+    pub fn rows() -> usize {
+        Self::current().size + 1
+    }
+
+    pub fn columns() -> usize {
+        Self::current().size + 2
+    }
 
     fn playable_rows() -> RangeInclusive<usize> {
-        RangeInclusive::new(0, 3)
+        RangeInclusive::new(0, Self::current().size - 1)
     }
 
     fn playable_columns() -> RangeInclusive<usize> {
-        RangeInclusive::new(1, 4)
+        RangeInclusive::new(1, Self::current().size)
+    }
+
+    fn is_wall(row: usize, column: usize) -> bool {
+        row == Self::rows() - 1 || column == 0 || column == Self::columns() - 1
     }
 }
 
@@ -113,63 +506,193 @@ impl RandomBoard {
     fn new() -> Self {
         RandomBoard {
             board: Board::new(),
-            seed: rand::rngs::StdRng::seed_from_u64(2024),
+            seed: CountingRng::rewound(0),
+        }
+    }
+
+    fn state(&self) -> RngState {
+        RngState {
+            seed: RANDOM_SEED,
+            draws: self.seed.draws,
         }
     }
 
     // TODO find a way to unify this and Board::new()
-    fn randomize_board(&mut self) {
-        self.board.tiles = (0..BoardConfig::ROWS)
+    fn randomize_board(&mut self, probabilities: Option<(f64, f64)>) {
+        self.board.tiles = (0..BoardConfig::rows())
             .map(|i| {
-                (0..BoardConfig::COLUMNS)
+                (0..BoardConfig::columns())
                     .map(|j| match (i, j) {
-                        (0..=3, 0 | 5) => Tile::Wall,
-                        (4, _) => Tile::Wall,
-                        _ => match self.seed.gen::<bool>() {
-                            true => Tile::Team(Team::Cookie),
-                            false => Tile::Team(Team::Milk),
+                        _ if BoardConfig::is_wall(i, j) => Tile::Wall,
+                        _ => match probabilities {
+                            // the challenge expects this exact sequence for its seed
+                            None => match self.seed.gen::<bool>() {
+                                true => Tile::Team(Team::Cookie),
+                                false => Tile::Team(Team::Milk),
+                            },
+                            Some((empty, _)) if self.seed.gen_bool(empty) => Tile::Empty,
+                            Some((_, cookie)) => match self.seed.gen_bool(cookie) {
+                                true => Tile::Team(Team::Cookie),
+                                false => Tile::Team(Team::Milk),
+                            },
                         },
                     })
                     .collect()
             })
             .collect();
+        self.board.drop_tiles();
     }
 }
 
 impl fmt::Display for Board {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_text(Language::English, &CLASSIC))
+    }
+}
+
+impl Board {
+    fn to_text(&self, language: Language, theme: &Theme) -> String {
         let board = &self
             .tiles
             .iter()
             .map(|row| {
                 row.iter()
-                    .map(|tile| tile.to_string())
-                    .collect::<Vec<String>>()
+                    .map(|tile| tile.symbol(theme))
+                    .collect::<Vec<&str>>()
                     .join("")
             })
             .collect::<Vec<String>>()
             .join("\n");
 
         match &self.winner {
-            Some(w) => writeln!(f, "{}\n{}", board.trim(), w),
-            _ => writeln!(f, "{}", board.trim()),
+            Some(w) => format!("{}\n{}\n", board.trim(), w.banner(language, Some(theme))),
+            _ => format!("{}\n", board.trim()),
         }
     }
-}
 
-impl Board {
-    fn new() -> Self {
+    fn render(
+        &self,
+        format: Format,
+        links: Links,
+        language: Language,
+        theme: &Theme,
+        spectators: usize,
+    ) -> String {
+        match format {
+            Format::Json => self.to_json(links, spectators),
+            Format::Svg => self.to_svg(language),
+            Format::Html => self.to_html(language, theme),
+            _ => self.to_text(language, theme),
+        }
+    }
+
+    pub(crate) fn to_json(&self, links: Links, spectators: usize) -> String {
+        let view = BoardView {
+            game: self.game,
+            tiles: self
+                .tiles
+                .iter()
+                .map(|row| row.iter().map(|t| t.name().to_string()).collect())
+                .collect(),
+            winner: self.winner.as_ref().map(|w| w.name().to_string()),
+            links,
+            spectators,
+            winning_cells: self.winning_cells(),
+        };
+        serde_json::to_string(&view).unwrap()
+    }
+
+    fn winning_cells(&self) -> Vec<Cell> {
+        self.line.clone().unwrap_or_default()
+    }
+
+    /// HTML fragment, meant to be swapped into a page by htmx
+    pub(crate) fn to_html(&self, language: Language, theme: &Theme) -> String {
+        let rows = self
+            .tiles
+            .iter()
+            .map(|row| {
+                let cells = row
+                    .iter()
+                    .map(|tile| {
+                        format!("<td class=\"{}\">{}</td>", tile.name(), tile.symbol(theme))
+                    })
+                    .collect::<String>();
+                format!("<tr>{}</tr>", cells)
+            })
+            .collect::<String>();
+
+        let winner = match &self.winner {
+            Some(w) => format!(
+                "<p class=\"winner\">{}</p>",
+                escape_string(&w.banner(language, Some(theme)))
+            ),
+            _ => "".to_string(),
+        };
+
+        format!("<div id=\"board\"><table>{}</table>{}</div>", rows, winner)
+    }
+
+    fn to_svg(&self, language: Language) -> String {
+        let width = BoardConfig::columns() * SVG_CELL_SIZE;
+        // an extra row at the bottom hosts the winner banner
+        let height = (BoardConfig::rows() + 1) * SVG_CELL_SIZE;
+
+        let cells = self
+            .tiles
+            .iter()
+            .enumerate()
+            .flat_map(|(i, row)| {
+                row.iter().enumerate().map(move |(j, tile)| {
+                    let cell = Cell { row: i, column: j };
+                    let highlight = match &self.line {
+                        Some(line) if line.contains(&cell) => SVG_HIGHLIGHT,
+                        _ => "",
+                    };
+                    format!(
+                        "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\"{}/>",
+                        j * SVG_CELL_SIZE,
+                        i * SVG_CELL_SIZE,
+                        SVG_CELL_SIZE,
+                        SVG_CELL_SIZE,
+                        tile.svg_color(),
+                        highlight
+                    )
+                })
+            })
+            .collect::<String>();
+
+        let banner = match &self.winner {
+            Some(w) => escape_string(&w.banner(language, None)),
+            _ => "".to_string(),
+        };
+
+        format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\">{}<text x=\"{}\" y=\"{}\" text-anchor=\"middle\">{}</text></svg>",
+            width,
+            height,
+            cells,
+            width / 2,
+            height - SVG_CELL_SIZE / 2,
+            banner
+        )
+    }
+
+    pub(crate) fn new() -> Self {
         let mut b = Board {
-            tiles: vec![vec![Tile::Wall; BoardConfig::COLUMNS]; BoardConfig::ROWS],
+            game: Uuid::new_v4(),
+            tiles: vec![vec![Tile::Wall; BoardConfig::columns()]; BoardConfig::rows()],
             winner: None,
+            moves: vec![],
+            seats: Seats::default(),
+            line: None,
         };
 
-        b.tiles = (0..BoardConfig::ROWS)
+        b.tiles = (0..BoardConfig::rows())
             .map(|i| {
-                (0..BoardConfig::COLUMNS)
+                (0..BoardConfig::columns())
                     .map(|j| match (i, j) {
-                        (0..=3, 0 | 5) => Tile::Wall,
-                        (4, _) => Tile::Wall,
+                        _ if BoardConfig::is_wall(i, j) => Tile::Wall,
                         _ => Tile::Empty,
                     })
                     .collect()
@@ -178,6 +701,15 @@ impl Board {
         b
     }
 
+    /// Whether the tiles fit the installed board shape, a snapshot may come from another one
+    pub(crate) fn has_current_shape(&self) -> bool {
+        self.tiles.len() == BoardConfig::rows()
+            && self
+                .tiles
+                .iter()
+                .all(|row| row.len() == BoardConfig::columns())
+    }
+
     fn board_full(&self) -> bool {
         !self
             .tiles
@@ -185,6 +717,103 @@ impl Board {
             .any(|r| r.iter().any(|c| c == &Tile::Empty))
     }
 
+    fn export(&self) -> GameExport {
+        GameExport {
+            version: EXPORT_VERSION,
+            config: ExportConfig {
+                rows: BoardConfig::rows(),
+                columns: BoardConfig::columns(),
+            },
+            tiles: self
+                .tiles
+                .iter()
+                .map(|row| row.iter().map(|t| t.name().to_string()).collect())
+                .collect(),
+            moves: self.moves.clone(),
+        }
+    }
+
+    /// Rebuilds a game from an export, replaying its moves when it has any
+    fn import(export: &GameExport) -> Result<Self, String> {
+        if export.version != EXPORT_VERSION {
+            return Err(format!("unsupported version {}", export.version));
+        }
+        if export.config.rows != BoardConfig::rows()
+            || export.config.columns != BoardConfig::columns()
+        {
+            return Err("board size differs from this server's".to_string());
+        }
+        let tiles = export
+            .tiles
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|name| Tile::from_name(name).ok_or(format!("unknown tile {}", name)))
+                    .collect::<Result<Vec<_>, _>>()
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut board = Board::new();
+        if tiles.len() != BoardConfig::rows()
+            || tiles.iter().any(|row| row.len() != BoardConfig::columns())
+        {
+            return Err("tiles don't match the board size".to_string());
+        }
+        for (i, m) in export.moves.iter().enumerate() {
+            if board.winner.is_some() {
+                return Err(format!("move {} comes after the end of the game", i));
+            }
+            if !BoardConfig::playable_columns().contains(&m.column)
+                || board.free_spot(&m.column) != Some(m.row)
+            {
+                return Err(format!("move {} can't be played", i));
+            }
+            board.place_team(&m.team, &m.row, &m.column);
+            board.set_winner();
+        }
+
+        if export.moves.is_empty() {
+            // nothing to replay, the position itself has to be reachable
+            let mut settled = Board {
+                tiles,
+                ..Board::new()
+            };
+            let structure = |t: &Tile| matches!(t, Tile::Wall);
+            let walls_match = settled
+                .tiles
+                .iter()
+                .flatten()
+                .zip(board.tiles.iter().flatten())
+                .all(|(a, b)| structure(a) == structure(b));
+            let tiles = settled.tiles.clone();
+            settled.drop_tiles();
+            if !walls_match || settled.tiles != tiles {
+                return Err("tiles aren't a reachable position".to_string());
+            }
+            settled.set_winner();
+            return Ok(settled);
+        }
+
+        if board.tiles != tiles {
+            return Err("tiles don't match the moves".to_string());
+        }
+        Ok(board)
+    }
+
+    /// Lets the tiles of every column fall over the empty cells below them
+    fn drop_tiles(&mut self) {
+        for col in BoardConfig::playable_columns() {
+            let tiles: Vec<Tile> = BoardConfig::playable_rows()
+                .rev()
+                .map(|row| self.tiles[row][col])
+                .filter(|t| *t != Tile::Empty)
+                .collect();
+            for (i, row) in BoardConfig::playable_rows().rev().enumerate() {
+                self.tiles[row][col] = tiles.get(i).copied().unwrap_or(Tile::Empty);
+            }
+        }
+    }
+
     /// Given the column index, returns the lowest empty tile in the board
     fn free_spot(&self, col: &usize) -> Option<usize> {
         self.tiles
@@ -195,26 +824,128 @@ impl Board {
             .map(|(i, _)| i)
     }
 
+    /// Result worth archiving, `None` while no tile has been placed
+    fn result(&self) -> Option<NewGameResult> {
+        let moves = self.placed();
+        (moves > 0).then(|| NewGameResult {
+            outcome: self
+                .winner
+                .as_ref()
+                .map_or("abandoned", |w| w.name())
+                .to_string(),
+            moves: moves as i32,
+            board: self
+                .to_text(Language::English, &CLASSIC)
+                .trim_end()
+                .to_string(),
+        })
+    }
+
+    /// Number of tiles taken by a team
+    fn placed(&self) -> usize {
+        self.tiles
+            .iter()
+            .flatten()
+            .filter(|t| matches!(t, Tile::Team(_)))
+            .count()
+    }
+
     fn place_team(&mut self, team: &Team, row: &usize, col: &usize) {
         self.tiles[*row][*col] = Tile::from(*team);
+        self.moves.push(Move {
+            row: *row,
+            column: *col,
+            team: *team,
+        });
     }
 
-    fn set_winner(&mut self) {
-        // check if there are 4 equal elements on any row
-        self.winner = self.winner_on_row();
-        if self.winner.is_some() {
-            return;
+    /// Seats the player on the team, unless it's taken or they already play the other one
+    fn seat(&mut self, team: Team, player: String) {
+        let Seats { cookie, milk } = &mut self.seats;
+        let (seat, other) = match team {
+            Team::Cookie => (cookie, milk),
+            Team::Milk => (milk, cookie),
+        };
+        if seat.is_none() && other.as_ref() != Some(&player) {
+            *seat = Some(player);
         }
+    }
 
-        // check if there are 4 equal elements on any column
-        self.winner = self.winner_on_column();
-        if self.winner.is_some() {
-            return;
+    /// Game to rate, once over and if both teams were played by registered players
+    fn rated(&self) -> Option<RatedGame> {
+        let winner = match self.winner.as_ref()? {
+            Winner::Team(t) => Some(*t),
+            Winner::Tie => None,
+        };
+        Some(RatedGame {
+            cookie: self.seats.cookie.clone()?,
+            milk: self.seats.milk.clone()?,
+            winner,
+        })
+    }
+
+    fn diff(&self, game: Uuid, since: usize) -> BoardDiff {
+        let winner = || {
+            PatchOp::replace(
+                "/winner".to_string(),
+                serde_json::json!(self.winner.as_ref().map(|w| w.name())),
+            )
+        };
+        let winning_cells = || {
+            PatchOp::replace(
+                "/winning_cells".to_string(),
+                serde_json::json!(self.winning_cells()),
+            )
+        };
+
+        let seen = self.moves.get(since..).filter(|_| game == self.game);
+        let patch = match seen {
+            Some(moves) => {
+                let mut patch: Vec<_> = moves
+                    .iter()
+                    .map(|m| {
+                        PatchOp::replace(
+                            format!("/tiles/{}/{}", m.row, m.column),
+                            serde_json::json!(Tile::from(m.team).name()),
+                        )
+                    })
+                    .collect();
+                if !moves.is_empty() && self.winner.is_some() {
+                    patch.extend([winner(), winning_cells()]);
+                }
+                patch
+            }
+            // the board was reset or replaced since, the client gets it whole
+            None => {
+                let tiles: Vec<Vec<&str>> = self
+                    .tiles
+                    .iter()
+                    .map(|row| row.iter().map(Tile::name).collect())
+                    .collect();
+                vec![
+                    PatchOp::replace("/tiles".to_string(), serde_json::json!(tiles)),
+                    winner(),
+                    winning_cells(),
+                ]
+            }
+        };
+
+        BoardDiff {
+            game: self.game,
+            moves: self.moves.len(),
+            patch,
         }
+    }
 
-        // check if there are 4 equal elements on the first diagonal
-        self.winner = self.winner_on_diagonal();
-        if self.winner.is_some() {
+    fn set_winner(&mut self) {
+        // check if a whole row, then column, then diagonal is held by a team
+        let won = self
+            .winner_on_row()
+            .or_else(|| self.winner_on_column())
+            .or_else(|| self.winner_on_diagonal());
+        if let Some((winner, line)) = won {
+            self.winner = Some(winner);
+            self.line = Some(line);
             return;
         }
 
@@ -224,116 +955,472 @@ impl Board {
         }
     }
 
-    fn winner_on_row(&self) -> Option<Winner> {
-        BoardConfig::playable_rows()
-            .find(|row| {
-                let row_tiles: Vec<&Tile> = BoardConfig::playable_columns()
-                    .map(|col| &self.tiles[*row][col])
-                    .collect();
-
-                row_tiles.iter().all(|&tile| tile == row_tiles[0]) && row_tiles[0] != &Tile::Empty
-            })
-            .and_then(|winning_row| match &self.tiles[winning_row][1] {
-                Tile::Team(team) => Some(Winner::Team(*team)),
-                _ => None,
-            })
+    fn winner_on_row(&self) -> Option<(Winner, Line)> {
+        BoardConfig::playable_rows().find_map(|row| {
+            self.winner_on_line(BoardConfig::playable_columns().map(|col| (row, col)))
+        })
     }
 
-    fn winner_on_column(&self) -> Option<Winner> {
+    fn winner_on_column(&self) -> Option<(Winner, Line)> {
         BoardConfig::playable_columns()
-            .find(|col| {
-                let column_tiles: Vec<&Tile> = BoardConfig::playable_rows()
-                    .map(|row| &self.tiles[row][*col])
-                    .collect();
+            .find_map(|col| self.winner_on_line(BoardConfig::playable_rows().map(|row| (row, col))))
+    }
 
-                column_tiles.iter().all(|&tile| tile == column_tiles[0])
-                    && column_tiles[0] != &Tile::Empty
-            })
-            .and_then(|winning_col| match &self.tiles[0][winning_col] {
-                Tile::Team(team) => Some(Winner::Team(*team)),
-                _ => None,
+    fn winner_on_diagonal(&self) -> Option<(Winner, Line)> {
+        self.winner_on_line(BoardConfig::playable_rows().zip(BoardConfig::playable_columns()))
+            .or_else(|| {
+                self.winner_on_line(
+                    BoardConfig::playable_rows().zip(BoardConfig::playable_columns().rev()),
+                )
             })
     }
 
-    fn winner_on_diagonal(&self) -> Option<Winner> {
-        let first_diagonal: Vec<&Tile> = BoardConfig::playable_rows()
-            .zip(BoardConfig::playable_columns())
-            .map(|(row, col)| &self.tiles[row][col])
-            .collect();
-        if first_diagonal.iter().all(|&tile| tile == first_diagonal[0])
-            && *first_diagonal[0] != Tile::Empty
-        {
-            return Some(Winner::from(*first_diagonal[0]));
+    /// The team holding all the cells, if any, along with the cells
+    fn winner_on_line(
+        &self,
+        cells: impl Iterator<Item = (usize, usize)>,
+    ) -> Option<(Winner, Line)> {
+        let line: Line = cells.map(|(row, column)| Cell { row, column }).collect();
+        let first = line.first()?;
+        match self.tiles[first.row][first.column] {
+            Tile::Team(team)
+                if line
+                    .iter()
+                    .all(|c| self.tiles[c.row][c.column] == Tile::Team(team)) =>
+            {
+                Some((Winner::Team(team), line))
+            }
+            _ => None,
         }
+    }
+}
 
-        let last_diagonal: Vec<&Tile> = BoardConfig::playable_rows()
-            .zip(BoardConfig::playable_columns().rev())
-            .map(|(row, col)| &self.tiles[row][col])
-            .collect();
-        if last_diagonal.iter().all(|&tile| tile == last_diagonal[0])
-            && *last_diagonal[0] != Tile::Empty
-        {
-            return Some(Winner::from(*last_diagonal[0]));
+/// Representation of the board preferred by the client, a 406 when there's none. Handlers
+/// changing the board negotiate before they do, so a refused response changes nothing.
+fn board_format(accept: &Accept) -> Result<Format, Response> {
+    accept
+        .negotiate(&[Format::Plain, Format::Json, Format::Html, Format::Svg])
+        .ok_or_else(|| StatusCode::NOT_ACCEPTABLE.into_response())
+}
+
+/// Renders the board in the negotiated representation
+fn board_response(
+    status: StatusCode,
+    board: &Board,
+    format: Format,
+    language: Language,
+    theme: &Theme,
+    links: Links,
+    spectators: usize,
+) -> Response {
+    (
+        status,
+        [(header::CONTENT_TYPE, format.mime())],
+        board.render(format, links, language, theme, spectators),
+    )
+        .into_response()
+}
+
+/// Place URLs of every column that still has room, none once the game is over
+fn place_links(board: &Board, mut links: LinkBuilder) -> Links {
+    if board.winner.is_some() {
+        return Links::new();
+    }
+
+    for column in BoardConfig::playable_columns() {
+        if board.free_spot(&column).is_none() {
+            continue;
         }
+        for team in ["cookie", "milk"] {
+            links = links.action(
+                &format!("place-{}-{}", team, column),
+                Method::POST,
+                &format!("/12/place/{}/{}", team, column),
+            );
+        }
+    }
+    links.build()
+}
 
-        None
+/// Games a dry run of `/12/reset` would clear
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Cleared {
+    /// Boards with at least a tile taken, out of the game and the random board
+    pub games_cleared: usize,
+    pub tiles_cleared: usize,
+}
+
+impl Cleared {
+    fn of(boards: &[&Board]) -> Self {
+        Self {
+            games_cleared: boards.iter().filter(|b| b.placed() > 0).count(),
+            tiles_cleared: boards.iter().map(|b| b.placed()).sum(),
+        }
     }
 }
 
-pub async fn reset(State(state): State<BoardState>) -> impl IntoResponse {
-    let mut board = state.board.lock().await;
-    *board = Board::new();
+pub fn operations() -> Vec<Operation> {
+    // every board is negotiated as text, JSON, HTML or SVG
+    let board = |operation: Operation| {
+        operation
+            .json_response::<BoardView>(StatusCode::OK, "The board")
+            .response(
+                StatusCode::NOT_ACCEPTABLE,
+                "No acceptable format for the board",
+            )
+    };
+    vec![
+        board(Operation::new(
+            Method::GET,
+            "/12/board",
+            "The current board",
+        )),
+        board(
+            Operation::new(Method::POST, "/12/reset", "Empties the board").query(
+                "dry_run",
+                "boolean",
+                "Only tells what would be cleared",
+            ),
+        ),
+        board(
+            Operation::new(
+                Method::POST,
+                "/12/place/:team/:column",
+                "Drops a tile in a column",
+            )
+            .response(
+                StatusCode::BAD_REQUEST,
+                "Unknown team or column out of range",
+            )
+            .json_response::<BoardView>(StatusCode::SERVICE_UNAVAILABLE, "The game is over"),
+        ),
+        board(
+            Operation::new(Method::GET, "/12/random-board", "A board filled at random")
+                .query(
+                    "density",
+                    "number",
+                    "Probability that a playable cell is empty",
+                )
+                .query("bias", "number", "Probability that a tile is a cookie")
+                .error(StatusCode::BAD_REQUEST, "Malformed probabilities"),
+        ),
+        Operation::new(
+            Method::GET,
+            "/12/board/diff",
+            "Moves played since a previous poll",
+        )
+        .required_query("game", "string", "Game the moves were seen in")
+        .required_query("since", "integer", "Moves already seen")
+        .media_response(
+            StatusCode::OK,
+            "application/json",
+            "JSON Patch of the board",
+        ),
+        Operation::new(
+            Method::GET,
+            "/12/history",
+            "Games played so far, latest first",
+        )
+        .query(
+            "limit",
+            "integer",
+            "Games returned, 10 by default and 100 at most",
+        )
+        .media_response(StatusCode::OK, "application/json", "The games"),
+        Operation::new(Method::GET, "/12/export", "The game, to be imported later").media_response(
+            StatusCode::OK,
+            "application/json",
+            "The exported game",
+        ),
+        board(
+            Operation::new(
+                Method::POST,
+                "/12/import",
+                "Replaces the game with an exported one",
+            )
+            .body("application/json")
+            .response(StatusCode::UNPROCESSABLE_ENTITY, "Not a playable game"),
+        ),
+    ]
+}
 
+pub async fn reset(
+    State(state): State<BoardState>,
+    Query(DryRun { dry_run }): Query<DryRun>,
+    accept: Accept,
+    language: Language,
+    theme: Theme,
+    links: LinkBuilder,
+) -> Response {
+    let mut board = state.board.lock().await;
     let mut random_board = state.random_board.lock().await;
+
+    if dry_run {
+        return Json(Preview::new(Cleared::of(&[&board, &random_board.board]))).into_response();
+    }
+    let format = match board_format(&accept) {
+        Ok(format) => format,
+        Err(response) => return response,
+    };
+
+    archive(state.results.as_ref(), &board).await;
+    *board = Board::new();
     *random_board = RandomBoard::new();
+    state.feed.publish(&board);
 
-    (StatusCode::OK, board.to_string())
+    board_response(
+        StatusCode::OK,
+        &board,
+        format,
+        language,
+        &theme,
+        place_links(&board, links),
+        state.feed.spectators(),
+    )
 }
 
-pub async fn board(State(BoardState { board, .. }): State<BoardState>) -> impl IntoResponse {
-    (StatusCode::OK, board.lock().await.to_string())
+/// Keeps the game about to be cleared, the reset goes on when archiving fails
+async fn archive(results: &dyn GameResultRepository, board: &Board) {
+    if let Some(result) = board.result() {
+        if let Err(e) = results.archive(result).await {
+            tracing::warn!("failed to archive the game: {}", e);
+        }
+    }
 }
 
-pub async fn random(
+pub async fn history(
+    State(state): State<BoardState>,
+    Query(history): Query<History>,
+) -> impl IntoResponse {
+    let limit = history
+        .limit
+        .unwrap_or(HISTORY_LIMIT)
+        .clamp(1, MAX_HISTORY_LIMIT);
+    match state.results.recent(limit).await {
+        Ok(games) => Ok((StatusCode::OK, Json(games))),
+        _ => Err((StatusCode::INTERNAL_SERVER_ERROR, "".to_string())),
+    }
+}
+
+pub async fn board(
+    State(BoardState { board, feed, .. }): State<BoardState>,
+    accept: Accept,
+    language: Language,
+    theme: Theme,
+    links: LinkBuilder,
+) -> impl IntoResponse {
+    let format = match board_format(&accept) {
+        Ok(format) => format,
+        Err(response) => return response,
+    };
+    let board = board.lock().await;
+    board_response(
+        StatusCode::OK,
+        &board,
+        format,
+        language,
+        &theme,
+        place_links(&board, links),
+        feed.spectators(),
+    )
+}
+
+pub async fn diff(
+    State(BoardState { board, .. }): State<BoardState>,
+    Query(Since { game, since }): Query<Since>,
+) -> impl IntoResponse {
+    Json(board.lock().await.diff(game, since))
+}
+
+pub async fn export(State(BoardState { board, .. }): State<BoardState>) -> impl IntoResponse {
+    Json(board.lock().await.export())
+}
+
+/// Replaces the game with an exported one, archiving it as a reset would
+pub async fn import(
+    State(state): State<BoardState>,
+    accept: Accept,
+    language: Language,
+    theme: Theme,
+    links: LinkBuilder,
+    Json(export): Json<GameExport>,
+) -> Response {
+    let format = match board_format(&accept) {
+        Ok(format) => format,
+        Err(response) => return response,
+    };
+    let imported = match Board::import(&export) {
+        Ok(board) => board,
+        Err(reason) => return (StatusCode::UNPROCESSABLE_ENTITY, reason).into_response(),
+    };
+
+    let mut board = state.board.lock().await;
+    archive(state.results.as_ref(), &board).await;
+    *board = imported;
+    state.feed.publish(&board);
+
+    board_response(
+        StatusCode::OK,
+        &board,
+        format,
+        language,
+        &theme,
+        place_links(&board, links),
+        state.feed.spectators(),
+    )
+}
+
+pub async fn random_state(
     State(BoardState { random_board, .. }): State<BoardState>,
 ) -> impl IntoResponse {
+    Json(random_board.lock().await.state())
+}
+
+/// Puts the random board generator back at a draw index, so a validator can be re-synced
+pub async fn rewind_random(
+    State(BoardState { random_board, .. }): State<BoardState>,
+    Json(Rewind { draws }): Json<Rewind>,
+) -> impl IntoResponse {
+    if draws > MAX_REWIND {
+        return Err((StatusCode::UNPROCESSABLE_ENTITY, "".to_string()));
+    }
+    let mut random_board = random_board.lock().await;
+    random_board.seed = CountingRng::rewound(draws);
+    Ok(Json(random_board.state()))
+}
+
+pub async fn random(
+    State(BoardState { random_board, .. }): State<BoardState>,
+    ValidatedQuery(fill): ValidatedQuery<RandomFill>,
+    accept: Accept,
+    language: Language,
+    theme: Theme,
+) -> Response {
+    let format = match board_format(&accept) {
+        Ok(format) => format,
+        Err(response) => return response,
+    };
     let mut random_board = random_board.lock().await;
-    random_board.randomize_board();
+    random_board.randomize_board(fill.probabilities());
 
-    (StatusCode::OK, random_board.board.to_string())
+    // moves can't be played on the random board
+    board_response(
+        StatusCode::OK,
+        &random_board.board,
+        format,
+        language,
+        &theme,
+        Links::new(),
+        0,
+    )
 }
 
 pub async fn place(
     State(state): State<BoardState>,
     Path((team, column)): Path<(Team, usize)>,
+    accept: Accept,
+    language: Language,
+    theme: Theme,
+    links: LinkBuilder,
+    jar: CookieJar,
 ) -> impl IntoResponse {
     // return if team does not exist
     if team != Team::Milk && team != Team::Cookie {
-        return (StatusCode::BAD_REQUEST, "".to_string());
+        return (StatusCode::BAD_REQUEST, "".to_string()).into_response();
     }
 
     // return if column is out of range
     if !BoardConfig::playable_columns().contains(&column) {
-        return (StatusCode::BAD_REQUEST, "".to_string());
+        return (StatusCode::BAD_REQUEST, "".to_string()).into_response();
     }
 
+    let format = match board_format(&accept) {
+        Ok(format) => format,
+        Err(response) => return response,
+    };
+
+    let Ok(player) = players::player(state.players.as_ref(), &jar).await else {
+        return (StatusCode::INTERNAL_SERVER_ERROR, "".to_string()).into_response();
+    };
+
     let mut board = state.board.lock().await;
 
     // return if game is over
     if board.winner.is_some() {
-        return (StatusCode::SERVICE_UNAVAILABLE, board.to_string());
+        return board_response(
+            StatusCode::SERVICE_UNAVAILABLE,
+            &board,
+            format,
+            language,
+            &theme,
+            Links::new(),
+            state.feed.spectators(),
+        );
     }
 
     // try to place the item
     match board.free_spot(&column) {
         Some(row) => {
             board.place_team(&team, &row, &column);
+            if let Some(player) = player {
+                board.seat(team, player);
+            }
             board.set_winner();
-            (StatusCode::OK, board.to_string())
+            if board.winner.is_some() {
+                STATS.game_finished();
+            }
+            if let Some(game) = board.rated() {
+                players::rate(state.players.as_ref(), game).await;
+            }
+            state.feed.publish(&board);
+            let links = place_links(&board, links);
+            let spectators = state.feed.spectators();
+            board_response(
+                StatusCode::OK,
+                &board,
+                format,
+                language,
+                &theme,
+                links,
+                spectators,
+            )
         }
         // column unavailable
-        _ => (StatusCode::SERVICE_UNAVAILABLE, board.to_string()),
+        _ => {
+            let links = place_links(&board, links);
+            board_response(
+                StatusCode::SERVICE_UNAVAILABLE,
+                &board,
+                format,
+                language,
+                &theme,
+                links,
+                state.feed.spectators(),
+            )
+        }
+    }
+}
+
+/// Game of up to `moves` random moves, as it would be archived, `None` without any move
+pub(crate) fn random_game(rng: &mut impl Rng, moves: usize) -> Option<NewGameResult> {
+    let mut board = Board::new();
+    let mut team = Team::Cookie;
+    for _ in 0..moves {
+        let free = BoardConfig::playable_columns()
+            .filter_map(|col| board.free_spot(&col).map(|row| (row, col)))
+            .collect::<Vec<_>>();
+        if board.winner.is_some() || free.is_empty() {
+            break;
+        }
+        let (row, col) = free[rng.gen_range(0..free.len())];
+        board.place_team(&team, &row, &col);
+        board.set_winner();
+        team = match team {
+            Team::Cookie => Team::Milk,
+            Team::Milk => Team::Cookie,
+        };
     }
+    board.result()
 }
 
 pub fn arc_board() -> Arc<Mutex<Board>> {
@@ -343,3 +1430,509 @@ pub fn arc_board() -> Arc<Mutex<Board>> {
 pub fn arc_random_board() -> Arc<Mutex<RandomBoard>> {
     Arc::new(Mutex::new(RandomBoard::new()))
 }
+
+pub fn state_game_results(pool: PgPool) -> Arc<dyn GameResultRepository> {
+    Arc::new(PostgresGameResultRepository::new(pool))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{extract::FromRequestParts, http::Request};
+    use axum_extra::extract::cookie::Cookie;
+
+    use super::*;
+    use crate::{
+        players::{MockPlayerRepository, PLAYER_COOKIE},
+        test_support::box_future,
+        theme::ASCII,
+    };
+
+    #[test]
+    fn test_board_json() {
+        let mut board = Board::new();
+        board.place_team(&Team::Cookie, &3, &1);
+
+        let json: serde_json::Value =
+            serde_json::from_str(&board.to_json(Links::new(), 0)).unwrap();
+        assert_eq!(json["tiles"][3][1], "cookie");
+        assert_eq!(json["tiles"][0][1], "empty");
+        assert_eq!(json["tiles"][4][0], "wall");
+        assert_eq!(json["winner"], serde_json::Value::Null);
+    }
+
+    #[test]
+    fn test_place_links() {
+        let mut board = Board::new();
+        for row in 0..BoardConfig::rows() - 1 {
+            board.place_team(&Team::Milk, &row, &4);
+        }
+
+        let links = place_links(&board, LinkBuilder::default());
+        assert_eq!(links["place-cookie-1"].href, "/12/place/cookie/1");
+        assert_eq!(links["place-milk-3"].method.as_deref(), Some("POST"));
+        assert!(!links.contains_key("place-milk-4"));
+
+        board.winner = Some(Winner::Team(Team::Milk));
+        assert!(place_links(&board, LinkBuilder::default()).is_empty());
+    }
+
+    #[test]
+    fn test_cleared() {
+        let mut board = Board::new();
+        board.place_team(&Team::Milk, &3, &2);
+        board.place_team(&Team::Cookie, &2, &2);
+
+        let cleared = Cleared::of(&[&board, &Board::new()]);
+        assert_eq!(cleared.games_cleared, 1);
+        assert_eq!(cleared.tiles_cleared, 2);
+    }
+
+    #[test]
+    fn test_result() {
+        assert_eq!(Board::new().result(), None);
+
+        let mut board = Board::new();
+        board.place_team(&Team::Milk, &3, &2);
+        let result = board.result().unwrap();
+        assert_eq!(result.outcome, "abandoned");
+        assert_eq!(result.moves, 1);
+        assert!(result.board.ends_with("⬜⬜⬜⬜⬜⬜"));
+
+        board.winner = Some(Winner::Team(Team::Milk));
+        assert_eq!(board.result().unwrap().outcome, "milk");
+    }
+
+    #[tokio::test]
+    async fn test_reset_archives_game() {
+        let mut results = MockGameResultRepository::new();
+        results
+            .expect_archive()
+            .withf(|r| r.outcome == "abandoned" && r.moves == 1)
+            .times(1)
+            .returning(|_| box_future(Ok(())));
+
+        let board = arc_board();
+        board.lock().await.place_team(&Team::Cookie, &3, &1);
+        let state = BoardState {
+            board: board.clone(),
+            random_board: arc_random_board(),
+            results: Arc::new(results),
+            feed: BoardFeed::default(),
+            players: Arc::new(MockPlayerRepository::new()),
+        };
+
+        let response = reset(
+            State(state),
+            Query(DryRun::default()),
+            Accept::default(),
+            Language::default(),
+            Theme::default(),
+            LinkBuilder::default(),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(board.lock().await.placed(), 0);
+    }
+
+    #[test]
+    fn test_seats() {
+        let mut board = Board::new();
+        board.seat(Team::Cookie, "Santa".to_string());
+        board.seat(Team::Cookie, "Rudolph".to_string());
+        board.seat(Team::Milk, "Santa".to_string());
+        assert_eq!(board.seats.cookie.as_deref(), Some("Santa"));
+        assert_eq!(board.seats.milk, None);
+
+        board.winner = Some(Winner::Tie);
+        assert_eq!(board.rated(), None);
+
+        board.seat(Team::Milk, "Rudolph".to_string());
+        assert_eq!(
+            board.rated(),
+            Some(RatedGame {
+                cookie: "Santa".to_string(),
+                milk: "Rudolph".to_string(),
+                winner: None,
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_winning_move_rates_players() {
+        let token = Uuid::new_v4();
+        let mut players = MockPlayerRepository::new();
+        players
+            .expect_by_token()
+            .withf(move |t| *t == token)
+            .returning(|_| box_future(Ok(Some("Santa".to_string()))));
+        players
+            .expect_record()
+            .withf(|g| g.cookie == "Santa" && g.milk == "Rudolph" && g.winner == Some(Team::Cookie))
+            .times(1)
+            .returning(|_| box_future(Ok(())));
+
+        let board = arc_board();
+        {
+            let mut board = board.lock().await;
+            for row in 1..=3 {
+                board.place_team(&Team::Cookie, &row, &1);
+            }
+            board.seat(Team::Milk, "Rudolph".to_string());
+        }
+        let state = BoardState {
+            board: board.clone(),
+            random_board: arc_random_board(),
+            results: Arc::new(MockGameResultRepository::new()),
+            feed: BoardFeed::default(),
+            players: Arc::new(players),
+        };
+
+        let response = place(
+            State(state),
+            Path((Team::Cookie, 1)),
+            Accept::default(),
+            Language::default(),
+            Theme::default(),
+            LinkBuilder::default(),
+            CookieJar::new().add(Cookie::new(PLAYER_COOKIE, token.to_string())),
+        )
+        .await
+        .into_response();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(board.lock().await.seats.cookie.as_deref(), Some("Santa"));
+    }
+
+    #[test]
+    fn test_diff() {
+        let mut board = Board::new();
+        board.place_team(&Team::Milk, &3, &2);
+        board.place_team(&Team::Cookie, &3, &3);
+
+        let game = board.game;
+        let diff = board.diff(game, 1);
+        assert_eq!(diff.game, game);
+        assert_eq!(diff.moves, 2);
+        assert_eq!(
+            diff.patch,
+            [PatchOp::replace(
+                "/tiles/3/3".to_string(),
+                serde_json::json!("cookie")
+            )]
+        );
+        assert!(board.diff(game, 2).patch.is_empty());
+
+        board.winner = Some(Winner::Team(Team::Cookie));
+        assert_eq!(board.diff(game, 1).patch[1].path, "/winner");
+
+        // a client still on a previous game
+        let stale = board.diff(game, 5);
+        assert_eq!(stale.patch[0].path, "/tiles");
+        assert_eq!(stale.patch[0].value[3][2], "milk");
+        assert_eq!(stale.patch[1].value, "cookie");
+
+        // the game started over and got as far as the one the client saw
+        let mut replay = Board::new();
+        replay.place_team(&Team::Cookie, &3, &2);
+        replay.place_team(&Team::Cookie, &3, &3);
+        let stale = replay.diff(game, 2);
+        assert_ne!(stale.game, game);
+        assert_eq!(stale.patch[0].path, "/tiles");
+        assert_eq!(stale.patch[0].value[3][2], "cookie");
+    }
+
+    #[test]
+    fn test_board_svg() {
+        let mut board = Board::new();
+        board.winner = Some(Winner::Tie);
+
+        let svg = board.to_svg(Language::default());
+        assert!(svg.starts_with("<svg"));
+        assert_eq!(
+            svg.matches("<rect").count(),
+            BoardConfig::rows() * BoardConfig::columns()
+        );
+        assert!(svg.contains("No winner."));
+    }
+
+    #[test]
+    fn test_winning_cells() {
+        let mut board = Board::new();
+        for column in 1..=4 {
+            board.place_team(&Team::Milk, &3, &column);
+            board.set_winner();
+        }
+        let cells = (1..=4)
+            .map(|column| Cell { row: 3, column })
+            .collect::<Vec<_>>();
+        assert_eq!(board.winning_cells(), cells);
+
+        let view: BoardView = serde_json::from_str(&board.to_json(Links::new(), 0)).unwrap();
+        assert_eq!(view.winner.as_deref(), Some("milk"));
+        assert_eq!(view.winning_cells, cells);
+
+        let svg = board.to_svg(Language::default());
+        assert_eq!(svg.matches(SVG_HIGHLIGHT).count(), 4);
+    }
+
+    #[test]
+    fn test_winning_diagonal() {
+        let mut board = Board::new();
+        for (row, column) in [(0, 4), (1, 3), (2, 2), (3, 1)] {
+            board.place_team(&Team::Cookie, &row, &column);
+        }
+        board.set_winner();
+        assert_eq!(board.winner.as_ref().map(Winner::name), Some("cookie"));
+        assert_eq!(board.winning_cells()[0], Cell { row: 0, column: 4 });
+    }
+
+    #[test]
+    fn test_random_fill() {
+        let fill = |density, bias| RandomFill { density, bias }.probabilities();
+        assert_eq!(fill(None, None), None);
+        assert_eq!(fill(Some(0.3), None), Some((0.3, 0.5)));
+        assert_eq!(fill(Some(-1.0), Some(2.0)), Some((0.0, 1.0)));
+
+        let mut random = RandomBoard::new();
+        random.randomize_board(Some((0.0, 1.0)));
+        assert!(random.board.board_full());
+        assert_eq!(random.board.placed(), 16);
+        assert!(random
+            .board
+            .tiles
+            .iter()
+            .flatten()
+            .all(|t| matches!(t, Tile::Team(Team::Cookie) | Tile::Wall)));
+
+        random.randomize_board(Some((1.0, 0.5)));
+        assert_eq!(random.board.placed(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_random_fill_params() {
+        let extract = |uri: &str| {
+            let (mut parts, _) = Request::builder().uri(uri).body(()).unwrap().into_parts();
+            async move { ValidatedQuery::<RandomFill>::from_request_parts(&mut parts, &()).await }
+        };
+        let ValidatedQuery(fill) = extract("/12/random-board?density=0.3").await.unwrap();
+        assert_eq!(fill.probabilities(), Some((0.3, 0.5)));
+        let rejection = extract("/12/random-board?density=NaN&bias=most")
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(rejection.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn test_random_board_unchanged_by_default() {
+        let mut random = RandomBoard::new();
+        random.randomize_board(None);
+        assert_eq!(
+            random.board.to_string(),
+            "⬜🍪🍪🍪🍪⬜\n⬜🥛🍪🍪🥛⬜\n⬜🥛🥛🥛🥛⬜\n⬜🍪🥛🍪🥛⬜\n⬜⬜⬜⬜⬜⬜\n"
+        );
+    }
+
+    #[test]
+    fn test_rewind_random_board() {
+        let mut random = RandomBoard::new();
+        random.randomize_board(None);
+        let first = random.board.to_string();
+        assert_eq!(random.state().draws, 16);
+        random.randomize_board(None);
+        let second = random.board.to_string();
+
+        random.seed = CountingRng::rewound(16);
+        random.randomize_board(None);
+        assert_eq!(random.board.to_string(), second);
+
+        random.seed = CountingRng::rewound(0);
+        random.randomize_board(None);
+        assert_eq!(random.board.to_string(), first);
+
+        // gen_bool draws 64 bits at a time
+        random.randomize_board(Some((0.0, 0.5)));
+        assert_eq!(random.state().draws, 16 + 2 * 16 * 2);
+    }
+
+    #[test]
+    fn test_drop_tiles() {
+        let mut board = Board::new();
+        board.tiles[0][2] = Tile::Team(Team::Milk);
+        board.tiles[2][2] = Tile::Team(Team::Cookie);
+        board.drop_tiles();
+        assert_eq!(board.tiles[3][2], Tile::Team(Team::Cookie));
+        assert_eq!(board.tiles[2][2], Tile::Team(Team::Milk));
+        assert_eq!(board.tiles[0][2], Tile::Empty);
+        assert_eq!(board.free_spot(&2), Some(1));
+    }
+
+    #[test]
+    fn test_export_round_trip() {
+        let mut board = Board::new();
+        for (team, column) in [(Team::Milk, 2), (Team::Cookie, 2), (Team::Milk, 3)] {
+            let row = board.free_spot(&column).unwrap();
+            board.place_team(&team, &row, &column);
+        }
+
+        let export = board.export();
+        let imported = Board::import(&export).unwrap();
+        assert_eq!(imported.export(), export);
+        assert_eq!(imported.to_string(), board.to_string());
+    }
+
+    #[test]
+    fn test_import_validation() {
+        let mut export = Board::new().export();
+        export.moves = vec![Move {
+            row: 0,
+            column: 1,
+            team: Team::Milk,
+        }];
+        assert_eq!(
+            Board::import(&export).unwrap_err(),
+            "move 0 can't be played"
+        );
+
+        // a tile without moves has to rest on the bottom of its column
+        let mut export = Board::new().export();
+        export.tiles[3][1] = "cookie".to_string();
+        assert!(Board::import(&export).is_ok());
+        export.tiles[1][2] = "milk".to_string();
+        assert!(Board::import(&export).is_err());
+
+        let mut export = Board::new().export();
+        export.tiles[3][0] = "empty".to_string();
+        assert!(Board::import(&export).is_err());
+        export.version = 2;
+        assert_eq!(Board::import(&export).unwrap_err(), "unsupported version 2");
+    }
+
+    #[test]
+    fn test_import_finished_game() {
+        let mut export = Board::new().export();
+        export.moves = (0..4)
+            .rev()
+            .map(|row| Move {
+                row,
+                column: 4,
+                team: Team::Cookie,
+            })
+            .collect();
+        export.tiles = Board::new().export().tiles;
+        for row in 0..4 {
+            export.tiles[row][4] = "cookie".to_string();
+        }
+        let board = Board::import(&export).unwrap();
+        assert_eq!(board.winner.as_ref().map(Winner::name), Some("cookie"));
+
+        export.moves.push(Move {
+            row: 3,
+            column: 1,
+            team: Team::Milk,
+        });
+        assert!(Board::import(&export).is_err());
+    }
+
+    #[test]
+    fn test_board_text_translated() {
+        let mut board = Board::new();
+        board.winner = Some(Winner::Team(Team::Cookie));
+
+        assert!(board.to_string().ends_with("\n🍪 wins!\n"));
+        assert!(board
+            .to_text(Language::Italian, &CLASSIC)
+            .ends_with("\n🍪 vince!\n"));
+    }
+
+    #[test]
+    fn test_board_themed() {
+        let mut board = Board::new();
+        board.place_team(&Team::Milk, &3, &2);
+        board.winner = Some(Winner::Team(Team::Milk));
+
+        assert_eq!(
+            board.to_text(Language::default(), &ASCII),
+            "#....#\n#....#\n#....#\n#.M..#\n######\nM wins!\n"
+        );
+        assert!(board
+            .to_html(Language::default(), &ASCII)
+            .contains("<td class=\"milk\">M</td>"));
+        // the classic theme is the challenge's output
+        assert_eq!(
+            board.to_text(Language::default(), &CLASSIC),
+            board.to_string()
+        );
+    }
+
+    #[test]
+    fn test_board_html() {
+        let mut board = Board::new();
+        board.place_team(&Team::Milk, &3, &2);
+
+        let html = board.to_html(Language::default(), &CLASSIC);
+        assert_eq!(html.matches("<tr>").count(), BoardConfig::rows());
+        assert!(html.contains("<td class=\"milk\">🥛</td>"));
+    }
+
+    #[test]
+    fn test_board_response_defaults_to_emoji() {
+        let board = Board::new();
+        let response = board_response(
+            StatusCode::OK,
+            &board,
+            board_format(&Accept::default()).unwrap(),
+            Language::default(),
+            &CLASSIC,
+            Links::new(),
+            0,
+        );
+        assert_eq!(
+            response.headers().get(header::CONTENT_TYPE).unwrap(),
+            Format::Plain.mime()
+        );
+    }
+
+    #[test]
+    fn test_board_response_not_acceptable() {
+        let response = board_format(&Accept::from_header("image/png")).unwrap_err();
+        assert_eq!(response.status(), StatusCode::NOT_ACCEPTABLE);
+    }
+
+    #[tokio::test]
+    async fn test_not_acceptable_leaves_the_board() {
+        let board = arc_board();
+        board.lock().await.place_team(&Team::Cookie, &3, &1);
+        let state = BoardState {
+            board: board.clone(),
+            random_board: arc_random_board(),
+            results: Arc::new(MockGameResultRepository::new()),
+            feed: BoardFeed::default(),
+            players: Arc::new(MockPlayerRepository::new()),
+        };
+
+        let response = place(
+            State(state.clone()),
+            Path((Team::Milk, 2)),
+            Accept::from_header("image/png"),
+            Language::default(),
+            Theme::default(),
+            LinkBuilder::default(),
+            CookieJar::new(),
+        )
+        .await
+        .into_response();
+        assert_eq!(response.status(), StatusCode::NOT_ACCEPTABLE);
+
+        let response = reset(
+            State(state),
+            Query(DryRun::default()),
+            Accept::from_header("image/png"),
+            Language::default(),
+            Theme::default(),
+            LinkBuilder::default(),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::NOT_ACCEPTABLE);
+        assert_eq!(board.lock().await.moves.len(), 1);
+    }
+}
@@ -1,42 +1,163 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{collections::HashSet, sync::Arc, time::Duration};
 
 use axum::{
-    extract::{Path, State},
-    http::StatusCode,
-    response::IntoResponse,
+    async_trait,
+    body::Bytes,
+    extract::{FromRef, FromRequestParts, Path, Query, State},
+    http::{header, request::Parts, HeaderMap, HeaderName, HeaderValue, Method, StatusCode},
+    response::{IntoResponse, Response},
     Json,
 };
-use axum_extra::extract::OptionalQuery;
+use axum_extra::extract::{CookieJar, OptionalQuery};
 use chrono::{DateTime, Utc};
 #[cfg(test)]
 use mockall::{automock, predicate::*};
-use rand::distributions::DistString;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use sqlx::{postgres::PgQueryResult, query, query_as, query_scalar, FromRow, PgPool};
-use tokio::sync::Mutex;
+use sqlx::{query, query_as, query_scalar, FromRow, PgConnection, PgPool, Postgres, Transaction};
 use uuid::Uuid;
 
-const PAGE_SIZE: i64 = 3;
+use crate::{
+    app_error::AppError,
+    citation::CitationFormat,
+    comments::Comment,
+    conventions,
+    day_12::GameResult,
+    dry_run::{DryRun, Preview, RowsAffected},
+    instrument,
+    links::{LinkBuilder, Linked},
+    moderation::{Moderator, Verdict},
+    negotiate::{Accept, Format},
+    openapi::Operation,
+    ordering, outbox,
+    players::{self, PlayerRepository},
+    preflight::RouteBudget,
+    settings::{QUOTES_PAGE_SIZE, SETTINGS},
+    stats::STATS,
+    tokens::{MemoryTokenStore, TokenStore},
+    uploads::{self, UploadRepository, UPLOAD_ID_HEADER},
+    validation::{FromParams, Params, RouteSchema, ValidatedQuery},
+};
+
+pub const PAGE_SIZE: i64 = 3;
+const BACKUP_VERSION: u32 = 2;
+const TOP_LIMIT: i64 = 10;
+const MAX_TOP_LIMIT: i64 = 100;
+const SUGGEST_LIMIT: i64 = 5;
+const MAX_SUGGEST_LIMIT: i64 = 20;
+/// Likes a restore dropped along with the quotes they were given to
+const DROPPED_LIKES_HEADER: &str = "x-dropped-likes";
+/// Comments a restore dropped along with the quotes of their threads
+const DROPPED_COMMENTS_HEADER: &str = "x-dropped-comments";
+/// Kind of the uploads of `/admin/restore`
+const RESTORE_UPLOAD: &str = "restore";
+const PUBLISH_INTERVAL: Duration = Duration::from_secs(10);
+/// Select items computed for a row of `quotes`
+const COMPUTED: &str = "(SELECT COUNT(*) FROM quote_likes WHERE quote_id = quotes.id) AS likes,
+    CASE WHEN pending_review THEN 'pending_review'
+        WHEN publish_at IS NULL OR publish_at <= now() THEN 'published'
+        ELSE 'draft' END AS status";
+/// Scheduled quotes stay hidden until their publication time, flagged ones until approved
+const PUBLISHED: &str = "(publish_at IS NULL OR publish_at <= now()) AND NOT pending_review";
 
 #[derive(Clone)]
 pub struct DbState {
     pub repository: Arc<dyn QuoteRepository>,
-    pub tokens: Arc<Mutex<HashMap<String, i64>>>,
+    pub tokens: Arc<dyn TokenStore>,
+    pub moderator: Arc<dyn Moderator>,
+    /// Quotes per page of `/19/list` set at startup, the `quotes.page_size` setting takes precedence
+    pub page_size: i64,
+    /// Registered players, the only clients whose likes are counted
+    pub players: Arc<dyn PlayerRepository>,
 }
 
-#[derive(Clone, Deserialize, Serialize, FromRow)]
+#[derive(Clone, Deserialize, Serialize, FromRow, JsonSchema)]
 pub struct Quote {
-    id: Uuid,
-    author: String,
-    quote: String,
-    created_at: DateTime<Utc>,
-    version: i32,
+    #[serde(with = "crate::conventions::uuid")]
+    #[schemars(with = "Uuid")]
+    pub id: Uuid,
+    pub author: String,
+    pub quote: String,
+    #[serde(with = "crate::conventions::datetime")]
+    #[schemars(with = "DateTime<Utc>")]
+    pub created_at: DateTime<Utc>,
+    pub version: i32,
+    /// Computed on read, backups from before likes existed have none
+    #[sqlx(default)]
+    #[serde(default)]
+    pub likes: i64,
+    /// Set while the quote is scheduled, cleared once its publication is announced
+    #[sqlx(default)]
+    #[serde(default, with = "crate::conventions::datetime::option")]
+    #[schemars(with = "Option<DateTime<Utc>>")]
+    pub publish_at: Option<DateTime<Utc>>,
+    #[sqlx(default, try_from = "String")]
+    #[serde(default)]
+    pub status: QuoteStatus,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum QuoteStatus {
+    Draft,
+    #[default]
+    Published,
+    /// Flagged by moderation, hidden until an admin approves it
+    PendingReview,
+}
+
+impl TryFrom<String> for QuoteStatus {
+    type Error = String;
+
+    fn try_from(status: String) -> Result<Self, Self::Error> {
+        conventions::from_tag(&status)
+    }
+}
+
+/// Quote held by moderation, along with why
+#[derive(Clone, Deserialize, Serialize, FromRow)]
+pub struct FlaggedQuote {
+    #[sqlx(flatten)]
+    #[serde(flatten)]
+    pub quote: Quote,
+    pub review_reason: Option<String>,
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct NewQuote {
-    author: String,
-    quote: String,
+    pub author: String,
+    pub quote: String,
+    /// Only honoured on creation, a time in the past publishes right away
+    #[serde(default, with = "crate::conventions::datetime::option")]
+    #[schemars(with = "Option<DateTime<Utc>>")]
+    pub publish_at: Option<DateTime<Utc>>,
+}
+
+const MAX_DRAFT_SIZE: u64 = 64 * 1024;
+
+pub fn budgets() -> Vec<RouteBudget> {
+    vec![RouteBudget::new(
+        Method::POST,
+        "/19/draft",
+        MAX_DRAFT_SIZE,
+        &["application/json"],
+    )]
+}
+
+pub fn schemas() -> Vec<RouteSchema> {
+    vec![RouteSchema::new(
+        Method::POST,
+        "/19/draft",
+        serde_json::json!({
+            "type": "object",
+            "required": ["author", "quote"],
+            "properties": {
+                "author": { "type": "string" },
+                "quote": { "type": "string" },
+                "publish_at": { "type": ["string", "null"], "format": "date-time" }
+            }
+        }),
+    )]
 }
 
 #[derive(Deserialize)]
@@ -44,11 +165,177 @@ pub struct Token {
     token: String,
 }
 
+#[derive(Deserialize)]
+pub struct Cite {
+    format: Option<CitationFormat>,
+}
+
+#[derive(Deserialize)]
+pub struct Top {
+    by: String,
+    limit: Option<i64>,
+}
+
+/// Published quotes to count, every filter is optional
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CountFilter {
+    pub author: Option<String>,
+    /// Inclusive lower bound on the creation time
+    pub from: Option<DateTime<Utc>>,
+    /// Exclusive upper bound on the creation time
+    pub to: Option<DateTime<Utc>>,
+}
+
+impl FromParams for CountFilter {
+    fn from_params(params: &mut Params) -> Option<Self> {
+        Some(Self {
+            author: params.optional("author"),
+            from: params.optional("from"),
+            to: params.optional("to"),
+        })
+    }
+}
+
+/// Quotes removed by `DELETE /19/quotes`, every filter is optional but one is required
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DeleteFilter {
+    pub author: Option<String>,
+    /// Exclusive upper bound on the creation time
+    pub created_before: Option<DateTime<Utc>>,
+}
+
+/// Unknown filters are refused rather than ignored, ignoring one would delete more than asked
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BulkDelete {
+    author: Option<String>,
+    created_before: Option<DateTime<Utc>>,
+    #[serde(default)]
+    dry_run: bool,
+    #[serde(default)]
+    confirm: bool,
+}
+
+#[derive(Clone)]
+pub struct RestoreState {
+    pub repository: Arc<dyn QuoteRepository>,
+    /// Where restored archives are kept, only with `STORE_UPLOADS` on
+    pub uploads: Option<Arc<dyn UploadRepository>>,
+}
+
+#[derive(Clone)]
+pub struct BulkDeleteState {
+    pub repository: Arc<dyn QuoteRepository>,
+    /// Deletions matching more quotes than this are rolled back
+    pub max_rows: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct Count {
+    pub count: i64,
+}
+
+#[derive(Deserialize)]
+pub struct Suggest {
+    q: String,
+    limit: Option<i64>,
+}
+
+/// Existing author spelling close to a searched name
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, FromRow)]
+pub struct AuthorMatch {
+    pub author: String,
+    /// Trigram similarity, from 0 to 1
+    pub similarity: f32,
+    pub quotes: i64,
+}
+
+/// Registered player liking a quote, known by the token of their player cookie. A header or
+/// cookie anyone can make up would let a client like a quote as many times as they want.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Liker(pub String);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for Liker
+where
+    DbState: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        // the jar never fails to extract
+        let jar = CookieJar::from_request_parts(parts, state).await.unwrap();
+        let players = DbState::from_ref(state).players;
+        match players::player(players.as_ref(), &jar).await? {
+            Some(name) => Ok(Self(format!("player:{}", name))),
+            None => Err(AppError::Unauthorized),
+        }
+    }
+}
+
+/// What a restore did, the likes and comments the backup doesn't bring back are dropped
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Restored {
+    pub quotes: u64,
+    pub dropped_likes: u64,
+    pub dropped_comments: u64,
+}
+
 #[derive(Deserialize, Serialize, FromRow)]
-struct Quotes {
-    quotes: Vec<Quote>,
-    page: i64,
-    next_token: Option<String>,
+pub struct Quotes {
+    pub quotes: Vec<Quote>,
+    pub page: i64,
+    pub next_token: Option<String>,
+}
+
+/// Quote of a backup, along with why moderation holds it when it does
+#[derive(Clone, Deserialize, Serialize, FromRow)]
+pub struct ArchivedQuote {
+    #[sqlx(flatten)]
+    #[serde(flatten)]
+    pub quote: Quote,
+    pub review_reason: Option<String>,
+}
+
+#[derive(Clone, Deserialize, Serialize, FromRow)]
+pub struct ArchivedLike {
+    pub quote_id: Uuid,
+    pub client_id: String,
+    #[serde(with = "crate::conventions::datetime")]
+    pub created_at: DateTime<Utc>,
+}
+
+/// Versioned archive of the quotes, their likes and comments, and the day 12 game results, as
+/// produced by `/admin/backup`
+#[derive(Deserialize, Serialize)]
+pub struct Backup {
+    version: u32,
+    quotes: Vec<ArchivedQuote>,
+    likes: Vec<ArchivedLike>,
+    comments: Vec<Comment>,
+    game_results: Vec<GameResult>,
+}
+
+impl Backup {
+    /// Likes and comments must belong to archived quotes, the restore would fail on them
+    fn validate(&self) -> Result<(), String> {
+        if self.version != BACKUP_VERSION {
+            return Err(format!("Unsupported backup version {}", self.version));
+        }
+        let quotes = self
+            .quotes
+            .iter()
+            .map(|q| q.quote.id)
+            .collect::<HashSet<_>>();
+        if let Some(like) = self.likes.iter().find(|l| !quotes.contains(&l.quote_id)) {
+            return Err(format!("like of unknown quote {}", like.quote_id));
+        }
+        if let Some(comment) = self.comments.iter().find(|c| !quotes.contains(&c.quote_id)) {
+            return Err(format!("comment on unknown quote {}", comment.quote_id));
+        }
+        Ok(())
+    }
 }
 
 #[async_trait::async_trait]
@@ -56,11 +343,67 @@ struct Quotes {
 pub trait QuoteRepository: Send + Sync + 'static {
     async fn get(&self, id: Uuid) -> Result<Quote, sqlx::Error>;
     async fn create(&self, new_quote: NewQuote) -> Result<Quote, sqlx::Error>;
-    async fn delete(&self, id: Uuid) -> Result<Quote, sqlx::Error>;
+    /// Destructive methods roll their transaction back on a dry run
+    async fn delete(&self, id: Uuid, dry_run: bool) -> Result<Quote, sqlx::Error>;
     async fn update(&self, id: Uuid, new_quote: NewQuote) -> Result<Quote, sqlx::Error>;
     async fn get_quotes(&self, offset: i64, limit: i64) -> Result<Vec<Quote>, sqlx::Error>;
     async fn count_quotes(&self) -> Result<i64, sqlx::Error>;
-    async fn reset_quotes(&self) -> Result<PgQueryResult, sqlx::Error>;
+    async fn count_matching(&self, filter: CountFilter) -> Result<i64, sqlx::Error>;
+    async fn reset_quotes(&self, dry_run: bool) -> Result<u64, sqlx::Error>;
+    async fn all_quotes(&self) -> Result<Vec<Quote>, sqlx::Error>;
+    /// Every archived table, as of the same instant
+    async fn backup(&self) -> Result<Backup, sqlx::Error>;
+    /// Replaces every archived table with the content of the backup
+    async fn restore(&self, backup: Backup, dry_run: bool) -> Result<Restored, sqlx::Error>;
+    /// Likes are idempotent, the quote is returned with its updated count
+    async fn like(&self, id: Uuid, client: String) -> Result<Quote, sqlx::Error>;
+    async fn unlike(&self, id: Uuid, client: String) -> Result<Quote, sqlx::Error>;
+    async fn top_liked(&self, limit: i64) -> Result<Vec<Quote>, sqlx::Error>;
+    /// Most similar authors first
+    async fn suggest_authors(
+        &self,
+        name: String,
+        limit: i64,
+    ) -> Result<Vec<AuthorMatch>, sqlx::Error>;
+    /// Marks the scheduled quotes whose time has come as published
+    async fn publish_due(&self) -> Result<Vec<Quote>, sqlx::Error>;
+    /// Unit of work for flows made of several changes that must all apply, or none
+    async fn begin(&self) -> Result<Box<dyn QuoteTransaction>, sqlx::Error>;
+    /// Creates the quote held for review, announcing nothing until it's approved
+    async fn create_flagged(
+        &self,
+        new_quote: NewQuote,
+        reason: String,
+    ) -> Result<Quote, sqlx::Error>;
+    /// Updates the quote and holds it for review. A quote that was visible is announced as
+    /// deleted, its new content is only announced once approved.
+    async fn update_flagged(
+        &self,
+        id: Uuid,
+        new_quote: NewQuote,
+        reason: String,
+    ) -> Result<Quote, sqlx::Error>;
+    /// Quotes flagged by moderation, oldest first
+    async fn pending_review(&self) -> Result<Vec<FlaggedQuote>, sqlx::Error>;
+    /// Clears the flag, the quote becomes visible unless it's scheduled for later
+    async fn approve(&self, id: Uuid) -> Result<Quote, sqlx::Error>;
+    /// Deletes a flagged quote
+    async fn reject(&self, id: Uuid) -> Result<Quote, sqlx::Error>;
+}
+
+/// Quote operations sharing one transaction, nothing is visible to others until `commit`.
+/// Dropping the handle without committing rolls everything back.
+#[async_trait::async_trait]
+#[cfg_attr(test, automock)]
+pub trait QuoteTransaction: Send {
+    async fn get(&mut self, id: Uuid) -> Result<Quote, sqlx::Error>;
+    async fn create(&mut self, new_quote: NewQuote) -> Result<Quote, sqlx::Error>;
+    async fn delete(&mut self, id: Uuid) -> Result<Quote, sqlx::Error>;
+    async fn update(&mut self, id: Uuid, new_quote: NewQuote) -> Result<Quote, sqlx::Error>;
+    /// Deletes every quote matching the filter, returning how many
+    async fn delete_matching(&mut self, filter: DeleteFilter) -> Result<u64, sqlx::Error>;
+    async fn commit(self: Box<Self>) -> Result<(), sqlx::Error>;
+    async fn rollback(self: Box<Self>) -> Result<(), sqlx::Error>;
 }
 
 pub struct PostgresQuoteRepository {
@@ -73,181 +416,1277 @@ impl PostgresQuoteRepository {
     }
 }
 
+// single-quote changes, shared by the repository and its transactions,
+// events are written in the same transaction as the change they describe
+
+async fn select_quote(conn: &mut PgConnection, id: Uuid) -> Result<Quote, sqlx::Error> {
+    query_as::<_, Quote>(&format!(
+        "SELECT *, {} FROM quotes WHERE id = $1 AND {}",
+        COMPUTED, PUBLISHED
+    ))
+    .bind(id)
+    .fetch_one(conn)
+    .await
+}
+
+async fn insert_quote(conn: &mut PgConnection, new_quote: NewQuote) -> Result<Quote, sqlx::Error> {
+    let publish_at = new_quote.publish_at.filter(|p| *p > Utc::now());
+    let quote = query_as::<_, Quote>(&format!(
+        "INSERT INTO quotes (id, author, quote, publish_at) VALUES ($1, $2, $3, $4) RETURNING *, {}",
+        COMPUTED
+    ))
+    .bind(Uuid::new_v4())
+    .bind(&new_quote.author)
+    .bind(&new_quote.quote)
+    .bind(publish_at)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    outbox::enqueue(conn, outbox::QUOTE_CREATED, &quote).await?;
+    Ok(quote)
+}
+
+async fn delete_quote(conn: &mut PgConnection, id: Uuid) -> Result<Quote, sqlx::Error> {
+    let quote = query_as::<_, Quote>(&format!(
+        "DELETE FROM quotes WHERE id = $1 RETURNING *, {}",
+        COMPUTED
+    ))
+    .bind(id)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    outbox::enqueue(conn, outbox::QUOTE_DELETED, &quote).await?;
+    Ok(quote)
+}
+
+async fn update_quote(
+    conn: &mut PgConnection,
+    id: Uuid,
+    new_quote: NewQuote,
+) -> Result<Quote, sqlx::Error> {
+    let quote = query_as::<_, Quote>(&format!(
+        "UPDATE quotes SET author = $2, quote = $3, version = version + 1 WHERE id = $1 RETURNING *, {}",
+        COMPUTED
+    ))
+    .bind(id)
+    .bind(&new_quote.author)
+    .bind(&new_quote.quote)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    outbox::enqueue(conn, outbox::QUOTE_UPDATED, &quote).await?;
+    Ok(quote)
+}
+
+async fn insert_flagged(
+    conn: &mut PgConnection,
+    new_quote: NewQuote,
+    reason: String,
+) -> Result<Quote, sqlx::Error> {
+    let publish_at = new_quote.publish_at.filter(|p| *p > Utc::now());
+    // held from the start, approval announces it
+    query_as::<_, Quote>(&format!(
+        "INSERT INTO quotes (id, author, quote, publish_at, pending_review, review_reason)
+         VALUES ($1, $2, $3, $4, TRUE, $5) RETURNING *, {}",
+        COMPUTED
+    ))
+    .bind(Uuid::new_v4())
+    .bind(&new_quote.author)
+    .bind(&new_quote.quote)
+    .bind(publish_at)
+    .bind(reason)
+    .fetch_one(conn)
+    .await
+}
+
+async fn update_flagged(
+    conn: &mut PgConnection,
+    id: Uuid,
+    new_quote: NewQuote,
+    reason: String,
+) -> Result<Quote, sqlx::Error> {
+    let visible = match select_quote(&mut *conn, id).await {
+        Ok(quote) => Some(quote),
+        Err(sqlx::Error::RowNotFound) => None,
+        Err(e) => return Err(e),
+    };
+    let quote = query_as::<_, Quote>(&format!(
+        "UPDATE quotes SET author = $2, quote = $3, version = version + 1,
+         pending_review = TRUE, review_reason = $4 WHERE id = $1 RETURNING *, {}",
+        COMPUTED
+    ))
+    .bind(id)
+    .bind(&new_quote.author)
+    .bind(&new_quote.quote)
+    .bind(reason)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    // read models learn that the quote is gone, from the content they already had
+    if let Some(visible) = visible {
+        outbox::enqueue(conn, outbox::QUOTE_DELETED, &visible).await?;
+    }
+    Ok(quote)
+}
+
+async fn delete_matching(
+    conn: &mut PgConnection,
+    filter: DeleteFilter,
+) -> Result<u64, sqlx::Error> {
+    let quotes = query_as::<_, Quote>(&format!(
+        "DELETE FROM quotes
+         WHERE ($1::TEXT IS NULL OR author = $1)
+         AND ($2::TIMESTAMPTZ IS NULL OR created_at < $2)
+         RETURNING *, {}",
+        COMPUTED
+    ))
+    .bind(filter.author)
+    .bind(filter.created_before)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    for quote in &quotes {
+        outbox::enqueue(conn, outbox::QUOTE_DELETED, quote).await?;
+    }
+    Ok(quotes.len() as u64)
+}
+
+pub struct PostgresQuoteTransaction {
+    tx: Transaction<'static, Postgres>,
+}
+
+#[async_trait::async_trait]
+impl QuoteTransaction for PostgresQuoteTransaction {
+    async fn get(&mut self, id: Uuid) -> Result<Quote, sqlx::Error> {
+        select_quote(&mut self.tx, id).await
+    }
+
+    async fn create(&mut self, new_quote: NewQuote) -> Result<Quote, sqlx::Error> {
+        insert_quote(&mut self.tx, new_quote).await
+    }
+
+    async fn delete(&mut self, id: Uuid) -> Result<Quote, sqlx::Error> {
+        delete_quote(&mut self.tx, id).await
+    }
+
+    async fn update(&mut self, id: Uuid, new_quote: NewQuote) -> Result<Quote, sqlx::Error> {
+        update_quote(&mut self.tx, id, new_quote).await
+    }
+
+    async fn delete_matching(&mut self, filter: DeleteFilter) -> Result<u64, sqlx::Error> {
+        delete_matching(&mut self.tx, filter).await
+    }
+
+    async fn commit(self: Box<Self>) -> Result<(), sqlx::Error> {
+        self.tx.commit().await
+    }
+
+    async fn rollback(self: Box<Self>) -> Result<(), sqlx::Error> {
+        self.tx.rollback().await
+    }
+}
+
+async fn finish(tx: Transaction<'_, Postgres>, dry_run: bool) -> Result<(), sqlx::Error> {
+    if dry_run {
+        tx.rollback().await
+    } else {
+        tx.commit().await
+    }
+}
+
 #[async_trait::async_trait]
 impl QuoteRepository for PostgresQuoteRepository {
     async fn get(&self, id: Uuid) -> Result<Quote, sqlx::Error> {
-        query_as::<_, Quote>("SELECT * FROM quotes WHERE id = $1")
-            .bind(id)
-            .fetch_one(&self.pool)
-            .await
+        select_quote(&mut *self.pool.acquire().await?, id).await
     }
 
     async fn create(&self, new_quote: NewQuote) -> Result<Quote, sqlx::Error> {
-        query_as::<_, Quote>(
-            "INSERT INTO quotes (id, author, quote) VALUES ($1, $2, $3) RETURNING *",
-        )
-        .bind(Uuid::new_v4())
-        .bind(&new_quote.author)
-        .bind(&new_quote.quote)
-        .fetch_one(&self.pool)
+        let mut tx = self.pool.begin().await?;
+        let quote = insert_quote(&mut tx, new_quote).await?;
+        tx.commit().await?;
+        Ok(quote)
+    }
+
+    async fn delete(&self, id: Uuid, dry_run: bool) -> Result<Quote, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        let quote = delete_quote(&mut tx, id).await?;
+        finish(tx, dry_run).await?;
+        Ok(quote)
+    }
+
+    async fn update(&self, id: Uuid, new_quote: NewQuote) -> Result<Quote, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        let quote = update_quote(&mut tx, id, new_quote).await?;
+        tx.commit().await?;
+        Ok(quote)
+    }
+
+    async fn get_quotes(&self, offset: i64, limit: i64) -> Result<Vec<Quote>, sqlx::Error> {
+        query_as::<_, Quote>(&format!(
+            "SELECT *, {} FROM quotes WHERE {} ORDER BY {} OFFSET $1 LIMIT $2",
+            COMPUTED,
+            PUBLISHED,
+            ordering::QUOTES
+        ))
+        .bind(offset)
+        .bind(limit)
+        .fetch_all(&self.pool)
         .await
     }
 
-    async fn delete(&self, id: Uuid) -> Result<Quote, sqlx::Error> {
-        query_as::<_, Quote>("DELETE FROM quotes WHERE id = $1 RETURNING *")
-            .bind(id)
+    async fn count_quotes(&self) -> Result<i64, sqlx::Error> {
+        query_scalar::<_, i64>(&format!("SELECT COUNT(*) FROM quotes WHERE {}", PUBLISHED))
             .fetch_one(&self.pool)
             .await
     }
 
-    async fn update(&self, id: Uuid, new_quote: NewQuote) -> Result<Quote, sqlx::Error> {
-        query_as::<_, Quote>(
-            "UPDATE quotes SET author = $2, quote = $3, version = version + 1 WHERE id = $1 RETURNING *",
+    async fn count_matching(&self, filter: CountFilter) -> Result<i64, sqlx::Error> {
+        query_scalar::<_, i64>(&format!(
+            "SELECT COUNT(*) FROM quotes WHERE {}
+             AND ($1::TEXT IS NULL OR author = $1)
+             AND ($2::TIMESTAMPTZ IS NULL OR created_at >= $2)
+             AND ($3::TIMESTAMPTZ IS NULL OR created_at < $3)",
+            PUBLISHED
+        ))
+        .bind(filter.author)
+        .bind(filter.from)
+        .bind(filter.to)
+        .fetch_one(&self.pool)
+        .await
+    }
+
+    async fn reset_quotes(&self, dry_run: bool) -> Result<u64, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        // truncating doesn't report the rows it removes
+        query("LOCK TABLE quotes").execute(&mut *tx).await?;
+        let count = query_scalar::<_, i64>("SELECT COUNT(*) FROM quotes")
+            .fetch_one(&mut *tx)
+            .await?;
+        query("TRUNCATE TABLE quotes CASCADE")
+            .execute(&mut *tx)
+            .await?;
+
+        outbox::enqueue(&mut tx, outbox::QUOTES_RESET, &()).await?;
+        finish(tx, dry_run).await?;
+        Ok(count as u64)
+    }
+
+    async fn all_quotes(&self) -> Result<Vec<Quote>, sqlx::Error> {
+        query_as::<_, Quote>(&format!(
+            "SELECT *, {} FROM quotes ORDER BY {}",
+            COMPUTED,
+            ordering::QUOTES
+        ))
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    async fn backup(&self) -> Result<Backup, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        // a single snapshot, so that no like or comment refers to a quote missing from it
+        query("SET TRANSACTION ISOLATION LEVEL REPEATABLE READ READ ONLY")
+            .execute(&mut *tx)
+            .await?;
+        let quotes = query_as::<_, ArchivedQuote>(&format!(
+            "SELECT *, {} FROM quotes ORDER BY {}",
+            COMPUTED,
+            ordering::QUOTES
+        ))
+        .fetch_all(&mut *tx)
+        .await?;
+        let likes = query_as::<_, ArchivedLike>(
+            "SELECT quote_id, client_id, created_at FROM quote_likes
+             ORDER BY created_at, quote_id, client_id",
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+        let comments = query_as::<_, Comment>(&format!(
+            "SELECT * FROM quote_comments ORDER BY {}",
+            ordering::COMMENTS
+        ))
+        .fetch_all(&mut *tx)
+        .await?;
+        let game_results = query_as::<_, GameResult>("SELECT * FROM game_results ORDER BY id")
+            .fetch_all(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        Ok(Backup {
+            version: BACKUP_VERSION,
+            quotes,
+            likes,
+            comments,
+            game_results,
+        })
+    }
+
+    async fn restore(&self, backup: Backup, dry_run: bool) -> Result<Restored, sqlx::Error> {
+        // the tables are replaced as a whole, a failing insert leaves them untouched
+        let mut tx = self.pool.begin().await?;
+        // likes and comments given while copying would go uncounted
+        query("LOCK TABLE quote_likes, quote_comments IN SHARE MODE")
+            .execute(&mut *tx)
+            .await?;
+        // what the backup doesn't bring back is dropped
+        query(
+            "CREATE TEMPORARY TABLE previous_likes ON COMMIT DROP AS
+             SELECT quote_id, client_id FROM quote_likes",
+        )
+        .execute(&mut *tx)
+        .await?;
+        query(
+            "CREATE TEMPORARY TABLE previous_comments ON COMMIT DROP AS
+             SELECT id FROM quote_comments",
+        )
+        .execute(&mut *tx)
+        .await?;
+        query("TRUNCATE TABLE quotes, game_results CASCADE")
+            .execute(&mut *tx)
+            .await?;
+
+        let mut restored = 0;
+        for q in backup.quotes {
+            restored += query(
+                "INSERT INTO quotes
+                 (id, author, quote, created_at, version, publish_at, pending_review, review_reason)
+                 VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
+            )
+            .bind(q.quote.id)
+            .bind(q.quote.author)
+            .bind(q.quote.quote)
+            .bind(q.quote.created_at)
+            .bind(q.quote.version)
+            .bind(q.quote.publish_at)
+            .bind(q.quote.status == QuoteStatus::PendingReview)
+            .bind(q.review_reason)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+        }
+        for l in backup.likes {
+            query("INSERT INTO quote_likes (quote_id, client_id, created_at) VALUES ($1, $2, $3)")
+                .bind(l.quote_id)
+                .bind(l.client_id)
+                .bind(l.created_at)
+                .execute(&mut *tx)
+                .await?;
+        }
+        for c in backup.comments {
+            query(
+                "INSERT INTO quote_comments (id, quote_id, author, body, created_at)
+                 VALUES ($1, $2, $3, $4, $5)",
+            )
+            .bind(c.id)
+            .bind(c.quote_id)
+            .bind(c.author)
+            .bind(c.body)
+            .bind(c.created_at)
+            .execute(&mut *tx)
+            .await?;
+        }
+        for g in backup.game_results {
+            query(
+                "INSERT INTO game_results (id, outcome, moves, board, finished_at)
+                 VALUES ($1, $2, $3, $4, $5)",
+            )
+            .bind(g.id)
+            .bind(g.outcome)
+            .bind(g.moves)
+            .bind(g.board)
+            .bind(g.finished_at)
+            .execute(&mut *tx)
+            .await?;
+        }
+        // the next game archived goes after the restored ones
+        query(
+            "SELECT setval(pg_get_serial_sequence('game_results', 'id'), COALESCE(MAX(id), 0) + 1, false)
+             FROM game_results",
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        // the authors index and the subscribers start over from the restored quotes, the
+        // flagged ones are announced once approved
+        let announced = query_as::<_, Quote>(&format!(
+            "SELECT *, {} FROM quotes WHERE NOT pending_review ORDER BY {}",
+            COMPUTED,
+            ordering::QUOTES
+        ))
+        .fetch_all(&mut *tx)
+        .await?;
+        outbox::enqueue(&mut tx, outbox::QUOTES_RESET, &()).await?;
+        outbox::enqueue_all(&mut tx, outbox::QUOTE_CREATED, &announced).await?;
+
+        let dropped_likes: i64 = query_scalar(
+            "SELECT COUNT(*) FROM previous_likes p WHERE NOT EXISTS (
+                SELECT 1 FROM quote_likes l WHERE l.quote_id = p.quote_id AND l.client_id = p.client_id
+             )",
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+        let dropped_comments: i64 = query_scalar(
+            "SELECT COUNT(*) FROM previous_comments p
+             WHERE NOT EXISTS (SELECT 1 FROM quote_comments c WHERE c.id = p.id)",
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        finish(tx, dry_run).await?;
+        Ok(Restored {
+            quotes: restored,
+            dropped_likes: dropped_likes as u64,
+            dropped_comments: dropped_comments as u64,
+        })
+    }
+
+    async fn like(&self, id: Uuid, client: String) -> Result<Quote, sqlx::Error> {
+        query(
+            "INSERT INTO quote_likes (quote_id, client_id) SELECT id, $2 FROM quotes WHERE id = $1
+             ON CONFLICT DO NOTHING",
         )
         .bind(id)
-        .bind(&new_quote.author)
-        .bind(&new_quote.quote)
-        .fetch_one(&self.pool)
+        .bind(client)
+        .execute(&self.pool)
+        .await?;
+        self.get(id).await
+    }
+
+    async fn unlike(&self, id: Uuid, client: String) -> Result<Quote, sqlx::Error> {
+        query("DELETE FROM quote_likes WHERE quote_id = $1 AND client_id = $2")
+            .bind(id)
+            .bind(client)
+            .execute(&self.pool)
+            .await?;
+        self.get(id).await
+    }
+
+    async fn top_liked(&self, limit: i64) -> Result<Vec<Quote>, sqlx::Error> {
+        query_as::<_, Quote>(&format!(
+            "SELECT *, {} FROM quotes WHERE {} ORDER BY {} LIMIT $1",
+            COMPUTED,
+            PUBLISHED,
+            ordering::TOP_QUOTES
+        ))
+        .bind(limit)
+        .fetch_all(&self.pool)
         .await
     }
 
-    async fn get_quotes(&self, offset: i64, limit: i64) -> Result<Vec<Quote>, sqlx::Error> {
-        query_as::<_, Quote>("SELECT * FROM quotes ORDER BY created_at OFFSET $1 LIMIT $2")
-            .bind(offset)
-            .bind(limit)
-            .fetch_all(&self.pool)
-            .await
+    async fn suggest_authors(
+        &self,
+        name: String,
+        limit: i64,
+    ) -> Result<Vec<AuthorMatch>, sqlx::Error> {
+        // prefixes are matched too, they are too short to be similar while typing
+        query_as::<_, AuthorMatch>(&format!(
+            "SELECT author, similarity(author, $1) AS similarity, COUNT(*) AS quotes
+             FROM quotes WHERE author % $1 OR author ILIKE $1 || '%'
+             GROUP BY author ORDER BY {} LIMIT $2",
+            ordering::AUTHOR_SUGGESTIONS
+        ))
+        .bind(name)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
     }
 
-    async fn count_quotes(&self) -> Result<i64, sqlx::Error> {
-        query_scalar::<_, i64>("SELECT COUNT(*) FROM quotes")
-            .fetch_one(&self.pool)
-            .await
+    async fn publish_due(&self) -> Result<Vec<Quote>, sqlx::Error> {
+        // announcements are written with the change, a quote is announced exactly once
+        let mut tx = self.pool.begin().await?;
+        let quotes = query_as::<_, Quote>(&format!(
+            "UPDATE quotes SET publish_at = NULL
+             WHERE publish_at <= now() AND NOT pending_review RETURNING *, {}",
+            COMPUTED
+        ))
+        .fetch_all(&mut *tx)
+        .await?;
+
+        for quote in &quotes {
+            outbox::enqueue(&mut tx, outbox::QUOTE_PUBLISHED, quote).await?;
+        }
+        tx.commit().await?;
+        Ok(quotes)
     }
 
-    async fn reset_quotes(&self) -> Result<PgQueryResult, sqlx::Error> {
-        query("TRUNCATE TABLE quotes").execute(&self.pool).await
+    async fn begin(&self) -> Result<Box<dyn QuoteTransaction>, sqlx::Error> {
+        Ok(Box::new(PostgresQuoteTransaction {
+            tx: self.pool.begin().await?,
+        }))
+    }
+
+    async fn create_flagged(
+        &self,
+        new_quote: NewQuote,
+        reason: String,
+    ) -> Result<Quote, sqlx::Error> {
+        insert_flagged(&mut *self.pool.acquire().await?, new_quote, reason).await
     }
-}
 
-pub async fn cite(Path(id): Path<Uuid>, State(state): State<DbState>) -> impl IntoResponse {
-    match state.repository.get(id).await {
-        Ok(q) => Ok((StatusCode::OK, Json(q))),
-        _ => Err((StatusCode::NOT_FOUND, "".to_string())),
+    async fn update_flagged(
+        &self,
+        id: Uuid,
+        new_quote: NewQuote,
+        reason: String,
+    ) -> Result<Quote, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        let quote = update_flagged(&mut tx, id, new_quote, reason).await?;
+        tx.commit().await?;
+        Ok(quote)
+    }
+
+    async fn pending_review(&self) -> Result<Vec<FlaggedQuote>, sqlx::Error> {
+        query_as::<_, FlaggedQuote>(&format!(
+            "SELECT *, {} FROM quotes WHERE pending_review ORDER BY {}",
+            COMPUTED,
+            ordering::QUOTES
+        ))
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    async fn approve(&self, id: Uuid) -> Result<Quote, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        let quote = query_as::<_, Quote>(&format!(
+            "UPDATE quotes SET pending_review = FALSE, review_reason = NULL
+             WHERE id = $1 AND pending_review RETURNING *, {}",
+            COMPUTED
+        ))
+        .bind(id)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        // scheduled quotes are announced by publish_due once their time comes
+        if quote.status == QuoteStatus::Published {
+            outbox::enqueue(&mut tx, outbox::QUOTE_PUBLISHED, &quote).await?;
+        }
+        tx.commit().await?;
+        Ok(quote)
+    }
+
+    async fn reject(&self, id: Uuid) -> Result<Quote, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        let flagged = query_scalar::<_, bool>("SELECT pending_review FROM quotes WHERE id = $1")
+            .bind(id)
+            .fetch_one(&mut *tx)
+            .await?;
+        if !flagged {
+            return Err(sqlx::Error::RowNotFound);
+        }
+        let quote = delete_quote(&mut tx, id).await?;
+        tx.commit().await?;
+        Ok(quote)
     }
 }
 
-pub async fn draft(
+pub fn operations() -> Vec<Operation> {
+    let quote = |operation: Operation| {
+        operation
+            .json_response::<Quote>(StatusCode::OK, "The quote")
+            .error(StatusCode::NOT_FOUND, "No quote has this id")
+    };
+    vec![
+        Operation::new(Method::POST, "/19/reset", "Deletes every quote")
+            .query(
+                "dry_run",
+                "boolean",
+                "Only counts the quotes that would be deleted",
+            )
+            .response(StatusCode::OK, "The quotes are gone"),
+        quote(Operation::new(Method::GET, "/19/cite/:id", "A quote by id"))
+            .query(
+                "format",
+                "string",
+                "`text`, `markdown`, `bibtex` or `html` citation",
+            )
+            .error(StatusCode::BAD_REQUEST, "Malformed id"),
+        quote(Operation::new(
+            Method::DELETE,
+            "/19/remove/:id",
+            "Deletes a quote",
+        ))
+        .query(
+            "dry_run",
+            "boolean",
+            "Only tells which quote would be deleted",
+        ),
+        quote(Operation::new(
+            Method::PUT,
+            "/19/undo/:id",
+            "Replaces the text of a quote",
+        ))
+        .json_body::<NewQuote>()
+        .error(StatusCode::UNPROCESSABLE_ENTITY, "Refused by moderation"),
+        Operation::new(Method::POST, "/19/draft", "Adds a quote")
+            .json_body::<NewQuote>()
+            .json_response::<Quote>(StatusCode::CREATED, "The new quote")
+            .error(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "Invalid or refused by moderation",
+            ),
+        Operation::new(Method::GET, "/19/list", "A page of quotes, oldest first")
+            .query("token", "string", "Continuation token of the previous page")
+            .media_response(
+                StatusCode::OK,
+                "application/json",
+                "The page and the next token",
+            )
+            .error(StatusCode::BAD_REQUEST, "Unknown token"),
+        Operation::new(Method::GET, "/19/count", "Counts published quotes")
+            .query("author", "string", "Author of the quotes")
+            .query(
+                "from",
+                "string",
+                "Inclusive lower bound on the creation time",
+            )
+            .query("to", "string", "Exclusive upper bound on the creation time")
+            .json_response::<Count>(StatusCode::OK, "The count")
+            .error(StatusCode::BAD_REQUEST, "Malformed filters"),
+    ]
+}
+
+pub async fn cite(
+    Path(id): Path<Uuid>,
     State(state): State<DbState>,
-    Json(new_quote): Json<NewQuote>,
-) -> impl IntoResponse {
-    match state.repository.create(new_quote).await {
-        Ok(q) => Ok((StatusCode::CREATED, Json(q))),
-        _ => Err((StatusCode::NOT_FOUND, "".to_string())),
+    Query(cite): Query<Cite>,
+    links: LinkBuilder,
+) -> Result<Response, AppError> {
+    let q = state.repository.get(id).await?;
+    if let Some(format) = cite.format {
+        return Ok((
+            StatusCode::OK,
+            [(header::CONTENT_TYPE, format.mime())],
+            format.render(&q),
+        )
+            .into_response());
     }
+
+    let links = links
+        .link("self", &format!("/19/cite/{}", id))
+        .action("update", Method::PUT, &format!("/19/undo/{}", id))
+        .action("delete", Method::DELETE, &format!("/19/remove/{}", id))
+        .build();
+    Ok((StatusCode::OK, Json(Linked { data: q, links })).into_response())
+}
+
+/// Why a new quote wasn't created
+#[derive(Debug)]
+pub enum DraftError {
+    /// Refused by moderation, with the reason
+    Rejected(String),
+    Failed(sqlx::Error),
 }
 
-pub async fn remove(Path(id): Path<Uuid>, State(state): State<DbState>) -> impl IntoResponse {
-    match state.repository.delete(id).await {
-        Ok(q) => Ok((StatusCode::OK, Json(q))),
-        _ => Err((StatusCode::NOT_FOUND, "".to_string())),
+impl From<DraftError> for AppError {
+    fn from(e: DraftError) -> Self {
+        match e {
+            DraftError::Rejected(reason) => AppError::Unprocessable(reason),
+            DraftError::Failed(e) => AppError::Database(e),
+        }
     }
 }
 
-pub async fn undo(
-    Path(id): Path<Uuid>,
+/// Creates a quote that moderation let through, flagged when an admin has to review it
+pub async fn create_quote(state: &DbState, new_quote: NewQuote) -> Result<Quote, DraftError> {
+    let created = match state.moderator.review(&new_quote).await {
+        Verdict::Accept => state.repository.create(new_quote).await,
+        Verdict::Reject(reason) => return Err(DraftError::Rejected(reason)),
+        Verdict::Flag(reason) => state.repository.create_flagged(new_quote, reason).await,
+    };
+    let quote = created.map_err(DraftError::Failed)?;
+    STATS.quote_created();
+    Ok(quote)
+}
+
+pub async fn draft(
     State(state): State<DbState>,
     Json(new_quote): Json<NewQuote>,
-) -> impl IntoResponse {
-    match state.repository.update(id, new_quote).await {
-        Ok(q) => Ok((StatusCode::OK, Json(q))),
-        _ => Err((StatusCode::NOT_FOUND, "".to_string())),
-    }
+) -> Result<impl IntoResponse, AppError> {
+    let q = create_quote(&state, new_quote).await?;
+    Ok((StatusCode::CREATED, Json(q)))
 }
 
-pub async fn reset_quotes(State(state): State<DbState>) -> impl IntoResponse {
-    match state.repository.reset_quotes().await {
-        Ok(_) => Ok(StatusCode::OK),
-        _ => Err((StatusCode::INTERNAL_SERVER_ERROR, "".to_string())),
-    }
+/// Quote a dry run of `/19/remove` would delete
+#[derive(Serialize, Deserialize)]
+pub struct Removal {
+    pub rows_affected: u64,
+    pub quote: Quote,
 }
 
-pub async fn list(token: OptionalQuery<Token>, State(state): State<DbState>) -> impl IntoResponse {
-    let mut tokens = state.tokens.lock().await;
+pub async fn remove(
+    Path(id): Path<Uuid>,
+    State(state): State<DbState>,
+    Query(DryRun { dry_run }): Query<DryRun>,
+) -> Result<Response, AppError> {
+    let quote = state.repository.delete(id, dry_run).await?;
+    Ok(match dry_run {
+        true => Json(Preview::new(Removal {
+            rows_affected: 1,
+            quote,
+        }))
+        .into_response(),
+        false => (StatusCode::OK, Json(quote)).into_response(),
+    })
+}
 
-    let page = if token.is_none() {
-        // if no token is given, fetch the first page
-        1
-    } else if let Some(p) = tokens.get(token.0.unwrap().token.as_str()) {
-        // if the token is valid, fetch the desired page
-        *p
-    } else {
-        // token not found, user error
-        return Err((StatusCode::BAD_REQUEST, "".to_string()));
+/// Edits a quote the way moderation allows, held for review again when it is flagged
+pub async fn edit_quote(
+    state: &DbState,
+    id: Uuid,
+    new_quote: NewQuote,
+) -> Result<Quote, DraftError> {
+    let updated = match state.moderator.review(&new_quote).await {
+        Verdict::Accept => state.repository.update(id, new_quote).await,
+        Verdict::Reject(reason) => return Err(DraftError::Rejected(reason)),
+        Verdict::Flag(reason) => state.repository.update_flagged(id, new_quote, reason).await,
     };
+    updated.map_err(DraftError::Failed)
+}
 
-    let total_pages = match total_pages(&state).await {
-        Ok(p) => p,
-        Err(e) => return Err(e),
-    };
+pub async fn undo(
+    Path(id): Path<Uuid>,
+    State(state): State<DbState>,
+    Json(new_quote): Json<NewQuote>,
+) -> Result<impl IntoResponse, AppError> {
+    let q = edit_quote(&state, id, new_quote).await?;
+    Ok((StatusCode::OK, Json(q)))
+}
 
-    let quotes = match page_quotes(&state, page).await {
-        Ok(q) => q,
-        Err(e) => return Err(e),
-    };
+pub async fn reset_quotes(
+    State(state): State<DbState>,
+    Query(DryRun { dry_run }): Query<DryRun>,
+) -> Result<Response, AppError> {
+    let rows_affected = state.repository.reset_quotes(dry_run).await?;
+    Ok(match dry_run {
+        true => Json(Preview::new(RowsAffected { rows_affected })).into_response(),
+        false => StatusCode::OK.into_response(),
+    })
+}
 
-    let next_token = if page < total_pages {
-        let n = rand::distributions::Alphanumeric.sample_string(&mut rand::thread_rng(), 16);
-        tokens.insert(n.clone(), page + 1);
-        Some(n)
-    } else {
-        None
+/// `DELETE /19/quotes`, a dry run tells how many quotes match before `confirm=true` deletes them
+pub async fn bulk_delete(
+    State(state): State<BulkDeleteState>,
+    Query(bulk): Query<BulkDelete>,
+) -> Result<Response, AppError> {
+    let filter = DeleteFilter {
+        author: bulk.author,
+        created_before: bulk.created_before,
     };
+    if filter == DeleteFilter::default() {
+        return Err(AppError::BadRequest("a filter is required".to_string()));
+    }
+    if !bulk.dry_run && !bulk.confirm {
+        return Err(AppError::BadRequest(
+            "preview with dry_run=true, then delete with confirm=true".to_string(),
+        ));
+    }
 
-    Ok((
-        StatusCode::OK,
-        Json(Quotes {
-            quotes,
-            page,
-            next_token,
-        }),
-    ))
-}
+    let mut tx = state.repository.begin().await?;
+    let rows_affected = tx.delete_matching(filter).await?;
 
-async fn total_pages(state: &DbState) -> Result<i64, (StatusCode, String)> {
-    match state.repository.count_quotes().await {
-        Ok(count) => Ok((count as f64 / PAGE_SIZE as f64).ceil() as i64),
-        _ => Err((StatusCode::INTERNAL_SERVER_ERROR, "".to_string())),
+    let over_cap = rows_affected > state.max_rows;
+    if bulk.dry_run || over_cap {
+        tx.rollback().await?;
+    } else {
+        tx.commit().await?;
+    }
+    if over_cap {
+        return Err(AppError::Unprocessable(format!(
+            "{} quotes match, more than the {} allowed at once",
+            rows_affected, state.max_rows
+        )));
     }
+    Ok(match bulk.dry_run {
+        true => Json(Preview::new(RowsAffected { rows_affected })).into_response(),
+        false => Json(RowsAffected { rows_affected }).into_response(),
+    })
 }
 
-async fn page_quotes(state: &DbState, page: i64) -> Result<Vec<Quote>, (StatusCode, String)> {
-    match state
-        .repository
-        .get_quotes((page - 1) * PAGE_SIZE, PAGE_SIZE)
-        .await
-    {
-        Ok(quotes) => Ok(quotes),
-        _ => Err((StatusCode::INTERNAL_SERVER_ERROR, "".to_string())),
-    }
+pub async fn like(
+    Path(id): Path<Uuid>,
+    State(state): State<DbState>,
+    Liker(client): Liker,
+) -> Result<impl IntoResponse, AppError> {
+    let q = state.repository.like(id, client).await?;
+    Ok((StatusCode::OK, Json(q)))
 }
 
-pub fn state_tokens() -> Arc<Mutex<HashMap<String, i64>>> {
-    Arc::new(Mutex::new(HashMap::new()))
+pub async fn unlike(
+    Path(id): Path<Uuid>,
+    State(state): State<DbState>,
+    Liker(client): Liker,
+) -> Result<impl IntoResponse, AppError> {
+    let q = state.repository.unlike(id, client).await?;
+    Ok((StatusCode::OK, Json(q)))
 }
 
-pub fn state_repository(pool: PgPool) -> Arc<dyn QuoteRepository> {
-    Arc::new(PostgresQuoteRepository::new(pool))
+pub async fn top(
+    State(state): State<DbState>,
+    Query(top): Query<Top>,
+) -> Result<impl IntoResponse, AppError> {
+    // likes are the only ranking so far
+    if top.by != "likes" {
+        return Err(AppError::BadRequest(
+            "quotes are only ranked by likes".to_string(),
+        ));
+    }
+
+    let limit = top.limit.unwrap_or(TOP_LIMIT).clamp(1, MAX_TOP_LIMIT);
+    let quotes = state.repository.top_liked(limit).await?;
+    Ok((StatusCode::OK, Json(quotes)))
 }
 
-#[cfg(test)]
-mod tests {
-    use core::{
-        future::{ready, Future},
-        pin::Pin,
-    };
+pub async fn suggest_authors(
+    State(state): State<DbState>,
+    Query(suggest): Query<Suggest>,
+) -> Result<impl IntoResponse, AppError> {
+    let name = suggest.q.trim();
+    if name.is_empty() {
+        return Err(AppError::BadRequest("q must not be blank".to_string()));
+    }
 
+    let limit = suggest
+        .limit
+        .unwrap_or(SUGGEST_LIMIT)
+        .clamp(1, MAX_SUGGEST_LIMIT);
+    let authors = state
+        .repository
+        .suggest_authors(name.to_string(), limit)
+        .await?;
+    Ok((StatusCode::OK, Json(authors)))
+}
+
+pub async fn backup(State(state): State<DbState>) -> Result<impl IntoResponse, AppError> {
+    let backup = state.repository.backup().await?;
+    Ok((
+        StatusCode::OK,
+        [(
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"backup-v{}.json\"", BACKUP_VERSION),
+        )],
+        Json(backup),
+    ))
+}
+
+pub async fn restore(
+    State(state): State<RestoreState>,
+    Query(DryRun { dry_run }): Query<DryRun>,
+    links: LinkBuilder,
+    archive: Bytes,
+) -> Result<Response, AppError> {
+    // the archive is kept as it was sent, the restore reads a copy of it
+    let backup = match Json::<Backup>::from_bytes(&archive) {
+        Ok(Json(backup)) => backup,
+        Err(rejection) => return Ok(rejection.into_response()),
+    };
+    // archives from other versions may have a different shape
+    backup.validate().map_err(AppError::Unprocessable)?;
+
+    let Restored {
+        quotes: rows_affected,
+        dropped_likes,
+        dropped_comments,
+    } = state.repository.restore(backup, dry_run).await?;
+    if (dropped_likes > 0 || dropped_comments > 0) && !dry_run {
+        tracing::warn!(
+            "restore dropped {} likes and {} comments",
+            dropped_likes,
+            dropped_comments
+        );
+    }
+    let dropped = [
+        (DROPPED_LIKES_HEADER, dropped_likes.to_string()),
+        (DROPPED_COMMENTS_HEADER, dropped_comments.to_string()),
+    ];
+    if dry_run {
+        return Ok((dropped, Json(Preview::new(RowsAffected { rows_affected }))).into_response());
+    }
+
+    // the restore went through already, an archive that isn't kept only goes without an id
+    let stored = match state.uploads {
+        Some(uploads) => match uploads
+            .store(RESTORE_UPLOAD, uploads::chunks(archive))
+            .await
+        {
+            Ok(Some(id)) => Some(id),
+            Ok(None) => {
+                tracing::warn!("upload quota used up, restored archive not stored");
+                None
+            }
+            Err(e) => {
+                tracing::warn!("failed to store a restored archive: {}", e);
+                None
+            }
+        },
+        None => None,
+    };
+    let response = (StatusCode::OK, dropped, rows_affected.to_string());
+    Ok(match stored {
+        Some(id) => (
+            [
+                (HeaderName::from_static(UPLOAD_ID_HEADER), id.to_string()),
+                (
+                    header::LOCATION,
+                    links.href(&format!("/admin/restore/{}", id)),
+                ),
+            ],
+            response,
+        )
+            .into_response(),
+        None => response.into_response(),
+    })
+}
+
+/// Archive of a restore made before, found by the id `/admin/restore` answered with
+pub async fn restored_archive(
+    State(state): State<RestoreState>,
+    Path(upload_id): Path<Uuid>,
+) -> Result<Response, AppError> {
+    let Some(uploads) = state.uploads else {
+        return Err(AppError::NotFound);
+    };
+
+    match uploads.load(RESTORE_UPLOAD, upload_id).await? {
+        Some(archive) => {
+            Ok(([(header::CONTENT_TYPE, "application/json")], archive).into_response())
+        }
+        None => Err(AppError::NotFound),
+    }
+}
+
+pub async fn list(
+    token: OptionalQuery<Token>,
+    State(state): State<DbState>,
+    accept: Accept,
+    links: LinkBuilder,
+) -> Result<Response, AppError> {
+    let format = accept
+        .negotiate(&[Format::Json, Format::NdJson])
+        .ok_or(AppError::NotAcceptable)?;
+
+    let token = token.0.map(|t| t.token);
+    let quotes = list_page(&state, token.clone()).await?;
+    if format == Format::NdJson {
+        return Ok(ndjson_page(quotes));
+    }
+
+    let mut links = links.link("self", &list_path(token.as_deref()));
+    if let Some(next) = &quotes.next_token {
+        links = links.link("next", &list_path(Some(next)));
+    }
+    if quotes.page > 1 {
+        let prev = page_token(&state, quotes.page - 1).await;
+        links = links.link("prev", &list_path(prev.as_deref()));
+    }
+
+    Ok((
+        StatusCode::OK,
+        Json(Linked {
+            data: quotes,
+            links: links.build(),
+        }),
+    )
+        .into_response())
+}
+
+/// `HEAD /19/list`, the totals without the quotes
+pub async fn list_totals(State(state): State<DbState>) -> Result<Response, AppError> {
+    let count = state.repository.count_quotes().await?;
+
+    let mut headers = HeaderMap::new();
+    headers.insert("x-total-count", HeaderValue::from(count));
+    headers.insert("x-total-pages", HeaderValue::from(pages(&state, count)));
+    Ok((StatusCode::OK, headers).into_response())
+}
+
+pub async fn count(
+    State(state): State<DbState>,
+    ValidatedQuery(filter): ValidatedQuery<CountFilter>,
+) -> Result<impl IntoResponse, AppError> {
+    let count = state.repository.count_matching(filter).await?;
+    Ok((StatusCode::OK, Json(Count { count })))
+}
+
+fn list_path(token: Option<&str>) -> String {
+    match token {
+        Some(t) => format!("/19/list?token={}", t),
+        None => "/19/list".to_string(),
+    }
+}
+
+/// Continuation token pointing to the given page, the first page needs none
+async fn page_token(state: &DbState, page: i64) -> Option<String> {
+    if page <= 1 {
+        return None;
+    }
+
+    state.tokens.issue(page).await.ok()
+}
+
+/// One quote per line, pagination details are moved to the headers
+fn ndjson_page(quotes: Quotes) -> Response {
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static(Format::NdJson.mime()),
+    );
+    headers.insert("x-page", HeaderValue::from(quotes.page));
+    if let Some(token) = quotes
+        .next_token
+        .and_then(|t| HeaderValue::from_str(&t).ok())
+    {
+        headers.insert("x-next-token", token);
+    }
+
+    let body = quotes
+        .quotes
+        .iter()
+        .map(|q| serde_json::to_string(q).unwrap() + "\n")
+        .collect::<String>();
+
+    (StatusCode::OK, headers, body).into_response()
+}
+
+/// Fetches the page pointed by the given continuation token, or the first page if no token is given
+pub(crate) async fn list_page(state: &DbState, token: Option<String>) -> Result<Quotes, AppError> {
+    let page = match token {
+        // if no token is given, fetch the first page
+        None => 1,
+        Some(t) => match state.tokens.resolve(t).await {
+            // if the token is valid, fetch the desired page
+            Ok(Some(p)) => p,
+            // token not found, user error
+            Ok(None) => return Err(AppError::BadRequest("unknown token".to_string())),
+            Err(_) => return Err(AppError::Internal),
+        },
+    };
+
+    let total_pages = total_pages(state).await?;
+    let quotes = page_quotes(state, page).await?;
+
+    let next_token = if page < total_pages {
+        match state.tokens.issue(page + 1).await {
+            Ok(n) => Some(n),
+            Err(_) => return Err(AppError::Internal),
+        }
+    } else {
+        None
+    };
+
+    Ok(Quotes {
+        quotes,
+        page,
+        next_token,
+    })
+}
+
+fn page_size(state: &DbState) -> i64 {
+    SETTINGS.get_or(QUOTES_PAGE_SIZE, state.page_size)
+}
+
+fn pages(state: &DbState, count: i64) -> i64 {
+    (count as f64 / page_size(state) as f64).ceil() as i64
+}
+
+async fn total_pages(state: &DbState) -> Result<i64, AppError> {
+    Ok(pages(state, state.repository.count_quotes().await?))
+}
+
+async fn page_quotes(state: &DbState, page: i64) -> Result<Vec<Quote>, AppError> {
+    let quotes = state
+        .repository
+        .get_quotes((page - 1) * page_size(state), page_size(state))
+        .await?;
+    Ok(quotes)
+}
+
+/// Times every call of the wrapped repository, see [`instrument::time`]
+pub struct InstrumentedQuoteRepository {
+    inner: Arc<dyn QuoteRepository>,
+    slow_query: Duration,
+}
+
+impl InstrumentedQuoteRepository {
+    pub fn wrap(inner: Arc<dyn QuoteRepository>, slow_query: Duration) -> Arc<dyn QuoteRepository> {
+        Arc::new(Self { inner, slow_query })
+    }
+}
+
+#[async_trait::async_trait]
+impl QuoteRepository for InstrumentedQuoteRepository {
+    async fn get(&self, id: Uuid) -> Result<Quote, sqlx::Error> {
+        let params = || "id=<uuid>".to_string();
+        instrument::time("quotes.get", self.slow_query, params, self.inner.get(id)).await
+    }
+
+    async fn create(&self, new_quote: NewQuote) -> Result<Quote, sqlx::Error> {
+        let params = || "new_quote=<author, quote, publish_at>".to_string();
+        let call = self.inner.create(new_quote);
+        instrument::time("quotes.create", self.slow_query, params, call).await
+    }
+
+    async fn delete(&self, id: Uuid, dry_run: bool) -> Result<Quote, sqlx::Error> {
+        let params = || "id=<uuid>, dry_run=<bool>".to_string();
+        let call = self.inner.delete(id, dry_run);
+        instrument::time("quotes.delete", self.slow_query, params, call).await
+    }
+
+    async fn update(&self, id: Uuid, new_quote: NewQuote) -> Result<Quote, sqlx::Error> {
+        let params = || "id=<uuid>, new_quote=<author, quote, publish_at>".to_string();
+        let call = self.inner.update(id, new_quote);
+        instrument::time("quotes.update", self.slow_query, params, call).await
+    }
+
+    async fn get_quotes(&self, offset: i64, limit: i64) -> Result<Vec<Quote>, sqlx::Error> {
+        let params = || "offset=<i64>, limit=<i64>".to_string();
+        let call = self.inner.get_quotes(offset, limit);
+        instrument::time("quotes.get_quotes", self.slow_query, params, call).await
+    }
+
+    async fn count_quotes(&self) -> Result<i64, sqlx::Error> {
+        let call = self.inner.count_quotes();
+        instrument::time("quotes.count_quotes", self.slow_query, String::new, call).await
+    }
+
+    async fn count_matching(&self, filter: CountFilter) -> Result<i64, sqlx::Error> {
+        let params = || "filter=<author, from, to>".to_string();
+        let call = self.inner.count_matching(filter);
+        instrument::time("quotes.count_matching", self.slow_query, params, call).await
+    }
+
+    async fn reset_quotes(&self, dry_run: bool) -> Result<u64, sqlx::Error> {
+        let params = || "dry_run=<bool>".to_string();
+        let call = self.inner.reset_quotes(dry_run);
+        instrument::time("quotes.reset_quotes", self.slow_query, params, call).await
+    }
+
+    async fn all_quotes(&self) -> Result<Vec<Quote>, sqlx::Error> {
+        let call = self.inner.all_quotes();
+        instrument::time("quotes.all_quotes", self.slow_query, String::new, call).await
+    }
+
+    async fn backup(&self) -> Result<Backup, sqlx::Error> {
+        let call = self.inner.backup();
+        instrument::time("quotes.backup", self.slow_query, String::new, call).await
+    }
+
+    async fn restore(&self, backup: Backup, dry_run: bool) -> Result<Restored, sqlx::Error> {
+        let count = backup.quotes.len();
+        let params = move || format!("quotes=<{} items>, dry_run=<bool>", count);
+        let call = self.inner.restore(backup, dry_run);
+        instrument::time("quotes.restore", self.slow_query, params, call).await
+    }
+
+    async fn like(&self, id: Uuid, client: String) -> Result<Quote, sqlx::Error> {
+        let params = || "id=<uuid>, client=<text>".to_string();
+        let call = self.inner.like(id, client);
+        instrument::time("quotes.like", self.slow_query, params, call).await
+    }
+
+    async fn unlike(&self, id: Uuid, client: String) -> Result<Quote, sqlx::Error> {
+        let params = || "id=<uuid>, client=<text>".to_string();
+        let call = self.inner.unlike(id, client);
+        instrument::time("quotes.unlike", self.slow_query, params, call).await
+    }
+
+    async fn top_liked(&self, limit: i64) -> Result<Vec<Quote>, sqlx::Error> {
+        let params = || "limit=<i64>".to_string();
+        let call = self.inner.top_liked(limit);
+        instrument::time("quotes.top_liked", self.slow_query, params, call).await
+    }
+
+    async fn suggest_authors(
+        &self,
+        name: String,
+        limit: i64,
+    ) -> Result<Vec<AuthorMatch>, sqlx::Error> {
+        let params = || "name=<text>, limit=<i64>".to_string();
+        let call = self.inner.suggest_authors(name, limit);
+        instrument::time("quotes.suggest_authors", self.slow_query, params, call).await
+    }
+
+    async fn publish_due(&self) -> Result<Vec<Quote>, sqlx::Error> {
+        let call = self.inner.publish_due();
+        instrument::time("quotes.publish_due", self.slow_query, String::new, call).await
+    }
+
+    async fn begin(&self) -> Result<Box<dyn QuoteTransaction>, sqlx::Error> {
+        let call = self.inner.begin();
+        instrument::time("quotes.begin", self.slow_query, String::new, call).await
+    }
+
+    async fn create_flagged(
+        &self,
+        new_quote: NewQuote,
+        reason: String,
+    ) -> Result<Quote, sqlx::Error> {
+        let params = || "new_quote=<author, quote, publish_at>, reason=<string>".to_string();
+        let call = self.inner.create_flagged(new_quote, reason);
+        instrument::time("quotes.create_flagged", self.slow_query, params, call).await
+    }
+
+    async fn update_flagged(
+        &self,
+        id: Uuid,
+        new_quote: NewQuote,
+        reason: String,
+    ) -> Result<Quote, sqlx::Error> {
+        let params =
+            || "id=<uuid>, new_quote=<author, quote, publish_at>, reason=<string>".to_string();
+        let call = self.inner.update_flagged(id, new_quote, reason);
+        instrument::time("quotes.update_flagged", self.slow_query, params, call).await
+    }
+
+    async fn pending_review(&self) -> Result<Vec<FlaggedQuote>, sqlx::Error> {
+        let call = self.inner.pending_review();
+        instrument::time("quotes.pending_review", self.slow_query, String::new, call).await
+    }
+
+    async fn approve(&self, id: Uuid) -> Result<Quote, sqlx::Error> {
+        let params = || "id=<uuid>".to_string();
+        let call = self.inner.approve(id);
+        instrument::time("quotes.approve", self.slow_query, params, call).await
+    }
+
+    async fn reject(&self, id: Uuid) -> Result<Quote, sqlx::Error> {
+        let params = || "id=<uuid>".to_string();
+        let call = self.inner.reject(id);
+        instrument::time("quotes.reject", self.slow_query, params, call).await
+    }
+}
+
+/// Periodically publishes the scheduled quotes that are due
+pub async fn publish_scheduled(repository: Arc<dyn QuoteRepository>) {
+    let mut interval = tokio::time::interval(PUBLISH_INTERVAL);
+    loop {
+        interval.tick().await;
+        match repository.publish_due().await {
+            Ok(quotes) if !quotes.is_empty() => {
+                tracing::info!("published {} scheduled quotes", quotes.len())
+            }
+            Ok(_) => {}
+            Err(e) => tracing::warn!("scheduled publication failed: {}", e),
+        }
+    }
+}
+
+pub fn state_tokens() -> Arc<dyn TokenStore> {
+    Arc::new(MemoryTokenStore::default())
+}
+
+pub fn state_repository(pool: PgPool) -> Arc<dyn QuoteRepository> {
+    Arc::new(PostgresQuoteRepository::new(pool))
+}
+
+#[cfg(test)]
+mod tests {
     use super::*;
+    use crate::{
+        moderation::{MockModerator, WordListModerator},
+        players::{MockPlayerRepository, PLAYER_COOKIE},
+        quota::API_KEY_HEADER,
+        test_support::box_future,
+        uploads::MockUploadRepository,
+    };
     use axum::{
         body::Body,
         http::{Request, StatusCode},
@@ -268,90 +1707,884 @@ mod tests {
             None
         };
 
-        (status, body_str)
-    }
+        (status, body_str)
+    }
+
+    const SANTA: Uuid = Uuid::from_u128(0x5a17a);
+
+    fn create_test_app(repository: Arc<dyn QuoteRepository>) -> Router {
+        create_moderated_app(repository, Arc::new(WordListModerator::default()))
+    }
+
+    fn create_moderated_app(
+        repository: Arc<dyn QuoteRepository>,
+        moderator: Arc<dyn Moderator>,
+    ) -> Router {
+        // Santa is the only registered player
+        let mut players = MockPlayerRepository::new();
+        players
+            .expect_by_token()
+            .returning(|token| box_future(Ok((token == SANTA).then(|| "Santa".to_string()))));
+        let restore_state = RestoreState {
+            repository: repository.clone(),
+            uploads: None,
+        };
+        let state = DbState {
+            repository,
+            tokens: state_tokens(),
+            moderator,
+            page_size: PAGE_SIZE,
+            players: Arc::new(players),
+        };
+
+        Router::new()
+            .route("/cite/:id", get(cite))
+            .route("/draft", post(draft))
+            .route("/remove/:id", delete(remove))
+            .route("/undo/:id", put(undo))
+            .route("/list", get(list).head(list_totals))
+            .route("/count", get(count))
+            .route("/reset", post(reset_quotes))
+            .route("/backup", post(backup))
+            .route("/restore", post(restore).with_state(restore_state))
+            .route("/cite/:id/like", post(like).delete(unlike))
+            .route("/top", get(top))
+            .route("/authors/suggest", get(suggest_authors))
+            .with_state(state)
+    }
+
+    #[tokio::test]
+    async fn test_cite_ok() {
+        let mut mock = MockQuoteRepository::new();
+        let quote_id = Uuid::new_v4();
+        let expected_quote = Quote {
+            id: quote_id,
+            author: "Test Author".to_string(),
+            quote: "Test Quote".to_string(),
+            created_at: Utc::now(),
+            version: 1,
+            likes: 0,
+            publish_at: None,
+            status: QuoteStatus::Published,
+        };
+
+        mock.expect_get()
+            .with(eq(quote_id))
+            .returning(move |_| box_future(Ok(expected_quote.clone())));
+
+        let app = create_test_app(Arc::new(mock));
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri(format!("/cite/{}", quote_id))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let (status, body_str) = get_response_parts(response).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let response_quote: Linked<Quote> = serde_json::from_str(&body_str.unwrap()).unwrap();
+        assert_eq!(response_quote.data.id, quote_id);
+        assert_eq!(
+            response_quote.links["delete"].href,
+            format!("/19/remove/{}", quote_id)
+        );
+        assert_eq!(
+            response_quote.links["update"].method.as_deref(),
+            Some("PUT")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_cite_formats() {
+        let mut mock = MockQuoteRepository::new();
+        let quote_id = Uuid::new_v4();
+        mock.expect_get()
+            .with(eq(quote_id))
+            .returning(|id| box_future(Ok(liked(id, 0))));
+        let app = create_test_app(Arc::new(mock));
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri(format!("/cite/{}?format=html", quote_id))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            "text/html; charset=utf-8"
+        );
+        let (status, body) = get_response_parts(response).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.unwrap().starts_with("<blockquote"));
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri(format!("/cite/{}?format=mla", quote_id))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_draft_ok() {
+        let mut mock = MockQuoteRepository::new();
+        let new_quote = NewQuote {
+            author: "New Author".to_string(),
+            quote: "New Quote".to_string(),
+            publish_at: None,
+        };
+
+        mock.expect_create()
+            .with(eq(new_quote.clone()))
+            .returning(|q| {
+                box_future(Ok(Quote {
+                    id: Uuid::new_v4(),
+                    author: q.author,
+                    quote: q.quote,
+                    created_at: Utc::now(),
+                    version: 1,
+                    likes: 0,
+                    publish_at: None,
+                    status: QuoteStatus::Published,
+                }))
+            });
+
+        let app = create_test_app(Arc::new(mock));
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/draft")
+                    .header("content-type", "application/json")
+                    .body(Body::from(serde_json::to_string(&new_quote).unwrap()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let (status, _) = get_response_parts(response).await;
+        assert_eq!(status, StatusCode::CREATED);
+    }
+
+    #[tokio::test]
+    async fn test_list_first_page_ok() {
+        let mut mock = MockQuoteRepository::new();
+        let quotes = vec![Quote {
+            id: Uuid::new_v4(),
+            author: "Author 1".to_string(),
+            quote: "Quote 1".to_string(),
+            created_at: Utc::now(),
+            version: 1,
+            likes: 0,
+            publish_at: None,
+            status: QuoteStatus::Published,
+        }];
+
+        mock.expect_count_quotes().returning(|| box_future(Ok(1)));
+
+        mock.expect_get_quotes()
+            .with(eq(0), eq(PAGE_SIZE))
+            .returning(move |_, _| box_future(Ok(quotes.clone())));
+
+        let app = create_test_app(Arc::new(mock));
+
+        let response = app
+            .oneshot(Request::builder().uri("/list").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        let (status, body_str) = get_response_parts(response).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let response_quotes: Quotes = serde_json::from_str(&body_str.unwrap()).unwrap();
+        assert_eq!(response_quotes.quotes.len(), 1);
+        assert_eq!(response_quotes.page, 1);
+        assert_eq!(response_quotes.next_token, None);
+    }
+
+    #[tokio::test]
+    async fn test_list_links() {
+        let mut mock = MockQuoteRepository::new();
+
+        mock.expect_count_quotes().returning(|| box_future(Ok(7)));
+        mock.expect_get_quotes()
+            .returning(|_, _| box_future(Ok(vec![])));
+
+        let app = create_test_app(Arc::new(mock));
+
+        let response = app
+            .clone()
+            .oneshot(Request::builder().uri("/list").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let (_, body_str) = get_response_parts(response).await;
+        let first: Linked<Quotes> = serde_json::from_str(&body_str.unwrap()).unwrap();
+        assert_eq!(first.links["self"].href, "/19/list");
+        assert!(!first.links.contains_key("prev"));
+
+        let next = first.data.next_token.unwrap();
+        assert_eq!(first.links["next"].href, format!("/19/list?token={}", next));
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri(format!("/list?token={}", next))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let (_, body_str) = get_response_parts(response).await;
+        let second: Linked<Quotes> = serde_json::from_str(&body_str.unwrap()).unwrap();
+        assert_eq!(second.data.page, 2);
+        assert_eq!(second.links["prev"].href, "/19/list");
+        assert!(second.links.contains_key("next"));
+    }
+
+    #[tokio::test]
+    async fn test_remove_ok() {
+        let mut mock = MockQuoteRepository::new();
+        let quote_id = Uuid::new_v4();
+        let quote = Quote {
+            id: quote_id,
+            author: "Author".to_string(),
+            quote: "Quote".to_string(),
+            created_at: Utc::now(),
+            version: 1,
+            likes: 0,
+            publish_at: None,
+            status: QuoteStatus::Published,
+        };
+
+        mock.expect_delete()
+            .with(eq(quote_id), eq(false))
+            .returning(move |_, _| box_future(Ok(quote.clone())));
+
+        let app = create_test_app(Arc::new(mock));
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("DELETE")
+                    .uri(format!("/remove/{}", quote_id))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let (status, body_str) = get_response_parts(response).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let response_quote: Quote = serde_json::from_str(&body_str.unwrap()).unwrap();
+        assert_eq!(response_quote.id, quote_id);
+    }
+
+    #[tokio::test]
+    async fn test_undo_ok() {
+        let mut mock = MockQuoteRepository::new();
+        let quote_id = Uuid::new_v4();
+        let new_quote = NewQuote {
+            author: "Updated Author".to_string(),
+            quote: "Updated Quote".to_string(),
+            publish_at: None,
+        };
+
+        mock.expect_update()
+            .with(eq(quote_id), eq(new_quote.clone()))
+            .returning(|id, q| {
+                box_future(Ok(Quote {
+                    id,
+                    author: q.author,
+                    quote: q.quote,
+                    created_at: Utc::now(),
+                    version: 2,
+                    likes: 0,
+                    publish_at: None,
+                    status: QuoteStatus::Published,
+                }))
+            });
+
+        let app = create_test_app(Arc::new(mock));
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("PUT")
+                    .uri(format!("/undo/{}", quote_id))
+                    .header("content-type", "application/json")
+                    .body(Body::from(serde_json::to_string(&new_quote).unwrap()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let (status, body_str) = get_response_parts(response).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let response_quote: Quote = serde_json::from_str(&body_str.unwrap()).unwrap();
+        assert_eq!(response_quote.id, quote_id);
+        assert_eq!(response_quote.author, new_quote.author);
+        assert_eq!(response_quote.quote, new_quote.quote);
+        assert_eq!(response_quote.version, 2);
+    }
+
+    #[tokio::test]
+    async fn test_remove_dry_run() {
+        let mut mock = MockQuoteRepository::new();
+        let quote_id = Uuid::new_v4();
+        let quote = Quote {
+            id: quote_id,
+            author: "Author".to_string(),
+            quote: "Quote".to_string(),
+            created_at: Utc::now(),
+            version: 1,
+            likes: 0,
+            publish_at: None,
+            status: QuoteStatus::Published,
+        };
+
+        mock.expect_delete()
+            .with(eq(quote_id), eq(true))
+            .returning(move |_, _| box_future(Ok(quote.clone())));
+
+        let app = create_test_app(Arc::new(mock));
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("DELETE")
+                    .uri(format!("/remove/{}?dry_run=true", quote_id))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let (status, body_str) = get_response_parts(response).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let preview: Preview<Removal> = serde_json::from_str(&body_str.unwrap()).unwrap();
+        assert!(preview.dry_run);
+        assert_eq!(preview.effect.rows_affected, 1);
+        assert_eq!(preview.effect.quote.id, quote_id);
+    }
+
+    #[tokio::test]
+    async fn test_reset_ok() {
+        let mut mock = MockQuoteRepository::new();
+
+        mock.expect_reset_quotes()
+            .with(eq(false))
+            .returning(|_| box_future(Ok(0)));
+
+        let app = create_test_app(Arc::new(mock));
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/reset")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let (status, _) = get_response_parts(response).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_backup_ok() {
+        let mut mock = MockQuoteRepository::new();
+        let id = Uuid::new_v4();
+        let quote = Quote {
+            id,
+            author: "Author".to_string(),
+            quote: "Quote".to_string(),
+            created_at: Utc::now(),
+            version: 1,
+            likes: 1,
+            publish_at: None,
+            status: QuoteStatus::PendingReview,
+        };
+
+        mock.expect_backup().returning(move || {
+            box_future(Ok(Backup {
+                version: BACKUP_VERSION,
+                quotes: vec![ArchivedQuote {
+                    quote: quote.clone(),
+                    review_reason: Some("written in capitals".to_string()),
+                }],
+                likes: vec![ArchivedLike {
+                    quote_id: id,
+                    client_id: "player:santa".to_string(),
+                    created_at: Utc::now(),
+                }],
+                comments: vec![],
+                game_results: vec![],
+            }))
+        });
+
+        let app = create_test_app(Arc::new(mock));
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/backup")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let (status, body_str) = get_response_parts(response).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let backup: Backup = serde_json::from_str(&body_str.unwrap()).unwrap();
+        assert_eq!(backup.version, BACKUP_VERSION);
+        assert_eq!(backup.quotes.len(), 1);
+        assert_eq!(
+            backup.quotes[0].review_reason.as_deref(),
+            Some("written in capitals")
+        );
+        assert_eq!(backup.likes.len(), 1);
+        assert!(backup.validate().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_restore_ok() {
+        let mut mock = MockQuoteRepository::new();
+
+        mock.expect_restore().returning(|backup, _| {
+            box_future(Ok(Restored {
+                quotes: backup.quotes.len() as u64,
+                dropped_likes: 4,
+                dropped_comments: 2,
+            }))
+        });
+
+        let app = create_test_app(Arc::new(mock));
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/restore")
+                    .header("content-type", "application/json")
+                    .body(Body::from(
+                        serde_json::json!({
+                            "version": BACKUP_VERSION,
+                            "quotes": [],
+                            "likes": [],
+                            "comments": [],
+                            "game_results": []
+                        })
+                        .to_string(),
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.headers()[DROPPED_LIKES_HEADER], "4");
+        assert_eq!(response.headers()[DROPPED_COMMENTS_HEADER], "2");
+        let (status, body_str) = get_response_parts(response).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body_str.unwrap(), "0");
+    }
+
+    #[tokio::test]
+    async fn test_restore_stored() {
+        let id = Uuid::new_v4();
+        let archive = serde_json::json!({
+            "version": BACKUP_VERSION,
+            "quotes": [],
+            "likes": [],
+            "comments": [],
+            "game_results": []
+        })
+        .to_string();
+        let mut repository = MockQuoteRepository::new();
+        repository.expect_restore().returning(|_, _| {
+            box_future(Ok(Restored {
+                quotes: 0,
+                dropped_likes: 0,
+                dropped_comments: 0,
+            }))
+        });
+        let mut uploads = MockUploadRepository::new();
+        uploads
+            .expect_store()
+            .withf(|kind, _| kind == RESTORE_UPLOAD)
+            .times(1)
+            .returning(move |_, _| box_future(Ok(Some(id))));
+        let expected = archive.clone();
+        uploads
+            .expect_load()
+            .with(eq(RESTORE_UPLOAD), eq(id))
+            .returning(move |_, _| box_future(Ok(Some(expected.clone().into_bytes()))));
+        let state = RestoreState {
+            repository: Arc::new(repository),
+            uploads: Some(Arc::new(uploads)),
+        };
+        let app = Router::new().nest(
+            "/v1/admin",
+            Router::new()
+                .route("/restore", post(restore))
+                .route("/restore/:upload_id", get(restored_archive))
+                .with_state(state),
+        );
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/v1/admin/restore")
+                    .header("content-type", "application/json")
+                    .body(Body::from(archive.clone()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[UPLOAD_ID_HEADER], id.to_string());
+        let location = response.headers()[header::LOCATION].to_str().unwrap();
+        assert_eq!(location, format!("/v1/admin/restore/{}", id));
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri(location)
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let (status, body_str) = get_response_parts(response).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body_str.unwrap(), archive);
+    }
+
+    #[tokio::test]
+    async fn test_restore_like_of_unknown_quote() {
+        let app = create_test_app(Arc::new(MockQuoteRepository::new()));
+
+        let backup = serde_json::json!({
+            "version": BACKUP_VERSION,
+            "quotes": [],
+            "likes": [{
+                "quote_id": Uuid::new_v4(),
+                "client_id": "player:santa",
+                "created_at": "2024-12-24T00:00:00Z"
+            }],
+            "comments": [],
+            "game_results": []
+        });
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/restore")
+                    .header("content-type", "application/json")
+                    .body(Body::from(backup.to_string()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let (status, _) = get_response_parts(response).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[tokio::test]
+    async fn test_restore_unsupported_version() {
+        let app = create_test_app(Arc::new(MockQuoteRepository::new()));
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/restore")
+                    .header("content-type", "application/json")
+                    .body(Body::from("{\"version\":999,\"quotes\":[]}"))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let (status, _) = get_response_parts(response).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[tokio::test]
+    async fn test_list_ndjson() {
+        let mut mock = MockQuoteRepository::new();
+        let quotes = vec![
+            Quote {
+                id: Uuid::new_v4(),
+                author: "Author 1".to_string(),
+                quote: "Quote 1".to_string(),
+                created_at: Utc::now(),
+                version: 1,
+                likes: 0,
+                publish_at: None,
+                status: QuoteStatus::Published,
+            },
+            Quote {
+                id: Uuid::new_v4(),
+                author: "Author 2".to_string(),
+                quote: "Quote 2".to_string(),
+                created_at: Utc::now(),
+                version: 1,
+                likes: 0,
+                publish_at: None,
+                status: QuoteStatus::Published,
+            },
+        ];
+
+        mock.expect_count_quotes().returning(|| box_future(Ok(2)));
+
+        mock.expect_get_quotes()
+            .with(eq(0), eq(PAGE_SIZE))
+            .returning(move |_, _| box_future(Ok(quotes.clone())));
+
+        let app = create_test_app(Arc::new(mock));
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/list")
+                    .header("accept", "application/x-ndjson")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.headers().get("x-page").unwrap(), "1");
+        assert!(response.headers().get("x-next-token").is_none());
 
-    fn create_test_app(repository: Arc<dyn QuoteRepository>) -> Router {
-        let state = DbState {
-            repository,
-            tokens: Arc::new(Mutex::new(HashMap::new())),
-        };
+        let (status, body_str) = get_response_parts(response).await;
+        assert_eq!(status, StatusCode::OK);
 
-        Router::new()
-            .route("/cite/:id", get(cite))
-            .route("/draft", post(draft))
-            .route("/remove/:id", delete(remove))
-            .route("/undo/:id", put(undo))
-            .route("/list", get(list))
-            .route("/reset", post(reset_quotes))
-            .with_state(state)
+        let lines: Vec<Quote> = body_str
+            .unwrap()
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[1].author, "Author 2");
     }
 
-    fn box_future<T>(value: T) -> Pin<Box<dyn Future<Output = T> + Send>>
-    where
-        T: Send + 'static,
-    {
-        Box::pin(ready(value))
+    fn liked(id: Uuid, likes: i64) -> Quote {
+        Quote {
+            id,
+            author: "Author".to_string(),
+            quote: "Quote".to_string(),
+            created_at: Utc::now(),
+            version: 1,
+            likes,
+            publish_at: None,
+            status: QuoteStatus::Published,
+        }
     }
 
     #[tokio::test]
-    async fn test_cite_ok() {
+    async fn test_like_by_player() {
         let mut mock = MockQuoteRepository::new();
         let quote_id = Uuid::new_v4();
-        let expected_quote = Quote {
-            id: quote_id,
-            author: "Test Author".to_string(),
-            quote: "Test Quote".to_string(),
-            created_at: Utc::now(),
-            version: 1,
+        mock.expect_like()
+            .with(eq(quote_id), eq("player:Santa".to_string()))
+            .returning(|id, _| box_future(Ok(liked(id, 1))));
+        mock.expect_unlike()
+            .with(eq(quote_id), eq("player:Santa".to_string()))
+            .returning(|id, _| box_future(Ok(liked(id, 0))));
+        let app = create_test_app(Arc::new(mock));
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri(format!("/cite/{}/like", quote_id))
+                    .header("cookie", format!("{}={}", PLAYER_COOKIE, SANTA))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let (status, body_str) = get_response_parts(response).await;
+        assert_eq!(status, StatusCode::OK);
+        let quote: Quote = serde_json::from_str(&body_str.unwrap()).unwrap();
+        assert_eq!(quote.likes, 1);
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("DELETE")
+                    .uri(format!("/cite/{}/like", quote_id))
+                    .header("cookie", format!("{}={}", PLAYER_COOKIE, SANTA))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let (status, body_str) = get_response_parts(response).await;
+        assert_eq!(status, StatusCode::OK);
+        let quote: Quote = serde_json::from_str(&body_str.unwrap()).unwrap();
+        assert_eq!(quote.likes, 0);
+    }
+
+    #[tokio::test]
+    async fn test_like_errors() {
+        let mut mock = MockQuoteRepository::new();
+        mock.expect_like()
+            .returning(|_, _| box_future(Err(sqlx::Error::RowNotFound)));
+        let app = create_test_app(Arc::new(mock));
+
+        let request = |header: Option<(&str, String)>| {
+            let mut request = Request::builder()
+                .method("POST")
+                .uri(format!("/cite/{}/like", Uuid::new_v4()));
+            if let Some((name, value)) = header {
+                request = request.header(name, value);
+            }
+            request.body(Body::empty()).unwrap()
         };
 
-        mock.expect_get()
-            .with(eq(quote_id))
-            .returning(move |_| box_future(Ok(expected_quote.clone())));
+        let response = app.clone().oneshot(request(None)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        // made up credentials don't make a client
+        let forged = [
+            (API_KEY_HEADER, "elf".to_string()),
+            ("cookie", "session=abc".to_string()),
+            ("cookie", format!("{}={}", PLAYER_COOKIE, Uuid::new_v4())),
+        ];
+        for header in forged {
+            let response = app.clone().oneshot(request(Some(header))).await.unwrap();
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        }
+
+        let player = ("cookie", format!("{}={}", PLAYER_COOKIE, SANTA));
+        let response = app.oneshot(request(Some(player))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
 
+    #[tokio::test]
+    async fn test_top() {
+        let mut mock = MockQuoteRepository::new();
+        mock.expect_top_liked()
+            .with(eq(MAX_TOP_LIMIT))
+            .returning(|_| box_future(Ok(vec![liked(Uuid::new_v4(), 3)])));
         let app = create_test_app(Arc::new(mock));
 
         let response = app
+            .clone()
             .oneshot(
                 Request::builder()
-                    .uri(format!("/cite/{}", quote_id))
+                    .uri("/top?by=likes&limit=1000")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let (status, body_str) = get_response_parts(response).await;
+        assert_eq!(status, StatusCode::OK);
+        let quotes: Vec<Quote> = serde_json::from_str(&body_str.unwrap()).unwrap();
+        assert_eq!(quotes[0].likes, 3);
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/top?by=views")
                     .body(Body::empty())
                     .unwrap(),
             )
             .await
             .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_suggest_authors() {
+        let mut mock = MockQuoteRepository::new();
+        mock.expect_suggest_authors()
+            .with(eq("Santa Claws".to_string()), eq(SUGGEST_LIMIT))
+            .returning(|_, _| {
+                box_future(Ok(vec![AuthorMatch {
+                    author: "Santa Claus".to_string(),
+                    similarity: 0.6,
+                    quotes: 2,
+                }]))
+            });
+        let app = create_test_app(Arc::new(mock));
 
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri("/authors/suggest?q=%20Santa%20Claws")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
         let (status, body_str) = get_response_parts(response).await;
         assert_eq!(status, StatusCode::OK);
+        let authors: Vec<AuthorMatch> = serde_json::from_str(&body_str.unwrap()).unwrap();
+        assert_eq!(authors[0].author, "Santa Claus");
 
-        let response_quote: Quote = serde_json::from_str(&body_str.unwrap()).unwrap();
-        assert_eq!(response_quote.id, quote_id);
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/authors/suggest?q=%20")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
     }
 
     #[tokio::test]
-    async fn test_draft_ok() {
+    async fn test_draft_scheduled() {
         let mut mock = MockQuoteRepository::new();
+        let publish_at = Utc::now() + chrono::Duration::days(1);
         let new_quote = NewQuote {
-            author: "New Author".to_string(),
-            quote: "New Quote".to_string(),
+            author: "Santa".to_string(),
+            quote: "Soon".to_string(),
+            publish_at: Some(publish_at),
         };
 
         mock.expect_create()
             .with(eq(new_quote.clone()))
             .returning(|q| {
-                box_future(Ok(Quote {
-                    id: Uuid::new_v4(),
-                    author: q.author,
-                    quote: q.quote,
-                    created_at: Utc::now(),
-                    version: 1,
-                }))
+                let mut quote = liked(Uuid::new_v4(), 0);
+                quote.publish_at = q.publish_at;
+                quote.status = QuoteStatus::Draft;
+                box_future(Ok(quote))
             });
 
-        let app = create_test_app(Arc::new(mock));
-
-        let response = app
+        let response = create_test_app(Arc::new(mock))
             .oneshot(
                 Request::builder()
                     .method("POST")
@@ -363,66 +2596,76 @@ mod tests {
             .await
             .unwrap();
 
-        let (status, _) = get_response_parts(response).await;
+        let (status, body_str) = get_response_parts(response).await;
         assert_eq!(status, StatusCode::CREATED);
+        let quote: serde_json::Value = serde_json::from_str(&body_str.unwrap()).unwrap();
+        assert_eq!(quote["status"], "draft");
+        assert!(quote["publish_at"].is_string());
     }
 
     #[tokio::test]
-    async fn test_list_first_page_ok() {
+    async fn test_instrumented_repository() {
         let mut mock = MockQuoteRepository::new();
-        let quotes = vec![Quote {
-            id: Uuid::new_v4(),
-            author: "Author 1".to_string(),
-            quote: "Quote 1".to_string(),
-            created_at: Utc::now(),
-            version: 1,
-        }];
-
-        mock.expect_count_quotes().returning(|| box_future(Ok(1)));
+        let quote_id = Uuid::new_v4();
+        mock.expect_like()
+            .with(eq(quote_id), eq("key:elf".to_string()))
+            .returning(|id, _| box_future(Ok(liked(id, 1))));
+        let repository = InstrumentedQuoteRepository::wrap(Arc::new(mock), Duration::ZERO);
+
+        let before = instrument::TIMINGS
+            .snapshot()
+            .get("quotes.like")
+            .map(|t| t.calls)
+            .unwrap_or(0);
+        let quote = repository
+            .like(quote_id, "key:elf".to_string())
+            .await
+            .unwrap();
+        assert_eq!(quote.likes, 1);
 
-        mock.expect_get_quotes()
-            .with(eq(0), eq(PAGE_SIZE))
-            .returning(move |_, _| box_future(Ok(quotes.clone())));
+        let timing = &instrument::TIMINGS.snapshot()["quotes.like"];
+        assert!(timing.calls > before);
+        assert!(timing.slow > 0);
+    }
 
-        let app = create_test_app(Arc::new(mock));
+    #[tokio::test]
+    async fn test_list_head() {
+        let mut mock = MockQuoteRepository::new();
+        mock.expect_count_quotes().returning(|| box_future(Ok(7)));
 
-        let response = app
-            .oneshot(Request::builder().uri("/list").body(Body::empty()).unwrap())
+        let response = create_test_app(Arc::new(mock))
+            .oneshot(
+                Request::builder()
+                    .method("HEAD")
+                    .uri("/list")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
             .await
             .unwrap();
 
+        assert_eq!(response.headers()["x-total-count"], "7");
+        assert_eq!(response.headers()["x-total-pages"], "3");
         let (status, body_str) = get_response_parts(response).await;
         assert_eq!(status, StatusCode::OK);
-
-        let response_quotes: Quotes = serde_json::from_str(&body_str.unwrap()).unwrap();
-        assert_eq!(response_quotes.quotes.len(), 1);
-        assert_eq!(response_quotes.page, 1);
-        assert_eq!(response_quotes.next_token, None);
+        assert!(body_str.is_none());
     }
 
     #[tokio::test]
-    async fn test_remove_ok() {
+    async fn test_count_filtered() {
         let mut mock = MockQuoteRepository::new();
-        let quote_id = Uuid::new_v4();
-        let quote = Quote {
-            id: quote_id,
-            author: "Author".to_string(),
-            quote: "Quote".to_string(),
-            created_at: Utc::now(),
-            version: 1,
-        };
-
-        mock.expect_delete()
-            .with(eq(quote_id))
-            .returning(move |_| box_future(Ok(quote.clone())));
-
-        let app = create_test_app(Arc::new(mock));
-
-        let response = app
+        mock.expect_count_matching()
+            .with(eq(CountFilter {
+                author: Some("Santa".to_string()),
+                from: Some("2024-12-01T00:00:00Z".parse().unwrap()),
+                to: None,
+            }))
+            .returning(|_| box_future(Ok(2)));
+
+        let response = create_test_app(Arc::new(mock))
             .oneshot(
                 Request::builder()
-                    .method("DELETE")
-                    .uri(format!("/remove/{}", quote_id))
+                    .uri("/count?author=Santa&from=2024-12-01T00:00:00Z")
                     .body(Body::empty())
                     .unwrap(),
             )
@@ -431,77 +2674,213 @@ mod tests {
 
         let (status, body_str) = get_response_parts(response).await;
         assert_eq!(status, StatusCode::OK);
-
-        let response_quote: Quote = serde_json::from_str(&body_str.unwrap()).unwrap();
-        assert_eq!(response_quote.id, quote_id);
+        assert_eq!(body_str.unwrap(), r#"{"count":2}"#);
     }
 
     #[tokio::test]
-    async fn test_undo_ok() {
+    async fn test_draft_rejected_by_moderation() {
+        let mut moderator = MockModerator::new();
+        moderator.expect_review().returning(|_| {
+            box_future(Verdict::Reject(
+                "contains the denied word grinch".to_string(),
+            ))
+        });
         let mut mock = MockQuoteRepository::new();
-        let quote_id = Uuid::new_v4();
-        let new_quote = NewQuote {
-            author: "Updated Author".to_string(),
-            quote: "Updated Quote".to_string(),
-        };
+        mock.expect_create().never();
 
-        mock.expect_update()
-            .with(eq(quote_id), eq(new_quote.clone()))
-            .returning(|id, q| {
+        let app = create_moderated_app(Arc::new(mock), Arc::new(moderator));
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/draft")
+                    .header("content-type", "application/json")
+                    .body(Body::from(r#"{"author":"Grinch","quote":"Bah"}"#))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let (status, body) = get_response_parts(response).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(
+            body.as_deref(),
+            Some(r#"{"errors":[{"path":"","message":"contains the denied word grinch"}]}"#)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_draft_flagged_by_moderation() {
+        let mut moderator = MockModerator::new();
+        moderator
+            .expect_review()
+            .returning(|_| box_future(Verdict::Flag("written in capitals".to_string())));
+
+        // held in the one insert, the plain create would announce the quote
+        let mut mock = MockQuoteRepository::new();
+        mock.expect_create().never();
+        mock.expect_begin().never();
+        mock.expect_create_flagged()
+            .with(always(), eq("written in capitals".to_string()))
+            .times(1)
+            .returning(|q, _| {
                 box_future(Ok(Quote {
-                    id,
                     author: q.author,
                     quote: q.quote,
-                    created_at: Utc::now(),
-                    version: 2,
+                    status: QuoteStatus::PendingReview,
+                    ..liked(Uuid::new_v4(), 0)
                 }))
             });
 
-        let app = create_test_app(Arc::new(mock));
-
+        let app = create_moderated_app(Arc::new(mock), Arc::new(moderator));
         let response = app
             .oneshot(
                 Request::builder()
-                    .method("PUT")
-                    .uri(format!("/undo/{}", quote_id))
+                    .method("POST")
+                    .uri("/draft")
                     .header("content-type", "application/json")
-                    .body(Body::from(serde_json::to_string(&new_quote).unwrap()))
+                    .body(Body::from(r#"{"author":"SANTA","quote":"HO HO HO"}"#))
                     .unwrap(),
             )
             .await
             .unwrap();
 
-        let (status, body_str) = get_response_parts(response).await;
-        assert_eq!(status, StatusCode::OK);
-
-        let response_quote: Quote = serde_json::from_str(&body_str.unwrap()).unwrap();
-        assert_eq!(response_quote.id, quote_id);
-        assert_eq!(response_quote.author, new_quote.author);
-        assert_eq!(response_quote.quote, new_quote.quote);
-        assert_eq!(response_quote.version, 2);
+        let (status, body) = get_response_parts(response).await;
+        assert_eq!(status, StatusCode::CREATED);
+        let quote: Quote = serde_json::from_str(&body.unwrap()).unwrap();
+        assert_eq!(quote.status, QuoteStatus::PendingReview);
     }
 
     #[tokio::test]
-    async fn test_reset_ok() {
+    async fn test_undo_flagged_by_moderation() {
+        let id = Uuid::new_v4();
+        let mut moderator = MockModerator::new();
+        moderator
+            .expect_review()
+            .returning(|_| box_future(Verdict::Flag("written in capitals".to_string())));
+
         let mut mock = MockQuoteRepository::new();
+        mock.expect_update().never();
+        mock.expect_update_flagged()
+            .with(eq(id), always(), eq("written in capitals".to_string()))
+            .times(1)
+            .returning(|id, q, _| {
+                box_future(Ok(Quote {
+                    author: q.author,
+                    quote: q.quote,
+                    status: QuoteStatus::PendingReview,
+                    ..liked(id, 0)
+                }))
+            });
 
-        mock.expect_reset_quotes()
-            .returning(|| box_future(Ok(PgQueryResult::default())));
+        let app = create_moderated_app(Arc::new(mock), Arc::new(moderator));
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("PUT")
+                    .uri(format!("/undo/{}", id))
+                    .header("content-type", "application/json")
+                    .body(Body::from(r#"{"author":"SANTA","quote":"HO HO HO"}"#))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
 
-        let app = create_test_app(Arc::new(mock));
+        let (status, body) = get_response_parts(response).await;
+        assert_eq!(status, StatusCode::OK);
+        let quote: Quote = serde_json::from_str(&body.unwrap()).unwrap();
+        assert_eq!(quote.status, QuoteStatus::PendingReview);
+    }
 
+    fn bulk_app(tx: MockQuoteTransaction, max_rows: u64) -> Router {
+        let mut mock = MockQuoteRepository::new();
+        let tx = std::sync::Mutex::new(Some(tx));
+        mock.expect_begin().returning(move || {
+            let tx: Box<dyn QuoteTransaction> = Box::new(tx.lock().unwrap().take().unwrap());
+            box_future(Ok(tx))
+        });
+        let state = BulkDeleteState {
+            repository: Arc::new(mock),
+            max_rows,
+        };
+        Router::new()
+            .route("/quotes", delete(bulk_delete))
+            .with_state(state)
+    }
+
+    async fn bulk_request(app: Router, query: &str) -> (StatusCode, Option<String>) {
         let response = app
             .oneshot(
                 Request::builder()
-                    .method("POST")
-                    .uri("/reset")
+                    .method("DELETE")
+                    .uri(format!("/quotes?{}", query))
                     .body(Body::empty())
                     .unwrap(),
             )
             .await
             .unwrap();
+        get_response_parts(response).await
+    }
 
-        let (status, _) = get_response_parts(response).await;
+    #[tokio::test]
+    async fn test_bulk_delete_requires_filter_and_confirmation() {
+        for query in [
+            "dry_run=true",
+            "author=Santa",
+            "author=Santa&tag=xmas&confirm=true",
+        ] {
+            let (status, _) = bulk_request(bulk_app(MockQuoteTransaction::new(), 10), query).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{}", query);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_bulk_delete_dry_run() {
+        let mut tx = MockQuoteTransaction::new();
+        tx.expect_delete_matching()
+            .with(eq(DeleteFilter {
+                author: Some("Santa".to_string()),
+                created_before: None,
+            }))
+            .returning(|_| box_future(Ok(2)));
+        tx.expect_rollback()
+            .times(1)
+            .returning(|| box_future(Ok(())));
+        tx.expect_commit().never();
+
+        let (status, body) = bulk_request(bulk_app(tx, 10), "author=Santa&dry_run=true").await;
+        assert_eq!(status, StatusCode::OK);
+        let preview: Preview<RowsAffected> = serde_json::from_str(&body.unwrap()).unwrap();
+        assert!(preview.dry_run);
+        assert_eq!(preview.effect.rows_affected, 2);
+    }
+
+    #[tokio::test]
+    async fn test_bulk_delete_over_cap() {
+        let mut tx = MockQuoteTransaction::new();
+        tx.expect_delete_matching().returning(|_| box_future(Ok(5)));
+        tx.expect_rollback()
+            .times(1)
+            .returning(|| box_future(Ok(())));
+        tx.expect_commit().never();
+
+        let (status, _) = bulk_request(
+            bulk_app(tx, 3),
+            "created_before=2024-12-01T00:00:00Z&confirm=true",
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[tokio::test]
+    async fn test_bulk_delete_confirmed() {
+        let mut tx = MockQuoteTransaction::new();
+        tx.expect_delete_matching().returning(|_| box_future(Ok(3)));
+        tx.expect_commit().times(1).returning(|| box_future(Ok(())));
+
+        let (status, body) = bulk_request(bulk_app(tx, 3), "author=Santa&confirm=true").await;
         assert_eq!(status, StatusCode::OK);
+        let deleted: RowsAffected = serde_json::from_str(&body.unwrap()).unwrap();
+        assert_eq!(deleted.rows_affected, 3);
     }
 }
@@ -1,32 +1,121 @@
 use std::net::{Ipv4Addr, Ipv6Addr};
 
-use axum::extract::Query;
+use axum::http::{Method, StatusCode};
 
-#[derive(Debug, serde::Deserialize)]
+use crate::{
+    openapi::Operation,
+    validation::{FromParams, Params, ValidatedQuery},
+};
+
+#[derive(Debug)]
 pub struct DestV4 {
     from: Ipv4Addr,
     key: Ipv4Addr,
 }
 
-#[derive(Debug, serde::Deserialize)]
+#[derive(Debug)]
 pub struct KeyV4 {
     from: Ipv4Addr,
     to: Ipv4Addr,
 }
 
-#[derive(Debug, serde::Deserialize)]
+#[derive(Debug)]
 pub struct DestV6 {
     from: Ipv6Addr,
     key: Ipv6Addr,
 }
 
-#[derive(Debug, serde::Deserialize)]
+#[derive(Debug)]
 pub struct KeyV6 {
     from: Ipv6Addr,
     to: Ipv6Addr,
 }
 
-pub async fn dest_v4(from_key: Query<DestV4>) -> String {
+impl FromParams for DestV4 {
+    fn from_params(params: &mut Params) -> Option<Self> {
+        let from = params.required("from");
+        let key = params.required("key");
+        Some(Self {
+            from: from?,
+            key: key?,
+        })
+    }
+}
+
+impl FromParams for KeyV4 {
+    fn from_params(params: &mut Params) -> Option<Self> {
+        let from = params.required("from");
+        let to = params.required("to");
+        Some(Self {
+            from: from?,
+            to: to?,
+        })
+    }
+}
+
+impl FromParams for DestV6 {
+    fn from_params(params: &mut Params) -> Option<Self> {
+        let from = params.required("from");
+        let key = params.required("key");
+        Some(Self {
+            from: from?,
+            key: key?,
+        })
+    }
+}
+
+impl FromParams for KeyV6 {
+    fn from_params(params: &mut Params) -> Option<Self> {
+        let from = params.required("from");
+        let to = params.required("to");
+        Some(Self {
+            from: from?,
+            to: to?,
+        })
+    }
+}
+
+pub fn operations() -> Vec<Operation> {
+    let address = |path, summary, first, second, family| {
+        Operation::new(Method::GET, path, summary)
+            .required_query(first, "string", family)
+            .required_query(second, "string", family)
+            .media_response(StatusCode::OK, "text/plain", "The computed address")
+            .error(StatusCode::BAD_REQUEST, "Missing or malformed addresses")
+    };
+    vec![
+        address(
+            "/2/dest",
+            "Encrypts an IPv4 address",
+            "from",
+            "key",
+            "IPv4 address",
+        ),
+        address(
+            "/2/key",
+            "Recovers an IPv4 key",
+            "from",
+            "to",
+            "IPv4 address",
+        ),
+        address(
+            "/2/v6/dest",
+            "Encrypts an IPv6 address",
+            "from",
+            "key",
+            "IPv6 address",
+        ),
+        address(
+            "/2/v6/key",
+            "Recovers an IPv6 key",
+            "from",
+            "to",
+            "IPv6 address",
+        ),
+    ]
+}
+
+pub async fn dest_v4(ValidatedQuery(from_key): ValidatedQuery<DestV4>) -> String {
     from_key
         .from
         .octets()
@@ -37,7 +126,7 @@ pub async fn dest_v4(from_key: Query<DestV4>) -> String {
         .join(".")
 }
 
-pub async fn key_v4(from_to: Query<KeyV4>) -> String {
+pub async fn key_v4(ValidatedQuery(from_to): ValidatedQuery<KeyV4>) -> String {
     from_to
         .to
         .octets()
@@ -48,7 +137,7 @@ pub async fn key_v4(from_to: Query<KeyV4>) -> String {
         .join(".")
 }
 
-pub async fn dest_v6(from_key: Query<DestV6>) -> String {
+pub async fn dest_v6(ValidatedQuery(from_key): ValidatedQuery<DestV6>) -> String {
     let segments: [u16; 8] = from_key
         .from
         .segments()
@@ -61,7 +150,7 @@ pub async fn dest_v6(from_key: Query<DestV6>) -> String {
     Ipv6Addr::from(segments).to_string()
 }
 
-pub async fn key_v6(from_to: Query<KeyV6>) -> String {
+pub async fn key_v6(ValidatedQuery(from_to): ValidatedQuery<KeyV6>) -> String {
     let segments: [u16; 8] = from_to
         .to
         .segments()
@@ -76,10 +165,34 @@ pub async fn key_v6(from_to: Query<KeyV6>) -> String {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use axum::{body::Body, http::Request, routing::get, Router};
+    use http_body_util::BodyExt;
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn test_invalid_addresses() {
+        let response = Router::new()
+            .route("/2/dest", get(dest_v4))
+            .oneshot(
+                Request::builder()
+                    .uri("/2/dest?from=10.0.0.256")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), axum::http::StatusCode::BAD_REQUEST);
+
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let errors = serde_json::from_slice::<serde_json::Value>(&body).unwrap()["errors"].clone();
+        assert_eq!(errors[0]["path"], "from");
+        assert_eq!(errors[1]["path"], "key");
+        assert_eq!(errors[1]["message"], "missing");
+    }
 
     #[tokio::test]
     async fn test_dest_v4() {
-        let from_key = Query(DestV4 {
+        let from_key = ValidatedQuery(DestV4 {
             from: Ipv4Addr::new(192, 168, 1, 1),
             key: Ipv4Addr::new(10, 0, 0, 1),
         });
@@ -89,7 +202,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_key_v4() {
-        let from_to = Query(KeyV4 {
+        let from_to = ValidatedQuery(KeyV4 {
             from: Ipv4Addr::new(192, 168, 1, 1),
             to: Ipv4Addr::new(202, 168, 1, 2),
         });
@@ -99,7 +212,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_dest_v6() {
-        let from_key = Query(DestV6 {
+        let from_key = ValidatedQuery(DestV6 {
             from: Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1),
             key: Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 2),
         });
@@ -109,7 +222,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_key_v6() {
-        let from_to = Query(KeyV6 {
+        let from_to = ValidatedQuery(KeyV6 {
             from: Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1),
             to: Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 3),
         });
@@ -119,7 +232,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_dest_v4_zero() {
-        let from_key = Query(DestV4 {
+        let from_key = ValidatedQuery(DestV4 {
             from: Ipv4Addr::new(0, 0, 0, 0),
             key: Ipv4Addr::new(0, 0, 0, 0),
         });
@@ -129,7 +242,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_key_v4_zero() {
-        let from_to = Query(KeyV4 {
+        let from_to = ValidatedQuery(KeyV4 {
             from: Ipv4Addr::new(0, 0, 0, 0),
             to: Ipv4Addr::new(0, 0, 0, 0),
         });
@@ -139,7 +252,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_dest_v6_zero() {
-        let from_key = Query(DestV6 {
+        let from_key = ValidatedQuery(DestV6 {
             from: Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 0),
             key: Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 0),
         });
@@ -149,7 +262,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_key_v6_zero() {
-        let from_to = Query(KeyV6 {
+        let from_to = ValidatedQuery(KeyV6 {
             from: Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 0),
             to: Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 0),
         });
@@ -159,7 +272,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_dest_v4_overflow() {
-        let from_key = Query(DestV4 {
+        let from_key = ValidatedQuery(DestV4 {
             from: Ipv4Addr::new(255, 255, 255, 255),
             key: Ipv4Addr::new(255, 255, 255, 255),
         });
@@ -169,7 +282,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_key_v4_overflow() {
-        let from_to = Query(KeyV4 {
+        let from_to = ValidatedQuery(KeyV4 {
             from: Ipv4Addr::new(255, 255, 255, 255),
             to: Ipv4Addr::new(254, 254, 254, 254),
         });
@@ -179,7 +292,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_dest_v6_max() {
-        let from_key = Query(DestV6 {
+        let from_key = ValidatedQuery(DestV6 {
             from: Ipv6Addr::new(
                 0xffff, 0xffff, 0xffff, 0xffff, 0xffff, 0xffff, 0xffff, 0xffff,
             ),
@@ -193,7 +306,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_key_v6_max() {
-        let from_to = Query(KeyV6 {
+        let from_to = ValidatedQuery(KeyV6 {
             from: Ipv6Addr::new(
                 0xffff, 0xffff, 0xffff, 0xffff, 0xffff, 0xffff, 0xffff, 0xffff,
             ),
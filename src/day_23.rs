@@ -1,9 +1,64 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    net::IpAddr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
 use axum::{
-    extract::{Multipart, Path},
-    http::StatusCode,
-    response::IntoResponse,
+    extract::{Multipart, Path, Query, State},
+    http::{header, HeaderName, Method, StatusCode},
+    response::{IntoResponse, Response},
+};
+use axum_extra::extract::{cookie::Cookie, CookieJar};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+use crate::{
+    app_error::AppError,
+    client_ip::ClientIp,
+    jobs::{self, JobOutput, JobRepository},
+    links::LinkBuilder,
+    multipart::{self, Parts},
+    negotiate::{Accept, Format},
+    openapi::Operation,
+    preflight::RouteBudget,
+    rate_limit::client_key,
+    theme::Theme,
+    uploads::{self, UploadRepository, UPLOAD_ID_HEADER},
 };
-use serde::Deserialize;
+
+const GRAPH_NODE_WIDTH: usize = 160;
+const GRAPH_NODE_HEIGHT: usize = 30;
+const GRAPH_GAP: usize = 20;
+/// Kind of the uploads of `/23/lockfile`
+const LOCKFILE_UPLOAD: &str = "lockfile";
+const LOCKFILE_LIMITS: multipart::Limits = multipart::Limits {
+    field: 1024 * 1024,
+    total: 2 * 1024 * 1024,
+};
+/// Room for the boundaries and headers of the parts around the lockfile
+const MULTIPART_OVERHEAD: u64 = 64 * 1024;
+/// Graphs of larger lockfiles are rendered by a job rather than in the request
+const ASYNC_GRAPH_SIZE: usize = 256 * 1024;
+pub const LOCKFILE_GRAPH_JOB: &str = "lockfile_graph";
+
+#[derive(Clone)]
+pub struct LockfileState {
+    /// Where lockfiles are kept, only with `STORE_UPLOADS` on
+    pub uploads: Option<Arc<dyn UploadRepository>>,
+    /// Where large graphs are queued, without it every graph is rendered in the request
+    pub jobs: Option<Arc<dyn JobRepository>>,
+}
+
+/// Payload of the lockfile graph jobs
+#[derive(Serialize, Deserialize)]
+struct GraphJob {
+    content: String,
+    content_type: String,
+}
 
 #[derive(Deserialize)]
 struct Package {
@@ -15,7 +70,272 @@ struct Lockfile {
     package: Vec<Package>,
 }
 
-fn escape_string(s: &str) -> String {
+/// Cookie naming the scene of a client
+const SCENE_COOKIE: &str = "scene";
+/// Ornaments hung on the tree of `23.html`
+const ORNAMENTS: usize = 7;
+const PRESENTS: usize = 3;
+/// Scenes untouched for this long are forgotten
+const SCENE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+/// Beyond this, the least recently touched scene makes room for a new one
+const MAX_SCENES: usize = 10_000;
+/// Parts of the tree that never change, as laid out in `23.html`
+const TREE_PARTS: &str = "<div class=\"tree-base\"></div><div class=\"tree-part tree-part5\"></div><div class=\"tree-part tree-part4\"></div><div class=\"tree-part tree-part3\"></div><div class=\"tree-part tree-part2\"></div><div class=\"tree-part tree-part1\"></div>";
+const PRESENT_WRAPS: &str = "<div class=\"present-wrap present-wrap1\"></div><div class=\"present-wrap present-wrap2\"></div><div class=\"present-wrap present-wrap3\"></div><div class=\"present-wrap present-wrap4\"></div>";
+const SWITCH: &str = "<button id=\"switch\" hx-get=\"/23/star\" hx-swap=\"outerHTML\" hx-target=\"#star\">Light the star</button>";
+
+/// What a client's tree currently shows
+#[derive(Debug, Clone, PartialEq)]
+struct Scene {
+    star: bool,
+    present: &'static str,
+    /// Whether each ornament is on, the first one being `ornament1`
+    ornaments: [bool; ORNAMENTS],
+    touched: Instant,
+}
+
+impl Default for Scene {
+    fn default() -> Self {
+        Self {
+            star: false,
+            present: "red",
+            ornaments: [false; ORNAMENTS],
+            touched: Instant::now(),
+        }
+    }
+}
+
+impl Scene {
+    fn render(&self, theme: &Theme) -> String {
+        let (_, next) = next_present(self.present).unwrap();
+        let mut html = String::from("<div class=\"tree\">");
+        for _ in 0..PRESENTS {
+            html.push_str(&present_div(self.present, next, theme));
+        }
+        html.push_str(TREE_PARTS);
+        for (i, on) in self.ornaments.iter().enumerate() {
+            html.push_str(&ornament_div(*on, &(i + 1).to_string(), theme));
+        }
+        html.push_str(PRESENT_WRAPS);
+        html.push_str(&match self.star {
+            true => star_lit(theme),
+            false => "<div id=\"star\"></div>".to_string(),
+        });
+        html.push_str(SWITCH);
+        html.push_str("</div>");
+        html
+    }
+}
+
+/// Scenes of every client, so that a page reload picks the animation up where it was
+#[derive(Clone, Default)]
+pub struct SceneRegistry {
+    scenes: Arc<Mutex<HashMap<Uuid, Scene>>>,
+}
+
+impl SceneRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn session(jar: &CookieJar) -> Option<Uuid> {
+        jar.get(SCENE_COOKIE).and_then(|c| c.value().parse().ok())
+    }
+
+    /// Applies a change to the client's scene, starting a new one when it has none
+    async fn update(&self, jar: CookieJar, change: impl FnOnce(&mut Scene)) -> CookieJar {
+        let mut scenes = self.scenes.lock().await;
+        let (id, jar) = match Self::session(&jar).filter(|id| scenes.contains_key(id)) {
+            Some(id) => (id, jar),
+            None => {
+                scenes.retain(|_, scene| scene.touched.elapsed() < SCENE_TTL);
+                if scenes.len() >= MAX_SCENES {
+                    let oldest = scenes
+                        .iter()
+                        .min_by_key(|(_, s)| s.touched)
+                        .map(|(id, _)| *id);
+                    if let Some(oldest) = oldest {
+                        scenes.remove(&oldest);
+                    }
+                }
+                let id = Uuid::new_v4();
+                scenes.insert(id, Scene::default());
+                let cookie = Cookie::build((SCENE_COOKIE, id.to_string()))
+                    .path("/23")
+                    .http_only(true);
+                (id, jar.add(cookie))
+            }
+        };
+
+        let scene = scenes.get_mut(&id).unwrap();
+        change(scene);
+        scene.touched = Instant::now();
+        jar
+    }
+
+    async fn get(&self, jar: &CookieJar) -> Scene {
+        let scenes = self.scenes.lock().await;
+        Self::session(jar)
+            .and_then(|id| scenes.get(&id).cloned())
+            .unwrap_or_default()
+    }
+}
+
+/// What `/23/lockfile` draws from the lockfile
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LockfileView {
+    /// A box per checksum, as the challenge has it
+    #[default]
+    Boxes,
+    /// Dependency graph of the packages, as SVG or a JSON adjacency list
+    Graph,
+}
+
+#[derive(Deserialize)]
+pub struct LockfileQuery {
+    #[serde(default)]
+    view: LockfileView,
+}
+
+/// Lockfile as read for the graph, the boxes only need checksums
+#[derive(Deserialize)]
+struct GraphLockfile {
+    package: Vec<GraphPackage>,
+}
+
+#[derive(Deserialize)]
+struct GraphPackage {
+    name: String,
+    version: String,
+    /// `name`, or `name version` when several versions are locked
+    #[serde(default)]
+    dependencies: Vec<String>,
+}
+
+/// Packages of a lockfile, `name version`, each with the indexes of its dependencies
+struct DependencyGraph {
+    nodes: Vec<String>,
+    edges: Vec<Vec<usize>>,
+}
+
+impl DependencyGraph {
+    fn new(packages: &[GraphPackage]) -> Self {
+        let resolve = |dependency: &str| {
+            let mut parts = dependency.split_whitespace();
+            let name = parts.next()?;
+            let version = parts.next();
+            packages
+                .iter()
+                .position(|p| p.name == name && version.is_none_or(|v| p.version == v))
+        };
+
+        Self {
+            nodes: packages
+                .iter()
+                .map(|p| format!("{} {}", p.name, p.version))
+                .collect(),
+            edges: packages
+                .iter()
+                .map(|p| p.dependencies.iter().filter_map(|d| resolve(d)).collect())
+                .collect(),
+        }
+    }
+
+    fn adjacency(&self) -> BTreeMap<&str, Vec<&str>> {
+        self.nodes
+            .iter()
+            .zip(&self.edges)
+            .map(|(node, edges)| {
+                let dependencies = edges.iter().map(|&i| self.nodes[i].as_str()).collect();
+                (node.as_str(), dependencies)
+            })
+            .collect()
+    }
+
+    /// Row of each package, every dependency below the packages depending on it
+    fn levels(&self) -> Vec<usize> {
+        let mut levels = vec![0; self.nodes.len()];
+        // a lockfile has no cycles, the bound only guards against crafted ones
+        for _ in 0..self.nodes.len() {
+            let mut changed = false;
+            for (from, edges) in self.edges.iter().enumerate() {
+                for &to in edges {
+                    if levels[to] < levels[from] + 1 {
+                        levels[to] = levels[from] + 1;
+                        changed = true;
+                    }
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+        levels
+    }
+
+    fn to_svg(&self) -> String {
+        let levels = self.levels();
+        let mut rows: Vec<Vec<usize>> = vec![vec![]; levels.iter().max().map_or(0, |l| l + 1)];
+        for (node, &level) in levels.iter().enumerate() {
+            rows[level].push(node);
+        }
+        let mut positions = vec![(0, 0); self.nodes.len()];
+        for (level, row) in rows.iter().enumerate() {
+            for (column, &node) in row.iter().enumerate() {
+                positions[node] = (
+                    column * (GRAPH_NODE_WIDTH + GRAPH_GAP),
+                    level * (GRAPH_NODE_HEIGHT + GRAPH_GAP * 2),
+                );
+            }
+        }
+
+        let edges = self
+            .edges
+            .iter()
+            .enumerate()
+            .flat_map(|(from, edges)| edges.iter().map(move |&to| (from, to)))
+            .map(|(from, to)| {
+                let ((x1, y1), (x2, y2)) = (positions[from], positions[to]);
+                format!(
+                    "<line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\" stroke=\"#888\"/>",
+                    x1 + GRAPH_NODE_WIDTH / 2,
+                    y1 + GRAPH_NODE_HEIGHT,
+                    x2 + GRAPH_NODE_WIDTH / 2,
+                    y2
+                )
+            })
+            .collect::<String>();
+        let nodes = self
+            .nodes
+            .iter()
+            .zip(&positions)
+            .map(|(node, (x, y))| {
+                format!(
+                    "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" rx=\"4\" fill=\"#060\"/><text x=\"{}\" y=\"{}\" text-anchor=\"middle\" fill=\"#eee\" font-size=\"12\">{}</text>",
+                    x,
+                    y,
+                    GRAPH_NODE_WIDTH,
+                    GRAPH_NODE_HEIGHT,
+                    x + GRAPH_NODE_WIDTH / 2,
+                    y + GRAPH_NODE_HEIGHT / 2 + 4,
+                    escape_string(node)
+                )
+            })
+            .collect::<String>();
+
+        let columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+        format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\">{}{}</svg>",
+            (columns * (GRAPH_NODE_WIDTH + GRAPH_GAP)).saturating_sub(GRAPH_GAP),
+            (rows.len() * (GRAPH_NODE_HEIGHT + GRAPH_GAP * 2)).saturating_sub(GRAPH_GAP * 2),
+            edges,
+            nodes
+        )
+    }
+}
+
+pub(crate) fn escape_string(s: &str) -> String {
     s.replace("&", "&amp;")
         .replace("<", "&lt;")
         .replace(">", "&gt;")
@@ -24,46 +344,291 @@ fn escape_string(s: &str) -> String {
         .replace("/", "&#x2F;")
 }
 
-pub async fn star() -> impl IntoResponse {
-    (StatusCode::OK, "<div id=\"star\" class=\"lit\"></div>")
+fn next_present(color: &str) -> Option<(&'static str, &'static str)> {
+    match color {
+        "blue" => Some(("blue", "purple")),
+        "purple" => Some(("purple", "red")),
+        "red" => Some(("red", "blue")),
+        _ => None,
+    }
 }
 
-pub async fn present(Path(color): Path<String>) -> impl IntoResponse {
-    let color = escape_string(&color);
+fn star_lit(theme: &Theme) -> String {
+    format!("<div id=\"star\" class=\"{}\"></div>", theme.star_lit)
+}
+
+fn present_div(color: &str, next: &str, theme: &Theme) -> String {
+    format!(
+        "<div class=\"present {}\" hx-get=\"/23/present/{}\" hx-swap=\"outerHTML\"> <div class=\"ribbon\"></div><div class=\"ribbon\"></div><div class=\"ribbon\"></div><div class=\"ribbon\"></div></div>",
+        theme.present(color), next)
+}
 
-    let (class, next) = match color {
-        c if c == "blue" => ("blue", "purple"),
-        c if c == "purple" => ("purple", "red"),
-        c if c == "red" => ("red", "blue"),
-        _ => return (StatusCode::IM_A_TEAPOT, "".to_string()),
+fn ornament_div(on: bool, number: &str, theme: &Theme) -> String {
+    let (class, next_state) = match on {
+        true => (format!(" {}", theme.ornament_on), "off"),
+        false => ("".to_string(), "on"),
     };
+    format!(
+        "<div class=\"ornament{}\" id=\"ornament{}\" hx-trigger=\"load delay:2s once\" hx-get=\"/23/ornament/{}/{}\" hx-swap=\"outerHTML\"></div>",
+        class, number, next_state, number
+    )
+}
 
-    let div = format!(
-        "<div class=\"present {}\" hx-get=\"/23/present/{}\" hx-swap=\"outerHTML\"> <div class=\"ribbon\"></div><div class=\"ribbon\"></div><div class=\"ribbon\"></div><div class=\"ribbon\"></div></div>",
-        class, next);
+pub fn operations() -> Vec<Operation> {
+    let fragment = |operation: Operation| {
+        operation.media_response(StatusCode::OK, "text/html", "HTML fragment to swap in")
+    };
+    vec![
+        fragment(Operation::new(Method::GET, "/23/star", "Lights the star")),
+        fragment(Operation::new(
+            Method::GET,
+            "/23/present/:color",
+            "Wraps the next present",
+        ))
+        .error(StatusCode::IM_A_TEAPOT, "Unknown color"),
+        fragment(Operation::new(
+            Method::GET,
+            "/23/ornament/:state/:number",
+            "Switches an ornament",
+        ))
+        .error(StatusCode::IM_A_TEAPOT, "Unknown state or number"),
+        fragment(Operation::new(
+            Method::GET,
+            "/23/scene",
+            "The whole tree as last left",
+        )),
+        Operation::new(
+            Method::POST,
+            "/23/lockfile",
+            "Renders the packages of a lockfile",
+        )
+        .query("view", "string", "`boxes`, the default, or `graph`")
+        .body("multipart/form-data")
+        .media_response(StatusCode::OK, "text/html", "The rendered lockfile")
+        .error(StatusCode::BAD_REQUEST, "No lockfile, or not a valid one")
+        .error(StatusCode::UNPROCESSABLE_ENTITY, "Malformed checksum")
+        .error(StatusCode::PAYLOAD_TOO_LARGE, "Lockfile over 1 MiB")
+        .error(
+            StatusCode::TOO_MANY_REQUESTS,
+            "Too many graphs of the client still queued",
+        ),
+    ]
+}
+
+pub async fn star(
+    State(scenes): State<SceneRegistry>,
+    jar: CookieJar,
+    theme: Theme,
+) -> impl IntoResponse {
+    let jar = scenes.update(jar, |scene| scene.star = true).await;
+    (StatusCode::OK, jar, star_lit(&theme))
+}
+
+pub async fn present(
+    State(scenes): State<SceneRegistry>,
+    jar: CookieJar,
+    theme: Theme,
+    Path(color): Path<String>,
+) -> Response {
+    let color = escape_string(&color);
+
+    let Some((class, next)) = next_present(&color) else {
+        return AppError::Status(StatusCode::IM_A_TEAPOT).into_response();
+    };
 
-    (StatusCode::OK, div)
+    let jar = scenes.update(jar, |scene| scene.present = class).await;
+    (StatusCode::OK, jar, present_div(class, next, &theme)).into_response()
 }
 
-pub async fn ornament(Path((state, number)): Path<(String, String)>) -> impl IntoResponse {
+pub async fn ornament(
+    State(scenes): State<SceneRegistry>,
+    jar: CookieJar,
+    theme: Theme,
+    Path((state, number)): Path<(String, String)>,
+) -> Response {
     let (state, number) = (escape_string(&state), escape_string(&number));
 
-    let (class, next_state) = match state {
-        s if s == "on" => (" on", "off"),
-        s if s == "off" => ("", "on"),
-        _ => return (StatusCode::IM_A_TEAPOT, "".to_string()),
+    let on = match state {
+        s if s == "on" => true,
+        s if s == "off" => false,
+        _ => return AppError::Status(StatusCode::IM_A_TEAPOT).into_response(),
     };
 
-    let div = format!(
-        "<div class=\"ornament{}\" id=\"ornament{}\" hx-trigger=\"load delay:2s once\" hx-get=\"/23/ornament/{}/{}\" hx-swap=\"outerHTML\"></div>",
-        class, number, next_state, number
-    );
+    // only the ornaments of the tree are remembered
+    let index = number
+        .parse::<usize>()
+        .ok()
+        .filter(|n| (1..=ORNAMENTS).contains(n));
+    let jar = match index {
+        Some(n) => {
+            scenes
+                .update(jar, |scene| scene.ornaments[n - 1] = on)
+                .await
+        }
+        None => jar,
+    };
+    (StatusCode::OK, jar, ornament_div(on, &number, &theme)).into_response()
+}
+
+pub fn budgets() -> Vec<RouteBudget> {
+    vec![RouteBudget::new(
+        Method::POST,
+        "/23/lockfile",
+        LOCKFILE_LIMITS.total as u64 + MULTIPART_OVERHEAD,
+        &["multipart/form-data"],
+    )]
+}
+
+/// The whole tree as the client last left it
+pub async fn scene(
+    State(scenes): State<SceneRegistry>,
+    jar: CookieJar,
+    theme: Theme,
+) -> impl IntoResponse {
+    (StatusCode::OK, scenes.get(&jar).await.render(&theme))
+}
+
+pub async fn lockfile(
+    State(state): State<LockfileState>,
+    Query(query): Query<LockfileQuery>,
+    ClientIp(client): ClientIp,
+    accept: Accept,
+    links: LinkBuilder,
+    multipart: Multipart,
+) -> Result<Response, AppError> {
+    let content = read_lockfile(multipart).await?;
+    if let Some(jobs) = state
+        .jobs
+        .filter(|_| query.view == LockfileView::Graph && content.len() > ASYNC_GRAPH_SIZE)
+    {
+        return enqueue_graph(jobs.as_ref(), client_key(client), content, &accept).await;
+    }
+
+    let rendered = render_view(&content, query.view, &accept)?;
+    let Some(uploads) = state.uploads else {
+        return Ok(rendered);
+    };
+
+    // only lockfiles that render are kept
+    let content = uploads::chunks(content.into());
+    match uploads.store(LOCKFILE_UPLOAD, content).await {
+        Ok(Some(id)) => Ok((
+            [
+                (HeaderName::from_static(UPLOAD_ID_HEADER), id.to_string()),
+                (
+                    header::LOCATION,
+                    links.href(&format!("/23/lockfile/{}", id)),
+                ),
+            ],
+            rendered,
+        )
+            .into_response()),
+        // still rendered, only without an id to come back to
+        Ok(None) => {
+            tracing::warn!("upload quota used up, lockfile not stored");
+            Ok(rendered)
+        }
+        Err(e) => {
+            tracing::warn!("failed to store a lockfile: {}", e);
+            Err(AppError::Internal)
+        }
+    }
+}
+
+/// Renders a lockfile uploaded before, found by the id `/23/lockfile` answered with
+pub async fn stored_lockfile(
+    State(state): State<LockfileState>,
+    Path(upload_id): Path<Uuid>,
+    Query(query): Query<LockfileQuery>,
+    accept: Accept,
+) -> Result<Response, AppError> {
+    let Some(uploads) = state.uploads else {
+        return Err(AppError::NotFound);
+    };
+
+    match uploads.load(LOCKFILE_UPLOAD, upload_id).await {
+        Ok(Some(content)) => {
+            let content = String::from_utf8(content).map_err(|_| AppError::Internal)?;
+            render_view(&content, query.view, &accept)
+        }
+        Ok(None) => Err(AppError::NotFound),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn render_view(content: &str, view: LockfileView, accept: &Accept) -> Result<Response, AppError> {
+    if view == LockfileView::Boxes {
+        return render_lockfile(content).map(IntoResponse::into_response);
+    }
+
+    let format = graph_format(accept)?;
+    let body = render_graph(content, format)
+        .ok_or_else(|| AppError::BadRequest("the lockfile doesn't parse".to_string()))?;
+    Ok(([(header::CONTENT_TYPE, format.mime())], body).into_response())
+}
 
-    (StatusCode::OK, div)
+fn graph_format(accept: &Accept) -> Result<Format, AppError> {
+    accept
+        .negotiate(&[Format::Html, Format::Svg, Format::Json])
+        .ok_or(AppError::NotAcceptable)
 }
 
-pub async fn lockfile(multipart: Multipart) -> Result<String, (StatusCode, String)> {
-    let lockfile: Lockfile = parse_lockfile(multipart).await?;
+/// `None` when the lockfile doesn't parse
+fn render_graph(content: &str, format: Format) -> Option<String> {
+    let lockfile = toml::from_str::<GraphLockfile>(content).ok()?;
+    let graph = DependencyGraph::new(&lockfile.package);
+    Some(match format {
+        Format::Json => serde_json::to_string(&graph.adjacency()).unwrap(),
+        // the svg is inlined as is by the htmx page
+        _ => graph.to_svg(),
+    })
+}
+
+/// Accepts the lockfile, its graph is then polled at `/jobs/:id`. Queued lockfiles aren't
+/// stored as uploads, whether they render is only known later. A client with too many lockfiles
+/// still waiting is turned away.
+async fn enqueue_graph(
+    jobs: &dyn JobRepository,
+    client: IpAddr,
+    content: String,
+    accept: &Accept,
+) -> Result<Response, AppError> {
+    let format = graph_format(accept)?;
+    let payload = serde_json::to_value(GraphJob {
+        content,
+        content_type: format.mime().to_string(),
+    })
+    .unwrap();
+    match jobs
+        .enqueue(LOCKFILE_GRAPH_JOB, &client.to_string(), payload)
+        .await
+    {
+        Ok(Some(id)) => Ok(jobs::accepted(id)),
+        Ok(None) => Err(AppError::RateLimited),
+        Err(e) => {
+            tracing::warn!("failed to queue a lockfile graph: {}", e);
+            Err(AppError::Internal)
+        }
+    }
+}
+
+/// Job handler of `LOCKFILE_GRAPH_JOB`
+pub fn render_graph_job(payload: &Value) -> Result<JobOutput, String> {
+    let job = GraphJob::deserialize(payload).map_err(|e| e.to_string())?;
+    let format = match job.content_type.as_str() {
+        ct if ct == Format::Json.mime() => Format::Json,
+        _ => Format::Svg,
+    };
+    let body = render_graph(&job.content, format).ok_or("invalid lockfile")?;
+    Ok(JobOutput {
+        content_type: job.content_type,
+        body,
+    })
+}
+
+fn render_lockfile(content: &str) -> Result<String, AppError> {
+    let lockfile = toml::from_str::<Lockfile>(content)
+        .map_err(|_| AppError::BadRequest("the lockfile doesn't parse".to_string()))?;
     let mut res = String::new();
     for p in lockfile.package {
         if let Some(checksum) = p.checksum {
@@ -75,38 +640,21 @@ pub async fn lockfile(multipart: Multipart) -> Result<String, (StatusCode, Strin
     Ok(res)
 }
 
-async fn parse_lockfile(mut multipart: Multipart) -> Result<Lockfile, (StatusCode, String)> {
-    let field = match multipart
-        .next_field()
-        .await
-        .map_err(|_| (StatusCode::BAD_REQUEST, "".to_string()))?
-    {
-        Some(f) => f,
-        _ => return Err((StatusCode::BAD_REQUEST, "".to_string())),
-    };
-
-    let text_field = field
-        .text()
-        .await
-        .map_err(|_| (StatusCode::BAD_REQUEST, "".to_string()))?;
-
-    let lockfile = toml::from_str::<Lockfile>(&text_field)
-        .map_err(|_| (StatusCode::BAD_REQUEST, "".to_string()))?;
-    Ok(lockfile)
+async fn read_lockfile(multipart: Multipart) -> Result<String, AppError> {
+    let parts = Parts::read(multipart, LOCKFILE_LIMITS).await?;
+    Ok(parts.field_or_first("lockfile")?.text()?.to_string())
 }
 
-fn div_from_checksum(checksum: String) -> Result<String, (StatusCode, String)> {
+fn div_from_checksum(checksum: String) -> Result<String, AppError> {
+    let invalid = || AppError::Unprocessable(format!("invalid checksum {}", checksum));
     if checksum.len() < 10 {
-        return Err((StatusCode::UNPROCESSABLE_ENTITY, "".to_string()));
+        return Err(invalid());
     }
 
     // making sure that color is a hex string
-    u64::from_str_radix(&checksum[..6], 16)
-        .map_err(|_| (StatusCode::UNPROCESSABLE_ENTITY, "".to_string()))?;
-    let top = u64::from_str_radix(&checksum[6..8], 16)
-        .map_err(|_| (StatusCode::UNPROCESSABLE_ENTITY, "".to_string()))?;
-    let left = u64::from_str_radix(&checksum[8..10], 16)
-        .map_err(|_| (StatusCode::UNPROCESSABLE_ENTITY, "".to_string()))?;
+    u64::from_str_radix(&checksum[..6], 16).map_err(|_| invalid())?;
+    let top = u64::from_str_radix(&checksum[6..8], 16).map_err(|_| invalid())?;
+    let left = u64::from_str_radix(&checksum[8..10], 16).map_err(|_| invalid())?;
 
     // color is printed as-is to include leading zeros
     Ok(format!(
@@ -116,3 +664,573 @@ fn div_from_checksum(checksum: String) -> Result<String, (StatusCode, String)> {
         left
     ))
 }
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, SocketAddr};
+
+    use super::*;
+    use crate::test_support::box_future;
+    use crate::{jobs::MockJobRepository, theme::CLASSIC, uploads::MockUploadRepository};
+    use axum::{
+        body::Body,
+        extract::connect_info::MockConnectInfo,
+        http::Request,
+        routing::{get, post},
+        Router,
+    };
+    use http_body_util::BodyExt;
+    use mockall::predicate::eq;
+    use tokio_stream::StreamExt;
+    use tower::ServiceExt;
+
+    const BOUNDARY: &str = "lockfile-boundary";
+    const CLIENT: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)), 4000);
+    const LOCKFILE: &str = "[[package]]\nchecksum = \"337a3f0a2c\"\n";
+    const RENDERED: &str = "<div style=\"background-color:#337a3f;top:10px;left:44px;\"></div>";
+
+    fn create_test_app(uploads: Option<MockUploadRepository>) -> Router {
+        Router::new()
+            .route("/23/lockfile", post(lockfile))
+            .route("/23/lockfile/:upload_id", get(stored_lockfile))
+            .with_state(LockfileState {
+                uploads: uploads.map(|u| Arc::new(u) as Arc<dyn UploadRepository>),
+                jobs: None,
+            })
+            .layer(MockConnectInfo(CLIENT))
+    }
+
+    fn create_queue_app(jobs: MockJobRepository) -> Router {
+        Router::new()
+            .route("/23/lockfile", post(lockfile))
+            .with_state(LockfileState {
+                uploads: None,
+                jobs: Some(Arc::new(jobs)),
+            })
+            .layer(MockConnectInfo(CLIENT))
+    }
+
+    fn upload(content: &str) -> Request<Body> {
+        let body = format!(
+            "--{b}\r\nContent-Disposition: form-data; name=\"lockfile\"; filename=\"Cargo.lock\"\r\n\r\n{c}\r\n--{b}--\r\n",
+            b = BOUNDARY,
+            c = content
+        );
+        Request::builder()
+            .method("POST")
+            .uri("/23/lockfile")
+            .header(
+                "content-type",
+                format!("multipart/form-data; boundary={}", BOUNDARY),
+            )
+            .body(Body::from(body))
+            .unwrap()
+    }
+
+    async fn body_text(response: Response) -> String {
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        String::from_utf8(body.to_vec()).unwrap()
+    }
+
+    fn create_scene_app() -> Router {
+        Router::new()
+            .route("/23/star", get(star))
+            .route("/23/present/:color", get(present))
+            .route("/23/ornament/:state/:number", get(ornament))
+            .route("/23/scene", get(scene))
+            .with_state(SceneRegistry::new())
+    }
+
+    fn get_with(uri: &str, cookie: Option<&str>) -> Request<Body> {
+        let mut request = Request::builder().uri(uri);
+        if let Some(cookie) = cookie {
+            request = request.header(header::COOKIE, cookie);
+        }
+        request.body(Body::empty()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_challenge_outputs() {
+        let app = create_scene_app();
+        let response = app
+            .clone()
+            .oneshot(get_with("/23/present/blue", None))
+            .await
+            .unwrap();
+        assert_eq!(
+            body_text(response).await,
+            "<div class=\"present blue\" hx-get=\"/23/present/purple\" hx-swap=\"outerHTML\"> <div class=\"ribbon\"></div><div class=\"ribbon\"></div><div class=\"ribbon\"></div><div class=\"ribbon\"></div></div>"
+        );
+
+        let response = app
+            .clone()
+            .oneshot(get_with("/23/ornament/on/1", None))
+            .await
+            .unwrap();
+        assert_eq!(
+            body_text(response).await,
+            "<div class=\"ornament on\" id=\"ornament1\" hx-trigger=\"load delay:2s once\" hx-get=\"/23/ornament/off/1\" hx-swap=\"outerHTML\"></div>"
+        );
+
+        let response = app
+            .oneshot(get_with("/23/present/green", None))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::IM_A_TEAPOT);
+    }
+
+    async fn get_status_text(uri: &str) -> (StatusCode, String) {
+        let response = create_scene_app()
+            .oneshot(get_with(uri, None))
+            .await
+            .unwrap();
+        (response.status(), body_text(response).await)
+    }
+
+    #[tokio::test]
+    async fn test_star() {
+        assert_eq!(
+            get_status_text("/23/star").await,
+            (StatusCode::OK, star_lit(&CLASSIC))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_present_cycle() {
+        for (color, next) in [("blue", "purple"), ("purple", "red"), ("red", "blue")] {
+            let (status, html) = get_status_text(&format!("/23/present/{}", color)).await;
+            assert_eq!(status, StatusCode::OK);
+            assert!(html.starts_with(&format!("<div class=\"present {}\"", color)));
+            assert!(html.contains(&format!("hx-get=\"/23/present/{}\"", next)));
+        }
+
+        for color in ["Blue", "%3Cblue%3E", "blue%2F"] {
+            assert_eq!(
+                get_status_text(&format!("/23/present/{}", color)).await,
+                (
+                    StatusCode::IM_A_TEAPOT,
+                    r#"{"errors":[{"path":"","message":"i'm a teapot"}]}"#.to_string()
+                )
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_ornament_states() {
+        let (status, html) = get_status_text("/23/ornament/off/2").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            html,
+            "<div class=\"ornament\" id=\"ornament2\" hx-trigger=\"load delay:2s once\" hx-get=\"/23/ornament/on/2\" hx-swap=\"outerHTML\"></div>"
+        );
+
+        for uri in [
+            "/23/ornament/lit/2",
+            "/23/ornament/ON/2",
+            "/23/ornament/%3Con%3E/2",
+        ] {
+            assert_eq!(
+                get_status_text(uri).await,
+                (
+                    StatusCode::IM_A_TEAPOT,
+                    r#"{"errors":[{"path":"","message":"i'm a teapot"}]}"#.to_string()
+                )
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_ornament_number_escaped() {
+        let (status, html) =
+            get_status_text("/23/ornament/on/%3Cscript%3Ealert(%22x%22)%3C%2Fscript%3E").await;
+        assert_eq!(status, StatusCode::OK);
+        let number = "&lt;script&gt;alert(&quot;x&quot;)&lt;&#x2F;script&gt;";
+        assert!(html.contains(&format!("id=\"ornament{}\"", number)));
+        assert!(html.contains(&format!("hx-get=\"/23/ornament/off/{}\"", number)));
+        assert!(!html.contains("<script>"));
+    }
+
+    #[test]
+    fn test_escape_string() {
+        assert_eq!(
+            escape_string("<a href='/x'>\"&\"</a>"),
+            "&lt;a href=&#x27;&#x2F;x&#x27;&gt;&quot;&amp;&quot;&lt;&#x2F;a&gt;"
+        );
+        assert_eq!(escape_string("&amp;"), "&amp;amp;");
+    }
+
+    #[tokio::test]
+    async fn test_scene_survives_reload() {
+        let app = create_scene_app();
+        let response = app
+            .clone()
+            .oneshot(get_with("/23/star", None))
+            .await
+            .unwrap();
+        let cookie = response.headers()[header::SET_COOKIE].to_str().unwrap();
+        let cookie = cookie.split(';').next().unwrap().to_string();
+        assert!(cookie.starts_with("scene="));
+
+        for uri in [
+            "/23/present/purple",
+            "/23/ornament/on/3",
+            "/23/ornament/on/99",
+        ] {
+            let response = app
+                .clone()
+                .oneshot(get_with(uri, Some(&cookie)))
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            // the session is kept, not replaced
+            assert!(!response.headers().contains_key(header::SET_COOKIE));
+        }
+
+        let response = app
+            .clone()
+            .oneshot(get_with("/23/scene", Some(&cookie)))
+            .await
+            .unwrap();
+        let html = body_text(response).await;
+        assert!(html.contains(&star_lit(&CLASSIC)));
+        assert_eq!(
+            html.matches(&present_div("purple", "red", &CLASSIC))
+                .count(),
+            3
+        );
+        assert!(html.contains(&ornament_div(true, "3", &CLASSIC)));
+        assert!(html.contains(&ornament_div(false, "1", &CLASSIC)));
+        assert!(!html.contains("ornament99"));
+
+        // a client without a session gets the tree as first served
+        let response = app.oneshot(get_with("/23/scene", None)).await.unwrap();
+        assert_eq!(body_text(response).await, Scene::default().render(&CLASSIC));
+    }
+
+    #[tokio::test]
+    async fn test_themed_tree() {
+        let (_, html) = get_status_text("/23/star?theme=winter").await;
+        assert_eq!(html, "<div id=\"star\" class=\"lit frosty\"></div>");
+
+        let (_, html) = get_status_text("/23/present/blue?theme=winter").await;
+        // the colors of the URLs are the challenge's, whatever the classes
+        assert!(html.starts_with("<div class=\"present ice\" hx-get=\"/23/present/purple\""));
+
+        let (_, html) = get_status_text("/23/ornament/on/1?theme=winter").await;
+        assert!(html.starts_with("<div class=\"ornament on frosty\" id=\"ornament1\""));
+
+        let (_, html) = get_status_text("/23/scene?theme=winter").await;
+        assert!(html.contains("<div class=\"present berry\""));
+    }
+
+    #[tokio::test]
+    async fn test_lockfile_not_stored() {
+        let app = create_test_app(None);
+        let response = app.clone().oneshot(upload(LOCKFILE)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(!response.headers().contains_key(UPLOAD_ID_HEADER));
+        assert_eq!(body_text(response).await, RENDERED);
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri(format!("/23/lockfile/{}", Uuid::new_v4()))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_lockfile_stored_then_rendered() {
+        let id = Uuid::new_v4();
+        let mut mock = MockUploadRepository::new();
+        let stored = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let chunks = stored.clone();
+        mock.expect_store()
+            .withf(|kind, _| kind == LOCKFILE_UPLOAD)
+            .times(1)
+            .returning(move |_, content| {
+                let chunks = chunks.clone();
+                Box::pin(async move {
+                    let content = content.collect::<Vec<_>>().await;
+                    *chunks.lock().unwrap() = content.concat();
+                    Ok(Some(id))
+                })
+            });
+        mock.expect_load()
+            .with(eq(LOCKFILE_UPLOAD), eq(id))
+            .returning(|_, _| box_future(Ok(Some(LOCKFILE.as_bytes().to_vec()))));
+        mock.expect_load().returning(|_, _| box_future(Ok(None)));
+        let app = create_test_app(Some(mock));
+
+        let response = app.clone().oneshot(upload(LOCKFILE)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[UPLOAD_ID_HEADER], id.to_string());
+        assert_eq!(
+            response.headers()[header::LOCATION],
+            format!("/23/lockfile/{}", id)
+        );
+        assert_eq!(body_text(response).await, RENDERED);
+        assert_eq!(*stored.lock().unwrap(), LOCKFILE.as_bytes());
+
+        let get = |id: Uuid| {
+            Request::builder()
+                .uri(format!("/23/lockfile/{}", id))
+                .body(Body::empty())
+                .unwrap()
+        };
+        let response = app.clone().oneshot(get(id)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(body_text(response).await, RENDERED);
+
+        let response = app.oneshot(get(Uuid::new_v4())).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_lockfile_over_quota_not_stored() {
+        let mut mock = MockUploadRepository::new();
+        mock.expect_store()
+            .times(1)
+            .returning(|_, _| box_future(Ok(None)));
+        let app = create_test_app(Some(mock));
+
+        let response = app.oneshot(upload(LOCKFILE)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(!response.headers().contains_key(UPLOAD_ID_HEADER));
+        assert!(!response.headers().contains_key(header::LOCATION));
+        assert_eq!(body_text(response).await, RENDERED);
+    }
+
+    const GRAPH_LOCKFILE: &str = r#"
+[[package]]
+name = "app"
+version = "0.1.0"
+dependencies = ["serde", "rand 0.8.5"]
+
+[[package]]
+name = "rand"
+version = "0.8.5"
+dependencies = ["serde"]
+
+[[package]]
+name = "rand"
+version = "0.9.0"
+
+[[package]]
+name = "serde"
+version = "1.0.215"
+"#;
+
+    fn upload_to(uri: &str, content: &str, accept: &str) -> Request<Body> {
+        let mut request = upload(content);
+        *request.uri_mut() = uri.parse().unwrap();
+        request
+            .headers_mut()
+            .insert(header::ACCEPT, accept.parse().unwrap());
+        request
+    }
+
+    #[tokio::test]
+    async fn test_graph_adjacency() {
+        let response = create_test_app(None)
+            .oneshot(upload_to(
+                "/23/lockfile?view=graph",
+                GRAPH_LOCKFILE,
+                "application/json",
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let adjacency: serde_json::Value =
+            serde_json::from_str(&body_text(response).await).unwrap();
+        assert_eq!(
+            adjacency,
+            serde_json::json!({
+                "app 0.1.0": ["serde 1.0.215", "rand 0.8.5"],
+                "rand 0.8.5": ["serde 1.0.215"],
+                "rand 0.9.0": [],
+                "serde 1.0.215": [],
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_graph_svg() {
+        let response = create_test_app(None)
+            .oneshot(upload_to("/23/lockfile?view=graph", GRAPH_LOCKFILE, "*/*"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            Format::Html.mime()
+        );
+        let svg = body_text(response).await;
+        assert!(svg.starts_with("<svg"));
+        assert_eq!(svg.matches("<rect").count(), 4);
+        assert_eq!(svg.matches("<line").count(), 3);
+
+        let packages = toml::from_str::<GraphLockfile>(GRAPH_LOCKFILE)
+            .unwrap()
+            .package;
+        // serde sits below rand, which depends on it
+        assert_eq!(DependencyGraph::new(&packages).levels(), [0, 1, 0, 2]);
+    }
+
+    #[tokio::test]
+    async fn test_large_graph_is_queued() {
+        let payloads = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let seen = payloads.clone();
+        let mut jobs = MockJobRepository::new();
+        jobs.expect_enqueue()
+            .withf(|kind, client, _| kind == LOCKFILE_GRAPH_JOB && client == "10.0.0.1")
+            .times(1)
+            .returning(move |_, _, payload| {
+                seen.lock().unwrap().push(payload);
+                box_future(Ok(Some(Uuid::nil())))
+            });
+        let app = create_queue_app(jobs);
+
+        // small lockfiles are still rendered in the request
+        let response = app
+            .clone()
+            .oneshot(upload_to(
+                "/23/lockfile?view=graph",
+                GRAPH_LOCKFILE,
+                "application/json",
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let large = format!("# {}\n{}", "x".repeat(ASYNC_GRAPH_SIZE), GRAPH_LOCKFILE);
+        let response = app
+            .oneshot(upload_to(
+                "/23/lockfile?view=graph",
+                &large,
+                "application/json",
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        assert_eq!(
+            response.headers()[header::LOCATION],
+            jobs::job_path(Uuid::nil())
+        );
+
+        let output = render_graph_job(&payloads.lock().unwrap()[0]).unwrap();
+        assert_eq!(output.content_type, Format::Json.mime());
+        let adjacency = serde_json::from_str::<Value>(&output.body).unwrap();
+        assert_eq!(
+            adjacency["rand 0.8.5"],
+            serde_json::json!(["serde 1.0.215"])
+        );
+        assert_eq!(
+            render_graph_job(&serde_json::json!({ "content": "[", "content_type": "" })),
+            Err("invalid lockfile".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_queue_full() {
+        let mut jobs = MockJobRepository::new();
+        jobs.expect_enqueue()
+            .times(1)
+            .returning(|_, _, _| box_future(Ok(None)));
+
+        let large = format!("# {}\n{}", "x".repeat(ASYNC_GRAPH_SIZE), GRAPH_LOCKFILE);
+        let response = create_queue_app(jobs)
+            .oneshot(upload_to(
+                "/23/lockfile?view=graph",
+                &large,
+                "application/json",
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[tokio::test]
+    async fn test_unknown_view() {
+        let response = create_test_app(None)
+            .oneshot(upload_to("/23/lockfile?view=tree", LOCKFILE, "*/*"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_lockfile_packages() {
+        let content = "[[package]]\nname = \"no-checksum\"\n\n[[package]]\nchecksum = \"00ff000000abcdef\"\n\n[[package]]\nchecksum = \"ABCDEFffff\"\n";
+        let response = create_test_app(None)
+            .oneshot(upload(content))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        // leading zeros of the color are kept, the case as well
+        assert_eq!(
+            body_text(response).await,
+            "<div style=\"background-color:#00ff00;top:0px;left:0px;\"></div><div style=\"background-color:#ABCDEF;top:255px;left:255px;\"></div>"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_lockfile_errors() {
+        for (content, status) in [
+            ("not = [toml", StatusCode::BAD_REQUEST),
+            (
+                "[package]\nchecksum = \"337a3f0a2c\"\n",
+                StatusCode::BAD_REQUEST,
+            ),
+            (
+                "[[package]]\nchecksum = \"337a3f0a2\"\n",
+                StatusCode::UNPROCESSABLE_ENTITY,
+            ),
+            (
+                "[[package]]\nchecksum = \"33za3f0a2c\"\n",
+                StatusCode::UNPROCESSABLE_ENTITY,
+            ),
+            (
+                "[[package]]\nchecksum = \"337a3fxx2c\"\n",
+                StatusCode::UNPROCESSABLE_ENTITY,
+            ),
+            (
+                "[[package]]\nchecksum = \"337a3f0axx\"\n",
+                StatusCode::UNPROCESSABLE_ENTITY,
+            ),
+        ] {
+            let response = create_test_app(None)
+                .oneshot(upload(content))
+                .await
+                .unwrap();
+            assert_eq!(response.status(), status, "{}", content);
+        }
+
+        // a multipart body without any field
+        let empty = Request::builder()
+            .method("POST")
+            .uri("/23/lockfile")
+            .header(
+                "content-type",
+                format!("multipart/form-data; boundary={}", BOUNDARY),
+            )
+            .body(Body::from(format!("--{}--\r\n", BOUNDARY)))
+            .unwrap();
+        let response = create_test_app(None).oneshot(empty).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_lockfile_not_stored() {
+        // the mock has no expectations, any store call fails the test
+        let response = create_test_app(Some(MockUploadRepository::new()))
+            .oneshot(upload("[[package]]\nchecksum = \"nothex\"\n"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+}
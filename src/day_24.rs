@@ -0,0 +1,240 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::State,
+    http::{Method, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{DateTime, Utc};
+#[cfg(test)]
+use mockall::automock;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::{query_as, types::Json as Jsonb, FromRow, PgPool};
+use uuid::Uuid;
+
+use crate::{ordering, validation::RouteSchema};
+
+#[derive(Clone)]
+pub struct QueueState {
+    pub repository: Arc<dyn OrderQueue>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct GiftOrder {
+    #[serde(with = "crate::conventions::uuid")]
+    pub id: Uuid,
+    pub priority: i32,
+    pub payload: Jsonb<Value>,
+    #[serde(with = "crate::conventions::datetime")]
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NewOrder {
+    pub priority: i32,
+    pub payload: Value,
+}
+
+pub fn schemas() -> Vec<RouteSchema> {
+    vec![RouteSchema::new(
+        Method::POST,
+        "/24/enqueue",
+        serde_json::json!({
+            "type": "object",
+            "required": ["priority", "payload"],
+            "properties": {
+                "priority": { "type": "integer" }
+            }
+        }),
+    )]
+}
+
+/// Gift orders served by highest priority first, then by age
+#[async_trait::async_trait]
+#[cfg_attr(test, automock)]
+pub trait OrderQueue: Send + Sync + 'static {
+    async fn enqueue(&self, order: NewOrder) -> Result<GiftOrder, sqlx::Error>;
+    /// Removes and returns the next order, `None` when the queue is empty
+    async fn dequeue(&self) -> Result<Option<GiftOrder>, sqlx::Error>;
+    async fn peek(&self) -> Result<Option<GiftOrder>, sqlx::Error>;
+}
+
+pub struct PostgresOrderQueue {
+    pool: PgPool,
+}
+
+impl PostgresOrderQueue {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl OrderQueue for PostgresOrderQueue {
+    async fn enqueue(&self, order: NewOrder) -> Result<GiftOrder, sqlx::Error> {
+        query_as::<_, GiftOrder>(
+            "INSERT INTO gift_orders (id, priority, payload) VALUES ($1, $2, $3) RETURNING *",
+        )
+        .bind(Uuid::new_v4())
+        .bind(order.priority)
+        .bind(Jsonb(order.payload))
+        .fetch_one(&self.pool)
+        .await
+    }
+
+    async fn dequeue(&self) -> Result<Option<GiftOrder>, sqlx::Error> {
+        // orders locked by another worker are skipped rather than waited for
+        query_as::<_, GiftOrder>(&format!(
+            "DELETE FROM gift_orders WHERE id = (
+                SELECT id FROM gift_orders ORDER BY {}
+                LIMIT 1 FOR UPDATE SKIP LOCKED
+             ) RETURNING *",
+            ordering::GIFT_ORDERS
+        ))
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    async fn peek(&self) -> Result<Option<GiftOrder>, sqlx::Error> {
+        query_as::<_, GiftOrder>(&format!(
+            "SELECT * FROM gift_orders ORDER BY {} LIMIT 1",
+            ordering::GIFT_ORDERS
+        ))
+        .fetch_optional(&self.pool)
+        .await
+    }
+}
+
+fn next_order(order: Result<Option<GiftOrder>, sqlx::Error>) -> Response {
+    match order {
+        Ok(Some(o)) => (StatusCode::OK, Json(o)).into_response(),
+        Ok(None) => StatusCode::NO_CONTENT.into_response(),
+        _ => (StatusCode::INTERNAL_SERVER_ERROR, "".to_string()).into_response(),
+    }
+}
+
+pub async fn enqueue(
+    State(state): State<QueueState>,
+    Json(order): Json<NewOrder>,
+) -> impl IntoResponse {
+    match state.repository.enqueue(order).await {
+        Ok(o) => Ok((StatusCode::CREATED, Json(o))),
+        _ => Err((StatusCode::INTERNAL_SERVER_ERROR, "".to_string())),
+    }
+}
+
+pub async fn dequeue(State(state): State<QueueState>) -> Response {
+    next_order(state.repository.dequeue().await)
+}
+
+pub async fn peek(State(state): State<QueueState>) -> Response {
+    next_order(state.repository.peek().await)
+}
+
+pub fn state_queue_repository(pool: PgPool) -> Arc<dyn OrderQueue> {
+    Arc::new(PostgresOrderQueue::new(pool))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::box_future;
+    use axum::{
+        body::Body,
+        http::Request,
+        routing::{get, post},
+        Router,
+    };
+    use http_body_util::BodyExt;
+    use mockall::predicate::eq;
+    use serde_json::json;
+    use tower::ServiceExt;
+
+    fn create_test_app(repository: MockOrderQueue) -> Router {
+        Router::new()
+            .route("/24/enqueue", post(enqueue))
+            .route("/24/dequeue", post(dequeue))
+            .route("/24/peek", get(peek))
+            .with_state(QueueState {
+                repository: Arc::new(repository),
+            })
+    }
+
+    fn order(priority: i32) -> GiftOrder {
+        GiftOrder {
+            id: Uuid::new_v4(),
+            priority,
+            payload: Jsonb(json!({ "gift": "sled" })),
+            created_at: Utc::now(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_enqueue() {
+        let mut mock = MockOrderQueue::new();
+        mock.expect_enqueue()
+            .with(eq(NewOrder {
+                priority: 7,
+                payload: json!({ "gift": "sled" }),
+            }))
+            .returning(|o| box_future(Ok(order(o.priority))));
+
+        let response = create_test_app(mock)
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/24/enqueue")
+                    .header("content-type", "application/json")
+                    .body(Body::from(r#"{"priority":7,"payload":{"gift":"sled"}}"#))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let created: GiftOrder = serde_json::from_slice(&body).unwrap();
+        assert_eq!(created.priority, 7);
+    }
+
+    #[tokio::test]
+    async fn test_dequeue() {
+        let mut mock = MockOrderQueue::new();
+        let mut orders = vec![order(1)];
+        mock.expect_dequeue()
+            .returning(move || box_future(Ok(orders.pop())));
+        let app = create_test_app(mock);
+
+        let request = || {
+            Request::builder()
+                .method("POST")
+                .uri("/24/dequeue")
+                .body(Body::empty())
+                .unwrap()
+        };
+        let response = app.clone().oneshot(request()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = app.oneshot(request()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    }
+
+    #[tokio::test]
+    async fn test_peek_empty() {
+        let mut mock = MockOrderQueue::new();
+        mock.expect_peek().returning(|| box_future(Ok(None)));
+
+        let response = create_test_app(mock)
+            .oneshot(
+                Request::builder()
+                    .uri("/24/peek")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    }
+}
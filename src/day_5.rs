@@ -1,12 +1,19 @@
 use axum::{
     body::Bytes,
-    http::{HeaderMap, StatusCode},
+    http::{header, HeaderMap, Method, StatusCode},
     response::{IntoResponse, Response},
 };
 use cargo_manifest::Manifest;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use serde_with::serde_as;
 
+use crate::{
+    i18n::{Language, Message},
+    negotiate::{Accept, Format},
+    openapi::Operation,
+    preflight::RouteBudget,
+};
+
 #[derive(Default, Debug, Deserialize)]
 struct Metadata {
     #[serde(default)]
@@ -14,7 +21,7 @@ struct Metadata {
 }
 
 #[serde_as]
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 struct Order {
     item: String,
     #[serde_as(deserialize_as = "serde_with::DefaultOnError")]
@@ -23,9 +30,55 @@ struct Order {
 }
 
 const MAGIC_KEYWORD: &str = "Christmas 2024";
+const MAX_MANIFEST_SIZE: u64 = 64 * 1024;
+
+pub fn budgets() -> Vec<RouteBudget> {
+    vec![RouteBudget::new(
+        Method::POST,
+        "/5/manifest",
+        MAX_MANIFEST_SIZE,
+        &["application/toml", "application/yaml", "application/json"],
+    )]
+}
+
+pub fn operations() -> Vec<Operation> {
+    vec![Operation::new(
+        Method::POST,
+        "/5/manifest",
+        "Lists the orders of a Cargo manifest",
+    )
+    .body("application/toml")
+    .media_response(
+        StatusCode::OK,
+        "text/plain",
+        "One `item: quantity` line per order",
+    )
+    .response(StatusCode::NO_CONTENT, "The manifest has no valid order")
+    .response(
+        StatusCode::BAD_REQUEST,
+        "Invalid manifest or missing keyword",
+    )
+    .response(
+        StatusCode::NOT_ACCEPTABLE,
+        "No acceptable format for the orders",
+    )
+    .error(
+        StatusCode::PAYLOAD_TOO_LARGE,
+        "Manifest over 64 KiB, or the configured limit",
+    )
+    .response(
+        StatusCode::UNSUPPORTED_MEDIA_TYPE,
+        "Neither TOML, YAML nor JSON",
+    )]
+}
 
 #[axum::debug_handler]
-pub async fn manifest(headers: HeaderMap, body: Bytes) -> impl IntoResponse {
+pub async fn manifest(
+    accept: Accept,
+    language: Language,
+    headers: HeaderMap,
+    body: Bytes,
+) -> impl IntoResponse {
     // parsing body depending on content-type
     let maybe_package = match headers.get("Content-Type") {
         Some(ct) if ct == "application/toml" => {
@@ -65,34 +118,56 @@ pub async fn manifest(headers: HeaderMap, body: Bytes) -> impl IntoResponse {
     {
         return Response::builder()
             .status(StatusCode::BAD_REQUEST)
-            .body("Magic keyword not provided".to_string())
+            .body(Message::MissingKeyword.text(language))
             .unwrap();
     }
 
     // collect potential orders
-    let response = package
+    let orders = package
         .metadata
         .map(|metadata| {
             metadata
                 .orders
-                .iter()
-                .filter_map(|o| o.quantity.map(|q| format!("{}: {}", o.item, q)))
+                .into_iter()
+                .filter(|o| o.quantity.is_some())
                 .collect::<Vec<_>>()
-                .join("\n")
         })
-        .filter(|r| !r.is_empty());
+        .unwrap_or_default();
 
     // 204 with no orders, 200 with orders
-    match response {
-        Some(r) => Response::builder()
-            .status(StatusCode::OK)
-            .body(r.trim().to_string())
-            .unwrap(),
-        _ => Response::builder()
+    if orders.is_empty() {
+        return Response::builder()
             .status(StatusCode::NO_CONTENT)
             .body("".to_string())
-            .unwrap(),
+            .unwrap();
     }
+
+    let format = match accept.negotiate(&[Format::Plain, Format::Json]) {
+        Some(f) => f,
+        _ => {
+            return Response::builder()
+                .status(StatusCode::NOT_ACCEPTABLE)
+                .body("".to_string())
+                .unwrap();
+        }
+    };
+
+    let body = match format {
+        Format::Json => serde_json::to_string(&orders).unwrap(),
+        _ => orders
+            .iter()
+            .filter_map(|o| o.quantity.map(|q| format!("{}: {}", o.item, q)))
+            .collect::<Vec<_>>()
+            .join("\n")
+            .trim()
+            .to_string(),
+    };
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, format.mime())
+        .body(body)
+        .unwrap()
 }
 
 #[cfg(test)]
@@ -132,7 +207,13 @@ mod tests {
         "#;
 
         let headers = create_headers("application/toml");
-        let response = manifest(headers, Bytes::from(toml_content.as_bytes().to_vec())).await;
+        let response = manifest(
+            Accept::default(),
+            Language::default(),
+            headers,
+            Bytes::from(toml_content.as_bytes().to_vec()),
+        )
+        .await;
 
         let (parts, body) = response.into_response().into_parts();
         assert_eq!(parts.status, StatusCode::OK);
@@ -157,7 +238,13 @@ mod tests {
         "#;
 
         let headers = create_headers("application/yaml");
-        let response = manifest(headers, Bytes::from(yaml_content.as_bytes().to_vec())).await;
+        let response = manifest(
+            Accept::default(),
+            Language::default(),
+            headers,
+            Bytes::from(yaml_content.as_bytes().to_vec()),
+        )
+        .await;
 
         let (parts, body) = response.into_response().into_parts();
         assert_eq!(parts.status, StatusCode::OK);
@@ -183,7 +270,13 @@ mod tests {
         }"#;
 
         let headers = create_headers("application/json");
-        let response = manifest(headers, Bytes::from(json_content.as_bytes().to_vec())).await;
+        let response = manifest(
+            Accept::default(),
+            Language::default(),
+            headers,
+            Bytes::from(json_content.as_bytes().to_vec()),
+        )
+        .await;
 
         let (parts, body) = response.into_response().into_parts();
         assert_eq!(parts.status, StatusCode::OK);
@@ -205,7 +298,13 @@ mod tests {
         "#;
 
         let headers = create_headers("application/toml");
-        let response = manifest(headers, Bytes::from(toml_content.as_bytes().to_vec())).await;
+        let response = manifest(
+            Accept::default(),
+            Language::default(),
+            headers,
+            Bytes::from(toml_content.as_bytes().to_vec()),
+        )
+        .await;
 
         let (parts, _) = response.into_response().into_parts();
         assert_eq!(parts.status, StatusCode::BAD_REQUEST);
@@ -223,7 +322,13 @@ mod tests {
         "#;
 
         let headers = create_headers("application/toml");
-        let response = manifest(headers, Bytes::from(toml_content.as_bytes().to_vec())).await;
+        let response = manifest(
+            Accept::default(),
+            Language::default(),
+            headers,
+            Bytes::from(toml_content.as_bytes().to_vec()),
+        )
+        .await;
 
         let (parts, _) = response.into_response().into_parts();
         assert_eq!(parts.status, StatusCode::BAD_REQUEST);
@@ -242,7 +347,13 @@ mod tests {
         "#;
 
         let headers = create_headers("application/toml");
-        let response = manifest(headers, Bytes::from(toml_content.as_bytes().to_vec())).await;
+        let response = manifest(
+            Accept::default(),
+            Language::default(),
+            headers,
+            Bytes::from(toml_content.as_bytes().to_vec()),
+        )
+        .await;
 
         let (parts, _) = response.into_response().into_parts();
         assert_eq!(parts.status, StatusCode::NO_CONTENT);
@@ -265,7 +376,13 @@ mod tests {
         "#;
 
         let headers = create_headers("application/toml");
-        let response = manifest(headers, Bytes::from(toml_content.as_bytes().to_vec())).await;
+        let response = manifest(
+            Accept::default(),
+            Language::default(),
+            headers,
+            Bytes::from(toml_content.as_bytes().to_vec()),
+        )
+        .await;
 
         let (parts, _) = response.into_response().into_parts();
         assert_eq!(parts.status, StatusCode::NO_CONTENT);
@@ -275,7 +392,13 @@ mod tests {
     async fn test_unsupported_content_type() {
         let content = "Some content";
         let headers = create_headers("application/xml");
-        let response = manifest(headers, Bytes::from(content.as_bytes().to_vec())).await;
+        let response = manifest(
+            Accept::default(),
+            Language::default(),
+            headers,
+            Bytes::from(content.as_bytes().to_vec()),
+        )
+        .await;
 
         let (parts, _) = response.into_response().into_parts();
         assert_eq!(parts.status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
@@ -285,9 +408,48 @@ mod tests {
     async fn test_invalid_manifest_format() {
         let invalid_content = "Invalid manifest content";
         let headers = create_headers("application/json");
-        let response = manifest(headers, Bytes::from(invalid_content.as_bytes().to_vec())).await;
+        let response = manifest(
+            Accept::default(),
+            Language::default(),
+            headers,
+            Bytes::from(invalid_content.as_bytes().to_vec()),
+        )
+        .await;
 
         let (parts, _) = response.into_response().into_parts();
         assert_eq!(parts.status, StatusCode::BAD_REQUEST);
     }
+
+    #[tokio::test]
+    async fn test_orders_as_json() {
+        let toml_content = r#"
+        [package]
+        name = "not-a-gift-order"
+        authors = ["Not Santa"]
+        keywords = ["Christmas 2024"]
+
+        [[package.metadata.orders]]
+        item = "Toy Car"
+        quantity = 5
+
+        [[package.metadata.orders]]
+        item = "Doll"
+        "#;
+
+        let headers = create_headers("application/toml");
+        let accept = Accept::from_header("application/json");
+        let response = manifest(
+            accept,
+            Language::default(),
+            headers,
+            Bytes::from(toml_content.as_bytes().to_vec()),
+        )
+        .await;
+
+        let (parts, body) = response.into_response().into_parts();
+        assert_eq!(parts.status, StatusCode::OK);
+
+        let body_content = body_to_string(body).await;
+        assert_eq!(body_content, r#"[{"item":"Toy Car","quantity":5}]"#);
+    }
 }
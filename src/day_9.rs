@@ -1,26 +1,116 @@
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
 use axum::{
     body::Bytes,
     extract::State,
-    http::{HeaderMap, StatusCode},
+    http::{HeaderMap, Method, StatusCode},
     response::IntoResponse,
+    Json,
 };
+use chrono::{DateTime, Utc};
 use leaky_bucket::RateLimiter;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use tokio::sync::Mutex;
 
+use crate::{
+    i18n::{Language, Message},
+    openapi::Operation,
+    prometheus,
+    settings::{MILK_MAX_TOKENS, MILK_REFILL_AMOUNT, MILK_REFILL_INTERVAL_SECS, SETTINGS},
+    stats::STATS,
+    validation::RouteSchema,
+};
+
 const INITIAL_TOKENS: usize = 5;
 const MAX_TOKENS: usize = 5;
-const REFILL_INTERVAL: u64 = 1;
+const REFILL_INTERVAL: Duration = Duration::from_secs(1);
 const REFILL_AMOUNT: usize = 1;
 
+/// Shape of the milk bucket set at startup, the `milk.*` settings take precedence over it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BucketConfig {
+    /// Milk of a new bucket, and of the bucket after a refill
+    pub initial: usize,
+    pub max: usize,
+    pub refill_amount: usize,
+    pub refill_interval: Duration,
+}
+
+impl Default for BucketConfig {
+    fn default() -> Self {
+        Self {
+            initial: INITIAL_TOKENS,
+            max: MAX_TOKENS,
+            refill_amount: REFILL_AMOUNT,
+            refill_interval: REFILL_INTERVAL,
+        }
+    }
+}
+
+impl BucketConfig {
+    /// Bucket starting with the given amount of milk, e.g. the level saved before a redeploy
+    pub fn limiter(&self, initial: usize) -> RateLimiter {
+        let max = SETTINGS.get_or(MILK_MAX_TOKENS, self.max);
+        let interval = SETTINGS
+            .get(MILK_REFILL_INTERVAL_SECS)
+            .map(Duration::from_secs)
+            .unwrap_or(self.refill_interval);
+        RateLimiter::builder()
+            .initial(initial.min(max))
+            .interval(interval)
+            .refill(SETTINGS.get_or(MILK_REFILL_AMOUNT, self.refill_amount))
+            .max(max)
+            .build()
+    }
+}
+
 #[derive(Clone)]
 pub struct RateLimiterState {
     pub limiter: Arc<Mutex<RateLimiter>>,
+    pub bucket: BucketConfig,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+impl RateLimiterState {
+    /// A full bucket of the given shape, built from the current settings
+    pub fn new(bucket: BucketConfig) -> Self {
+        Self {
+            limiter: Arc::new(Mutex::new(bucket.limiter(bucket.initial))),
+            bucket,
+        }
+    }
+}
+
+impl Default for RateLimiterState {
+    fn default() -> Self {
+        Self::new(BucketConfig::default())
+    }
+}
+
+/// The bucket as left by a refill
+#[derive(Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Bucket {
+    pub level: usize,
+    pub max: usize,
+    pub refill_amount: usize,
+    /// When the bucket gains `refill_amount` more milk, unless it's full
+    #[serde(with = "crate::conventions::datetime")]
+    pub next_refill: DateTime<Utc>,
+}
+
+impl Bucket {
+    fn of(limiter: &RateLimiter) -> Self {
+        Self {
+            level: limiter.balance(),
+            max: limiter.max(),
+            refill_amount: limiter.refill(),
+            next_refill: Utc::now()
+                + chrono::Duration::from_std(limiter.interval()).unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum Milk {
     Liters(f32),
@@ -29,6 +119,43 @@ pub enum Milk {
     Pints(f32),
 }
 
+pub fn schemas() -> Vec<RouteSchema> {
+    // exactly one known unit, invalid bodies have always been answered with 400
+    vec![RouteSchema::new(
+        Method::POST,
+        "/9/milk",
+        serde_json::json!({
+            "type": "object",
+            "minProperties": 1,
+            "maxProperties": 1,
+            "additionalProperties": false,
+            "properties": {
+                "liters": { "type": "number" },
+                "gallons": { "type": "number" },
+                "litres": { "type": "number" },
+                "pints": { "type": "number" }
+            }
+        }),
+    )
+    .rejection(StatusCode::BAD_REQUEST)]
+}
+
+pub fn operations() -> Vec<Operation> {
+    vec![
+        Operation::new(
+            Method::POST,
+            "/9/milk",
+            "Withdraws milk, or converts a quantity of it",
+        )
+        .json_body::<Milk>()
+        .json_response::<Milk>(StatusCode::OK, "The quantity in the other unit")
+        .response(StatusCode::BAD_REQUEST, "Not exactly one known unit")
+        .response(StatusCode::TOO_MANY_REQUESTS, "The bucket is empty"),
+        Operation::new(Method::POST, "/9/refill", "Fills the bucket up")
+            .json_response::<Bucket>(StatusCode::OK, "The full bucket"),
+    ]
+}
+
 impl Milk {
     fn convert(&self) -> Self {
         match self {
@@ -42,15 +169,18 @@ impl Milk {
 
 pub async fn milk(
     State(state): State<RateLimiterState>,
+    language: Language,
     headers: HeaderMap,
     body: Bytes,
 ) -> impl IntoResponse {
     if !state.limiter.lock().await.try_acquire(1) {
+        metrics::counter!(prometheus::MILK_REJECTIONS).increment(1);
         return (
             StatusCode::TOO_MANY_REQUESTS,
-            "No milk available\n".to_string(),
+            Message::NoMilk.text(language),
         );
     }
+    STATS.milk_withdrawn();
 
     // parse content-type header
     let ct = match headers.get("Content-Type") {
@@ -73,21 +203,139 @@ pub async fn milk(
     }
 }
 
-pub async fn refill(State(state): State<RateLimiterState>) -> StatusCode {
+/// Admin only, anyone able to refill could bypass the rate limit
+pub async fn refill(State(state): State<RateLimiterState>) -> Json<Bucket> {
     let mut limiter = state.limiter.lock().await;
-    *limiter = rate_limiter();
-    StatusCode::OK
+    *limiter = state.bucket.limiter(state.bucket.initial);
+    Json(Bucket::of(&limiter))
 }
 
-pub fn state_rate_limiter() -> Arc<Mutex<RateLimiter>> {
-    Arc::new(Mutex::new(rate_limiter()))
+/// Rebuilds the bucket when the settings change, keeping the milk it holds
+pub async fn follow_settings(state: RateLimiterState) {
+    let mut changes = SETTINGS.subscribe();
+    while changes.changed().await.is_ok() {
+        let mut limiter = state.limiter.lock().await;
+        *limiter = state.bucket.limiter(limiter.balance());
+    }
 }
 
-fn rate_limiter() -> RateLimiter {
-    RateLimiter::builder()
-        .initial(INITIAL_TOKENS)
-        .interval(tokio::time::Duration::from_secs(REFILL_INTERVAL))
-        .refill(REFILL_AMOUNT)
-        .max(MAX_TOKENS)
-        .build()
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, http::Request, routing::post, Router};
+    use http_body_util::BodyExt;
+    use tower::ServiceExt;
+
+    fn create_test_app(state: RateLimiterState) -> Router {
+        Router::new()
+            .route("/9/milk", post(milk))
+            .route("/9/refill", post(refill))
+            .with_state(state)
+    }
+
+    fn withdraw(body: Option<&'static str>) -> Request<Body> {
+        let request = Request::builder().method("POST").uri("/9/milk");
+        match body {
+            Some(b) => request
+                .header("content-type", "application/json")
+                .body(Body::from(b))
+                .unwrap(),
+            None => request.body(Body::empty()).unwrap(),
+        }
+    }
+
+    async fn text(response: axum::response::Response) -> String {
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        String::from_utf8(body.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_milk() {
+        let app = create_test_app(RateLimiterState::default());
+
+        let response = app.clone().oneshot(withdraw(None)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(text(response).await, "Milk withdrawn\n");
+
+        let response = app
+            .clone()
+            .oneshot(withdraw(Some(r#"{"gallons":1}"#)))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(text(response).await, r#"{"liters":3.78541}"#);
+
+        let response = app
+            .oneshot(withdraw(Some(r#"{"liters":1,"gallons":1}"#)))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_refill() {
+        let app = create_test_app(RateLimiterState::default());
+
+        for _ in 0..INITIAL_TOKENS {
+            let response = app.clone().oneshot(withdraw(None)).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+        let response = app.clone().oneshot(withdraw(None)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(text(response).await, "No milk available\n");
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/9/refill")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let bucket: Bucket = serde_json::from_str(&text(response).await).unwrap();
+        assert_eq!(bucket.level, INITIAL_TOKENS);
+        assert_eq!(bucket.max, MAX_TOKENS);
+        assert_eq!(bucket.refill_amount, REFILL_AMOUNT);
+        assert!(bucket.next_refill > Utc::now());
+
+        let response = app.oneshot(withdraw(None)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_configured_bucket() {
+        let app = create_test_app(RateLimiterState::new(BucketConfig {
+            initial: 2,
+            max: 3,
+            refill_amount: 2,
+            refill_interval: Duration::from_secs(60),
+        }));
+
+        for _ in 0..2 {
+            let response = app.clone().oneshot(withdraw(None)).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+        let response = app.clone().oneshot(withdraw(None)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/9/refill")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let bucket: Bucket = serde_json::from_str(&text(response).await).unwrap();
+        assert_eq!(bucket.level, 2);
+        assert_eq!(bucket.max, 3);
+        assert_eq!(bucket.refill_amount, 2);
+    }
 }
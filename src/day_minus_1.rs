@@ -1,8 +1,22 @@
 use axum::{
-    http::{header, StatusCode},
+    http::{header, Method, StatusCode},
     response::IntoResponse,
 };
 
+use crate::openapi::Operation;
+
+pub fn operations() -> Vec<Operation> {
+    vec![
+        Operation::new(Method::GET, "/", "Greets the bird").media_response(
+            StatusCode::OK,
+            "text/plain",
+            "Hello, bird!",
+        ),
+        Operation::new(Method::GET, "/-1/seek", "Sends the seeker on their way")
+            .response(StatusCode::FOUND, "Redirect to the video"),
+    ]
+}
+
 pub async fn hello_bird() -> &'static str {
     "Hello, bird!"
 }
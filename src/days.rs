@@ -0,0 +1,140 @@
+//! Days of the challenge a deployment serves, e.g. a staging one without the database-backed
+//! day 19. The routes of a disabled day answer 404 as if they were never mounted.
+
+use std::{collections::BTreeSet, sync::Arc};
+
+use axum::{
+    extract::{MatchedPath, Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+
+use crate::{progress::DAYS, versioning::unversioned};
+
+#[derive(Debug, Clone, Default)]
+pub struct EnabledDays {
+    disabled: Arc<BTreeSet<i8>>,
+}
+
+#[derive(Debug, Serialize)]
+struct DayStatus {
+    day: i8,
+    enabled: bool,
+}
+
+impl EnabledDays {
+    pub fn new(disabled: BTreeSet<i8>) -> Self {
+        Self {
+            disabled: Arc::new(disabled),
+        }
+    }
+
+    pub fn is_enabled(&self, day: i8) -> bool {
+        !self.disabled.contains(&day)
+    }
+}
+
+/// Day of a route template, from its first segment. `/` is the greeting of day -1.
+pub(crate) fn day_of(path: &str) -> Option<i8> {
+    match unversioned(path) {
+        "/" => Some(-1),
+        path => path.trim_start_matches('/').split('/').next()?.parse().ok(),
+    }
+}
+
+/// Route middleware answering 404 for the routes of a disabled day
+pub async fn gate(
+    State(days): State<EnabledDays>,
+    path: Option<MatchedPath>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let disabled = path
+        .and_then(|p| day_of(p.as_str()))
+        .is_some_and(|day| !days.is_enabled(day));
+    if disabled {
+        return (StatusCode::NOT_FOUND, "".to_string()).into_response();
+    }
+    next.run(request).await
+}
+
+/// Every day the service has tasks for and whether it's served
+pub async fn days(State(days): State<EnabledDays>) -> impl IntoResponse {
+    Json(
+        DAYS.iter()
+            .map(|d| DayStatus {
+                day: d.day,
+                enabled: days.is_enabled(d.day),
+            })
+            .collect::<Vec<_>>(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::versioning::versioned;
+    use axum::{body::Body, middleware, routing::get, Router};
+    use http_body_util::BodyExt;
+    use serde_json::Value;
+    use tower::ServiceExt;
+
+    fn create_test_app() -> Router {
+        let days = EnabledDays::new(BTreeSet::from([-1, 19]));
+        let routes = Router::new()
+            .route("/", get(|| async { "Hello, bird!" }))
+            .route("/2/dest", get(|| async { "10.0.0.0" }))
+            .route("/19/cite/:id", get(|| async { "Ho ho ho" }))
+            .route("/health", get(|| async { "ok" }))
+            .route_layer(middleware::from_fn_with_state(days.clone(), gate));
+        versioned(routes).merge(
+            Router::new()
+                .route("/days", get(super::days))
+                .with_state(days),
+        )
+    }
+
+    async fn get_status(uri: &str) -> StatusCode {
+        create_test_app()
+            .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+            .await
+            .unwrap()
+            .status()
+    }
+
+    #[test]
+    fn test_day_of() {
+        assert_eq!(day_of("/"), Some(-1));
+        assert_eq!(day_of("/-1/seek"), Some(-1));
+        assert_eq!(day_of("/v1/19/cite/:id"), Some(19));
+        assert_eq!(day_of("/admin/slo"), None);
+    }
+
+    #[tokio::test]
+    async fn test_gate() {
+        assert_eq!(get_status("/2/dest").await, StatusCode::OK);
+        assert_eq!(get_status("/v1/2/dest").await, StatusCode::OK);
+        assert_eq!(get_status("/health").await, StatusCode::OK);
+        assert_eq!(get_status("/19/cite/1").await, StatusCode::NOT_FOUND);
+        assert_eq!(get_status("/v1/19/cite/1").await, StatusCode::NOT_FOUND);
+        assert_eq!(get_status("/").await, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_days() {
+        let response = create_test_app()
+            .oneshot(Request::builder().uri("/days").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let days = serde_json::from_slice::<Value>(&body).unwrap();
+        let days = days.as_array().unwrap();
+        assert_eq!(days.len(), DAYS.len());
+        assert!(days.contains(&serde_json::json!({ "day": 19, "enabled": false })));
+        assert!(days.contains(&serde_json::json!({ "day": 2, "enabled": true })));
+    }
+}
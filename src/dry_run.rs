@@ -0,0 +1,31 @@
+use serde::{Deserialize, Serialize};
+
+/// `?dry_run=true` query of the destructive endpoints
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+pub struct DryRun {
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/// What a dry run would have done, nothing of it is kept
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Preview<T> {
+    pub dry_run: bool,
+    #[serde(flatten)]
+    pub effect: T,
+}
+
+impl<T> Preview<T> {
+    pub fn new(effect: T) -> Self {
+        Self {
+            dry_run: true,
+            effect,
+        }
+    }
+}
+
+/// Rows a statement would change
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RowsAffected {
+    pub rows_affected: u64,
+}
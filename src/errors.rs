@@ -0,0 +1,376 @@
+//! Error counters and the most recent error responses, for alerting without digging in the logs
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::Arc,
+};
+
+use axum::{
+    extract::{MatchedPath, Query, Request, State},
+    http::{HeaderValue, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+use tracing::Instrument;
+use uuid::Uuid;
+
+use crate::auth::{require_admin, Auth};
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+/// Longer client ids are replaced, they would bloat every log line of the request
+const MAX_REQUEST_ID: usize = 128;
+const HISTORY: usize = 100;
+const UNMATCHED: &str = "unmatched";
+
+/// Labels an error is counted under
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct ErrorLabels {
+    /// Day module serving the route, e.g. `day_16`
+    pub module: String,
+    pub route: String,
+    /// Snake-cased reason of the status code, e.g. `not_found`
+    pub kind: String,
+}
+
+/// Finer reason of an error response, set by the handler as a response extension
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ErrorKind(pub &'static str);
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ErrorCount {
+    #[serde(flatten)]
+    pub labels: ErrorLabels,
+    pub count: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ErrorRecord {
+    pub request_id: String,
+    #[serde(with = "crate::conventions::datetime")]
+    pub at: DateTime<Utc>,
+    pub method: String,
+    pub status: u16,
+    #[serde(flatten)]
+    pub labels: ErrorLabels,
+}
+
+#[derive(Default)]
+struct Inner {
+    counts: BTreeMap<ErrorLabels, u64>,
+    recent: VecDeque<ErrorRecord>,
+}
+
+/// Error counters since startup, plus a ring buffer of the last errors
+#[derive(Clone, Default)]
+pub struct ErrorLog {
+    inner: Arc<Mutex<Inner>>,
+}
+
+#[derive(Deserialize)]
+pub struct Recent {
+    limit: Option<usize>,
+}
+
+impl ErrorLabels {
+    fn new(route: Option<&str>, status: StatusCode) -> Self {
+        let route = route.unwrap_or(UNMATCHED);
+        Self {
+            module: module_of(route),
+            route: route.to_string(),
+            kind: status
+                .canonical_reason()
+                .unwrap_or("unknown")
+                .to_ascii_lowercase()
+                .replace([' ', '-'], "_"),
+        }
+    }
+}
+
+/// Day module a route belongs to, taken from its first segment
+fn module_of(route: &str) -> String {
+    let segment = route
+        .trim_start_matches('/')
+        .split('/')
+        .next()
+        .unwrap_or("");
+    match segment {
+        "-1" => "day_minus_1".to_string(),
+        s if !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit()) => format!("day_{}", s),
+        "" => "root".to_string(),
+        s => s.to_string(),
+    }
+}
+
+impl ErrorLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub async fn record(&self, record: ErrorRecord) {
+        let mut inner = self.inner.lock().await;
+        *inner.counts.entry(record.labels.clone()).or_default() += 1;
+        if inner.recent.len() == HISTORY {
+            inner.recent.pop_front();
+        }
+        inner.recent.push_back(record);
+    }
+
+    pub async fn counts(&self) -> Vec<ErrorCount> {
+        self.inner
+            .lock()
+            .await
+            .counts
+            .iter()
+            .map(|(labels, count)| ErrorCount {
+                labels: labels.clone(),
+                count: *count,
+            })
+            .collect()
+    }
+
+    /// Most recent errors first
+    pub async fn recent(&self, limit: usize) -> Vec<ErrorRecord> {
+        self.inner
+            .lock()
+            .await
+            .recent
+            .iter()
+            .rev()
+            .take(limit)
+            .cloned()
+            .collect()
+    }
+}
+
+/// Ids of the client are kept to characters that can't be mistaken for log syntax
+fn is_valid_request_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_REQUEST_ID
+        && id
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"-_.:".contains(&b))
+}
+
+/// Tags every request with an id, reusing the one sent by the client, and records error responses.
+/// The id is also attached to every log line of the request.
+pub async fn track_errors(
+    State(log): State<ErrorLog>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let request_id = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .filter(|v| is_valid_request_id(v))
+        .map(str::to_string)
+        .unwrap_or_else(|| Uuid::new_v4().to_string());
+    let header = HeaderValue::from_str(&request_id).ok();
+    if let Some(value) = &header {
+        request
+            .headers_mut()
+            .insert(REQUEST_ID_HEADER, value.clone());
+    }
+
+    let method = request.method().to_string();
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|p| p.as_str().to_string());
+
+    let mut response = next
+        .run(request)
+        .instrument(tracing::info_span!("request", id = %request_id))
+        .await;
+    if let Some(value) = header {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+
+    let status = response.status();
+    if status.is_client_error() || status.is_server_error() {
+        let mut labels = ErrorLabels::new(route.as_deref(), status);
+        if let Some(ErrorKind(kind)) = response.extensions().get::<ErrorKind>() {
+            labels.kind = kind.to_string();
+        }
+        log.record(ErrorRecord {
+            request_id,
+            at: Utc::now(),
+            method,
+            status: status.as_u16(),
+            labels,
+        })
+        .await;
+    }
+
+    response
+}
+
+/// Routes exposing the error log, meant to be nested under `/admin/errors`
+pub fn errors_router(log: ErrorLog, auth: Auth) -> Router {
+    Router::new()
+        .route("/", get(counts))
+        .route("/recent", get(recent))
+        .with_state(log)
+        .layer(middleware::from_fn_with_state(auth, require_admin))
+}
+
+pub async fn counts(State(log): State<ErrorLog>) -> impl IntoResponse {
+    Json(log.counts().await)
+}
+
+pub async fn recent(State(log): State<ErrorLog>, Query(query): Query<Recent>) -> impl IntoResponse {
+    Json(log.recent(query.limit.unwrap_or(HISTORY)).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use axum::{body::Body, routing::post, Extension};
+    use http_body_util::BodyExt;
+    use tower::ServiceExt;
+
+    fn create_test_app(log: ErrorLog) -> Router {
+        Router::new()
+            .route("/16/unwrap", get(|| async { StatusCode::BAD_REQUEST }))
+            .route("/19/draft", post(|| async { StatusCode::CREATED }))
+            .route(
+                "/16/open",
+                post(|| async {
+                    (
+                        StatusCode::UNAUTHORIZED,
+                        Extension(ErrorKind("signature_failed")),
+                    )
+                }),
+            )
+            .nest(
+                "/admin/errors",
+                errors_router(log.clone(), Auth::new(&Config::default())),
+            )
+            .layer(middleware::from_fn_with_state(log, track_errors))
+    }
+
+    fn request(method: &str, uri: &str) -> Request {
+        Request::builder()
+            .method(method)
+            .uri(uri)
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[test]
+    fn test_labels() {
+        let labels = ErrorLabels::new(Some("/19/cite/:id"), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(labels.module, "day_19");
+        assert_eq!(labels.kind, "internal_server_error");
+        assert_eq!(module_of("/-1/seek"), "day_minus_1");
+        assert_eq!(module_of("/admin/ui/quotes"), "admin");
+        assert_eq!(
+            ErrorLabels::new(None, StatusCode::NOT_FOUND).route,
+            UNMATCHED
+        );
+    }
+
+    #[tokio::test]
+    async fn test_ring_buffer() {
+        let log = ErrorLog::new();
+        for i in 0..HISTORY + 5 {
+            log.record(ErrorRecord {
+                request_id: i.to_string(),
+                at: Utc::now(),
+                method: "GET".to_string(),
+                status: 404,
+                labels: ErrorLabels::new(None, StatusCode::NOT_FOUND),
+            })
+            .await;
+        }
+
+        let recent = log.recent(HISTORY * 2).await;
+        assert_eq!(recent.len(), HISTORY);
+        assert_eq!(recent[0].request_id, (HISTORY + 4).to_string());
+        assert_eq!(log.counts().await[0].count, (HISTORY + 5) as u64);
+    }
+
+    #[tokio::test]
+    async fn test_records_errors() {
+        let log = ErrorLog::new();
+        let app = create_test_app(log.clone());
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri("/16/unwrap")
+                    .header(REQUEST_ID_HEADER, "elf-1")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(response.headers()[REQUEST_ID_HEADER], "elf-1");
+
+        let response = app
+            .clone()
+            .oneshot(request("POST", "/19/draft"))
+            .await
+            .unwrap();
+        assert!(response.headers().contains_key(REQUEST_ID_HEADER));
+
+        let response = app
+            .oneshot(request("GET", "/admin/errors/recent?limit=5"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let recent: Vec<ErrorRecord> = serde_json::from_slice(&body).unwrap();
+        assert_eq!(recent.len(), 1);
+        assert_eq!(recent[0].request_id, "elf-1");
+        assert_eq!(recent[0].labels.module, "day_16");
+        assert_eq!(recent[0].labels.route, "/16/unwrap");
+        assert_eq!(recent[0].labels.kind, "bad_request");
+    }
+
+    #[tokio::test]
+    async fn test_replaces_invalid_request_ids() {
+        let app = create_test_app(ErrorLog::new());
+        let long = "a".repeat(MAX_REQUEST_ID + 1);
+        for id in ["id=1 level=error", long.as_str()] {
+            let response = app
+                .clone()
+                .oneshot(
+                    Request::builder()
+                        .uri("/19/draft")
+                        .method("POST")
+                        .header(REQUEST_ID_HEADER, id)
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            let echoed = response.headers()[REQUEST_ID_HEADER].to_str().unwrap();
+            assert!(Uuid::parse_str(echoed).is_ok());
+        }
+        assert!(is_valid_request_id("cch24:day-19.run_2"));
+    }
+
+    #[tokio::test]
+    async fn test_error_kind_from_handler() {
+        let log = ErrorLog::new();
+        let response = create_test_app(log.clone())
+            .oneshot(request("POST", "/16/open"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let recent = log.recent(1).await;
+        assert_eq!(recent[0].status, 401);
+        assert_eq!(recent[0].labels.kind, "signature_failed");
+    }
+}
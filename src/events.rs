@@ -0,0 +1,130 @@
+//! `GET /19/events`, quote changes from the outbox as Server-Sent Events
+
+use core::convert::Infallible;
+
+use axum::{
+    extract::{Query, State},
+    response::{
+        sse::{Event, KeepAlive},
+        Sse,
+    },
+};
+use serde::Deserialize;
+use tokio::sync::broadcast;
+use tokio_stream::{wrappers::BroadcastStream, Stream, StreamExt};
+
+use crate::{outbox::OutboxEvent, shutdown::until_draining};
+
+#[derive(Clone)]
+pub struct EventsState {
+    pub events: broadcast::Sender<OutboxEvent>,
+}
+
+#[derive(Deserialize)]
+pub struct Feed {
+    author: Option<String>,
+}
+
+/// Whether the event belongs in the feed, only changes to visible quotes are announced
+fn visible(event: &OutboxEvent, author: Option<&str>) -> bool {
+    let quote = &event.payload.0;
+    event.topic.starts_with("quote.")
+        && !matches!(
+            quote["status"].as_str(),
+            Some("draft") | Some("pending_review")
+        )
+        && author.is_none_or(|a| quote["author"] == a)
+}
+
+pub async fn events(
+    State(state): State<EventsState>,
+    Query(feed): Query<Feed>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    // subscribers that fall behind miss events rather than slow the others down
+    let stream = BroadcastStream::new(state.events.subscribe()).filter_map(move |event| {
+        let event = event.ok()?;
+        visible(&event, feed.author.as_deref()).then(|| {
+            Ok(Event::default()
+                .id(event.id.to_string())
+                .event(&event.topic)
+                .data(event.payload.0.to_string()))
+        })
+    });
+    Sse::new(until_draining(stream)).keep_alive(KeepAlive::default())
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, http::Request, routing::get, Router};
+    use http_body_util::BodyExt;
+    use serde_json::json;
+    use sqlx::types::Json;
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::outbox::{QUOTES_RESET, QUOTE_CREATED, QUOTE_DELETED};
+
+    fn event(id: i64, topic: &str, payload: serde_json::Value) -> OutboxEvent {
+        OutboxEvent {
+            id,
+            topic: topic.to_string(),
+            payload: Json(payload),
+            attempts: 0,
+        }
+    }
+
+    #[test]
+    fn test_visible() {
+        let santa = json!({"author": "Santa", "status": "published"});
+        assert!(visible(&event(1, QUOTE_CREATED, santa.clone()), None));
+        assert!(visible(
+            &event(1, QUOTE_DELETED, santa.clone()),
+            Some("Santa")
+        ));
+        assert!(!visible(&event(1, QUOTE_CREATED, santa), Some("Grinch")));
+        assert!(!visible(&event(1, QUOTES_RESET, json!(null)), None));
+        assert!(!visible(
+            &event(
+                1,
+                QUOTE_CREATED,
+                json!({"author": "Santa", "status": "pending_review"})
+            ),
+            None
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_stream_filtered_by_author() {
+        let (sender, _) = broadcast::channel(8);
+        let app = Router::new()
+            .route("/19/events", get(events))
+            .with_state(EventsState {
+                events: sender.clone(),
+            });
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/19/events?author=Santa")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.headers()["content-type"], "text/event-stream");
+
+        sender
+            .send(event(1, QUOTE_CREATED, json!({"author": "Grinch"})))
+            .unwrap();
+        sender
+            .send(event(2, QUOTE_CREATED, json!({"author": "Santa"})))
+            .unwrap();
+
+        let mut body = response.into_body();
+        let frame = body.frame().await.unwrap().unwrap().into_data().unwrap();
+        assert_eq!(
+            String::from_utf8(frame.to_vec()).unwrap(),
+            "id: 2\nevent: quote.created\ndata: {\"author\":\"Santa\"}\n\n"
+        );
+    }
+}
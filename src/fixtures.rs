@@ -0,0 +1,603 @@
+//! Curated datasets for manual testing and benchmarks, loaded with `POST /admin/seed?set=`.
+//! Every set is generated from a fixed seed and dated from a fixed instant, so that loading it
+//! always inserts the same rows. Rows have fixed keys, negative ones in the tables numbered by a
+//! sequence, and loading a set again inserts nothing new.
+
+use core::str::FromStr;
+use std::{collections::HashSet, sync::Arc};
+
+use axum::{extract::State, http::StatusCode, Json};
+use chrono::{DateTime, Duration, Utc};
+#[cfg(test)]
+use mockall::automock;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use sqlx::{query, query_scalar, PgPool};
+use uuid::Uuid;
+
+use crate::{
+    day_12::{random_game, NewGameResult},
+    day_19::{Quote, QuoteStatus},
+    outbox,
+    players::elo,
+    validation::{FromParams, Params, ValidatedQuery},
+};
+
+/// 2024-12-01T00:00:00Z, the newest row of every set is dated from it
+const EPOCH: i64 = 1_733_011_200;
+const INITIAL_RATING: i32 = 1200;
+
+const AUTHORS: [&str; 12] = [
+    "Santa",
+    "Mrs. Claus",
+    "Rudolph",
+    "Buddy the Elf",
+    "Ebenezer Scrooge",
+    "The Grinch",
+    "Jack Frost",
+    "Frosty",
+    "Tiny Tim",
+    "Clarice",
+    "Hermey",
+    "Yukon Cornelius",
+];
+const OPENINGS: [&str; 8] = [
+    "Christmas is",
+    "A cookie a day is",
+    "Every present is",
+    "Snow on the roof is",
+    "A well wrapped gift is",
+    "Milk before midnight is",
+    "The naughty list is",
+    "A quiet workshop is",
+];
+const MIDDLES: [&str; 8] = [
+    "a promise",
+    "a reminder",
+    "a lesson",
+    "a secret",
+    "a warning",
+    "a gift",
+    "a tradition",
+    "an adventure",
+];
+const ENDINGS: [&str; 8] = [
+    "that the sleigh keeps.",
+    "worth sharing.",
+    "best kept until the 25th.",
+    "that no elf forgets.",
+    "written in the snow.",
+    "for the whole north pole.",
+    "that melts by spring.",
+    "in every chimney.",
+];
+const REINDEER: [&str; 8] = [
+    "dasher", "dancer", "prancer", "vixen", "comet", "cupid", "donner", "blitzen",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FixtureSet {
+    /// Enough of everything to click around
+    Demo,
+    /// Large enough for pagination and search to be measured
+    Bench,
+}
+
+impl FromStr for FixtureSet {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "demo" => Ok(Self::Demo),
+            "bench" => Ok(Self::Bench),
+            _ => Err(format!("unknown fixture set {}", s)),
+        }
+    }
+}
+
+struct Sizes {
+    quotes: usize,
+    /// Most likes a quote gets
+    likes: usize,
+    games: usize,
+    players: usize,
+    rated_games: usize,
+}
+
+impl FixtureSet {
+    fn seed(&self) -> u64 {
+        match self {
+            Self::Demo => 2024,
+            Self::Bench => 1225,
+        }
+    }
+
+    fn sizes(&self) -> Sizes {
+        match self {
+            Self::Demo => Sizes {
+                quotes: 300,
+                likes: 12,
+                games: 60,
+                players: 16,
+                rated_games: 200,
+            },
+            Self::Bench => Sizes {
+                quotes: 10_000,
+                likes: 40,
+                games: 2_000,
+                players: 200,
+                rated_games: 5_000,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FixtureQuote {
+    pub id: Uuid,
+    pub author: String,
+    pub quote: String,
+    pub created_at: DateTime<Utc>,
+    /// Clients that liked the quote, seeded players as `/19/cite/:id/like` records them
+    pub likes: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FixtureGame {
+    pub id: i64,
+    pub result: NewGameResult,
+    pub finished_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FixturePlayer {
+    pub name: String,
+    pub token: Uuid,
+    pub rating: i32,
+    pub games: i32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FixtureRatingChange {
+    pub id: i64,
+    pub player: String,
+    pub opponent: String,
+    pub outcome: &'static str,
+    pub rating: i32,
+    pub delta: i32,
+    pub played_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Dataset {
+    pub quotes: Vec<FixtureQuote>,
+    pub games: Vec<FixtureGame>,
+    pub players: Vec<FixturePlayer>,
+    pub rating_changes: Vec<FixtureRatingChange>,
+}
+
+/// Rows inserted by a load, all zero when the set was loaded already
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Seeded {
+    pub quotes: u64,
+    pub likes: u64,
+    pub games: u64,
+    pub players: u64,
+    pub rating_changes: u64,
+}
+
+fn uuid(rng: &mut StdRng) -> Uuid {
+    uuid::Builder::from_random_bytes(rng.gen()).into_uuid()
+}
+
+/// Picks early entries more often, so that a few authors are prolific
+fn skewed<'a>(rng: &mut StdRng, values: &[&'a str]) -> &'a str {
+    let index = rng
+        .gen_range(0..values.len())
+        .min(rng.gen_range(0..values.len()));
+    values[index]
+}
+
+/// Name of the i-th seeded player, known before the players are
+fn player_name(i: usize) -> String {
+    format!("{}-{:03}", REINDEER[i % REINDEER.len()], i)
+}
+
+fn quotes(rng: &mut StdRng, sizes: &Sizes, epoch: DateTime<Utc>) -> Vec<FixtureQuote> {
+    (0..sizes.quotes)
+        .map(|i| FixtureQuote {
+            id: uuid(rng),
+            author: skewed(rng, &AUTHORS).to_string(),
+            quote: format!(
+                "{} {} {}",
+                OPENINGS[rng.gen_range(0..OPENINGS.len())],
+                MIDDLES[rng.gen_range(0..MIDDLES.len())],
+                ENDINGS[rng.gen_range(0..ENDINGS.len())]
+            ),
+            // three quotes share every timestamp, for the tie breakers of the list orders
+            created_at: epoch - Duration::minutes((i / 3) as i64),
+            likes: {
+                // a run of players from a random one, each liking once
+                let first = rng.gen_range(0..sizes.players);
+                (0..rng.gen_range(0..=sizes.likes.min(sizes.players)))
+                    .map(|n| format!("player:{}", player_name((first + n) % sizes.players)))
+                    .collect()
+            },
+        })
+        .collect()
+}
+
+fn games(rng: &mut StdRng, sizes: &Sizes, epoch: DateTime<Utc>) -> Vec<FixtureGame> {
+    (0..sizes.games)
+        .filter_map(|i| {
+            // short games are abandoned, long ones are won or tied
+            let moves = rng.gen_range(1..=16);
+            random_game(rng, moves).map(|result| FixtureGame {
+                id: -(i as i64) - 1,
+                result,
+                finished_at: epoch - Duration::minutes(17 * i as i64),
+            })
+        })
+        .collect()
+}
+
+fn ratings(
+    rng: &mut StdRng,
+    sizes: &Sizes,
+    epoch: DateTime<Utc>,
+) -> (Vec<FixturePlayer>, Vec<FixtureRatingChange>) {
+    let mut players = (0..sizes.players)
+        .map(|i| FixturePlayer {
+            name: player_name(i),
+            token: uuid(rng),
+            rating: INITIAL_RATING,
+            games: 0,
+        })
+        .collect::<Vec<_>>();
+
+    let mut changes = Vec::with_capacity(sizes.rated_games * 2);
+    // played oldest first, so that the latest rating is the one of the players
+    for game in 0..sizes.rated_games {
+        let a = rng.gen_range(0..players.len());
+        let b = (a + rng.gen_range(1..players.len())) % players.len();
+        let (score, outcomes) = match rng.gen_range(0..10) {
+            0 => (0.5, ("tie", "tie")),
+            n if n % 2 == 0 => (1.0, ("win", "loss")),
+            _ => (0.0, ("loss", "win")),
+        };
+        let (before_a, before_b) = (players[a].rating, players[b].rating);
+        let (after_a, after_b) = elo(before_a, before_b, score);
+        let played_at = epoch - Duration::minutes(7 * (sizes.rated_games - game) as i64);
+
+        for (player, opponent, outcome, before, after) in [
+            (a, b, outcomes.0, before_a, after_a),
+            (b, a, outcomes.1, before_b, after_b),
+        ] {
+            players[player].rating = after;
+            players[player].games += 1;
+            changes.push(FixtureRatingChange {
+                id: -(changes.len() as i64) - 1,
+                player: players[player].name.clone(),
+                opponent: players[opponent].name.clone(),
+                outcome,
+                rating: after,
+                delta: after - before,
+                played_at,
+            });
+        }
+    }
+    (players, changes)
+}
+
+/// Rows of a set, the same ones on every call
+pub fn dataset(set: FixtureSet) -> Dataset {
+    let mut rng = StdRng::seed_from_u64(set.seed());
+    let sizes = set.sizes();
+    let epoch = DateTime::from_timestamp(EPOCH, 0).unwrap();
+
+    let quotes = quotes(&mut rng, &sizes, epoch);
+    let games = games(&mut rng, &sizes, epoch);
+    let (players, rating_changes) = ratings(&mut rng, &sizes, epoch);
+    Dataset {
+        quotes,
+        games,
+        players,
+        rating_changes,
+    }
+}
+
+#[async_trait::async_trait]
+#[cfg_attr(test, automock)]
+pub trait FixtureLoader: Send + Sync + 'static {
+    /// Inserts the rows missing from the database, all of them or none
+    async fn load(&self, dataset: Dataset) -> Result<Seeded, sqlx::Error>;
+}
+
+pub struct PostgresFixtureLoader {
+    pool: PgPool,
+}
+
+impl PostgresFixtureLoader {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl FixtureLoader for PostgresFixtureLoader {
+    async fn load(&self, dataset: Dataset) -> Result<Seeded, sqlx::Error> {
+        let Dataset {
+            quotes,
+            games,
+            players,
+            rating_changes,
+        } = dataset;
+        let mut tx = self.pool.begin().await?;
+
+        // one statement per table, bench sets are too large for a round trip per row
+        let inserted = query_scalar::<_, Uuid>(
+            "INSERT INTO quotes (id, author, quote, created_at) \
+             SELECT * FROM UNNEST($1::uuid[], $2::text[], $3::text[], $4::timestamptz[]) \
+             ON CONFLICT (id) DO NOTHING RETURNING id",
+        )
+        .bind(quotes.iter().map(|q| q.id).collect::<Vec<_>>())
+        .bind(quotes.iter().map(|q| q.author.clone()).collect::<Vec<_>>())
+        .bind(quotes.iter().map(|q| q.quote.clone()).collect::<Vec<_>>())
+        .bind(quotes.iter().map(|q| q.created_at).collect::<Vec<_>>())
+        .fetch_all(&mut *tx)
+        .await?
+        .into_iter()
+        .collect::<HashSet<_>>();
+        let seeded_quotes = inserted.len() as u64;
+
+        // the authors index and the subscribers only learn of quotes through the outbox
+        let created = quotes
+            .iter()
+            .filter(|q| inserted.contains(&q.id))
+            .map(|q| Quote {
+                id: q.id,
+                author: q.author.clone(),
+                quote: q.quote.clone(),
+                created_at: q.created_at,
+                version: 1,
+                likes: q.likes.len() as i64,
+                publish_at: None,
+                status: QuoteStatus::Published,
+            })
+            .collect::<Vec<_>>();
+        outbox::enqueue_all(&mut tx, outbox::QUOTE_CREATED, &created).await?;
+
+        let (liked, clients): (Vec<_>, Vec<_>) = quotes
+            .iter()
+            .flat_map(|q| q.likes.iter().map(|client| (q.id, client.clone())))
+            .unzip();
+        let likes = query(
+            "INSERT INTO quote_likes (quote_id, client_id) \
+             SELECT * FROM UNNEST($1::uuid[], $2::text[]) ON CONFLICT DO NOTHING",
+        )
+        .bind(liked)
+        .bind(clients)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+
+        let seeded_games = query(
+            "INSERT INTO game_results (id, outcome, moves, board, finished_at) \
+             SELECT * FROM UNNEST($1::bigint[], $2::text[], $3::int[], $4::text[], $5::timestamptz[]) \
+             ON CONFLICT (id) DO NOTHING",
+        )
+        .bind(games.iter().map(|g| g.id).collect::<Vec<_>>())
+        .bind(games.iter().map(|g| g.result.outcome.clone()).collect::<Vec<_>>())
+        .bind(games.iter().map(|g| g.result.moves).collect::<Vec<_>>())
+        .bind(games.iter().map(|g| g.result.board.clone()).collect::<Vec<_>>())
+        .bind(games.iter().map(|g| g.finished_at).collect::<Vec<_>>())
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+
+        let seeded_players = query(
+            "INSERT INTO players (name, token, rating, games) \
+             SELECT * FROM UNNEST($1::text[], $2::uuid[], $3::int[], $4::int[]) \
+             ON CONFLICT DO NOTHING",
+        )
+        .bind(players.iter().map(|p| p.name.clone()).collect::<Vec<_>>())
+        .bind(players.iter().map(|p| p.token).collect::<Vec<_>>())
+        .bind(players.iter().map(|p| p.rating).collect::<Vec<_>>())
+        .bind(players.iter().map(|p| p.games).collect::<Vec<_>>())
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+
+        let seeded_changes = query(
+            "INSERT INTO rating_changes (id, player, opponent, outcome, rating, delta, played_at) \
+             SELECT * FROM UNNEST($1::bigint[], $2::text[], $3::text[], $4::text[], $5::int[], \
+             $6::int[], $7::timestamptz[]) \
+             ON CONFLICT (id) DO NOTHING",
+        )
+        .bind(rating_changes.iter().map(|c| c.id).collect::<Vec<_>>())
+        .bind(
+            rating_changes
+                .iter()
+                .map(|c| c.player.clone())
+                .collect::<Vec<_>>(),
+        )
+        .bind(
+            rating_changes
+                .iter()
+                .map(|c| c.opponent.clone())
+                .collect::<Vec<_>>(),
+        )
+        .bind(rating_changes.iter().map(|c| c.outcome).collect::<Vec<_>>())
+        .bind(rating_changes.iter().map(|c| c.rating).collect::<Vec<_>>())
+        .bind(rating_changes.iter().map(|c| c.delta).collect::<Vec<_>>())
+        .bind(
+            rating_changes
+                .iter()
+                .map(|c| c.played_at)
+                .collect::<Vec<_>>(),
+        )
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+
+        tx.commit().await?;
+        Ok(Seeded {
+            quotes: seeded_quotes,
+            likes,
+            games: seeded_games,
+            players: seeded_players,
+            rating_changes: seeded_changes,
+        })
+    }
+}
+
+pub fn state_fixture_loader(pool: PgPool) -> Arc<dyn FixtureLoader> {
+    Arc::new(PostgresFixtureLoader::new(pool))
+}
+
+#[derive(Clone)]
+pub struct FixtureState {
+    pub loader: Arc<dyn FixtureLoader>,
+}
+
+pub struct SeedQuery {
+    set: FixtureSet,
+}
+
+impl FromParams for SeedQuery {
+    fn from_params(params: &mut Params) -> Option<Self> {
+        Some(Self {
+            set: params.required("set")?,
+        })
+    }
+}
+
+pub async fn seed(
+    State(state): State<FixtureState>,
+    ValidatedQuery(query): ValidatedQuery<SeedQuery>,
+) -> Result<Json<Seeded>, (StatusCode, String)> {
+    let dataset = dataset(query.set);
+    match state.loader.load(dataset).await {
+        Ok(seeded) => Ok(Json(seeded)),
+        Err(e) => {
+            tracing::error!("loading the fixtures failed: {}", e);
+            Err((StatusCode::INTERNAL_SERVER_ERROR, "".to_string()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, HashSet};
+
+    use super::*;
+    use crate::test_support::box_future;
+    use axum::{body::Body, http::Request, routing::post, Router};
+    use http_body_util::BodyExt;
+    use tower::ServiceExt;
+
+    #[test]
+    fn test_sets_are_deterministic() {
+        assert_eq!(dataset(FixtureSet::Demo), dataset(FixtureSet::Demo));
+        assert_ne!(
+            dataset(FixtureSet::Demo).quotes[0],
+            dataset(FixtureSet::Bench).quotes[0]
+        );
+        assert_eq!("bench".parse(), Ok(FixtureSet::Bench));
+        assert!("prod".parse::<FixtureSet>().is_err());
+    }
+
+    #[test]
+    fn test_demo_set() {
+        let demo = dataset(FixtureSet::Demo);
+        assert_eq!(demo.quotes.len(), 300);
+        let ids = demo.quotes.iter().map(|q| q.id).collect::<HashSet<_>>();
+        assert_eq!(ids.len(), demo.quotes.len());
+        assert!(demo.quotes.iter().all(|q| q.quote.ends_with('.')));
+
+        // likes come from the seeded players, as the like route records them
+        let clients = demo
+            .players
+            .iter()
+            .map(|p| format!("player:{}", p.name))
+            .collect::<HashSet<_>>();
+        for quote in &demo.quotes {
+            let likers = quote.likes.iter().collect::<HashSet<_>>();
+            assert_eq!(likers.len(), quote.likes.len());
+            assert!(likers.iter().all(|c| clients.contains(*c)));
+        }
+        assert!(demo.quotes.iter().any(|q| !q.likes.is_empty()));
+
+        // every game had a move, so every one of them was archived
+        assert_eq!(demo.games.len(), 60);
+        assert!(demo.games.iter().all(|g| g.id < 0));
+        let outcomes = demo
+            .games
+            .iter()
+            .map(|g| g.result.outcome.as_str())
+            .collect::<HashSet<_>>();
+        assert!(outcomes.contains("abandoned"));
+        assert!(outcomes.contains("cookie") || outcomes.contains("milk"));
+
+        // ratings are where the recorded changes lead
+        let mut ratings = HashMap::new();
+        for change in &demo.rating_changes {
+            *ratings.entry(&change.player).or_insert(INITIAL_RATING) += change.delta;
+            assert_eq!(ratings[&change.player], change.rating);
+        }
+        for player in &demo.players {
+            assert_eq!(
+                ratings.get(&player.name).copied().unwrap_or(INITIAL_RATING),
+                player.rating
+            );
+        }
+        assert_eq!(demo.rating_changes.len(), 400);
+    }
+
+    #[tokio::test]
+    async fn test_seed() {
+        let mut loader = MockFixtureLoader::new();
+        loader
+            .expect_load()
+            .withf(|dataset| dataset.quotes.len() == 300)
+            .times(1)
+            .returning(|dataset| {
+                box_future(Ok(Seeded {
+                    quotes: dataset.quotes.len() as u64,
+                    ..Seeded::default()
+                }))
+            });
+        let app = Router::new()
+            .route("/admin/seed", post(seed))
+            .with_state(FixtureState {
+                loader: Arc::new(loader),
+            });
+
+        let post_seed = |uri: &'static str| {
+            let app = app.clone();
+            async move {
+                let response = app
+                    .oneshot(
+                        Request::builder()
+                            .method("POST")
+                            .uri(uri)
+                            .body(Body::empty())
+                            .unwrap(),
+                    )
+                    .await
+                    .unwrap();
+                let status = response.status();
+                let body = response.into_body().collect().await.unwrap().to_bytes();
+                (status, String::from_utf8(body.to_vec()).unwrap())
+            }
+        };
+
+        let (status, body) = post_seed("/admin/seed?set=demo").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(serde_json::from_str::<Seeded>(&body).unwrap().quotes, 300);
+        let (status, body) = post_seed("/admin/seed?set=prod").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body.contains("unknown fixture set prod"));
+        assert_eq!(post_seed("/admin/seed").await.0, StatusCode::BAD_REQUEST);
+    }
+}
@@ -0,0 +1,223 @@
+//! Conversions between ULIDs, timestamps and timezones
+
+use axum::{extract::Query, http::StatusCode, response::IntoResponse, Json};
+use chrono::{DateTime, FixedOffset, LocalResult, NaiveDateTime, SecondsFormat, TimeZone, Utc};
+use chrono_tz::{OffsetComponents, OffsetName, Tz};
+use serde::{Deserialize, Serialize};
+use ulid::Ulid;
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct GeoError {
+    pub error: &'static str,
+    pub message: String,
+}
+
+type GeoResult<T> = Result<T, (StatusCode, Json<GeoError>)>;
+
+fn error<T>(status: StatusCode, error: &'static str, message: String) -> GeoResult<T> {
+    Err((status, Json(GeoError { error, message })))
+}
+
+#[derive(Deserialize)]
+pub struct Coords {
+    ulid: String,
+    /// Zone to also express the instant in
+    tz: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct Conversion {
+    /// Instant with an offset, e.g. `2024-12-25T00:00:00Z`
+    timestamp: Option<DateTime<FixedOffset>>,
+    ulid: Option<String>,
+    /// Wall-clock time in the `from` zone
+    local: Option<NaiveDateTime>,
+    from: Option<String>,
+    to: String,
+}
+
+/// An instant as seen from a zone
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ZonedTime {
+    pub zone: String,
+    pub local: String,
+    pub utc_offset: String,
+    pub abbreviation: String,
+    pub dst: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Instant {
+    pub utc: String,
+    pub unix_ms: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ulid: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub zoned: Option<ZonedTime>,
+}
+
+fn zone(name: &str) -> GeoResult<Tz> {
+    name.parse::<Tz>().or_else(|_| {
+        error(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "unknown_zone",
+            format!("{} is not an IANA timezone", name),
+        )
+    })
+}
+
+fn ulid_instant(ulid: &str) -> GeoResult<DateTime<Utc>> {
+    let ulid = match Ulid::from_string(ulid) {
+        Ok(u) => u,
+        Err(e) => return error(StatusCode::BAD_REQUEST, "invalid_ulid", e.to_string()),
+    };
+    match DateTime::from_timestamp_millis(ulid.timestamp_ms() as i64) {
+        Some(t) => Ok(t),
+        None => error(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "out_of_range",
+            "the ULID timestamp can't be represented".to_string(),
+        ),
+    }
+}
+
+/// Resolves a wall-clock time, which may be skipped or repeated around DST changes
+fn resolve_local(local: NaiveDateTime, tz: Tz) -> GeoResult<DateTime<Utc>> {
+    match tz.from_local_datetime(&local) {
+        LocalResult::Single(t) => Ok(t.with_timezone(&Utc)),
+        LocalResult::Ambiguous(first, second) => error(
+            StatusCode::CONFLICT,
+            "ambiguous_time",
+            format!(
+                "{} happens twice in {}, at {} and at {}",
+                local,
+                tz,
+                first.to_rfc3339(),
+                second.to_rfc3339()
+            ),
+        ),
+        LocalResult::None => error(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "nonexistent_time",
+            format!("{} is skipped by a DST change in {}", local, tz),
+        ),
+    }
+}
+
+fn zoned(instant: DateTime<Utc>, tz: Tz) -> ZonedTime {
+    let local = instant.with_timezone(&tz);
+    let offset = local.offset();
+    ZonedTime {
+        zone: tz.name().to_string(),
+        local: local.to_rfc3339_opts(SecondsFormat::Millis, false),
+        utc_offset: local.format("%:z").to_string(),
+        abbreviation: offset.abbreviation().unwrap_or_default().to_string(),
+        dst: !offset.dst_offset().is_zero(),
+    }
+}
+
+fn instant(utc: DateTime<Utc>, ulid: Option<String>, zoned: Option<ZonedTime>) -> Instant {
+    Instant {
+        utc: utc.to_rfc3339_opts(SecondsFormat::Millis, true),
+        unix_ms: utc.timestamp_millis(),
+        ulid,
+        zoned,
+    }
+}
+
+pub async fn coords(Query(coords): Query<Coords>) -> impl IntoResponse {
+    let utc = ulid_instant(&coords.ulid)?;
+    let zoned = match coords.tz.as_deref() {
+        Some(name) => Some(zoned(utc, zone(name)?)),
+        None => None,
+    };
+    GeoResult::Ok(Json(instant(
+        utc,
+        Some(coords.ulid.to_ascii_uppercase()),
+        zoned,
+    )))
+}
+
+pub async fn timezone(Json(conversion): Json<Conversion>) -> impl IntoResponse {
+    let to = zone(&conversion.to)?;
+
+    let utc = match (conversion.timestamp, conversion.ulid, conversion.local) {
+        (Some(t), None, None) => t.with_timezone(&Utc),
+        (None, Some(u), None) => ulid_instant(&u)?,
+        (None, None, Some(local)) => match conversion.from.as_deref() {
+            Some(from) => resolve_local(local, zone(from)?)?,
+            None => {
+                return error(
+                    StatusCode::BAD_REQUEST,
+                    "missing_zone",
+                    "a local time needs the zone it's expressed in".to_string(),
+                )
+            }
+        },
+        _ => {
+            return error(
+                StatusCode::BAD_REQUEST,
+                "invalid_input",
+                "exactly one of timestamp, ulid or local is required".to_string(),
+            )
+        }
+    };
+
+    Ok(Json(instant(utc, None, Some(zoned(utc, to)))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn local(y: i32, m: u32, d: u32, h: u32, min: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(y, m, d)
+            .unwrap()
+            .and_hms_opt(h, min, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_ulid_instant() {
+        let ulid = Ulid::from_parts(1_735_084_800_000, 42).to_string();
+        let utc = ulid_instant(&ulid).unwrap();
+        assert_eq!(utc.to_rfc3339(), "2024-12-25T00:00:00+00:00");
+
+        let (status, Json(e)) = ulid_instant("not-a-ulid").unwrap_err();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(e.error, "invalid_ulid");
+    }
+
+    #[test]
+    fn test_zoned_is_dst_aware() {
+        let winter = zoned(local(2024, 12, 25, 12, 0).and_utc(), Tz::Europe__Rome);
+        assert_eq!(winter.utc_offset, "+01:00");
+        assert_eq!(winter.abbreviation, "CET");
+        assert!(!winter.dst);
+
+        let summer = zoned(local(2024, 7, 1, 12, 0).and_utc(), Tz::Europe__Rome);
+        assert_eq!(summer.utc_offset, "+02:00");
+        assert!(summer.dst);
+        assert_eq!(summer.local, "2024-07-01T14:00:00.000+02:00");
+    }
+
+    #[test]
+    fn test_resolve_local_around_dst_changes() {
+        let skipped = resolve_local(local(2024, 3, 31, 2, 30), Tz::Europe__Rome).unwrap_err();
+        assert_eq!(skipped.1.error, "nonexistent_time");
+
+        let repeated = resolve_local(local(2024, 10, 27, 2, 30), Tz::Europe__Rome).unwrap_err();
+        assert_eq!(repeated.0, StatusCode::CONFLICT);
+
+        let noon = resolve_local(local(2024, 12, 25, 12, 0), Tz::Europe__Rome).unwrap();
+        assert_eq!(noon, local(2024, 12, 25, 11, 0).and_utc());
+    }
+
+    #[test]
+    fn test_unknown_zone() {
+        let (status, Json(e)) = zone("Europe/Atlantis").unwrap_err();
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(e.error, "unknown_zone");
+    }
+}
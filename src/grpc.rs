@@ -0,0 +1,393 @@
+use axum::http::StatusCode;
+use tonic::{Request, Response, Status};
+use uuid::Uuid;
+
+use crate::{
+    app_error::AppError,
+    auth::{Auth, Gate, Role},
+    day_19::{create_quote, edit_quote, list_page, DbState, DraftError, NewQuote, Quote},
+};
+
+pub mod pb {
+    tonic::include_proto!("quotes.v1");
+}
+
+use pb::quote_service_server::{QuoteService, QuoteServiceServer};
+
+/// gRPC facade over the same repository used by the day 19 REST endpoints
+pub struct QuoteGrpcService {
+    state: DbState,
+    /// Roles of the REST routes doing the same, `/19/cite` for reads and `/19/draft` for writes
+    reader: Gate,
+    editor: Gate,
+}
+
+impl From<Quote> for pb::Quote {
+    fn from(q: Quote) -> Self {
+        Self {
+            id: q.id.to_string(),
+            author: q.author,
+            quote: q.quote,
+            created_at: q.created_at.to_rfc3339(),
+            version: q.version,
+        }
+    }
+}
+
+// tonic::Status is large by design, every handler returns it anyway
+#[allow(clippy::result_large_err)]
+fn parse_id(id: &str) -> Result<Uuid, Status> {
+    Uuid::parse_str(id).map_err(|_| Status::invalid_argument("invalid quote id"))
+}
+
+// the metadata holds the HTTP/2 headers, so the credentials are the same as over REST
+#[allow(clippy::result_large_err)]
+fn authorize<T>(gate: &Gate, request: &Request<T>) -> Result<(), Status> {
+    gate.check(&request.metadata().clone().into_headers())
+        .map_err(|status| match status {
+            StatusCode::FORBIDDEN => Status::permission_denied("role not allowed"),
+            _ => Status::unauthenticated("missing or unknown credentials"),
+        })
+}
+
+// a missing quote is the only database error the client can do something about
+fn database_status(e: sqlx::Error) -> Status {
+    match e {
+        sqlx::Error::RowNotFound => Status::not_found("quote not found"),
+        e => {
+            tracing::error!("database error: {}", e);
+            Status::internal("database error")
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl QuoteService for QuoteGrpcService {
+    async fn get(
+        &self,
+        request: Request<pb::GetQuoteRequest>,
+    ) -> Result<Response<pb::Quote>, Status> {
+        authorize(&self.reader, &request)?;
+        let id = parse_id(&request.into_inner().id)?;
+        match self.state.repository.get(id).await {
+            Ok(q) => Ok(Response::new(q.into())),
+            Err(e) => Err(database_status(e)),
+        }
+    }
+
+    async fn create(
+        &self,
+        request: Request<pb::CreateQuoteRequest>,
+    ) -> Result<Response<pb::Quote>, Status> {
+        authorize(&self.editor, &request)?;
+        let pb::CreateQuoteRequest { author, quote } = request.into_inner();
+        let new_quote = NewQuote {
+            author,
+            quote,
+            publish_at: None,
+        };
+        // moderated like `/19/draft`, a flagged quote is saved but held for review
+        match create_quote(&self.state, new_quote).await {
+            Ok(q) => Ok(Response::new(q.into())),
+            Err(DraftError::Rejected(reason)) => Err(Status::invalid_argument(reason)),
+            Err(DraftError::Failed(e)) => Err(database_status(e)),
+        }
+    }
+
+    async fn update(
+        &self,
+        request: Request<pb::UpdateQuoteRequest>,
+    ) -> Result<Response<pb::Quote>, Status> {
+        authorize(&self.editor, &request)?;
+        let pb::UpdateQuoteRequest { id, author, quote } = request.into_inner();
+        let id = parse_id(&id)?;
+        let new_quote = NewQuote {
+            author,
+            quote,
+            publish_at: None,
+        };
+        match edit_quote(&self.state, id, new_quote).await {
+            Ok(q) => Ok(Response::new(q.into())),
+            Err(DraftError::Rejected(reason)) => Err(Status::invalid_argument(reason)),
+            Err(DraftError::Failed(e)) => Err(database_status(e)),
+        }
+    }
+
+    async fn delete(
+        &self,
+        request: Request<pb::DeleteQuoteRequest>,
+    ) -> Result<Response<pb::Quote>, Status> {
+        authorize(&self.editor, &request)?;
+        let id = parse_id(&request.into_inner().id)?;
+        match self.state.repository.delete(id, false).await {
+            Ok(q) => Ok(Response::new(q.into())),
+            Err(e) => Err(database_status(e)),
+        }
+    }
+
+    async fn list(
+        &self,
+        request: Request<pb::ListQuotesRequest>,
+    ) -> Result<Response<pb::ListQuotesResponse>, Status> {
+        authorize(&self.reader, &request)?;
+        let token = Some(request.into_inner().token).filter(|t| !t.is_empty());
+        match list_page(&self.state, token).await {
+            Ok(q) => Ok(Response::new(pb::ListQuotesResponse {
+                quotes: q.quotes.into_iter().map(pb::Quote::from).collect(),
+                page: q.page,
+                next_token: q.next_token,
+            })),
+            Err(AppError::BadRequest(_)) => Err(Status::invalid_argument("unknown token")),
+            Err(AppError::Database(e)) => Err(database_status(e)),
+            Err(_) => Err(Status::internal("could not list quotes")),
+        }
+    }
+}
+
+/// Router serving the gRPC quote service, meant to be merged into the main one
+pub fn grpc_router(state: DbState, auth: &Auth) -> axum::Router {
+    tonic::service::Routes::new(QuoteServiceServer::new(QuoteGrpcService {
+        state,
+        reader: auth.require(Role::Reader),
+        editor: auth.require(Role::Editor),
+    }))
+    .into_axum_router()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, sync::Arc};
+
+    use super::*;
+    use crate::{
+        config::Config,
+        day_19::{state_tokens, MockQuoteRepository, QuoteStatus, PAGE_SIZE},
+        moderation::WordListModerator,
+        players::MockPlayerRepository,
+        test_support::box_future,
+    };
+    use chrono::Utc;
+    use mockall::predicate::eq;
+
+    fn service(mock: MockQuoteRepository) -> QuoteGrpcService {
+        service_with(mock, Auth::new(&Config::default()))
+    }
+
+    fn enforced() -> Auth {
+        Auth::new(&Config {
+            admin_token: Some("elf".to_string()),
+            api_keys: HashMap::from([("reader-key".to_string(), Role::Reader)]),
+            enforce_roles: true,
+            production: true,
+            ..Config::default()
+        })
+    }
+
+    fn service_with(mock: MockQuoteRepository, auth: Auth) -> QuoteGrpcService {
+        QuoteGrpcService {
+            reader: auth.require(Role::Reader),
+            editor: auth.require(Role::Editor),
+            state: DbState {
+                repository: Arc::new(mock),
+                tokens: state_tokens(),
+                moderator: Arc::new(WordListModerator::default()),
+                page_size: PAGE_SIZE,
+                players: Arc::new(MockPlayerRepository::new()),
+            },
+        }
+    }
+
+    fn quote(id: Uuid) -> Quote {
+        Quote {
+            id,
+            author: "Author".to_string(),
+            quote: "Quote".to_string(),
+            created_at: Utc::now(),
+            version: 1,
+            likes: 0,
+            publish_at: None,
+            status: QuoteStatus::Published,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_get_ok() {
+        let mut mock = MockQuoteRepository::new();
+        let id = Uuid::new_v4();
+        mock.expect_get()
+            .with(eq(id))
+            .returning(move |id| box_future(Ok(quote(id))));
+
+        let res = service(mock)
+            .get(Request::new(pb::GetQuoteRequest { id: id.to_string() }))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(res.id, id.to_string());
+        assert_eq!(res.author, "Author");
+    }
+
+    #[tokio::test]
+    async fn test_get_invalid_id() {
+        let status = service(MockQuoteRepository::new())
+            .get(Request::new(pb::GetQuoteRequest {
+                id: "not-a-uuid".to_string(),
+            }))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+    }
+
+    #[tokio::test]
+    async fn test_delete_not_found() {
+        let mut mock = MockQuoteRepository::new();
+        mock.expect_delete()
+            .returning(|_, _| box_future(Err(sqlx::Error::RowNotFound)));
+
+        let status = service(mock)
+            .delete(Request::new(pb::DeleteQuoteRequest {
+                id: Uuid::new_v4().to_string(),
+            }))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::NotFound);
+    }
+
+    #[tokio::test]
+    async fn test_delete_without_credentials() {
+        let status = service_with(MockQuoteRepository::new(), enforced())
+            .delete(Request::new(pb::DeleteQuoteRequest {
+                id: Uuid::new_v4().to_string(),
+            }))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::Unauthenticated);
+    }
+
+    #[tokio::test]
+    async fn test_delete_with_reader_key() {
+        let mut request = Request::new(pb::DeleteQuoteRequest {
+            id: Uuid::new_v4().to_string(),
+        });
+        request
+            .metadata_mut()
+            .insert("x-api-key", "reader-key".parse().unwrap());
+
+        let status = service_with(MockQuoteRepository::new(), enforced())
+            .delete(request)
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::PermissionDenied);
+    }
+
+    #[tokio::test]
+    async fn test_delete_with_admin_token() {
+        let mut mock = MockQuoteRepository::new();
+        mock.expect_delete()
+            .returning(|id, _| box_future(Ok(quote(id))));
+        let mut request = Request::new(pb::DeleteQuoteRequest {
+            id: Uuid::new_v4().to_string(),
+        });
+        request
+            .metadata_mut()
+            .insert("authorization", "Bearer elf".parse().unwrap());
+
+        assert!(service_with(mock, enforced()).delete(request).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_create_rejected() {
+        let mut service = service(MockQuoteRepository::new());
+        service.state.moderator = Arc::new(WordListModerator::new(&["grinch".to_string()]));
+
+        let status = service
+            .create(Request::new(pb::CreateQuoteRequest {
+                author: "Author".to_string(),
+                quote: "The Grinch was right".to_string(),
+            }))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+    }
+
+    #[tokio::test]
+    async fn test_create_flagged() {
+        let mut mock = MockQuoteRepository::new();
+        mock.expect_create_flagged().returning(|q, _| {
+            box_future(Ok(Quote {
+                quote: q.quote,
+                status: QuoteStatus::PendingReview,
+                ..quote(Uuid::new_v4())
+            }))
+        });
+
+        let res = service(mock)
+            .create(Request::new(pb::CreateQuoteRequest {
+                author: "Author".to_string(),
+                quote: "a".repeat(2_000),
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(res.quote.len(), 2_000);
+    }
+
+    #[tokio::test]
+    async fn test_update_rejected() {
+        let mut service = service(MockQuoteRepository::new());
+        service.state.moderator = Arc::new(WordListModerator::new(&["grinch".to_string()]));
+
+        let status = service
+            .update(Request::new(pb::UpdateQuoteRequest {
+                id: Uuid::new_v4().to_string(),
+                author: "Grinch".to_string(),
+                quote: "Quote".to_string(),
+            }))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+    }
+
+    #[tokio::test]
+    async fn test_get_database_error() {
+        let mut mock = MockQuoteRepository::new();
+        mock.expect_get()
+            .returning(|_| box_future(Err(sqlx::Error::PoolTimedOut)));
+
+        let status = service(mock)
+            .get(Request::new(pb::GetQuoteRequest {
+                id: Uuid::new_v4().to_string(),
+            }))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::Internal);
+    }
+
+    #[tokio::test]
+    async fn test_list_first_page() {
+        let mut mock = MockQuoteRepository::new();
+        mock.expect_count_quotes().returning(|| box_future(Ok(1)));
+        mock.expect_get_quotes()
+            .returning(|_, _| box_future(Ok(vec![quote(Uuid::new_v4())])));
+
+        let res = service(mock)
+            .list(Request::new(pb::ListQuotesRequest::default()))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(res.quotes.len(), 1);
+        assert_eq!(res.page, 1);
+        assert_eq!(res.next_token, None);
+    }
+
+    #[tokio::test]
+    async fn test_list_unknown_token() {
+        let status = service(MockQuoteRepository::new())
+            .list(Request::new(pb::ListQuotesRequest {
+                token: "unknown".to_string(),
+            }))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+    }
+}
@@ -0,0 +1,203 @@
+//! Liveness at `/health` and readiness at `/ready`, for monitors and the proxy in front
+
+use std::{sync::Arc, time::Duration};
+
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+#[cfg(test)]
+use mockall::automock;
+use serde::Serialize;
+use sqlx::PgPool;
+use tokio::sync::watch;
+
+use crate::migrations::{MigrationStatus, RunState};
+
+/// A pool with no connection left is as good as down for the requests waiting on it
+const PING_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[async_trait::async_trait]
+#[cfg_attr(test, automock)]
+pub trait DatabaseProbe: Send + Sync + 'static {
+    async fn ping(&self) -> Result<(), sqlx::Error>;
+}
+
+pub struct PostgresProbe {
+    pool: PgPool,
+}
+
+impl PostgresProbe {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl DatabaseProbe for PostgresProbe {
+    async fn ping(&self) -> Result<(), sqlx::Error> {
+        sqlx::query("SELECT 1").execute(&self.pool).await?;
+        Ok(())
+    }
+}
+
+pub fn state_probe(pool: PgPool) -> Arc<dyn DatabaseProbe> {
+    Arc::new(PostgresProbe::new(pool))
+}
+
+#[derive(Clone)]
+pub struct HealthState {
+    pub database: Arc<dyn DatabaseProbe>,
+    pub migrations: MigrationStatus,
+    /// From `shutdown::draining`, the proxy stops sending requests before they are cut
+    pub draining: watch::Receiver<bool>,
+}
+
+#[derive(Debug, Serialize)]
+struct Readiness {
+    database: bool,
+    migrations: RunState,
+    draining: bool,
+}
+
+/// Answers as long as the process does, whatever its dependencies
+pub async fn health() -> impl IntoResponse {
+    "OK"
+}
+
+/// 503 until the database answers and the latest migration run succeeded, in manual mode that
+/// means after `POST /admin/migrations`, and again once shutdown is requested
+pub async fn ready(State(state): State<HealthState>) -> impl IntoResponse {
+    let database = match tokio::time::timeout(PING_TIMEOUT, state.database.ping()).await {
+        Ok(Ok(())) => true,
+        Ok(Err(e)) => {
+            tracing::warn!("readiness probe failed: {}", e);
+            false
+        }
+        Err(_) => {
+            tracing::warn!("readiness probe timed out");
+            false
+        }
+    };
+    let migrations = state.migrations.progress().state;
+    let draining = *state.draining.borrow();
+
+    let status = match database && migrations == RunState::Succeeded && !draining {
+        true => StatusCode::OK,
+        false => StatusCode::SERVICE_UNAVAILABLE,
+    };
+    (
+        status,
+        Json(Readiness {
+            database,
+            migrations,
+            draining,
+        }),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migrations::{MigrationState, MockMigrationRunner};
+    use crate::test_support::box_future;
+    use axum::{body::Body, http::Request, routing::get, Router};
+    use http_body_util::BodyExt;
+    use serde_json::{json, Value};
+    use tower::ServiceExt;
+
+    fn create_test_app(database: MockDatabaseProbe, migrations: MigrationStatus) -> Router {
+        create_draining_app(database, migrations, watch::channel(false).1)
+    }
+
+    fn create_draining_app(
+        database: MockDatabaseProbe,
+        migrations: MigrationStatus,
+        draining: watch::Receiver<bool>,
+    ) -> Router {
+        Router::new()
+            .route("/health", get(health))
+            .route("/ready", get(super::ready))
+            .with_state(HealthState {
+                database: Arc::new(database),
+                migrations,
+                draining,
+            })
+    }
+
+    async fn migrated(result: Result<(), String>) -> MigrationStatus {
+        let mut runner = MockMigrationRunner::new();
+        runner
+            .expect_run()
+            .returning(move |_| box_future(result.clone()));
+        let state = MigrationState::new(Arc::new(runner));
+        let _ = state.migrate().await;
+        state.status
+    }
+
+    async fn get_response(app: Router, uri: &str) -> (StatusCode, Value) {
+        let response = app
+            .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let status = response.status();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        (status, serde_json::from_slice(&body).unwrap_or_default())
+    }
+
+    #[tokio::test]
+    async fn test_ready() {
+        let mut database = MockDatabaseProbe::new();
+        database.expect_ping().returning(|| box_future(Ok(())));
+        let app = create_test_app(database, migrated(Ok(())).await);
+        let (status, body) = get_response(app, "/ready").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            body,
+            json!({ "database": true, "migrations": "succeeded", "draining": false })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_not_ready_while_draining() {
+        let mut database = MockDatabaseProbe::new();
+        database.expect_ping().returning(|| box_future(Ok(())));
+        let (draining, receiver) = watch::channel(false);
+        let app = create_draining_app(database, migrated(Ok(())).await, receiver);
+        let (status, _) = get_response(app.clone(), "/ready").await;
+        assert_eq!(status, StatusCode::OK);
+
+        draining.send_replace(true);
+        let (status, body) = get_response(app.clone(), "/ready").await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body["draining"], true);
+        let (status, _) = get_response(app, "/health").await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_not_ready() {
+        let mut database = MockDatabaseProbe::new();
+        database
+            .expect_ping()
+            .returning(|| box_future(Err(sqlx::Error::PoolTimedOut)));
+        let app = create_test_app(database, migrated(Ok(())).await);
+        let (status, body) = get_response(app.clone(), "/ready").await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body["database"], false);
+        // the process itself is still up
+        let (status, _) = get_response(app, "/health").await;
+        assert_eq!(status, StatusCode::OK);
+
+        let mut database = MockDatabaseProbe::new();
+        database.expect_ping().returning(|| box_future(Ok(())));
+        let app = create_test_app(database, migrated(Err("dirty".to_string())).await);
+        let (status, body) = get_response(app, "/ready").await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body["migrations"], "failed");
+
+        let mut database = MockDatabaseProbe::new();
+        database.expect_ping().returning(|| box_future(Ok(())));
+        let app = create_test_app(database, MigrationStatus::default());
+        let (status, body) = get_response(app, "/ready").await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body["migrations"], "idle");
+    }
+}
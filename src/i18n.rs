@@ -0,0 +1,128 @@
+use core::convert::Infallible;
+
+use axum::{
+    async_trait,
+    extract::FromRequestParts,
+    http::{header, request::Parts},
+};
+
+/// Languages of the user-facing strings, English keeps the exact challenge wording
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Language {
+    #[default]
+    English,
+    German,
+    Italian,
+}
+
+impl Language {
+    fn from_tag(tag: &str) -> Option<Self> {
+        // only the primary subtag matters, e.g. `it-CH` is served in Italian
+        let primary = tag.split('-').next()?.trim().to_ascii_lowercase();
+        match primary.as_str() {
+            "en" => Some(Language::English),
+            "de" => Some(Language::German),
+            "it" => Some(Language::Italian),
+            _ => None,
+        }
+    }
+
+    /// Picks the supported language with the highest quality in an `Accept-Language` header
+    pub fn from_header(value: &str) -> Self {
+        value
+            .split(',')
+            .enumerate()
+            .filter_map(|(i, range)| {
+                let mut params = range.split(';').map(str::trim);
+                let language = Language::from_tag(params.next()?)?;
+                let q = params
+                    .filter_map(|p| p.strip_prefix("q="))
+                    .find_map(|q| q.parse::<f32>().ok())
+                    .unwrap_or(1.0);
+                Some((i, language, q))
+            })
+            .filter(|(_, _, q)| *q > 0.0)
+            // highest quality wins, ties go to the first listed language
+            .max_by(|(i, _, q1), (j, _, q2)| q1.total_cmp(q2).then(j.cmp(i)))
+            .map(|(_, language, _)| language)
+            .unwrap_or_default()
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for Language
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(parts
+            .headers
+            .get(header::ACCEPT_LANGUAGE)
+            .and_then(|v| v.to_str().ok())
+            .map(Language::from_header)
+            .unwrap_or_default())
+    }
+}
+
+/// Catalog of the user-facing strings
+pub enum Message<'a> {
+    NoMilk,
+    MissingKeyword,
+    /// The given team won the game
+    Wins(&'a str),
+    NoWinner,
+}
+
+impl Message<'_> {
+    pub fn text(&self, language: Language) -> String {
+        match (self, language) {
+            (Message::NoMilk, Language::English) => "No milk available\n".to_string(),
+            (Message::NoMilk, Language::German) => "Keine Milch verfügbar\n".to_string(),
+            (Message::NoMilk, Language::Italian) => "Latte esaurito\n".to_string(),
+            (Message::MissingKeyword, Language::English) => {
+                "Magic keyword not provided".to_string()
+            }
+            (Message::MissingKeyword, Language::German) => "Zauberwort fehlt".to_string(),
+            (Message::MissingKeyword, Language::Italian) => "Parola magica non fornita".to_string(),
+            (Message::Wins(team), Language::English) => format!("{} wins!", team),
+            (Message::Wins(team), Language::German) => format!("{} gewinnt!", team),
+            (Message::Wins(team), Language::Italian) => format!("{} vince!", team),
+            (Message::NoWinner, Language::English) => "No winner.".to_string(),
+            (Message::NoWinner, Language::German) => "Kein Gewinner.".to_string(),
+            (Message::NoWinner, Language::Italian) => "Nessun vincitore.".to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_is_english() {
+        assert_eq!(Language::from_header(""), Language::English);
+        assert_eq!(Language::from_header("fr-FR, ja"), Language::English);
+        assert_eq!(
+            Message::NoMilk.text(Language::default()),
+            "No milk available\n"
+        );
+    }
+
+    #[test]
+    fn test_quality_ordering() {
+        assert_eq!(
+            Language::from_header("fr;q=0.9, de;q=0.5, it-CH;q=0.8"),
+            Language::Italian
+        );
+        assert_eq!(Language::from_header("de, it"), Language::German);
+        assert_eq!(Language::from_header("de;q=0, it;q=0.1"), Language::Italian);
+    }
+
+    #[test]
+    fn test_messages() {
+        assert_eq!(Message::Wins("🍪").text(Language::Italian), "🍪 vince!");
+        assert_eq!(Message::NoWinner.text(Language::German), "Kein Gewinner.");
+    }
+}
@@ -0,0 +1,108 @@
+//! Timing of repository operations, with a log line for the slow ones
+
+use std::{
+    collections::BTreeMap,
+    future::Future,
+    sync::{LazyLock, Mutex},
+    time::{Duration, Instant},
+};
+
+use axum::{response::IntoResponse, Json};
+use serde::{Deserialize, Serialize};
+
+use crate::prometheus;
+
+/// Durations per operation since startup
+pub static TIMINGS: LazyLock<Timings> = LazyLock::new(Timings::default);
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Timing {
+    pub calls: u64,
+    pub total_ms: f64,
+    pub max_ms: f64,
+    /// Calls above the slow query threshold
+    pub slow: u64,
+}
+
+#[derive(Default)]
+pub struct Timings {
+    operations: Mutex<BTreeMap<&'static str, Timing>>,
+}
+
+impl Timings {
+    fn record(&self, operation: &'static str, elapsed: Duration, slow: bool) {
+        let ms = elapsed.as_secs_f64() * 1000.0;
+        let mut operations = self.operations.lock().unwrap();
+        let timing = operations.entry(operation).or_default();
+        timing.calls += 1;
+        timing.total_ms += ms;
+        timing.max_ms = timing.max_ms.max(ms);
+        timing.slow += slow as u64;
+    }
+
+    pub fn snapshot(&self) -> BTreeMap<&'static str, Timing> {
+        self.operations.lock().unwrap().clone()
+    }
+}
+
+/// Runs the operation, logging it along with its parameters when it takes longer than `threshold`.
+/// Parameters are only described, e.g. `id=<uuid>`, their values never reach the logs.
+pub async fn time<T>(
+    operation: &'static str,
+    threshold: Duration,
+    params: impl FnOnce() -> String,
+    future: impl Future<Output = T>,
+) -> T {
+    let start = Instant::now();
+    let output = future.await;
+    let elapsed = start.elapsed();
+
+    let slow = elapsed > threshold;
+    if slow {
+        tracing::warn!(
+            "slow query: {}({}) took {} ms",
+            operation,
+            params(),
+            elapsed.as_millis()
+        );
+    }
+    TIMINGS.record(operation, elapsed, slow);
+    metrics::counter!(prometheus::DB_QUERIES, "operation" => operation).increment(1);
+    metrics::histogram!(prometheus::DB_QUERY_DURATION, "operation" => operation).record(elapsed);
+    output
+}
+
+pub async fn timings() -> impl IntoResponse {
+    Json(TIMINGS.snapshot())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record() {
+        let timings = Timings::default();
+        timings.record("get", Duration::from_millis(10), false);
+        timings.record("get", Duration::from_millis(30), true);
+
+        let get = &timings.snapshot()["get"];
+        assert_eq!(get.calls, 2);
+        assert_eq!(get.slow, 1);
+        assert!((get.total_ms - 40.0).abs() < 1e-6);
+        assert!((get.max_ms - 30.0).abs() < 1e-6);
+    }
+
+    #[tokio::test]
+    async fn test_params_only_described_when_slow() {
+        let output = time(
+            "instrument.fast",
+            Duration::from_secs(60),
+            || panic!("parameters described for a fast query"),
+            async { 42 },
+        )
+        .await;
+        assert_eq!(output, 42);
+        assert_eq!(TIMINGS.snapshot()["instrument.fast"].calls, 1);
+    }
+}
@@ -0,0 +1,478 @@
+//! Work accepted by a request and done in the background, so that heavy uploads don't hold the
+//! request. Workers claim jobs from Postgres with `SKIP LOCKED`, clients poll `/jobs/:id`.
+
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use axum::{
+    extract::{Path, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{DateTime, Utc};
+#[cfg(test)]
+use mockall::automock;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::{query, query_as, query_scalar, FromRow, PgPool};
+use uuid::Uuid;
+
+use crate::{conventions, ordering};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// A job running for longer is taken as lost along with its worker, and is claimed again
+const STALE_AFTER_SECS: f64 = 300.0;
+const MAX_ATTEMPTS: i32 = 3;
+/// Jobs a client may have queued or running at once
+const MAX_UNFINISHED_PER_CLIENT: i64 = 5;
+/// How long finished jobs, payload and result, are kept for their clients to poll
+const RETENTION: Duration = Duration::from_secs(24 * 60 * 60);
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    #[default]
+    Queued,
+    Running,
+    Succeeded,
+    Failed,
+}
+
+impl TryFrom<String> for JobStatus {
+    type Error = String;
+
+    fn try_from(status: String) -> Result<Self, Self::Error> {
+        conventions::from_tag(&status)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, FromRow)]
+pub struct Job {
+    #[serde(with = "crate::conventions::uuid")]
+    pub id: Uuid,
+    pub kind: String,
+    #[sqlx(try_from = "String")]
+    pub status: JobStatus,
+    pub attempts: i32,
+    /// Set once succeeded, the result is then served at `/jobs/:id/result`
+    #[serde(skip)]
+    pub content_type: Option<String>,
+    #[serde(skip)]
+    pub result: Option<String>,
+    pub error: Option<String>,
+    #[serde(with = "crate::conventions::datetime")]
+    pub created_at: DateTime<Utc>,
+    #[serde(default, with = "crate::conventions::datetime::option")]
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+/// Job handed to a worker
+#[derive(Debug, Clone, PartialEq, FromRow)]
+pub struct ClaimedJob {
+    pub id: Uuid,
+    pub kind: String,
+    pub payload: sqlx::types::Json<Value>,
+}
+
+/// What a job produced, served as is
+#[derive(Debug, Clone, PartialEq)]
+pub struct JobOutput {
+    pub content_type: String,
+    pub body: String,
+}
+
+/// Does the work of one kind of job, on a blocking thread
+pub type JobHandler = fn(&Value) -> Result<JobOutput, String>;
+
+#[async_trait::async_trait]
+#[cfg_attr(test, automock)]
+pub trait JobRepository: Send + Sync + 'static {
+    /// Queues the job for `client`, `None` when the client already has its share of
+    /// unfinished jobs
+    async fn enqueue(
+        &self,
+        kind: &str,
+        client: &str,
+        payload: Value,
+    ) -> Result<Option<Uuid>, sqlx::Error>;
+    async fn get(&self, id: Uuid) -> Result<Option<Job>, sqlx::Error>;
+    /// Marks the oldest queued job as running, skipping those other workers hold
+    async fn claim(&self) -> Result<Option<ClaimedJob>, sqlx::Error>;
+    async fn finish(&self, id: Uuid, output: Result<JobOutput, String>) -> Result<(), sqlx::Error>;
+    /// Drops the jobs finished before the given time
+    async fn purge(&self, before: DateTime<Utc>) -> Result<u64, sqlx::Error>;
+}
+
+pub struct PostgresJobRepository {
+    pool: PgPool,
+}
+
+impl PostgresJobRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl JobRepository for PostgresJobRepository {
+    async fn enqueue(
+        &self,
+        kind: &str,
+        client: &str,
+        payload: Value,
+    ) -> Result<Option<Uuid>, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        // concurrent requests of a client are counted one after the other
+        query("SELECT pg_advisory_xact_lock(hashtext($1))")
+            .bind(client)
+            .execute(&mut *tx)
+            .await?;
+        let id = query_scalar(
+            "INSERT INTO jobs (id, kind, client, payload) SELECT $1, $2, $3, $4
+             WHERE (SELECT COUNT(*) FROM jobs
+                    WHERE client = $3 AND status IN ('queued', 'running')) < $5
+             RETURNING id",
+        )
+        .bind(Uuid::new_v4())
+        .bind(kind)
+        .bind(client)
+        .bind(sqlx::types::Json(payload))
+        .bind(MAX_UNFINISHED_PER_CLIENT)
+        .fetch_optional(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(id)
+    }
+
+    async fn get(&self, id: Uuid) -> Result<Option<Job>, sqlx::Error> {
+        query_as::<_, Job>(
+            "SELECT id, kind, status, attempts, content_type, result, error, created_at, finished_at
+             FROM jobs WHERE id = $1",
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    async fn claim(&self) -> Result<Option<ClaimedJob>, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        // jobs that ran out of attempts while stale are given up on
+        query(
+            "UPDATE jobs SET status = 'failed', error = 'worker lost', finished_at = now()
+             WHERE status = 'running' AND attempts >= $1
+             AND started_at < now() - make_interval(secs => $2)",
+        )
+        .bind(MAX_ATTEMPTS)
+        .bind(STALE_AFTER_SECS)
+        .execute(&mut *tx)
+        .await?;
+
+        let job = query_as::<_, ClaimedJob>(&format!(
+            "UPDATE jobs SET status = 'running', started_at = now(), attempts = attempts + 1
+             WHERE id = (
+                 SELECT id FROM jobs
+                 WHERE status = 'queued'
+                 OR (status = 'running' AND started_at < now() - make_interval(secs => $1))
+                 ORDER BY {} LIMIT 1 FOR UPDATE SKIP LOCKED
+             )
+             RETURNING id, kind, payload",
+            ordering::JOBS
+        ))
+        .bind(STALE_AFTER_SECS)
+        .fetch_optional(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(job)
+    }
+
+    async fn finish(&self, id: Uuid, output: Result<JobOutput, String>) -> Result<(), sqlx::Error> {
+        let (status, content_type, result, error) = match output {
+            Ok(output) => (
+                "succeeded",
+                Some(output.content_type),
+                Some(output.body),
+                None,
+            ),
+            Err(e) => ("failed", None, None, Some(e)),
+        };
+        query(
+            "UPDATE jobs SET status = $2, content_type = $3, result = $4, error = $5,
+             finished_at = now() WHERE id = $1",
+        )
+        .bind(id)
+        .bind(status)
+        .bind(content_type)
+        .bind(result)
+        .bind(error)
+        .execute(&self.pool)
+        .await
+        .map(|_| ())
+    }
+
+    async fn purge(&self, before: DateTime<Utc>) -> Result<u64, sqlx::Error> {
+        query("DELETE FROM jobs WHERE finished_at < $1")
+            .bind(before)
+            .execute(&self.pool)
+            .await
+            .map(|r| r.rows_affected())
+    }
+}
+
+pub fn state_job_repository(pool: PgPool) -> Arc<dyn JobRepository> {
+    Arc::new(PostgresJobRepository::new(pool))
+}
+
+/// Claims jobs one at a time and runs the handler of their kind
+pub struct JobWorker {
+    repository: Arc<dyn JobRepository>,
+    handlers: Arc<HashMap<&'static str, JobHandler>>,
+}
+
+impl JobWorker {
+    pub fn new(
+        repository: Arc<dyn JobRepository>,
+        handlers: Arc<HashMap<&'static str, JobHandler>>,
+    ) -> Self {
+        Self {
+            repository,
+            handlers,
+        }
+    }
+
+    pub async fn run(self) {
+        let mut interval = tokio::time::interval(POLL_INTERVAL);
+        loop {
+            interval.tick().await;
+            // a busy queue is drained before waiting again
+            loop {
+                match self.work_one().await {
+                    Ok(true) => continue,
+                    Ok(false) => break,
+                    Err(e) => {
+                        tracing::warn!("job processing failed: {}", e);
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Runs the next job, `false` when there was none
+    pub async fn work_one(&self) -> Result<bool, sqlx::Error> {
+        let Some(job) = self.repository.claim().await? else {
+            return Ok(false);
+        };
+
+        let output = match self.handlers.get(job.kind.as_str()).copied() {
+            Some(handler) => {
+                let payload = job.payload.0;
+                tokio::task::spawn_blocking(move || handler(&payload))
+                    .await
+                    .unwrap_or_else(|e| Err(format!("job panicked: {}", e)))
+            }
+            None => Err(format!("unknown job kind {}", job.kind)),
+        };
+        if let Err(e) = &output {
+            tracing::warn!("job {} ({}) failed: {}", job.id, job.kind, e);
+        }
+        self.repository.finish(job.id, output).await?;
+        Ok(true)
+    }
+}
+
+/// Periodically drops the jobs finished for longer than clients are given to poll them
+pub async fn cleanup(repository: Arc<dyn JobRepository>) {
+    let mut interval = tokio::time::interval(CLEANUP_INTERVAL);
+    loop {
+        interval.tick().await;
+        let before = Utc::now() - chrono::Duration::from_std(RETENTION).unwrap_or_default();
+        if let Err(e) = repository.purge(before).await {
+            tracing::warn!("job cleanup failed: {}", e);
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct JobState {
+    pub repository: Arc<dyn JobRepository>,
+}
+
+/// Where the status of an accepted job is polled
+pub fn job_path(id: Uuid) -> String {
+    format!("/jobs/{}", id)
+}
+
+/// `202 Accepted` pointing to the job
+pub fn accepted(id: Uuid) -> Response {
+    (
+        StatusCode::ACCEPTED,
+        [(header::LOCATION, job_path(id))],
+        Json(serde_json::json!({ "id": id })),
+    )
+        .into_response()
+}
+
+pub async fn status(
+    State(state): State<JobState>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Job>, (StatusCode, String)> {
+    match state.repository.get(id).await {
+        Ok(Some(job)) => Ok(Json(job)),
+        Ok(None) => Err((StatusCode::NOT_FOUND, "".to_string())),
+        _ => Err((StatusCode::INTERNAL_SERVER_ERROR, "".to_string())),
+    }
+}
+
+/// What a succeeded job produced, a 409 while it isn't done
+pub async fn result(
+    State(state): State<JobState>,
+    Path(id): Path<Uuid>,
+) -> Result<Response, (StatusCode, String)> {
+    let job = match state.repository.get(id).await {
+        Ok(Some(job)) => job,
+        Ok(None) => return Err((StatusCode::NOT_FOUND, "".to_string())),
+        _ => return Err((StatusCode::INTERNAL_SERVER_ERROR, "".to_string())),
+    };
+
+    match (job.status, job.content_type, job.result) {
+        (JobStatus::Succeeded, Some(content_type), Some(body)) => {
+            Ok(([(header::CONTENT_TYPE, content_type)], body).into_response())
+        }
+        _ => Err((StatusCode::CONFLICT, "".to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::test_support::box_future;
+    use axum::{body::Body, http::Request, routing::get, Router};
+    use http_body_util::BodyExt;
+    use mockall::predicate::eq;
+    use serde_json::json;
+    use tower::ServiceExt;
+
+    fn job(id: Uuid, status: JobStatus) -> Job {
+        Job {
+            id,
+            kind: "shout".to_string(),
+            status,
+            attempts: 1,
+            content_type: (status == JobStatus::Succeeded).then(|| "text/plain".to_string()),
+            result: (status == JobStatus::Succeeded).then(|| "HO HO HO".to_string()),
+            error: None,
+            created_at: Utc::now(),
+            finished_at: None,
+        }
+    }
+
+    fn shout(payload: &Value) -> Result<JobOutput, String> {
+        let text = payload["text"].as_str().ok_or("no text")?;
+        Ok(JobOutput {
+            content_type: "text/plain".to_string(),
+            body: text.to_uppercase(),
+        })
+    }
+
+    async fn get_response(app: Router, uri: &str) -> (StatusCode, String) {
+        let response = app
+            .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let status = response.status();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_worker_runs_the_handler_of_the_kind() {
+        let finished = Arc::new(Mutex::new(Vec::new()));
+        let mut repository = MockJobRepository::new();
+        let mut claims = vec![
+            None,
+            Some(ClaimedJob {
+                id: Uuid::nil(),
+                kind: "sing".to_string(),
+                payload: sqlx::types::Json(json!({})),
+            }),
+            Some(ClaimedJob {
+                id: Uuid::max(),
+                kind: "shout".to_string(),
+                payload: sqlx::types::Json(json!({ "text": "ho ho ho" })),
+            }),
+        ];
+        repository
+            .expect_claim()
+            .returning(move || box_future(Ok(claims.pop().unwrap())));
+        let seen = finished.clone();
+        repository.expect_finish().returning(move |id, output| {
+            seen.lock().unwrap().push((id, output));
+            box_future(Ok(()))
+        });
+
+        let handlers = HashMap::from([("shout", shout as JobHandler)]);
+        let worker = JobWorker::new(Arc::new(repository), Arc::new(handlers));
+        assert!(worker.work_one().await.unwrap());
+        assert!(worker.work_one().await.unwrap());
+        assert!(!worker.work_one().await.unwrap());
+
+        let finished = finished.lock().unwrap();
+        assert_eq!(
+            finished[0],
+            (
+                Uuid::max(),
+                Ok(JobOutput {
+                    content_type: "text/plain".to_string(),
+                    body: "HO HO HO".to_string()
+                })
+            )
+        );
+        assert_eq!(
+            finished[1],
+            (Uuid::nil(), Err("unknown job kind sing".to_string()))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_status_and_result() {
+        let (done, running) = (Uuid::max(), Uuid::nil());
+        let mut repository = MockJobRepository::new();
+        repository
+            .expect_get()
+            .with(eq(done))
+            .returning(move |id| box_future(Ok(Some(job(id, JobStatus::Succeeded)))));
+        repository
+            .expect_get()
+            .with(eq(running))
+            .returning(move |id| box_future(Ok(Some(job(id, JobStatus::Running)))));
+        repository.expect_get().returning(|_| box_future(Ok(None)));
+        let app = Router::new()
+            .route("/jobs/:id", get(status))
+            .route("/jobs/:id/result", get(result))
+            .with_state(JobState {
+                repository: Arc::new(repository),
+            });
+
+        let (code, body) = get_response(app.clone(), &job_path(running)).await;
+        assert_eq!(code, StatusCode::OK);
+        let job = serde_json::from_str::<Value>(&body).unwrap();
+        assert_eq!(job["status"], "running");
+        assert!(job.get("result").is_none());
+
+        let result_path = format!("{}/result", job_path(done));
+        assert_eq!(
+            get_response(app.clone(), &result_path).await,
+            (StatusCode::OK, "HO HO HO".to_string())
+        );
+        let result_path = format!("{}/result", job_path(running));
+        assert_eq!(
+            get_response(app.clone(), &result_path).await.0,
+            StatusCode::CONFLICT
+        );
+        let missing = job_path(Uuid::from_u128(7));
+        assert_eq!(get_response(app, &missing).await.0, StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,157 @@
+//! Compact JWE with direct AES-256-GCM encryption (RFC 7516), just what sealed gifts need
+
+use core::fmt;
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use ring::{
+    aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN},
+    rand::{SecureRandom, SystemRandom},
+};
+use serde::{Deserialize, Serialize};
+
+/// Content type of a JWE whose payload is itself a JWT
+pub const NESTED_JWT: &str = "JWT";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JweHeader {
+    pub alg: String,
+    pub enc: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cty: Option<String>,
+}
+
+impl JweHeader {
+    pub fn nested(&self) -> bool {
+        self.cty
+            .as_deref()
+            .is_some_and(|cty| cty.eq_ignore_ascii_case(NESTED_JWT))
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum JweError {
+    Malformed,
+    /// Another algorithm than `dir` with `A256GCM`
+    Unsupported,
+    Encryption,
+    /// Wrong key, or a token tampered with
+    Decryption,
+}
+
+impl fmt::Display for JweError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JweError::Malformed => write!(f, "not a compact JWE"),
+            JweError::Unsupported => write!(f, "only dir with A256GCM is supported"),
+            JweError::Encryption => write!(f, "the payload can't be encrypted"),
+            JweError::Decryption => write!(f, "the token can't be decrypted"),
+        }
+    }
+}
+
+/// Compact serializations of a JWE have five parts, those of a JWS three
+pub fn is_jwe(token: &str) -> bool {
+    token.split('.').count() == 5
+}
+
+pub fn encrypt(key: &[u8; 32], payload: &[u8], cty: Option<&str>) -> Result<String, JweError> {
+    let header = JweHeader {
+        alg: "dir".to_string(),
+        enc: "A256GCM".to_string(),
+        cty: cty.map(str::to_string),
+    };
+    let header =
+        URL_SAFE_NO_PAD.encode(serde_json::to_vec(&header).map_err(|_| JweError::Encryption)?);
+
+    let mut iv = [0u8; NONCE_LEN];
+    SystemRandom::new()
+        .fill(&mut iv)
+        .map_err(|_| JweError::Encryption)?;
+    let mut ciphertext = payload.to_vec();
+    let tag = cipher(key)
+        .seal_in_place_separate_tag(
+            Nonce::assume_unique_for_key(iv),
+            // the protected header is authenticated along with the payload
+            Aad::from(header.as_bytes()),
+            &mut ciphertext,
+        )
+        .map_err(|_| JweError::Encryption)?;
+
+    Ok(format!(
+        "{}..{}.{}.{}",
+        header,
+        URL_SAFE_NO_PAD.encode(iv),
+        URL_SAFE_NO_PAD.encode(ciphertext),
+        URL_SAFE_NO_PAD.encode(tag.as_ref())
+    ))
+}
+
+pub fn decrypt(key: &[u8; 32], token: &str) -> Result<(JweHeader, Vec<u8>), JweError> {
+    let [header, encrypted_key, iv, ciphertext, tag]: [&str; 5] = token
+        .split('.')
+        .collect::<Vec<_>>()
+        .try_into()
+        .map_err(|_| JweError::Malformed)?;
+    let decode = |part: &str| {
+        URL_SAFE_NO_PAD
+            .decode(part)
+            .map_err(|_| JweError::Malformed)
+    };
+
+    let parsed: JweHeader =
+        serde_json::from_slice(&decode(header)?).map_err(|_| JweError::Malformed)?;
+    if parsed.alg != "dir" || parsed.enc != "A256GCM" || !encrypted_key.is_empty() {
+        return Err(JweError::Unsupported);
+    }
+    let iv: [u8; NONCE_LEN] = decode(iv)?.try_into().map_err(|_| JweError::Malformed)?;
+
+    let mut in_out = decode(ciphertext)?;
+    in_out.extend(decode(tag)?);
+    let payload = cipher(key)
+        .open_in_place(
+            Nonce::assume_unique_for_key(iv),
+            Aad::from(header.as_bytes()),
+            &mut in_out,
+        )
+        .map_err(|_| JweError::Decryption)?;
+    Ok((parsed, payload.to_vec()))
+}
+
+fn cipher(key: &[u8; 32]) -> LessSafeKey {
+    // a 32 byte key is always valid for AES-256
+    LessSafeKey::new(UnboundKey::new(&AES_256_GCM, key).unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: [u8; 32] = [7; 32];
+
+    #[test]
+    fn test_round_trip() {
+        let token = encrypt(&KEY, b"a.signed.jwt", Some(NESTED_JWT)).unwrap();
+        assert!(is_jwe(&token));
+
+        let (header, payload) = decrypt(&KEY, &token).unwrap();
+        assert!(header.nested());
+        assert_eq!(payload, b"a.signed.jwt");
+    }
+
+    #[test]
+    fn test_tampering() {
+        let token = encrypt(&KEY, b"payload", None).unwrap();
+        assert_eq!(decrypt(&[8; 32], &token), Err(JweError::Decryption));
+
+        // swapping the header breaks the authentication tag
+        let other = encrypt(&KEY, b"payload", Some(NESTED_JWT)).unwrap();
+        let forged = format!(
+            "{}.{}",
+            other.split('.').next().unwrap(),
+            token.split_once('.').unwrap().1
+        );
+        assert_eq!(decrypt(&KEY, &forged), Err(JweError::Decryption));
+
+        assert_eq!(decrypt(&KEY, "a.b.c"), Err(JweError::Malformed));
+    }
+}
@@ -0,0 +1,285 @@
+//! Keys of the day 16 routes, held in memory, read from files or kept in an external signer
+
+use core::{fmt, str::FromStr};
+use std::{fs, path::Path, sync::Arc};
+
+use jsonwebtoken::{crypto, Algorithm, DecodingKey, EncodingKey};
+#[cfg(test)]
+use mockall::automock;
+use ring::{aead::AES_256_GCM, hkdf};
+
+use crate::{config::Config, day_16::RSA_PEM};
+
+/// Algorithm of the gifts signed by `/16/wrap`
+pub const GIFT_ALGORITHM: Algorithm = Algorithm::HS256;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum KeyBackend {
+    /// Gift secret from the configuration, public key bundled with the binary
+    #[default]
+    Memory,
+    /// Both read at startup from files, such as mounted secrets
+    File,
+    /// Gifts signed by an external key service, the private key never enters the process
+    Kms,
+}
+
+impl FromStr for KeyBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "memory" => Ok(Self::Memory),
+            "file" => Ok(Self::File),
+            "kms" => Ok(Self::Kms),
+            _ => Err(format!("unknown key backend {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum KeyError {
+    /// A setting the backend needs is missing
+    Missing(&'static str),
+    Invalid(String),
+    /// The signer can't be reached, the request may be retried
+    Unavailable(String),
+}
+
+impl fmt::Display for KeyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyError::Missing(setting) => write!(f, "{} must be set for this key backend", setting),
+            KeyError::Invalid(e) => write!(f, "invalid key: {}", e),
+            KeyError::Unavailable(e) => write!(f, "key service unavailable: {}", e),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+#[cfg_attr(test, automock)]
+pub trait SigningKeyProvider: Send + Sync + 'static {
+    /// Signature of a gift's `header.payload`, base64url encoded as in a JWT
+    async fn sign(&self, message: &[u8]) -> Result<String, KeyError>;
+    /// Whether the signature of a gift is genuine
+    async fn verify(&self, message: &[u8], signature: &str) -> Result<bool, KeyError>;
+    /// Public key checking the tokens of `/16/decode`
+    fn decode_key(&self) -> Result<DecodingKey, KeyError>;
+    /// AES-256 key of sealed gifts
+    fn content_key(&self) -> Result<[u8; 32], KeyError>;
+    /// Ids of the keys, as the `kid` header of their tokens names them
+    fn key_ids(&self) -> Vec<String> {
+        Vec::new()
+    }
+}
+
+pub struct InMemoryKeys {
+    encoding: EncodingKey,
+    decoding: DecodingKey,
+    public: DecodingKey,
+    content: [u8; 32],
+}
+
+/// Encryption key derived from the gift secret, so that sealing needs no extra setting
+fn derive_content_key(secret: &[u8]) -> [u8; 32] {
+    let mut key = [0u8; 32];
+    hkdf::Salt::new(hkdf::HKDF_SHA256, b"shuttlings-cch24 gift")
+        .extract(secret)
+        .expand(&[b"A256GCM"], &AES_256_GCM)
+        .and_then(|okm| okm.fill(&mut key))
+        // only fails for outputs longer than 255 hashes
+        .unwrap();
+    key
+}
+
+impl InMemoryKeys {
+    pub fn new(secret: &[u8], public_pem: &[u8]) -> Result<Self, KeyError> {
+        Ok(Self {
+            encoding: EncodingKey::from_secret(secret),
+            decoding: DecodingKey::from_secret(secret),
+            public: DecodingKey::from_rsa_pem(public_pem)
+                .map_err(|e| KeyError::Invalid(e.to_string()))?,
+            content: derive_content_key(secret),
+        })
+    }
+
+    /// Reads the keys once, a trailing newline of the secret file isn't part of the secret
+    pub fn from_files(secret: &Path, public_pem: Option<&Path>) -> Result<Self, KeyError> {
+        let read = |path: &Path| {
+            fs::read(path).map_err(|e| KeyError::Invalid(format!("{}: {}", path.display(), e)))
+        };
+        let mut secret = read(secret)?;
+        while secret.last().is_some_and(|b| b.is_ascii_whitespace()) {
+            secret.pop();
+        }
+        if secret.is_empty() {
+            return Err(KeyError::Invalid(
+                "the gift secret file is empty".to_string(),
+            ));
+        }
+        let public = match public_pem {
+            Some(path) => read(path)?,
+            None => RSA_PEM.as_bytes().to_vec(),
+        };
+        Self::new(&secret, &public)
+    }
+}
+
+#[async_trait::async_trait]
+impl SigningKeyProvider for InMemoryKeys {
+    async fn sign(&self, message: &[u8]) -> Result<String, KeyError> {
+        crypto::sign(message, &self.encoding, GIFT_ALGORITHM)
+            .map_err(|e| KeyError::Invalid(e.to_string()))
+    }
+
+    async fn verify(&self, message: &[u8], signature: &str) -> Result<bool, KeyError> {
+        // a signature that isn't even base64 is just not genuine
+        Ok(crypto::verify(signature, message, &self.decoding, GIFT_ALGORITHM).unwrap_or(false))
+    }
+
+    fn decode_key(&self) -> Result<DecodingKey, KeyError> {
+        Ok(self.public.clone())
+    }
+
+    fn content_key(&self) -> Result<[u8; 32], KeyError> {
+        Ok(self.content)
+    }
+}
+
+/// Placeholder for a KMS or PKCS#11 signer, which answers every signature as unavailable
+/// until a client for the service is wired in
+pub struct KmsKeys {
+    key_id: String,
+    public: DecodingKey,
+}
+
+impl KmsKeys {
+    pub fn new(key_id: String) -> Result<Self, KeyError> {
+        Ok(Self {
+            key_id,
+            public: DecodingKey::from_rsa_pem(RSA_PEM.as_bytes())
+                .map_err(|e| KeyError::Invalid(e.to_string()))?,
+        })
+    }
+
+    fn unavailable(&self) -> KeyError {
+        KeyError::Unavailable(format!("no client configured for key {}", self.key_id))
+    }
+}
+
+#[async_trait::async_trait]
+impl SigningKeyProvider for KmsKeys {
+    async fn sign(&self, _message: &[u8]) -> Result<String, KeyError> {
+        Err(self.unavailable())
+    }
+
+    async fn verify(&self, _message: &[u8], _signature: &str) -> Result<bool, KeyError> {
+        Err(self.unavailable())
+    }
+
+    fn decode_key(&self) -> Result<DecodingKey, KeyError> {
+        Ok(self.public.clone())
+    }
+
+    fn content_key(&self) -> Result<[u8; 32], KeyError> {
+        Err(self.unavailable())
+    }
+
+    fn key_ids(&self) -> Vec<String> {
+        vec![self.key_id.clone()]
+    }
+}
+
+pub fn state_keys(config: &Config) -> Result<Arc<dyn SigningKeyProvider>, KeyError> {
+    Ok(match config.key_backend {
+        KeyBackend::Memory => Arc::new(InMemoryKeys::new(
+            config.gift_secret.as_bytes(),
+            RSA_PEM.as_bytes(),
+        )?),
+        KeyBackend::File => Arc::new(InMemoryKeys::from_files(
+            config
+                .gift_secret_file
+                .as_deref()
+                .ok_or(KeyError::Missing("GIFT_SECRET_FILE"))?,
+            config.decode_key_file.as_deref(),
+        )?),
+        KeyBackend::Kms => Arc::new(KmsKeys::new(
+            config
+                .kms_key_id
+                .clone()
+                .ok_or(KeyError::Missing("KMS_KEY_ID"))?,
+        )?),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_in_memory_sign_and_verify() {
+        let keys = InMemoryKeys::new(b"secret", RSA_PEM.as_bytes()).unwrap();
+        let signature = keys.sign(b"header.payload").await.unwrap();
+
+        assert!(keys.verify(b"header.payload", &signature).await.unwrap());
+        assert!(!keys.verify(b"header.forged", &signature).await.unwrap());
+        assert!(!keys.verify(b"header.payload", "%%%").await.unwrap());
+    }
+
+    #[test]
+    fn test_content_key_derived_from_secret() {
+        let key = |secret: &[u8]| {
+            InMemoryKeys::new(secret, RSA_PEM.as_bytes())
+                .unwrap()
+                .content_key()
+                .unwrap()
+        };
+        assert_eq!(key(b"secret"), key(b"secret"));
+        assert_ne!(key(b"secret"), key(b"other"));
+    }
+
+    #[tokio::test]
+    async fn test_file_keys() {
+        let dir = std::env::temp_dir().join(format!("keys-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let secret = dir.join("gift_secret");
+        fs::write(&secret, "secret\n").unwrap();
+
+        let from_file = InMemoryKeys::from_files(&secret, None).unwrap();
+        let in_memory = InMemoryKeys::new(b"secret", RSA_PEM.as_bytes()).unwrap();
+        assert_eq!(
+            from_file.sign(b"message").await,
+            in_memory.sign(b"message").await
+        );
+
+        fs::write(&secret, "\n").unwrap();
+        assert!(InMemoryKeys::from_files(&secret, None).is_err());
+        assert!(InMemoryKeys::from_files(&PathBuf::from("/nonexistent"), None).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_kms_stub() {
+        let keys = KmsKeys::new("gift-key".to_string()).unwrap();
+        assert!(matches!(
+            keys.sign(b"message").await,
+            Err(KeyError::Unavailable(_))
+        ));
+        assert!(keys.decode_key().is_ok());
+    }
+
+    #[test]
+    fn test_state_keys() {
+        let config = Config {
+            key_backend: KeyBackend::Kms,
+            ..Config::default()
+        };
+        assert_eq!(
+            state_keys(&config).err(),
+            Some(KeyError::Missing("KMS_KEY_ID"))
+        );
+        assert!(state_keys(&Config::default()).is_ok());
+    }
+}
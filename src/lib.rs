@@ -0,0 +1,83 @@
+pub mod admin;
+pub mod admin_api;
+pub mod app;
+pub mod app_error;
+pub mod app_state;
+pub mod assets;
+pub mod auth;
+pub mod authors;
+pub mod board_feed;
+pub mod caching;
+pub mod citation;
+#[cfg(feature = "client")]
+pub mod client;
+pub mod client_ip;
+pub mod clock;
+pub mod coalesce;
+pub mod comments;
+pub mod compression;
+pub mod config;
+pub mod conventions;
+pub mod countdown;
+pub mod day_1;
+pub mod day_11;
+pub mod day_12;
+pub mod day_16;
+pub mod day_19;
+pub mod day_2;
+pub mod day_23;
+pub mod day_24;
+pub mod day_5;
+pub mod day_9;
+pub mod day_minus_1;
+pub mod days;
+pub mod dry_run;
+pub mod errors;
+pub mod events;
+pub mod fixtures;
+pub mod geo;
+pub mod grpc;
+pub mod health;
+pub mod i18n;
+pub mod instrument;
+pub mod jobs;
+pub mod jwe;
+pub mod keys;
+pub mod links;
+pub mod maintenance;
+pub mod migrations;
+pub mod moderation;
+pub mod multipart;
+pub mod negotiate;
+pub mod openapi;
+pub mod ordering;
+pub mod outbox;
+pub mod password;
+pub mod players;
+pub mod preflight;
+pub mod progress;
+pub mod prometheus;
+pub mod quota;
+pub mod quote_form;
+pub mod rate_limit;
+pub mod room;
+pub mod schemas;
+pub mod self_check;
+pub mod settings;
+pub mod shutdown;
+pub mod slo;
+pub mod snapshot;
+pub mod stats;
+pub mod tasks;
+#[cfg(test)]
+mod test_support;
+pub mod theme;
+pub mod token_metrics;
+pub mod tokens;
+pub mod trace;
+pub mod uploads;
+pub mod validation;
+pub mod versioning;
+
+/// Schema migrations of every module, applied at startup
+pub static MIGRATOR: sqlx::migrate::Migrator = sqlx::migrate!();
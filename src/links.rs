@@ -0,0 +1,140 @@
+use core::convert::Infallible;
+use std::collections::BTreeMap;
+
+use axum::{
+    async_trait,
+    extract::{FromRequestParts, OriginalUri},
+    http::{request::Parts, Method},
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Related resource or action, advertised in JSON responses
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Link {
+    pub href: String,
+    /// Method to use on the link, GET when missing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub method: Option<String>,
+}
+
+/// Links keyed by relation name
+pub type Links = BTreeMap<String, Link>;
+
+/// JSON body extended with a `links` section
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Linked<T> {
+    #[serde(flatten)]
+    pub data: T,
+    pub links: Links,
+}
+
+/// Builds links relative to the prefix the router is mounted on,
+/// so that they stay valid when routes are nested under a versioned path
+#[derive(Debug, Clone, Default)]
+pub struct LinkBuilder {
+    prefix: String,
+    links: Links,
+}
+
+impl LinkBuilder {
+    pub fn new(prefix: impl Into<String>) -> Self {
+        Self {
+            prefix: prefix.into().trim_end_matches('/').to_string(),
+            links: Links::new(),
+        }
+    }
+
+    pub fn href(&self, path: &str) -> String {
+        format!("{}{}", self.prefix, path)
+    }
+
+    pub fn link(mut self, rel: &str, path: &str) -> Self {
+        let href = self.href(path);
+        self.links
+            .insert(rel.to_string(), Link { href, method: None });
+        self
+    }
+
+    pub fn action(mut self, rel: &str, method: Method, path: &str) -> Self {
+        let href = self.href(path);
+        self.links.insert(
+            rel.to_string(),
+            Link {
+                href,
+                method: Some(method.to_string()),
+            },
+        );
+        self
+    }
+
+    pub fn build(self) -> Links {
+        self.links
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for LinkBuilder
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        // nested routers only see the path below their mount point
+        let prefix = parts
+            .extensions
+            .get::<OriginalUri>()
+            .and_then(|original| match parts.uri.path() {
+                // the root of a nested router is the mount point itself
+                "/" => Some(original.path()),
+                path => original.path().strip_suffix(path),
+            })
+            .unwrap_or_default();
+
+        Ok(Self::new(prefix))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, http::Request, routing::get, Json, Router};
+    use http_body_util::BodyExt;
+    use tower::ServiceExt;
+
+    #[test]
+    fn test_links() {
+        let links = LinkBuilder::new("/v1/")
+            .link("self", "/19/cite/1")
+            .action("delete", Method::DELETE, "/19/remove/1")
+            .build();
+
+        assert_eq!(links["self"].href, "/v1/19/cite/1");
+        assert_eq!(links["self"].method, None);
+        assert_eq!(links["delete"].href, "/v1/19/remove/1");
+        assert_eq!(links["delete"].method.as_deref(), Some("DELETE"));
+    }
+
+    #[tokio::test]
+    async fn test_prefix_from_nesting() {
+        async fn handler(links: LinkBuilder) -> Json<Links> {
+            Json(links.link("self", "/here").build())
+        }
+
+        let app = Router::new().nest("/v1", Router::new().route("/here", get(handler)));
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/v1/here")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let links: Links = serde_json::from_slice(&body).unwrap();
+        assert_eq!(links["self"].href, "/v1/here");
+    }
+}
@@ -0,0 +1,103 @@
+//! Maintenance mode, a settings flag turning clients away with a 503 while the admin, health and
+//! metrics routes stay up
+
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::{
+    app_error::AppError,
+    settings::{Settings, FLAG_PREFIX},
+    versioning::unversioned,
+};
+
+/// Name of the flag, set as `flags.maintenance`
+pub const MAINTENANCE: &str = "maintenance";
+/// Seconds clients are told to wait before trying again
+const RETRY_AFTER_SECS: u32 = 60;
+/// Routes answering through maintenance, so that it can be turned off and watched
+const EXEMPT: &[&str] = &["/admin", "/health", "/ready", "/metrics"];
+
+/// Key of the flag in the settings
+pub fn setting_key() -> String {
+    format!("{}{}", FLAG_PREFIX, MAINTENANCE)
+}
+
+fn exempt(path: &str) -> bool {
+    let path = unversioned(path);
+    EXEMPT.iter().any(|prefix| {
+        path.strip_prefix(prefix)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+    })
+}
+
+/// Answers a 503 with `Retry-After` to every request outside of the exempt routes while the flag
+/// is set
+pub async fn guard(State(settings): State<Settings>, request: Request, next: Next) -> Response {
+    if !settings.flag(MAINTENANCE) || exempt(request.uri().path()) {
+        return next.run(request).await;
+    }
+    let mut response = AppError::Status(StatusCode::SERVICE_UNAVAILABLE).into_response();
+    response
+        .headers_mut()
+        .insert(header::RETRY_AFTER, HeaderValue::from(RETRY_AFTER_SECS));
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::Setting;
+    use axum::{body::Body, middleware, routing::get, Router};
+    use chrono::Utc;
+    use serde_json::json;
+    use sqlx::types::Json;
+    use tower::ServiceExt;
+
+    fn create_test_app(settings: Settings) -> Router {
+        Router::new()
+            .route("/9/milk", get(|| async { "Milk withdrawn\n" }))
+            .route("/admin/state", get(|| async { "{}" }))
+            .route("/health", get(|| async { "ok" }))
+            .layer(middleware::from_fn_with_state(settings, guard))
+    }
+
+    async fn send(app: &Router, uri: &str) -> Response {
+        app.clone()
+            .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+            .await
+            .unwrap()
+    }
+
+    #[test]
+    fn test_exempt() {
+        assert!(exempt("/admin/state"));
+        assert!(exempt("/v1/admin/ui"));
+        assert!(exempt("/health"));
+        assert!(!exempt("/healthz"));
+        assert!(!exempt("/9/milk"));
+    }
+
+    #[tokio::test]
+    async fn test_guard() {
+        let settings = Settings::new();
+        let app = create_test_app(settings.clone());
+        assert_eq!(send(&app, "/9/milk").await.status(), StatusCode::OK);
+
+        settings.replace(vec![Setting {
+            key: setting_key(),
+            version: 1,
+            value: Json(json!(true)),
+            updated_at: Utc::now(),
+            deleted_at: None,
+        }]);
+        let response = send(&app, "/9/milk").await;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "60");
+        assert_eq!(send(&app, "/admin/state").await.status(), StatusCode::OK);
+        assert_eq!(send(&app, "/health").await.status(), StatusCode::OK);
+    }
+}
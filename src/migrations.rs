@@ -0,0 +1,340 @@
+//! Schema migrations, applied one at a time under an advisory lock so that instances starting
+//! together don't race, with their progress reported at `/admin/migrations`
+
+use core::str::FromStr;
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex},
+};
+
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use chrono::{DateTime, Utc};
+#[cfg(test)]
+use mockall::automock;
+use serde::{Deserialize, Serialize};
+use sqlx::{
+    migrate::{Migrate, Migrator},
+    PgPool,
+};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum MigrationMode {
+    /// Applied before the service starts, which waits for them
+    #[default]
+    Startup,
+    /// Applied while the service already answers, for migrations the running code copes with
+    Background,
+    /// Applied when an operator asks with `POST /admin/migrations`
+    Manual,
+}
+
+impl FromStr for MigrationMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "startup" => Ok(Self::Startup),
+            "background" => Ok(Self::Background),
+            "manual" => Ok(Self::Manual),
+            _ => Err(format!("unknown migration mode {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RunState {
+    /// No run since startup
+    #[default]
+    Idle,
+    /// Waiting for the lock or applying migrations
+    Running,
+    Succeeded,
+    Failed,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Progress {
+    pub state: RunState,
+    /// Versions found applied, including those of the current run
+    pub applied: Vec<i64>,
+    pub pending: Vec<i64>,
+    /// Version being applied
+    pub current: Option<i64>,
+    pub error: Option<String>,
+    #[serde(default, with = "crate::conventions::datetime::option")]
+    pub started_at: Option<DateTime<Utc>>,
+    #[serde(default, with = "crate::conventions::datetime::option")]
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+/// Progress of the latest run, shared between the runner and the status endpoint
+#[derive(Clone, Default)]
+pub struct MigrationStatus(Arc<Mutex<Progress>>);
+
+impl MigrationStatus {
+    pub fn progress(&self) -> Progress {
+        self.0.lock().unwrap().clone()
+    }
+
+    /// Marks the start of a run, `false` when one is going on already
+    fn start(&self) -> bool {
+        let mut progress = self.0.lock().unwrap();
+        if progress.state == RunState::Running {
+            return false;
+        }
+        *progress = Progress {
+            state: RunState::Running,
+            started_at: Some(Utc::now()),
+            ..Progress::default()
+        };
+        true
+    }
+
+    /// Migrations found once the lock is held
+    pub fn planned(&self, applied: Vec<i64>, pending: Vec<i64>) {
+        let mut progress = self.0.lock().unwrap();
+        progress.applied = applied;
+        progress.pending = pending;
+    }
+
+    pub fn applying(&self, version: i64) {
+        self.0.lock().unwrap().current = Some(version);
+    }
+
+    pub fn applied(&self, version: i64) {
+        let mut progress = self.0.lock().unwrap();
+        progress.current = None;
+        progress.pending.retain(|v| *v != version);
+        progress.applied.push(version);
+    }
+
+    fn finish(&self, result: &Result<(), String>) {
+        let mut progress = self.0.lock().unwrap();
+        progress.state = match result {
+            Ok(_) => RunState::Succeeded,
+            Err(_) => RunState::Failed,
+        };
+        progress.error = result.clone().err();
+        progress.finished_at = Some(Utc::now());
+    }
+}
+
+#[async_trait::async_trait]
+#[cfg_attr(test, automock)]
+pub trait MigrationRunner: Send + Sync + 'static {
+    /// Applies the pending migrations, reporting each one to `status`
+    async fn run(&self, status: MigrationStatus) -> Result<(), String>;
+}
+
+pub struct PostgresMigrationRunner {
+    pool: PgPool,
+    migrator: &'static Migrator,
+}
+
+impl PostgresMigrationRunner {
+    pub fn new(pool: PgPool, migrator: &'static Migrator) -> Self {
+        Self { pool, migrator }
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationRunner for PostgresMigrationRunner {
+    async fn run(&self, status: MigrationStatus) -> Result<(), String> {
+        let mut conn = self.pool.acquire().await.map_err(|e| e.to_string())?;
+        // another instance holding the lock is waited for, its migrations are then found applied
+        conn.lock().await.map_err(|e| e.to_string())?;
+
+        let result = async {
+            conn.ensure_migrations_table().await?;
+            if let Some(version) = conn.dirty_version().await? {
+                return Err(sqlx::migrate::MigrateError::Dirty(version));
+            }
+
+            let applied = conn
+                .list_applied_migrations()
+                .await?
+                .into_iter()
+                .map(|m| m.version)
+                .collect::<HashSet<_>>();
+            let pending = self
+                .migrator
+                .iter()
+                .filter(|m| !m.migration_type.is_down_migration())
+                .filter(|m| !applied.contains(&m.version))
+                .collect::<Vec<_>>();
+            let mut versions = applied.iter().copied().collect::<Vec<_>>();
+            versions.sort();
+            status.planned(versions, pending.iter().map(|m| m.version).collect());
+
+            for migration in pending {
+                status.applying(migration.version);
+                tracing::info!(
+                    "applying migration {} ({})",
+                    migration.version,
+                    migration.description
+                );
+                conn.apply(migration).await?;
+                status.applied(migration.version);
+            }
+            Ok(())
+        }
+        .await;
+
+        // the lock belongs to the session, a connection that can't release it isn't pooled again
+        if result.is_err() || conn.unlock().await.is_err() {
+            conn.close_on_drop();
+        }
+        result.map_err(|e| e.to_string())
+    }
+}
+
+pub fn state_migration_runner(
+    pool: PgPool,
+    migrator: &'static Migrator,
+) -> Arc<dyn MigrationRunner> {
+    Arc::new(PostgresMigrationRunner::new(pool, migrator))
+}
+
+#[derive(Clone)]
+pub struct MigrationState {
+    pub runner: Arc<dyn MigrationRunner>,
+    pub status: MigrationStatus,
+}
+
+impl MigrationState {
+    pub fn new(runner: Arc<dyn MigrationRunner>) -> Self {
+        Self {
+            runner,
+            status: MigrationStatus::default(),
+        }
+    }
+
+    /// Runs the migrations unless a run is going on already
+    pub async fn migrate(&self) -> Result<(), String> {
+        if !self.status.start() {
+            return Err("migrations are already running".to_string());
+        }
+        self.run_started().await
+    }
+
+    async fn run_started(&self) -> Result<(), String> {
+        let result = self.runner.run(self.status.clone()).await;
+        if let Err(e) = &result {
+            tracing::error!("migrations failed: {}", e);
+        }
+        self.status.finish(&result);
+        result
+    }
+}
+
+pub async fn status(State(state): State<MigrationState>) -> impl IntoResponse {
+    Json(state.status.progress())
+}
+
+/// Starts a run in the background, its progress is then polled with `GET`
+pub async fn trigger(State(state): State<MigrationState>) -> impl IntoResponse {
+    if !state.status.start() {
+        return (StatusCode::CONFLICT, Json(state.status.progress()));
+    }
+
+    let migrations = state.clone();
+    tokio::spawn(async move { migrations.run_started().await });
+    (StatusCode::ACCEPTED, Json(state.status.progress()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::box_future;
+    use axum::{body::Body, http::Request, routing::get, Router};
+    use http_body_util::BodyExt;
+    use tokio::sync::Notify;
+    use tower::ServiceExt;
+
+    fn create_test_app(state: MigrationState) -> Router {
+        Router::new()
+            .route("/admin/migrations", get(status).post(trigger))
+            .with_state(state)
+    }
+
+    async fn request(app: Router, method: &str) -> (StatusCode, Progress) {
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method(method)
+                    .uri("/admin/migrations")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let status = response.status();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[test]
+    fn test_mode_from_str() {
+        assert_eq!("manual".parse(), Ok(MigrationMode::Manual));
+        assert!("later".parse::<MigrationMode>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_migrate_reports_progress() {
+        let mut runner = MockMigrationRunner::new();
+        runner.expect_run().times(1).returning(|status| {
+            status.planned(vec![1, 2], vec![3, 4]);
+            status.applying(3);
+            status.applied(3);
+            status.applying(4);
+            box_future(Err("syntax error at 4".to_string()))
+        });
+        let state = MigrationState::new(Arc::new(runner));
+
+        assert!(state.migrate().await.is_err());
+        let progress = state.status.progress();
+        assert_eq!(progress.state, RunState::Failed);
+        assert_eq!(progress.applied, [1, 2, 3]);
+        assert_eq!(progress.pending, [4]);
+        assert_eq!(progress.current, Some(4));
+        assert_eq!(progress.error.as_deref(), Some("syntax error at 4"));
+        assert!(progress.finished_at.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_trigger() {
+        let release = Arc::new(Notify::new());
+        let waiting = release.clone();
+        let mut runner = MockMigrationRunner::new();
+        runner.expect_run().times(1).returning(move |status| {
+            let waiting = waiting.clone();
+            Box::pin(async move {
+                status.planned(vec![], vec![1]);
+                waiting.notified().await;
+                status.applied(1);
+                Ok(())
+            })
+        });
+        let state = MigrationState::new(Arc::new(runner));
+        let app = create_test_app(state.clone());
+
+        let (code, progress) = request(app.clone(), "GET").await;
+        assert_eq!(code, StatusCode::OK);
+        assert_eq!(progress.state, RunState::Idle);
+
+        let (code, _) = request(app.clone(), "POST").await;
+        assert_eq!(code, StatusCode::ACCEPTED);
+        let (code, progress) = request(app.clone(), "POST").await;
+        assert_eq!(code, StatusCode::CONFLICT);
+        assert_eq!(progress.state, RunState::Running);
+
+        release.notify_one();
+        while state.status.progress().state == RunState::Running {
+            tokio::task::yield_now().await;
+        }
+        let (_, progress) = request(app, "GET").await;
+        assert_eq!(progress.state, RunState::Succeeded);
+        assert_eq!(progress.applied, [1]);
+    }
+}
@@ -0,0 +1,287 @@
+//! Moderation of the quotes drafted or edited through `/19`
+
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+#[cfg(test)]
+use mockall::automock;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    config::Config,
+    day_19::{DbState, NewQuote},
+};
+
+/// Quotes longer than this are always rejected
+const MAX_LENGTH: usize = 10_000;
+/// Quotes longer than this are held for review
+const REVIEW_LENGTH: usize = 1_000;
+/// Shouting only counts past this many letters
+const SHOUTING_LETTERS: usize = 20;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "verdict", content = "reason", rename_all = "snake_case")]
+pub enum Verdict {
+    Accept,
+    /// Kept out of sight until an admin approves it
+    Flag(String),
+    /// Refused with a 422
+    Reject(String),
+}
+
+#[async_trait::async_trait]
+#[cfg_attr(test, automock)]
+pub trait Moderator: Send + Sync + 'static {
+    async fn review(&self, quote: &NewQuote) -> Verdict;
+}
+
+/// Local checks: a word denylist and a few length heuristics
+#[derive(Debug, Clone)]
+pub struct WordListModerator {
+    denied: Vec<String>,
+    max_length: usize,
+    review_length: usize,
+}
+
+impl Default for WordListModerator {
+    fn default() -> Self {
+        Self {
+            denied: vec![],
+            max_length: MAX_LENGTH,
+            review_length: REVIEW_LENGTH,
+        }
+    }
+}
+
+impl WordListModerator {
+    pub fn new(denied: &[String]) -> Self {
+        Self {
+            denied: denied.iter().map(|w| w.to_lowercase()).collect(),
+            ..Self::default()
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Moderator for WordListModerator {
+    async fn review(&self, quote: &NewQuote) -> Verdict {
+        let text = format!("{} {}", quote.author, quote.quote).to_lowercase();
+        // whole words only, so that denying "ass" doesn't reject "classic"
+        let denied = text
+            .split(|c: char| !c.is_alphanumeric())
+            .find(|w| self.denied.iter().any(|d| d == w));
+        if let Some(word) = denied {
+            return Verdict::Reject(format!("contains the denied word {}", word));
+        }
+
+        let length = quote.quote.chars().count();
+        if length > self.max_length {
+            return Verdict::Reject(format!("longer than {} characters", self.max_length));
+        }
+        if length > self.review_length {
+            return Verdict::Flag(format!("longer than {} characters", self.review_length));
+        }
+
+        let letters = quote.quote.chars().filter(|c| c.is_alphabetic());
+        if letters.clone().count() > SHOUTING_LETTERS && letters.clone().all(char::is_uppercase) {
+            return Verdict::Flag("written in capitals".to_string());
+        }
+
+        Verdict::Accept
+    }
+}
+
+/// Asks an external moderation API, which answers with a [`Verdict`].
+/// The quote is accepted when the API is slow or unreachable.
+#[cfg(feature = "http-moderation")]
+pub struct HttpModerator {
+    client: reqwest::Client,
+    url: String,
+}
+
+#[cfg(feature = "http-moderation")]
+impl HttpModerator {
+    pub fn new(url: String, timeout: std::time::Duration) -> Result<Self, reqwest::Error> {
+        Ok(Self {
+            client: reqwest::Client::builder().timeout(timeout).build()?,
+            url,
+        })
+    }
+}
+
+#[cfg(feature = "http-moderation")]
+#[async_trait::async_trait]
+impl Moderator for HttpModerator {
+    async fn review(&self, quote: &NewQuote) -> Verdict {
+        let response = self
+            .client
+            .post(&self.url)
+            .json(quote)
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status);
+        match response {
+            Ok(r) => r.json::<Verdict>().await.unwrap_or_else(|e| {
+                tracing::warn!("unreadable moderation verdict: {}", e);
+                Verdict::Accept
+            }),
+            Err(e) => {
+                tracing::warn!("moderation API unavailable: {}", e);
+                Verdict::Accept
+            }
+        }
+    }
+}
+
+/// Runs each moderator in turn, the first verdict other than `Accept` wins
+pub struct ModeratorChain {
+    moderators: Vec<Arc<dyn Moderator>>,
+}
+
+#[async_trait::async_trait]
+impl Moderator for ModeratorChain {
+    async fn review(&self, quote: &NewQuote) -> Verdict {
+        for moderator in &self.moderators {
+            match moderator.review(quote).await {
+                Verdict::Accept => continue,
+                verdict => return verdict,
+            }
+        }
+        Verdict::Accept
+    }
+}
+
+#[cfg(feature = "http-moderation")]
+fn external_moderator(config: &Config) -> Option<Arc<dyn Moderator>> {
+    let url = config.moderation_url.clone()?;
+    match HttpModerator::new(url, config.moderation_timeout) {
+        Ok(m) => Some(Arc::new(m)),
+        Err(e) => {
+            tracing::warn!("moderation API disabled: {}", e);
+            None
+        }
+    }
+}
+
+#[cfg(not(feature = "http-moderation"))]
+fn external_moderator(config: &Config) -> Option<Arc<dyn Moderator>> {
+    if config.moderation_url.is_some() {
+        tracing::warn!("MODERATION_URL ignored, built without the http-moderation feature");
+    }
+    None
+}
+
+pub fn state_moderator(config: &Config) -> Arc<dyn Moderator> {
+    let word_list: Arc<dyn Moderator> =
+        Arc::new(WordListModerator::new(&config.moderation_denylist));
+    Arc::new(ModeratorChain {
+        moderators: std::iter::once(word_list)
+            .chain(external_moderator(config))
+            .collect(),
+    })
+}
+
+pub async fn pending(State(state): State<DbState>) -> impl IntoResponse {
+    match state.repository.pending_review().await {
+        Ok(quotes) => Ok((StatusCode::OK, Json(quotes))),
+        _ => Err((StatusCode::INTERNAL_SERVER_ERROR, "".to_string())),
+    }
+}
+
+pub async fn approve(Path(id): Path<Uuid>, State(state): State<DbState>) -> impl IntoResponse {
+    match state.repository.approve(id).await {
+        Ok(q) => Ok((StatusCode::OK, Json(q))),
+        _ => Err((StatusCode::NOT_FOUND, "".to_string())),
+    }
+}
+
+/// Rejected quotes are deleted
+pub async fn reject(Path(id): Path<Uuid>, State(state): State<DbState>) -> impl IntoResponse {
+    match state.repository.reject(id).await {
+        Ok(q) => Ok((StatusCode::OK, Json(q))),
+        _ => Err((StatusCode::NOT_FOUND, "".to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::box_future;
+
+    fn quote(text: &str) -> NewQuote {
+        NewQuote {
+            author: "Santa".to_string(),
+            quote: text.to_string(),
+            publish_at: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_word_list() {
+        let moderator = WordListModerator::new(&["Grinch".to_string()]);
+        let cases = [
+            ("Ho ho ho", Verdict::Accept),
+            ("A classic grinchy move", Verdict::Accept),
+            (
+                "The grinch stole it",
+                Verdict::Reject("contains the denied word grinch".to_string()),
+            ),
+            (
+                "WHO STOLE ALL THE PRESENTS THIS YEAR",
+                Verdict::Flag("written in capitals".to_string()),
+            ),
+            ("HO HO HO", Verdict::Accept),
+        ];
+        for (text, verdict) in cases {
+            assert_eq!(moderator.review(&quote(text)).await, verdict, "{}", text);
+        }
+
+        let long = "a".repeat(REVIEW_LENGTH + 1);
+        assert!(matches!(
+            moderator.review(&quote(&long)).await,
+            Verdict::Flag(_)
+        ));
+        let too_long = "a".repeat(MAX_LENGTH + 1);
+        assert!(matches!(
+            moderator.review(&quote(&too_long)).await,
+            Verdict::Reject(_)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_chain_stops_at_first_objection() {
+        let mut flagging = MockModerator::new();
+        flagging
+            .expect_review()
+            .returning(|_| box_future(Verdict::Flag("suspicious".to_string())));
+        let mut unreachable = MockModerator::new();
+        unreachable.expect_review().never();
+
+        let chain = ModeratorChain {
+            moderators: vec![
+                Arc::new(WordListModerator::default()),
+                Arc::new(flagging),
+                Arc::new(unreachable),
+            ],
+        };
+        assert_eq!(
+            chain.review(&quote("Ho ho ho")).await,
+            Verdict::Flag("suspicious".to_string())
+        );
+    }
+
+    #[test]
+    fn test_verdict_wire_format() {
+        let verdict: Verdict =
+            serde_json::from_str(r#"{"verdict":"reject","reason":"spam"}"#).unwrap();
+        assert_eq!(verdict, Verdict::Reject("spam".to_string()));
+        let verdict: Verdict = serde_json::from_str(r#"{"verdict":"accept"}"#).unwrap();
+        assert_eq!(verdict, Verdict::Accept);
+    }
+}
@@ -0,0 +1,222 @@
+//! Reading of multipart uploads, bounded in size, shared by the routes taking files
+
+use axum::{
+    body::Bytes,
+    extract::{
+        multipart::{Field, MultipartError},
+        Multipart,
+    },
+    http::StatusCode,
+};
+
+/// Size bounds of an upload, in bytes
+#[derive(Debug, Clone, Copy)]
+pub struct Limits {
+    pub field: usize,
+    pub total: usize,
+}
+
+#[derive(Debug)]
+pub struct Part {
+    pub name: Option<String>,
+    pub content_type: Option<String>,
+    pub bytes: Bytes,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum UploadError {
+    Malformed,
+    /// A field or the whole upload exceeds its limit
+    TooLarge,
+    Missing,
+    UnsupportedType,
+}
+
+/// Fields of an upload, in the order they were sent
+#[derive(Debug)]
+pub struct Parts(Vec<Part>);
+
+impl UploadError {
+    /// Bodies cut short by the request body limit are reported as too large too
+    fn from_body(e: MultipartError) -> Self {
+        match e.status() {
+            StatusCode::PAYLOAD_TOO_LARGE => UploadError::TooLarge,
+            _ => UploadError::Malformed,
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            UploadError::Malformed | UploadError::Missing => StatusCode::BAD_REQUEST,
+            UploadError::TooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            UploadError::UnsupportedType => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+        }
+    }
+}
+
+impl From<UploadError> for (StatusCode, String) {
+    fn from(e: UploadError) -> Self {
+        (e.status(), "".to_string())
+    }
+}
+
+impl Part {
+    /// Fails unless the declared type is one of `allowed`, parts without a type are let through
+    pub fn expect_type(&self, allowed: &[&str]) -> Result<&Self, UploadError> {
+        match &self.content_type {
+            Some(ct) if !allowed.contains(&ct.as_str()) => Err(UploadError::UnsupportedType),
+            _ => Ok(self),
+        }
+    }
+
+    pub fn text(&self) -> Result<&str, UploadError> {
+        std::str::from_utf8(&self.bytes).map_err(|_| UploadError::Malformed)
+    }
+}
+
+impl Parts {
+    /// Reads every field, stopping as soon as a limit is crossed
+    pub async fn read(mut multipart: Multipart, limits: Limits) -> Result<Self, UploadError> {
+        let mut parts = Vec::new();
+        let mut total = 0;
+        while let Some(field) = multipart
+            .next_field()
+            .await
+            .map_err(UploadError::from_body)?
+        {
+            let part = read_field(field, limits, &mut total).await?;
+            parts.push(part);
+        }
+        Ok(Self(parts))
+    }
+
+    pub fn field(&self, name: &str) -> Option<&Part> {
+        self.0.iter().find(|p| p.name.as_deref() == Some(name))
+    }
+
+    /// The field of that name, else the first one, for clients naming it otherwise
+    pub fn field_or_first(&self, name: &str) -> Result<&Part, UploadError> {
+        self.field(name)
+            .or_else(|| self.0.first())
+            .ok_or(UploadError::Missing)
+    }
+}
+
+async fn read_field(
+    mut field: Field<'_>,
+    limits: Limits,
+    total: &mut usize,
+) -> Result<Part, UploadError> {
+    let name = field.name().map(str::to_string);
+    let content_type = field.content_type().map(str::to_string);
+
+    let mut bytes = Vec::new();
+    while let Some(chunk) = field.chunk().await.map_err(UploadError::from_body)? {
+        *total += chunk.len();
+        if bytes.len() + chunk.len() > limits.field || *total > limits.total {
+            return Err(UploadError::TooLarge);
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+    Ok(Part {
+        name,
+        content_type,
+        bytes: bytes.into(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{
+        body::Body,
+        extract::FromRequest,
+        http::{header, Request},
+    };
+
+    const BOUNDARY: &str = "parts";
+    const LIMITS: Limits = Limits {
+        field: 8,
+        total: 12,
+    };
+
+    async fn read(
+        fields: &[(&str, Option<&str>, &str)],
+        limits: Limits,
+    ) -> Result<Parts, UploadError> {
+        let mut body = String::new();
+        for (name, content_type, content) in fields {
+            body.push_str(&format!(
+                "--{}\r\nContent-Disposition: form-data; name=\"{}\"\r\n",
+                BOUNDARY, name
+            ));
+            if let Some(ct) = content_type {
+                body.push_str(&format!("Content-Type: {}\r\n", ct));
+            }
+            body.push_str(&format!("\r\n{}\r\n", content));
+        }
+        body.push_str(&format!("--{}--\r\n", BOUNDARY));
+
+        let request = Request::builder()
+            .header(
+                header::CONTENT_TYPE,
+                format!("multipart/form-data; boundary={}", BOUNDARY),
+            )
+            .body(Body::from(body))
+            .unwrap();
+        let multipart = Multipart::from_request(request, &()).await.unwrap();
+        Parts::read(multipart, limits).await
+    }
+
+    #[tokio::test]
+    async fn test_named_fields() {
+        let parts = read(
+            &[
+                ("note", None, "hi"),
+                ("lockfile", Some("text/plain"), "toml"),
+            ],
+            LIMITS,
+        )
+        .await
+        .unwrap();
+        let lockfile = parts.field("lockfile").unwrap();
+        assert_eq!(lockfile.text(), Ok("toml"));
+        assert_eq!(lockfile.content_type.as_deref(), Some("text/plain"));
+        assert_eq!(parts.field_or_first("image").unwrap().text(), Ok("hi"));
+        assert!(parts.field("image").is_none());
+
+        let empty = read(&[], LIMITS).await.unwrap();
+        assert_eq!(
+            empty.field_or_first("lockfile").err(),
+            Some(UploadError::Missing)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_limits() {
+        assert!(read(&[("a", None, "12345678")], LIMITS).await.is_ok());
+        assert_eq!(
+            read(&[("a", None, "123456789")], LIMITS).await.err(),
+            Some(UploadError::TooLarge)
+        );
+        assert_eq!(
+            read(&[("a", None, "1234567"), ("b", None, "123456")], LIMITS)
+                .await
+                .err(),
+            Some(UploadError::TooLarge)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_content_type() {
+        let parts = read(&[("a", Some("image/jpeg"), ""), ("b", None, "")], LIMITS)
+            .await
+            .unwrap();
+        let allowed = ["image/png"];
+        assert_eq!(
+            parts.field("a").unwrap().expect_type(&allowed).err(),
+            Some(UploadError::UnsupportedType)
+        );
+        assert!(parts.field("b").unwrap().expect_type(&allowed).is_ok());
+    }
+}
@@ -0,0 +1,186 @@
+use core::convert::Infallible;
+
+use axum::{
+    async_trait,
+    extract::FromRequestParts,
+    http::{header, request::Parts},
+};
+
+/// Representations a handler can offer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Plain,
+    Json,
+    Html,
+    Svg,
+    NdJson,
+}
+
+impl Format {
+    pub fn mime(&self) -> &'static str {
+        match self {
+            Format::Plain => "text/plain; charset=utf-8",
+            Format::Json => "application/json",
+            Format::Html => "text/html; charset=utf-8",
+            Format::Svg => "image/svg+xml",
+            Format::NdJson => "application/x-ndjson",
+        }
+    }
+
+    fn essence(&self) -> (&'static str, &'static str) {
+        match self {
+            Format::Plain => ("text", "plain"),
+            Format::Json => ("application", "json"),
+            Format::Html => ("text", "html"),
+            Format::Svg => ("image", "svg+xml"),
+            Format::NdJson => ("application", "x-ndjson"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct MediaRange {
+    kind: String,
+    subtype: String,
+    q: f32,
+}
+
+impl MediaRange {
+    fn parse(s: &str) -> Option<Self> {
+        let mut params = s.split(';').map(str::trim);
+        let (kind, subtype) = params.next()?.split_once('/')?;
+        let q = params
+            .filter_map(|p| p.strip_prefix("q="))
+            .find_map(|q| q.parse::<f32>().ok())
+            .unwrap_or(1.0);
+
+        Some(Self {
+            kind: kind.to_ascii_lowercase(),
+            subtype: subtype.to_ascii_lowercase(),
+            q,
+        })
+    }
+
+    /// Specificity of the match against the given format, if any
+    fn matches(&self, format: &Format) -> Option<u8> {
+        let (kind, subtype) = format.essence();
+        match (self.kind.as_str(), self.subtype.as_str()) {
+            (k, s) if k == kind && s == subtype => Some(2),
+            (k, "*") if k == kind => Some(1),
+            ("*", "*") => Some(0),
+            _ => None,
+        }
+    }
+}
+
+/// Parsed `Accept` header of the request
+#[derive(Debug, Default)]
+pub struct Accept(Vec<MediaRange>);
+
+impl Accept {
+    pub fn from_header(value: &str) -> Self {
+        Accept(value.split(',').filter_map(MediaRange::parse).collect())
+    }
+
+    /// Picks the offered format the client prefers the most.
+    /// Without an `Accept` header the first offered format is the default,
+    /// `None` means that the client accepts none of them.
+    pub fn negotiate(&self, offered: &[Format]) -> Option<Format> {
+        if self.0.is_empty() {
+            return offered.first().copied();
+        }
+
+        offered
+            .iter()
+            .enumerate()
+            .filter_map(|(i, format)| {
+                // the most specific range decides the quality of a format
+                self.0
+                    .iter()
+                    .filter_map(|range| range.matches(format).map(|s| (s, range.q)))
+                    .max_by_key(|(specificity, _)| *specificity)
+                    .map(|(_, q)| (i, *format, q))
+            })
+            .filter(|(_, _, q)| *q > 0.0)
+            // highest quality wins, ties go to the handler's preferred format
+            .max_by(|(i, _, q1), (j, _, q2)| q1.total_cmp(q2).then(j.cmp(i)))
+            .map(|(_, format, _)| format)
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for Accept
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(parts
+            .headers
+            .get_all(header::ACCEPT)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .map(Accept::from_header)
+            .fold(Accept::default(), |mut acc, a| {
+                acc.0.extend(a.0);
+                acc
+            }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_no_header_picks_first_offered() {
+        let accept = Accept::default();
+        assert_eq!(
+            accept.negotiate(&[Format::Plain, Format::Json]),
+            Some(Format::Plain)
+        );
+    }
+
+    #[test]
+    fn test_exact_match() {
+        let accept = Accept::from_header("application/json");
+        assert_eq!(
+            accept.negotiate(&[Format::Plain, Format::Json]),
+            Some(Format::Json)
+        );
+    }
+
+    #[test]
+    fn test_wildcard_picks_first_offered() {
+        let accept = Accept::from_header("*/*");
+        assert_eq!(
+            accept.negotiate(&[Format::Plain, Format::Json]),
+            Some(Format::Plain)
+        );
+    }
+
+    #[test]
+    fn test_quality_ordering() {
+        let accept = Accept::from_header("text/plain;q=0.5, image/svg+xml, */*;q=0.1");
+        assert_eq!(
+            accept.negotiate(&[Format::Plain, Format::Json, Format::Svg]),
+            Some(Format::Svg)
+        );
+    }
+
+    #[test]
+    fn test_specific_range_overrides_wildcard() {
+        let accept = Accept::from_header("text/*, text/html;q=0");
+        assert_eq!(
+            accept.negotiate(&[Format::Html, Format::Plain]),
+            Some(Format::Plain)
+        );
+    }
+
+    #[test]
+    fn test_not_acceptable() {
+        let accept = Accept::from_header("image/png");
+        assert_eq!(accept.negotiate(&[Format::Plain, Format::Json]), None);
+    }
+}
@@ -0,0 +1,392 @@
+//! OpenAPI description of the challenge routes, served at `/openapi.json` and browsable at `/docs`.
+//! Each day module lists its own operations, the body and response schemas come from their types.
+
+use std::{collections::BTreeMap, sync::Arc};
+
+use axum::{
+    extract::State,
+    http::{Method, StatusCode},
+    response::Html,
+    Json,
+};
+use schemars::{
+    gen::{SchemaGenerator, SchemaSettings},
+    schema::Schema,
+    JsonSchema,
+};
+use serde_json::{json, Map, Value};
+
+use crate::{validation::ValidationErrors, versioning};
+
+const TITLE: &str = "Shuttle Christmas Code Hunt 2024";
+const DOCS_PAGE: &str = include_str!("./openapi/docs.html");
+
+type SchemaFn = fn(&mut SchemaGenerator) -> Schema;
+
+fn schema_of<T: JsonSchema>(generator: &mut SchemaGenerator) -> Schema {
+    generator.subschema_for::<T>()
+}
+
+struct Parameter {
+    name: &'static str,
+    kind: &'static str,
+    description: &'static str,
+    required: bool,
+}
+
+struct Content {
+    media_type: &'static str,
+    schema: Option<SchemaFn>,
+}
+
+/// What a route takes and answers, as listed by the modules serving it
+pub struct Operation {
+    method: Method,
+    path: &'static str,
+    summary: &'static str,
+    query: Vec<Parameter>,
+    body: Option<Content>,
+    responses: Vec<(StatusCode, &'static str, Option<Content>)>,
+}
+
+impl Operation {
+    /// `path` is the route template, its `:name` segments becoming path parameters
+    pub fn new(method: Method, path: &'static str, summary: &'static str) -> Self {
+        Self {
+            method,
+            path,
+            summary,
+            query: vec![],
+            body: None,
+            responses: vec![],
+        }
+    }
+
+    /// Optional query parameter, `kind` being its JSON type
+    pub fn query(
+        mut self,
+        name: &'static str,
+        kind: &'static str,
+        description: &'static str,
+    ) -> Self {
+        self.query.push(Parameter {
+            name,
+            kind,
+            description,
+            required: false,
+        });
+        self
+    }
+
+    pub fn required_query(
+        mut self,
+        name: &'static str,
+        kind: &'static str,
+        description: &'static str,
+    ) -> Self {
+        self.query.push(Parameter {
+            name,
+            kind,
+            description,
+            required: true,
+        });
+        self
+    }
+
+    /// Body of a media type without a schema, e.g. plain text or a multipart form
+    pub fn body(mut self, media_type: &'static str) -> Self {
+        self.body = Some(Content {
+            media_type,
+            schema: None,
+        });
+        self
+    }
+
+    pub fn json_body<T: JsonSchema>(mut self) -> Self {
+        self.body = Some(Content {
+            media_type: "application/json",
+            schema: Some(schema_of::<T>),
+        });
+        self
+    }
+
+    /// Response without a documented body
+    pub fn response(mut self, status: StatusCode, description: &'static str) -> Self {
+        self.responses.push((status, description, None));
+        self
+    }
+
+    /// Response body of a media type without a schema, e.g. plain text or HTML
+    pub fn media_response(
+        mut self,
+        status: StatusCode,
+        media_type: &'static str,
+        description: &'static str,
+    ) -> Self {
+        let content = Content {
+            media_type,
+            schema: None,
+        };
+        self.responses.push((status, description, Some(content)));
+        self
+    }
+
+    pub fn json_response<T: JsonSchema>(
+        mut self,
+        status: StatusCode,
+        description: &'static str,
+    ) -> Self {
+        let content = Content {
+            media_type: "application/json",
+            schema: Some(schema_of::<T>),
+        };
+        self.responses.push((status, description, Some(content)));
+        self
+    }
+
+    /// Response answered by `AppError`, with its list of errors
+    pub fn error(self, status: StatusCode, description: &'static str) -> Self {
+        self.json_response::<ValidationErrors>(status, description)
+    }
+
+    /// `/19/cite/:id` as `/19/cite/{id}`, along with the names of its parameters
+    fn template(&self) -> (String, Vec<&'static str>) {
+        let mut names = vec![];
+        let template = self
+            .path
+            .split('/')
+            .map(|segment| match segment.strip_prefix(':') {
+                Some(name) => {
+                    names.push(name);
+                    format!("{{{}}}", name)
+                }
+                None => segment.to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join("/");
+        (template, names)
+    }
+
+    /// `day 19` for `/19/cite/:id`, the first segment for the routes outside of a day
+    fn tag(&self) -> Option<String> {
+        let first = self.path.trim_start_matches('/').split('/').next()?;
+        match first.parse::<i32>() {
+            Ok(day) => Some(format!("day {}", day)),
+            Err(_) if first.is_empty() => None,
+            Err(_) => Some(first.to_string()),
+        }
+    }
+
+    fn to_json(&self, generator: &mut SchemaGenerator, path_params: &[&str]) -> Value {
+        let mut parameters = path_params
+            .iter()
+            .map(|name| {
+                json!({
+                    "name": name,
+                    "in": "path",
+                    "required": true,
+                    "schema": { "type": "string" },
+                })
+            })
+            .collect::<Vec<_>>();
+        parameters.extend(self.query.iter().map(|p| {
+            json!({
+                "name": p.name,
+                "in": "query",
+                "required": p.required,
+                "description": p.description,
+                "schema": { "type": p.kind },
+            })
+        }));
+
+        let mut operation = Map::new();
+        operation.insert("summary".to_string(), json!(self.summary));
+        if let Some(tag) = self.tag() {
+            operation.insert("tags".to_string(), json!([tag]));
+        }
+        if !parameters.is_empty() {
+            operation.insert("parameters".to_string(), Value::Array(parameters));
+        }
+        if let Some(body) = &self.body {
+            operation.insert(
+                "requestBody".to_string(),
+                json!({ "required": true, "content": content(body, generator) }),
+            );
+        }
+
+        let mut responses = Map::new();
+        for (status, description, body) in &self.responses {
+            let mut response = json!({ "description": description });
+            if let Some(body) = body {
+                response["content"] = content(body, generator);
+            }
+            responses.insert(status.as_u16().to_string(), response);
+        }
+        operation.insert("responses".to_string(), Value::Object(responses));
+
+        Value::Object(operation)
+    }
+}
+
+fn content(content: &Content, generator: &mut SchemaGenerator) -> Value {
+    let schema = content
+        .schema
+        .map(|schema| serde_json::to_value(schema(generator)).unwrap_or_default())
+        .unwrap_or_else(|| json!({}));
+    json!({ content.media_type: { "schema": schema } })
+}
+
+/// The OpenAPI document, built once from the operations of every module
+#[derive(Clone)]
+pub struct ApiDoc {
+    document: Arc<Value>,
+}
+
+impl ApiDoc {
+    pub fn new(operations: impl IntoIterator<Item = Operation>) -> Self {
+        let mut generator = SchemaSettings::draft2019_09()
+            .with(|s| {
+                s.definitions_path = "#/components/schemas/".to_string();
+                s.meta_schema = None;
+            })
+            .into_generator();
+
+        let mut paths = BTreeMap::<String, Map<String, Value>>::new();
+        for operation in operations {
+            let (template, path_params) = operation.template();
+            let json = operation.to_json(&mut generator, &path_params);
+            paths
+                .entry(template)
+                .or_default()
+                .insert(operation.method.as_str().to_ascii_lowercase(), json);
+        }
+
+        let document = json!({
+            "openapi": "3.1.0",
+            "info": {
+                "title": TITLE,
+                "version": env!("CARGO_PKG_VERSION"),
+            },
+            "servers": [
+                { "url": versioning::CURRENT },
+                { "url": "/", "description": "Unversioned aliases" },
+            ],
+            "paths": paths,
+            "components": { "schemas": generator.take_definitions() },
+        });
+        Self {
+            document: Arc::new(document),
+        }
+    }
+}
+
+pub async fn spec(State(doc): State<ApiDoc>) -> Json<Value> {
+    Json(doc.document.as_ref().clone())
+}
+
+/// Swagger UI pointed at `/openapi.json`
+pub async fn docs() -> Html<&'static str> {
+    Html(DOCS_PAGE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::day_19::{NewQuote, Quote};
+    use axum::{body::Body, http::Request, routing::get, Router};
+    use http_body_util::BodyExt;
+    use tower::ServiceExt;
+
+    fn create_test_doc() -> ApiDoc {
+        ApiDoc::new([
+            Operation::new(Method::GET, "/", "Greets the bird").media_response(
+                StatusCode::OK,
+                "text/plain",
+                "The greeting",
+            ),
+            Operation::new(Method::GET, "/19/cite/:id", "A quote by id")
+                .query("format", "string", "Citation format")
+                .json_response::<Quote>(StatusCode::OK, "The quote")
+                .error(StatusCode::NOT_FOUND, "No quote has this id"),
+            Operation::new(Method::PUT, "/19/undo/:id", "Replaces a quote")
+                .json_body::<NewQuote>()
+                .json_response::<Quote>(StatusCode::OK, "The updated quote"),
+        ])
+    }
+
+    #[test]
+    fn test_document() {
+        let doc = create_test_doc();
+        let doc = doc.document.as_ref();
+        assert_eq!(doc["openapi"], "3.1.0");
+        assert_eq!(doc["servers"][0]["url"], "/v1");
+
+        let cite = &doc["paths"]["/19/cite/{id}"]["get"];
+        assert_eq!(cite["tags"], json!(["day 19"]));
+        assert_eq!(
+            cite["parameters"],
+            json!([
+                { "name": "id", "in": "path", "required": true, "schema": { "type": "string" } },
+                {
+                    "name": "format",
+                    "in": "query",
+                    "required": false,
+                    "description": "Citation format",
+                    "schema": { "type": "string" }
+                }
+            ])
+        );
+        assert_eq!(
+            cite["responses"]["200"]["content"]["application/json"]["schema"]["$ref"],
+            "#/components/schemas/Quote"
+        );
+        assert_eq!(
+            cite["responses"]["404"]["content"]["application/json"]["schema"]["$ref"],
+            "#/components/schemas/ValidationErrors"
+        );
+
+        let undo = &doc["paths"]["/19/undo/{id}"]["put"];
+        assert_eq!(
+            undo["requestBody"]["content"]["application/json"]["schema"]["$ref"],
+            "#/components/schemas/NewQuote"
+        );
+        for name in ["Quote", "NewQuote", "ValidationErrors"] {
+            assert!(doc["components"]["schemas"][name].is_object(), "{}", name);
+        }
+
+        let hello = &doc["paths"]["/"]["get"];
+        assert!(hello.get("tags").is_none());
+        assert!(hello.get("parameters").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_serve() {
+        let app = Router::new()
+            .route("/openapi.json", get(spec))
+            .with_state(create_test_doc())
+            .route("/docs", get(docs));
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri("/openapi.json")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let doc: Value = serde_json::from_slice(&body).unwrap();
+        assert!(doc["paths"]["/19/undo/{id}"]["put"].is_object());
+
+        let response = app
+            .oneshot(Request::builder().uri("/docs").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert!(String::from_utf8_lossy(&body).contains("/openapi.json"));
+    }
+}
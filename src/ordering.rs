@@ -0,0 +1,202 @@
+//! Sort orders of the list endpoints. Each one ends with a unique column, so that rows with
+//! equal timestamps always come back in the same order and offset pages never overlap or skip.
+
+use core::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Direction {
+    Asc,
+    Desc,
+}
+
+/// `ORDER BY` clause, formatted as its SQL
+#[derive(Debug, Clone, Copy)]
+pub struct Order {
+    keys: &'static [(&'static str, Direction)],
+    /// Unique column settling the rows that `keys` leave equal
+    tie_breaker: (&'static str, Direction),
+}
+
+/// `/19/list`, backups and the moderation queue: oldest first
+pub const QUOTES: Order = Order {
+    keys: &[("created_at", Direction::Asc)],
+    tie_breaker: ("id", Direction::Asc),
+};
+
+pub const TOP_QUOTES: Order = Order {
+    keys: &[("likes", Direction::Desc), ("created_at", Direction::Asc)],
+    tie_breaker: ("id", Direction::Asc),
+};
+
+/// Authors are grouped on, so they are unique among the suggestions
+pub const AUTHOR_SUGGESTIONS: Order = Order {
+    keys: &[("similarity", Direction::Desc)],
+    tie_breaker: ("author", Direction::Asc),
+};
+
+pub const COMMENTS: Order = Order {
+    keys: &[("created_at", Direction::Asc)],
+    tie_breaker: ("id", Direction::Asc),
+};
+
+/// `/12/history`, the latest game first
+pub const GAME_RESULTS: Order = Order {
+    keys: &[("finished_at", Direction::Desc)],
+    tie_breaker: ("id", Direction::Desc),
+};
+
+pub const LEADERBOARD: Order = Order {
+    keys: &[("rating", Direction::Desc)],
+    tie_breaker: ("name", Direction::Asc),
+};
+
+pub const RATING_CHANGES: Order = Order {
+    keys: &[("played_at", Direction::Desc)],
+    tie_breaker: ("id", Direction::Desc),
+};
+
+/// Next gift order to work on, the earliest of the highest priority
+pub const GIFT_ORDERS: Order = Order {
+    keys: &[
+        ("priority", Direction::Desc),
+        ("created_at", Direction::Asc),
+    ],
+    tie_breaker: ("id", Direction::Asc),
+};
+
+/// Next job to claim, the oldest first
+pub const JOBS: Order = Order {
+    keys: &[("created_at", Direction::Asc)],
+    tie_breaker: ("id", Direction::Asc),
+};
+
+impl Order {
+    /// Columns in sort order, the tie breaker last
+    pub fn columns(&self) -> impl Iterator<Item = (&'static str, Direction)> + '_ {
+        self.keys.iter().copied().chain([self.tie_breaker])
+    }
+}
+
+impl fmt::Display for Order {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let columns = self
+            .columns()
+            .map(|(column, direction)| match direction {
+                Direction::Asc => column.to_string(),
+                Direction::Desc => format!("{} DESC", column),
+            })
+            .collect::<Vec<_>>();
+        write!(f, "{}", columns.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::cmp;
+
+    use super::*;
+    use proptest::prelude::*;
+    use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+
+    /// Row as Postgres would sort it, with few distinct values so that ties are common
+    #[derive(Debug, Clone)]
+    struct Row {
+        id: usize,
+        created_at: u8,
+        likes: u8,
+    }
+
+    impl Row {
+        fn value(&self, column: &str) -> usize {
+            match column {
+                "id" => self.id,
+                "created_at" => self.created_at as usize,
+                "likes" => self.likes as usize,
+                _ => unreachable!("no column {}", column),
+            }
+        }
+    }
+
+    fn sorted(order: &Order, mut rows: Vec<Row>) -> Vec<Row> {
+        rows.sort_by(|a, b| {
+            order
+                .columns()
+                .map(|(column, direction)| {
+                    let ordering = a.value(column).cmp(&b.value(column));
+                    match direction {
+                        Direction::Asc => ordering,
+                        Direction::Desc => ordering.reverse(),
+                    }
+                })
+                .find(|o| o.is_ne())
+                .unwrap_or(cmp::Ordering::Equal)
+        });
+        rows
+    }
+
+    fn rows() -> impl Strategy<Value = Vec<Row>> {
+        prop::collection::vec((0..3u8, 0..3u8), 0..40).prop_map(|values| {
+            values
+                .into_iter()
+                .enumerate()
+                .map(|(id, (created_at, likes))| Row {
+                    id,
+                    created_at,
+                    likes,
+                })
+                .collect()
+        })
+    }
+
+    #[test]
+    fn test_sql() {
+        assert_eq!(QUOTES.to_string(), "created_at, id");
+        assert_eq!(TOP_QUOTES.to_string(), "likes DESC, created_at, id");
+        assert_eq!(GAME_RESULTS.to_string(), "finished_at DESC, id DESC");
+    }
+
+    /// Rows in whatever order the table happens to hold them for a query
+    fn shuffled(rows: &[Row], seed: u64) -> Vec<Row> {
+        let mut rows = rows.to_vec();
+        rows.shuffle(&mut StdRng::seed_from_u64(seed));
+        rows
+    }
+
+    proptest! {
+        #[test]
+        fn test_order_is_deterministic(rows in rows(), seeds in any::<(u64, u64)>()) {
+            for order in [QUOTES, TOP_QUOTES] {
+                let ids = |seed| {
+                    sorted(&order, shuffled(&rows, seed))
+                        .iter()
+                        .map(|r| r.id)
+                        .collect::<Vec<_>>()
+                };
+                prop_assert_eq!(ids(seeds.0), ids(seeds.1));
+            }
+        }
+
+        #[test]
+        fn test_pages_have_no_gaps_or_duplicates(
+            rows in rows(),
+            limit in 1..7usize,
+            seed in any::<u64>(),
+        ) {
+            for order in [QUOTES, TOP_QUOTES] {
+                let count = rows.len();
+                // every page is a query of its own, over rows held in any order
+                let mut seen = (0..count.div_ceil(limit))
+                    .flat_map(|page| {
+                        sorted(&order, shuffled(&rows, seed.wrapping_add(page as u64)))
+                            .into_iter()
+                            .skip(page * limit)
+                            .take(limit)
+                    })
+                    .map(|r| r.id)
+                    .collect::<Vec<_>>();
+                seen.sort();
+                prop_assert_eq!(seen, (0..count).collect::<Vec<_>>());
+            }
+        }
+    }
+}
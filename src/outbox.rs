@@ -0,0 +1,223 @@
+use std::{sync::Arc, time::Duration};
+
+use serde::{Deserialize, Serialize};
+use sqlx::{query, query_as, types::Json, FromRow, PgConnection, PgPool};
+use tokio::sync::broadcast;
+
+use crate::shutdown::Shutdown;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+const BATCH_SIZE: i64 = 100;
+const MAX_ATTEMPTS: i32 = 10;
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+/// How long delivered events stay around, e.g. to look into what a sink was sent
+const DELIVERED_RETENTION: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+pub const QUOTE_CREATED: &str = "quote.created";
+pub const QUOTE_UPDATED: &str = "quote.updated";
+pub const QUOTE_DELETED: &str = "quote.deleted";
+pub const QUOTE_PUBLISHED: &str = "quote.published";
+pub const QUOTES_RESET: &str = "quotes.reset";
+pub const GAME_COMPLETED: &str = "game.completed";
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct OutboxEvent {
+    pub id: i64,
+    pub topic: String,
+    pub payload: Json<serde_json::Value>,
+    pub attempts: i32,
+}
+
+/// Destination of the events stored in the outbox
+#[async_trait::async_trait]
+pub trait EventSink: Send + Sync + 'static {
+    async fn deliver(&self, event: &OutboxEvent) -> Result<(), String>;
+}
+
+/// In-process fan-out to whoever is currently subscribed (e.g. SSE streams)
+pub struct BroadcastSink {
+    sender: broadcast::Sender<OutboxEvent>,
+}
+
+impl BroadcastSink {
+    pub fn new(sender: broadcast::Sender<OutboxEvent>) -> Self {
+        Self { sender }
+    }
+}
+
+#[async_trait::async_trait]
+impl EventSink for BroadcastSink {
+    async fn deliver(&self, event: &OutboxEvent) -> Result<(), String> {
+        // no subscribers is not an error, there's simply nobody to notify
+        let _ = self.sender.send(event.clone());
+        Ok(())
+    }
+}
+
+/// Stores an event in the outbox, using the same transaction as the data change it describes
+pub async fn enqueue<T: Serialize + Sync>(
+    conn: &mut PgConnection,
+    topic: &str,
+    payload: &T,
+) -> Result<(), sqlx::Error> {
+    query("INSERT INTO outbox (topic, payload) VALUES ($1, $2)")
+        .bind(topic)
+        .bind(Json(payload))
+        .execute(conn)
+        .await
+        .map(|_| ())
+}
+
+/// Stores one event per payload in a single statement, for changes touching many rows at once
+pub async fn enqueue_all<T: Serialize + Sync>(
+    conn: &mut PgConnection,
+    topic: &str,
+    payloads: &[T],
+) -> Result<(), sqlx::Error> {
+    query("INSERT INTO outbox (topic, payload) SELECT $1, * FROM UNNEST($2::jsonb[])")
+        .bind(topic)
+        .bind(payloads.iter().map(Json).collect::<Vec<_>>())
+        .execute(conn)
+        .await
+        .map(|_| ())
+}
+
+/// Polls the outbox and delivers pending events to every sink, at least once
+pub struct OutboxDispatcher {
+    pool: PgPool,
+    sinks: Vec<Arc<dyn EventSink>>,
+}
+
+impl OutboxDispatcher {
+    pub fn new(pool: PgPool, sinks: Vec<Arc<dyn EventSink>>) -> Self {
+        Self { pool, sinks }
+    }
+
+    pub async fn run(self) {
+        let mut interval = tokio::time::interval(POLL_INTERVAL);
+        loop {
+            interval.tick().await;
+            if let Err(e) = self.dispatch_batch().await {
+                tracing::warn!("outbox dispatch failed: {}", e);
+            }
+        }
+    }
+
+    async fn dispatch_batch(&self) -> Result<(), sqlx::Error> {
+        // rows stay locked until commit so that concurrent dispatchers skip them
+        let mut tx = self.pool.begin().await?;
+        let events = query_as::<_, OutboxEvent>(
+            "SELECT id, topic, payload, attempts FROM outbox
+             WHERE delivered_at IS NULL AND attempts < $1 AND next_attempt_at <= now()
+             ORDER BY id LIMIT $2 FOR UPDATE SKIP LOCKED",
+        )
+        .bind(MAX_ATTEMPTS)
+        .bind(BATCH_SIZE)
+        .fetch_all(&mut *tx)
+        .await?;
+
+        for event in events {
+            match self.deliver(&event).await {
+                Ok(()) => {
+                    query("UPDATE outbox SET delivered_at = now(), attempts = attempts + 1 WHERE id = $1")
+                        .bind(event.id)
+                        .execute(&mut *tx)
+                        .await?;
+                }
+                Err(e) => {
+                    // exponential backoff: 1s, 2s, 4s...
+                    query(
+                        "UPDATE outbox SET attempts = attempts + 1, last_error = $2,
+                         next_attempt_at = now() + make_interval(secs => power(2, attempts))
+                         WHERE id = $1",
+                    )
+                    .bind(event.id)
+                    .bind(e)
+                    .execute(&mut *tx)
+                    .await?;
+                }
+            }
+        }
+
+        tx.commit().await
+    }
+
+    async fn deliver(&self, event: &OutboxEvent) -> Result<(), String> {
+        for sink in &self.sinks {
+            sink.deliver(event).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Deletes the events delivered long ago, those that ran out of attempts are kept to be looked into
+pub async fn sweep(pool: PgPool) {
+    let mut interval = tokio::time::interval(SWEEP_INTERVAL);
+    loop {
+        interval.tick().await;
+        match query("DELETE FROM outbox WHERE delivered_at < now() - make_interval(secs => $1)")
+            .bind(DELIVERED_RETENTION.as_secs_f64())
+            .execute(&pool)
+            .await
+        {
+            Ok(r) if r.rows_affected() > 0 => {
+                tracing::info!("swept {} delivered outbox events", r.rows_affected())
+            }
+            Ok(_) => {}
+            Err(e) => tracing::warn!("outbox sweep failed: {}", e),
+        }
+    }
+}
+
+/// Delivers the events of the last requests, left to the next instance otherwise
+#[async_trait::async_trait]
+impl Shutdown for OutboxDispatcher {
+    fn name(&self) -> &'static str {
+        "outbox"
+    }
+
+    async fn shutdown(self: Box<Self>) {
+        if let Err(e) = self.dispatch_batch().await {
+            tracing::warn!("final outbox dispatch failed: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_broadcast_sink_delivers_to_subscribers() {
+        let (sender, mut receiver) = broadcast::channel(8);
+        let sink = BroadcastSink::new(sender);
+        let event = OutboxEvent {
+            id: 1,
+            topic: QUOTE_CREATED.to_string(),
+            payload: Json(json!({"author": "Santa"})),
+            attempts: 0,
+        };
+
+        sink.deliver(&event).await.unwrap();
+
+        let received = receiver.recv().await.unwrap();
+        assert_eq!(received.id, 1);
+        assert_eq!(received.topic, QUOTE_CREATED);
+        assert_eq!(received.payload.0, json!({"author": "Santa"}));
+    }
+
+    #[tokio::test]
+    async fn test_broadcast_sink_without_subscribers() {
+        let (sender, _) = broadcast::channel(8);
+        let sink = BroadcastSink::new(sender);
+        let event = OutboxEvent {
+            id: 1,
+            topic: QUOTES_RESET.to_string(),
+            payload: Json(json!(null)),
+            attempts: 0,
+        };
+
+        assert!(sink.deliver(&event).await.is_ok());
+    }
+}
@@ -0,0 +1,394 @@
+//! The password game: tiered validation rules, each reporting why an input failed
+
+use axum::{http::StatusCode, response::IntoResponse, Json};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+#[derive(Deserialize)]
+pub struct Input {
+    input: String,
+}
+
+struct Rule {
+    name: &'static str,
+    status: StatusCode,
+    reason: &'static str,
+    check: fn(&str) -> bool,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Failure {
+    pub rule: String,
+    pub reason: String,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Verdict {
+    pub result: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub failures: Vec<Failure>,
+}
+
+const NICE_RULES: &[Rule] = &[
+    Rule {
+        name: "vowels",
+        status: StatusCode::BAD_REQUEST,
+        reason: "needs at least three vowels",
+        check: |s| s.chars().filter(|c| "aeiouyAEIOUY".contains(*c)).count() >= 3,
+    },
+    Rule {
+        name: "double_letter",
+        status: StatusCode::BAD_REQUEST,
+        reason: "needs a letter twice in a row",
+        check: |s| {
+            let chars = s.chars().collect::<Vec<char>>();
+            chars
+                .windows(2)
+                .any(|w| w[0] == w[1] && w[0].is_alphabetic())
+        },
+    },
+    Rule {
+        name: "forbidden_substrings",
+        status: StatusCode::BAD_REQUEST,
+        reason: "contains ab, cd, pq or xy",
+        check: |s| !["ab", "cd", "pq", "xy"].iter().any(|f| s.contains(f)),
+    },
+];
+
+const GAME_RULES: &[Rule] = &[
+    Rule {
+        name: "length",
+        status: StatusCode::BAD_REQUEST,
+        reason: "8 chars",
+        check: |s| s.chars().count() >= 8,
+    },
+    Rule {
+        name: "char_types",
+        status: StatusCode::BAD_REQUEST,
+        reason: "more types of chars",
+        check: |s| {
+            s.chars().any(|c| c.is_ascii_uppercase())
+                && s.chars().any(|c| c.is_ascii_lowercase())
+                && s.chars().any(|c| c.is_ascii_digit())
+        },
+    },
+    Rule {
+        name: "digits",
+        status: StatusCode::BAD_REQUEST,
+        reason: "55555",
+        check: |s| s.chars().filter(char::is_ascii_digit).count() >= 5,
+    },
+    Rule {
+        name: "integer_sum",
+        status: StatusCode::BAD_REQUEST,
+        reason: "math is hard",
+        check: |s| integer_sum(s) == 2023,
+    },
+    Rule {
+        name: "joy",
+        status: StatusCode::NOT_ACCEPTABLE,
+        reason: "not joyful enough",
+        check: is_joyful,
+    },
+    Rule {
+        name: "sandwich",
+        status: StatusCode::UNAVAILABLE_FOR_LEGAL_REASONS,
+        reason: "illegal: no sandwich",
+        check: |s| {
+            let chars = s.chars().collect::<Vec<char>>();
+            chars
+                .windows(3)
+                .any(|w| w[0] == w[2] && w[0] != w[1] && w.iter().all(|c| c.is_alphabetic()))
+        },
+    },
+    Rule {
+        name: "unicode_range",
+        status: StatusCode::RANGE_NOT_SATISFIABLE,
+        reason: "outranged",
+        check: |s| s.chars().any(|c| ('\u{2980}'..='\u{2BFF}').contains(&c)),
+    },
+    Rule {
+        name: "emoji",
+        status: StatusCode::UPGRADE_REQUIRED,
+        reason: "😳",
+        check: |s| s.chars().any(is_emoji),
+    },
+    Rule {
+        name: "sha256",
+        status: StatusCode::IM_A_TEAPOT,
+        reason: "not a coffee brewer",
+        check: |s| format!("{:x}", Sha256::digest(s.as_bytes())).ends_with('a'),
+    },
+];
+
+/// Sum of the maximal runs of digits, saturating on runs too long to parse
+fn integer_sum(s: &str) -> u128 {
+    s.split(|c: char| !c.is_ascii_digit())
+        .filter(|run| !run.is_empty())
+        .map(|run| run.parse::<u128>().unwrap_or(u128::MAX))
+        .fold(0, u128::saturating_add)
+}
+
+/// j, o and y in this order, with no j or o left after the y
+fn is_joyful(s: &str) -> bool {
+    let (Some(j), Some(o), Some(y)) = (s.find('j'), s.rfind('o'), s.rfind('y')) else {
+        return false;
+    };
+    j < o && o < y && !s[y..].contains(['j', 'o'])
+}
+
+/// The main emoji blocks, modifiers and joiners alone don't count
+fn is_emoji(c: char) -> bool {
+    matches!(
+        c as u32,
+        0x1F300..=0x1F5FF
+            | 0x1F600..=0x1F64F
+            | 0x1F680..=0x1F6FF
+            | 0x1F900..=0x1F9FF
+            | 0x1FA70..=0x1FAFF
+            | 0x2600..=0x26FF
+            | 0x2700..=0x27BF
+    )
+}
+
+/// Every failing rule, the first one decides the status code
+fn judge(rules: &[Rule], input: &str, success: &str) -> (StatusCode, Verdict) {
+    let failed = rules
+        .iter()
+        .filter(|r| !(r.check)(input))
+        .collect::<Vec<&Rule>>();
+
+    match failed.first() {
+        None => (
+            StatusCode::OK,
+            Verdict {
+                result: "nice".to_string(),
+                reason: Some(success.to_string()).filter(|s| !s.is_empty()),
+                failures: vec![],
+            },
+        ),
+        Some(first) => (
+            first.status,
+            Verdict {
+                result: "naughty".to_string(),
+                reason: Some(first.reason.to_string()),
+                failures: failed
+                    .iter()
+                    .map(|r| Failure {
+                        rule: r.name.to_string(),
+                        reason: r.reason.to_string(),
+                    })
+                    .collect(),
+            },
+        ),
+    }
+}
+
+pub async fn nice(Json(body): Json<Input>) -> impl IntoResponse {
+    let (status, verdict) = judge(NICE_RULES, &body.input, "");
+    (status, Json(verdict))
+}
+
+pub async fn game(Json(body): Json<Input>) -> impl IntoResponse {
+    let (status, verdict) = judge(GAME_RULES, &body.input, "that's a nice password");
+    (status, Json(verdict))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, http::Request, routing::post, Router};
+    use http_body_util::BodyExt;
+    use tower::ServiceExt;
+
+    const NICE_PASSWORD: &str = "2000.23.A jo AbA y ⦄ 😀";
+
+    fn create_test_app() -> Router {
+        Router::new()
+            .route("/validate/nice", post(nice))
+            .route("/validate/game", post(game))
+    }
+
+    #[test]
+    fn test_nice_rules() {
+        let cases: &[(&str, StatusCode, &[&str])] = &[
+            ("hello there", StatusCode::OK, &[]),
+            (
+                "abcd",
+                StatusCode::BAD_REQUEST,
+                &["vowels", "double_letter", "forbidden_substrings"],
+            ),
+            ("aeiouu", StatusCode::OK, &[]),
+            ("aeiou", StatusCode::BAD_REQUEST, &["double_letter"]),
+            (
+                "eeyore xy",
+                StatusCode::BAD_REQUEST,
+                &["forbidden_substrings"],
+            ),
+            ("11 aei", StatusCode::BAD_REQUEST, &["double_letter"]),
+            ("AAEI", StatusCode::OK, &[]),
+            ("", StatusCode::BAD_REQUEST, &["vowels", "double_letter"]),
+        ];
+
+        for (input, status, failures) in cases {
+            let (got, verdict) = judge(NICE_RULES, input, "");
+            assert_eq!(got, *status, "{}", input);
+            let rules = verdict
+                .failures
+                .iter()
+                .map(|f| f.rule.as_str())
+                .collect::<Vec<&str>>();
+            assert_eq!(&rules, failures, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_game_first_failure() {
+        let cases: &[(&str, StatusCode, &str)] = &[
+            ("password", StatusCode::BAD_REQUEST, "more types of chars"),
+            ("Pass1", StatusCode::BAD_REQUEST, "8 chars"),
+            ("Password12", StatusCode::BAD_REQUEST, "55555"),
+            ("Password12345", StatusCode::BAD_REQUEST, "math is hard"),
+            (
+                "2000.23.A yoj",
+                StatusCode::NOT_ACCEPTABLE,
+                "not joyful enough",
+            ),
+            (
+                "2000.23.A joy",
+                StatusCode::UNAVAILABLE_FOR_LEGAL_REASONS,
+                "illegal: no sandwich",
+            ),
+            (
+                "2000.23.A jo ab y",
+                StatusCode::UNAVAILABLE_FOR_LEGAL_REASONS,
+                "illegal: no sandwich",
+            ),
+            (
+                "2000.23.A jo aba y",
+                StatusCode::RANGE_NOT_SATISFIABLE,
+                "outranged",
+            ),
+            ("2000.23.A jo aba y ⦄", StatusCode::UPGRADE_REQUIRED, "😳"),
+            (
+                "2000.23.A jo aba y ⦄ 😀",
+                StatusCode::IM_A_TEAPOT,
+                "not a coffee brewer",
+            ),
+            (NICE_PASSWORD, StatusCode::OK, "that's a nice password"),
+        ];
+
+        for (input, status, reason) in cases {
+            let (got, verdict) = judge(GAME_RULES, input, "that's a nice password");
+            assert_eq!(got, *status, "{}", input);
+            assert_eq!(verdict.reason.as_deref(), Some(*reason), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_integer_sum() {
+        let cases: &[(&str, u128)] = &[
+            ("", 0),
+            ("abc", 0),
+            ("2023", 2023),
+            ("2000.23", 2023),
+            ("1a2b3c", 6),
+            ("007", 7),
+            ("99999999999999999999999999999999999999999", u128::MAX),
+        ];
+        for (input, sum) in cases {
+            assert_eq!(integer_sum(input), *sum, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_joy() {
+        let cases: &[(&str, bool)] = &[
+            ("joy", true),
+            ("j o y", true),
+            ("jjoy", true),
+            ("yoj", false),
+            ("joyo", false),
+            ("joyj", false),
+            ("oy", false),
+            ("", false),
+        ];
+        for (input, joyful) in cases {
+            assert_eq!(is_joyful(input), *joyful, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_emoji() {
+        for c in ['😀', '🥶', '🚀', '☃', '✂'] {
+            assert!(is_emoji(c), "{}", c);
+        }
+        for c in ['a', '⦄', '\u{200D}', '\u{FE0F}'] {
+            assert!(!is_emoji(c), "{}", c);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_game_success() {
+        let response = create_test_app()
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/validate/game")
+                    .header("content-type", "application/json")
+                    .body(Body::from(
+                        serde_json::json!({ "input": NICE_PASSWORD }).to_string(),
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let verdict: Verdict = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            verdict,
+            Verdict {
+                result: "nice".to_string(),
+                reason: Some("that's a nice password".to_string()),
+                failures: vec![],
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_nice_failures() {
+        let response = create_test_app()
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/validate/nice")
+                    .header("content-type", "application/json")
+                    .body(Body::from(r#"{"input":"xylophone"}"#))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let verdict: Verdict = serde_json::from_slice(&body).unwrap();
+        assert_eq!(verdict.result, "naughty");
+        assert_eq!(
+            verdict.failures,
+            vec![
+                Failure {
+                    rule: "double_letter".to_string(),
+                    reason: "needs a letter twice in a row".to_string(),
+                },
+                Failure {
+                    rule: "forbidden_substrings".to_string(),
+                    reason: "contains ab, cd, pq or xy".to_string(),
+                },
+            ]
+        );
+    }
+}
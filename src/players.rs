@@ -0,0 +1,382 @@
+//! Registered players of the day 12 game, rated with Elo when a game between two of them ends
+
+use std::{collections::HashMap, sync::Arc};
+
+use axum::{
+    extract::{Query, State},
+    http::{Method, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use axum_extra::extract::{cookie::Cookie, CookieJar};
+use chrono::{DateTime, Utc};
+#[cfg(test)]
+use mockall::automock;
+use serde::{Deserialize, Serialize};
+use sqlx::{query, query_as, FromRow, PgPool};
+use uuid::Uuid;
+
+use crate::{
+    day_12::{BoardState, Team},
+    ordering,
+    validation::RouteSchema,
+};
+
+pub const PLAYER_COOKIE: &str = "player";
+const INITIAL_RATING: i32 = 1200;
+const K_FACTOR: f64 = 32.0;
+const LEADERBOARD_LIMIT: i64 = 10;
+const MAX_LEADERBOARD_LIMIT: i64 = 100;
+/// Rating changes shown per player on the leaderboard
+const HISTORY_LENGTH: i64 = 5;
+
+pub fn schemas() -> Vec<RouteSchema> {
+    vec![RouteSchema::new(
+        Method::POST,
+        "/12/players",
+        serde_json::json!({
+            "type": "object",
+            "required": ["name"],
+            "properties": {
+                "name": {
+                    "type": "string",
+                    "minLength": 1,
+                    "maxLength": 32,
+                    "pattern": "^\\S(.*\\S)?$"
+                }
+            }
+        }),
+    )]
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, FromRow)]
+pub struct Player {
+    pub name: String,
+    pub rating: i32,
+    pub games: i32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, FromRow)]
+pub struct RatingChange {
+    pub opponent: String,
+    /// `win`, `loss` or `tie`
+    pub outcome: String,
+    /// Rating after the game
+    pub rating: i32,
+    pub delta: i32,
+    #[serde(with = "crate::conventions::datetime")]
+    pub played_at: DateTime<Utc>,
+}
+
+#[derive(FromRow)]
+struct PlayerChange {
+    player: String,
+    #[sqlx(flatten)]
+    change: RatingChange,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Standing {
+    #[serde(flatten)]
+    pub player: Player,
+    /// Most recent games first
+    pub history: Vec<RatingChange>,
+}
+
+/// Finished game between two registered players
+#[derive(Debug, Clone, PartialEq)]
+pub struct RatedGame {
+    pub cookie: String,
+    pub milk: String,
+    /// `None` on a tie
+    pub winner: Option<Team>,
+}
+
+#[derive(Deserialize)]
+pub struct Registration {
+    name: String,
+}
+
+#[derive(Deserialize)]
+pub struct Leaderboard {
+    limit: Option<i64>,
+}
+
+/// New ratings of two players, `score` being 1 when the first won, 0.5 on a tie and 0 when they lost
+pub fn elo(a: i32, b: i32, score: f64) -> (i32, i32) {
+    let expected = 1.0 / (1.0 + 10f64.powf(f64::from(b - a) / 400.0));
+    let delta = (K_FACTOR * (score - expected)).round() as i32;
+    (a + delta, b - delta)
+}
+
+#[async_trait::async_trait]
+#[cfg_attr(test, automock)]
+pub trait PlayerRepository: Send + Sync + 'static {
+    /// Session token of the new player, `None` when the name is taken
+    async fn register(&self, name: String) -> Result<Option<Uuid>, sqlx::Error>;
+    async fn by_token(&self, token: Uuid) -> Result<Option<String>, sqlx::Error>;
+    async fn record(&self, game: RatedGame) -> Result<(), sqlx::Error>;
+    /// Highest ratings first, players that never finished a game are left out
+    async fn leaderboard(&self, limit: i64) -> Result<Vec<Standing>, sqlx::Error>;
+}
+
+pub struct PostgresPlayerRepository {
+    pool: PgPool,
+}
+
+impl PostgresPlayerRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl PlayerRepository for PostgresPlayerRepository {
+    async fn register(&self, name: String) -> Result<Option<Uuid>, sqlx::Error> {
+        query_as::<_, (Uuid,)>(
+            "INSERT INTO players (name, token) VALUES ($1, $2) \
+             ON CONFLICT (name) DO NOTHING RETURNING token",
+        )
+        .bind(name)
+        .bind(Uuid::new_v4())
+        .fetch_optional(&self.pool)
+        .await
+        .map(|token| token.map(|(t,)| t))
+    }
+
+    async fn by_token(&self, token: Uuid) -> Result<Option<String>, sqlx::Error> {
+        query_as::<_, (String,)>("SELECT name FROM players WHERE token = $1")
+            .bind(token)
+            .fetch_optional(&self.pool)
+            .await
+            .map(|name| name.map(|(n,)| n))
+    }
+
+    async fn record(&self, game: RatedGame) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        // locked in name order, two games ending together can't deadlock
+        let ratings: HashMap<String, i32> = query_as::<_, (String, i32)>(
+            "SELECT name, rating FROM players WHERE name = ANY($1) ORDER BY name FOR UPDATE",
+        )
+        .bind([game.cookie.clone(), game.milk.clone()])
+        .fetch_all(&mut *tx)
+        .await?
+        .into_iter()
+        .collect();
+        let (Some(&cookie), Some(&milk)) = (ratings.get(&game.cookie), ratings.get(&game.milk))
+        else {
+            return Err(sqlx::Error::RowNotFound);
+        };
+
+        let (score, cookie_outcome, milk_outcome) = match game.winner {
+            Some(Team::Cookie) => (1.0, "win", "loss"),
+            Some(Team::Milk) => (0.0, "loss", "win"),
+            None => (0.5, "tie", "tie"),
+        };
+        let (new_cookie, new_milk) = elo(cookie, milk, score);
+
+        for (player, opponent, outcome, before, after) in [
+            (&game.cookie, &game.milk, cookie_outcome, cookie, new_cookie),
+            (&game.milk, &game.cookie, milk_outcome, milk, new_milk),
+        ] {
+            query("UPDATE players SET rating = $2, games = games + 1 WHERE name = $1")
+                .bind(player)
+                .bind(after)
+                .execute(&mut *tx)
+                .await?;
+            query(
+                "INSERT INTO rating_changes (player, opponent, outcome, rating, delta) \
+                 VALUES ($1, $2, $3, $4, $5)",
+            )
+            .bind(player)
+            .bind(opponent)
+            .bind(outcome)
+            .bind(after)
+            .bind(after - before)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await
+    }
+
+    async fn leaderboard(&self, limit: i64) -> Result<Vec<Standing>, sqlx::Error> {
+        let players = query_as::<_, Player>(&format!(
+            "SELECT name, rating, games FROM players WHERE games > 0 \
+             ORDER BY {} LIMIT $1",
+            ordering::LEADERBOARD
+        ))
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let names: Vec<&str> = players.iter().map(|p| p.name.as_str()).collect();
+        let changes = query_as::<_, PlayerChange>(&format!(
+            "SELECT player, opponent, outcome, rating, delta, played_at FROM ( \
+                SELECT *, ROW_NUMBER() OVER (PARTITION BY player ORDER BY {order}) AS n \
+                FROM rating_changes WHERE player = ANY($1) \
+             ) recent WHERE n <= $2 ORDER BY {order}",
+            order = ordering::RATING_CHANGES
+        ))
+        .bind(names)
+        .bind(HISTORY_LENGTH)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut history: HashMap<String, Vec<RatingChange>> = HashMap::new();
+        for c in changes {
+            history.entry(c.player).or_default().push(c.change);
+        }
+        Ok(players
+            .into_iter()
+            .map(|player| Standing {
+                history: history.remove(&player.name).unwrap_or_default(),
+                player,
+            })
+            .collect())
+    }
+}
+
+/// Name of the registered player behind the request, if any
+pub(crate) async fn player(
+    players: &dyn PlayerRepository,
+    jar: &CookieJar,
+) -> Result<Option<String>, sqlx::Error> {
+    match jar
+        .get(PLAYER_COOKIE)
+        .and_then(|c| Uuid::parse_str(c.value()).ok())
+    {
+        Some(token) => players.by_token(token).await,
+        None => Ok(None),
+    }
+}
+
+/// Updates the ratings of a finished game, the move stands when it fails
+pub(crate) async fn rate(players: &dyn PlayerRepository, game: RatedGame) {
+    if let Err(e) = players.record(game).await {
+        tracing::warn!("failed to rate the game: {}", e);
+    }
+}
+
+pub async fn register(
+    State(state): State<BoardState>,
+    jar: CookieJar,
+    Json(Registration { name }): Json<Registration>,
+) -> impl IntoResponse {
+    match state.players.register(name.clone()).await {
+        Ok(Some(token)) => {
+            let cookie = Cookie::build((PLAYER_COOKIE, token.to_string()))
+                .path("/12")
+                .http_only(true);
+            Ok((
+                StatusCode::CREATED,
+                jar.add(cookie),
+                Json(Player {
+                    name,
+                    rating: INITIAL_RATING,
+                    games: 0,
+                }),
+            ))
+        }
+        Ok(None) => Err((StatusCode::CONFLICT, "".to_string())),
+        _ => Err((StatusCode::INTERNAL_SERVER_ERROR, "".to_string())),
+    }
+}
+
+pub async fn leaderboard(
+    State(state): State<BoardState>,
+    Query(leaderboard): Query<Leaderboard>,
+) -> impl IntoResponse {
+    let limit = leaderboard
+        .limit
+        .unwrap_or(LEADERBOARD_LIMIT)
+        .clamp(1, MAX_LEADERBOARD_LIMIT);
+    match state.players.leaderboard(limit).await {
+        Ok(standings) => Ok(Json(standings)),
+        _ => Err((StatusCode::INTERNAL_SERVER_ERROR, "".to_string())),
+    }
+}
+
+pub fn state_players(pool: PgPool) -> Arc<dyn PlayerRepository> {
+    Arc::new(PostgresPlayerRepository::new(pool))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::header;
+
+    use super::*;
+    use crate::{
+        board_feed::BoardFeed,
+        day_12::{arc_board, arc_random_board, MockGameResultRepository},
+        test_support::box_future,
+    };
+
+    fn board_state(players: MockPlayerRepository) -> BoardState {
+        BoardState {
+            board: arc_board(),
+            random_board: arc_random_board(),
+            results: Arc::new(MockGameResultRepository::new()),
+            feed: BoardFeed::default(),
+            players: Arc::new(players),
+        }
+    }
+
+    #[test]
+    fn test_elo() {
+        assert_eq!(elo(1200, 1200, 1.0), (1216, 1184));
+        assert_eq!(elo(1200, 1200, 0.5), (1200, 1200));
+        // an upset moves the ratings further than the expected result
+        assert_eq!(elo(1000, 1400, 1.0), (1029, 1371));
+        assert_eq!(elo(1400, 1000, 1.0), (1403, 997));
+    }
+
+    #[tokio::test]
+    async fn test_register() {
+        let token = Uuid::new_v4();
+        let mut players = MockPlayerRepository::new();
+        players
+            .expect_register()
+            .returning(move |name| box_future(Ok((name == "Santa").then_some(token))));
+
+        let response = register(
+            State(board_state(players)),
+            CookieJar::new(),
+            Json(Registration {
+                name: "Santa".to_string(),
+            }),
+        )
+        .await
+        .into_response();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        assert!(response.headers()[header::SET_COOKIE]
+            .to_str()
+            .unwrap()
+            .starts_with(&format!("{}={}", PLAYER_COOKIE, token)));
+    }
+
+    #[tokio::test]
+    async fn test_register_taken_name() {
+        let mut players = MockPlayerRepository::new();
+        players
+            .expect_register()
+            .returning(|_| box_future(Ok(None)));
+
+        let response = register(
+            State(board_state(players)),
+            CookieJar::new(),
+            Json(Registration {
+                name: "Grinch".to_string(),
+            }),
+        )
+        .await
+        .into_response();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+    }
+
+    #[tokio::test]
+    async fn test_player_ignores_invalid_token() {
+        let players = MockPlayerRepository::new();
+        let jar = CookieJar::new().add(Cookie::new(PLAYER_COOKIE, "not-a-token"));
+        assert_eq!(player(&players, &jar).await.unwrap(), None);
+    }
+}
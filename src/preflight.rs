@@ -0,0 +1,302 @@
+//! Declared size and type of request bodies, checked before anything reads them.
+//! Bodies sent without a `Content-Length` are cut at the same limit by `body_limit` as they
+//! are read.
+
+use std::{collections::HashMap, sync::Arc};
+
+use axum::{
+    extract::{MatchedPath, Request, State},
+    http::{header, HeaderMap, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use tower_http::limit::RequestBodyLimitLayer;
+
+use crate::{app_error::AppError, versioning::unversioned};
+
+/// What a route accepts as a body
+pub struct RouteBudget {
+    method: Method,
+    path: &'static str,
+    max_length: u64,
+    content_types: &'static [&'static str],
+}
+
+impl RouteBudget {
+    /// `content_types` are compared without their parameters, e.g. `charset`
+    pub fn new(
+        method: Method,
+        path: &'static str,
+        max_length: u64,
+        content_types: &'static [&'static str],
+    ) -> Self {
+        Self {
+            method,
+            path,
+            max_length,
+            content_types,
+        }
+    }
+}
+
+struct Budget {
+    max_length: u64,
+    content_types: &'static [&'static str],
+}
+
+/// Budgets looked up by method and route path
+#[derive(Clone, Default)]
+pub struct BudgetRegistry {
+    budgets: Arc<HashMap<(Method, String), Budget>>,
+}
+
+impl BudgetRegistry {
+    pub fn new(routes: impl IntoIterator<Item = RouteBudget>) -> Self {
+        let budgets = routes
+            .into_iter()
+            .map(|r| {
+                (
+                    (r.method, r.path.to_string()),
+                    Budget {
+                        max_length: r.max_length,
+                        content_types: r.content_types,
+                    },
+                )
+            })
+            .collect();
+        Self {
+            budgets: Arc::new(budgets),
+        }
+    }
+
+    /// Overrides the length allowed by route path, whatever the method
+    pub fn with_limits(self, limits: &HashMap<String, u64>) -> Self {
+        for path in limits.keys() {
+            if !self.budgets.keys().any(|(_, p)| p == path) {
+                tracing::warn!("ignoring body limit of {}: route without a budget", path);
+            }
+        }
+        let budgets = self
+            .budgets
+            .iter()
+            .map(|((method, path), budget)| {
+                (
+                    (method.clone(), path.clone()),
+                    Budget {
+                        max_length: limits.get(path).copied().unwrap_or(budget.max_length),
+                        content_types: budget.content_types,
+                    },
+                )
+            })
+            .collect();
+        Self {
+            budgets: Arc::new(budgets),
+        }
+    }
+
+    /// Layer of the route cutting its body at the budget while it's read, for the bodies whose
+    /// length isn't declared
+    ///
+    /// # Panics
+    ///
+    /// When the route has no budget
+    pub fn body_limit(&self, method: Method, path: &str) -> RequestBodyLimitLayer {
+        let budget = self
+            .budgets
+            .get(&(method, path.to_string()))
+            .unwrap_or_else(|| panic!("no body budget for {}", path));
+        RequestBodyLimitLayer::new(budget.max_length.try_into().unwrap_or(usize::MAX))
+    }
+}
+
+/// Media type without its parameters, lowercased
+fn essence(headers: &HeaderMap) -> Option<String> {
+    let content_type = headers.get(header::CONTENT_TYPE)?.to_str().ok()?;
+    let essence = content_type.split(';').next().unwrap_or_default();
+    Some(essence.trim().to_ascii_lowercase())
+}
+
+/// Answers a 413 for a declared length over the budget of the matched route, and a 415 for a
+/// content type it doesn't allow. The 413 of a body cut short further in, by `body_limit` or an
+/// extractor, gets the same error body.
+pub async fn preflight(
+    State(registry): State<BudgetRegistry>,
+    path: Option<MatchedPath>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(budget) = path.and_then(|p| {
+        registry.budgets.get(&(
+            request.method().clone(),
+            unversioned(p.as_str()).to_string(),
+        ))
+    }) else {
+        return next.run(request).await;
+    };
+
+    let length = match request.headers().get(header::CONTENT_LENGTH) {
+        Some(length) => match length.to_str().ok().and_then(|l| l.parse::<u64>().ok()) {
+            Some(length) => Some(length),
+            None => return (StatusCode::BAD_REQUEST, "".to_string()).into_response(),
+        },
+        None => None,
+    };
+    if length.is_some_and(|l| l > budget.max_length) {
+        return AppError::TooLarge(budget.max_length).into_response();
+    }
+
+    let allowed = essence(request.headers())
+        .is_some_and(|essence| budget.content_types.contains(&essence.as_str()));
+    if !allowed {
+        return (StatusCode::UNSUPPORTED_MEDIA_TYPE, "".to_string()).into_response();
+    }
+
+    let response = next.run(request).await;
+    let json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .is_some_and(|t| t.as_bytes().starts_with(b"application/json"));
+    if response.status() == StatusCode::PAYLOAD_TOO_LARGE && !json {
+        return AppError::TooLarge(budget.max_length).into_response();
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{
+        body::{Body, Bytes},
+        middleware,
+        routing::post,
+        Router,
+    };
+    use http_body_util::BodyExt;
+    use serde_json::Value;
+    use tower::ServiceExt;
+
+    fn create_test_registry() -> BudgetRegistry {
+        BudgetRegistry::new([RouteBudget::new(
+            Method::POST,
+            "/upload/:id",
+            8,
+            &["application/json", "application/toml"],
+        )])
+    }
+
+    fn create_test_app() -> Router {
+        create_app(create_test_registry())
+    }
+
+    fn create_app(registry: BudgetRegistry) -> Router {
+        let limit = registry.body_limit(Method::POST, "/upload/:id");
+        Router::new()
+            .route(
+                "/upload/:id",
+                post(|body: String| async move { body }).route_layer(limit),
+            )
+            .route("/free", post(|body: String| async move { body }))
+            .layer(middleware::from_fn_with_state(registry, preflight))
+    }
+
+    async fn post_status(uri: &str, content_type: Option<&str>, body: &'static str) -> StatusCode {
+        let mut request = Request::builder().method("POST").uri(uri);
+        if let Some(content_type) = content_type {
+            request = request.header(header::CONTENT_TYPE, content_type);
+        }
+        request = request.header(header::CONTENT_LENGTH, body.len());
+        create_test_app()
+            .oneshot(request.body(Body::from(body)).unwrap())
+            .await
+            .unwrap()
+            .status()
+    }
+
+    #[tokio::test]
+    async fn test_preflight() {
+        let json = Some("application/json");
+        assert_eq!(post_status("/upload/1", json, "{}").await, StatusCode::OK);
+        assert_eq!(
+            post_status(
+                "/upload/1",
+                Some("Application/TOML; charset=utf-8"),
+                "a = 1"
+            )
+            .await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            post_status("/upload/1", json, "[1, 2, 3, 4]").await,
+            StatusCode::PAYLOAD_TOO_LARGE
+        );
+        assert_eq!(
+            post_status("/upload/1", Some("text/plain"), "{}").await,
+            StatusCode::UNSUPPORTED_MEDIA_TYPE
+        );
+        assert_eq!(
+            post_status("/upload/1", None, "{}").await,
+            StatusCode::UNSUPPORTED_MEDIA_TYPE
+        );
+        // routes without a budget are left alone
+        assert_eq!(
+            post_status("/free", Some("text/plain"), "any length at all").await,
+            StatusCode::OK
+        );
+    }
+
+    #[tokio::test]
+    async fn test_invalid_content_length() {
+        let response = create_test_app()
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/upload/1")
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .header(header::CONTENT_LENGTH, "lots")
+                    .body(Body::from("{}"))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_undeclared_length() {
+        let chunks = ["[1, 2, ", "3, 4]"].map(|c| Ok::<_, std::io::Error>(Bytes::from(c)));
+        let response = create_test_app()
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/upload/1")
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .body(Body::from_stream(tokio_stream::iter(chunks)))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["errors"][0]["message"], "body over 8 bytes");
+    }
+
+    #[tokio::test]
+    async fn test_configured_limits() {
+        let registry =
+            create_test_registry().with_limits(&HashMap::from([("/upload/:id".to_string(), 64)]));
+        let response = create_app(registry)
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/upload/1")
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .header(header::CONTENT_LENGTH, 12)
+                    .body(Body::from("[1, 2, 3, 4]"))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
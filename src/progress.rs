@@ -0,0 +1,241 @@
+//! Challenge progress, uptime and build details at `/meta/progress`
+
+use std::sync::Arc;
+
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::{Method, StatusCode},
+    response::IntoResponse,
+    Json, Router,
+};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tower::ServiceExt;
+
+use crate::{day_12::GameResultRepository, day_19::QuoteRepository};
+
+/// A challenge day and an example request path for each of its tasks
+pub struct Day {
+    pub day: i8,
+    pub paths: &'static [&'static str],
+}
+
+/// Every challenge day the service has tasks for, probed against the router at startup
+pub const DAYS: &[Day] = &[
+    Day {
+        day: -1,
+        paths: &["/", "/-1/seek"],
+    },
+    Day {
+        day: 1,
+        paths: &["/1/slice"],
+    },
+    Day {
+        day: 2,
+        paths: &["/2/dest", "/2/key", "/2/v6/dest", "/2/v6/key"],
+    },
+    Day {
+        day: 5,
+        paths: &["/5/manifest"],
+    },
+    Day {
+        day: 9,
+        paths: &["/9/milk", "/9/refill"],
+    },
+    Day {
+        day: 11,
+        paths: &["/11/red_pixels"],
+    },
+    Day {
+        day: 12,
+        paths: &[
+            "/12/board",
+            "/12/reset",
+            "/12/place/cookie/1",
+            "/12/random-board",
+        ],
+    },
+    Day {
+        day: 16,
+        paths: &["/16/wrap", "/16/unwrap", "/16/decode"],
+    },
+    Day {
+        day: 19,
+        paths: &[
+            "/19/reset",
+            "/19/cite/0",
+            "/19/remove/0",
+            "/19/undo/0",
+            "/19/draft",
+            "/19/list",
+        ],
+    },
+    Day {
+        day: 23,
+        paths: &[
+            "/23/star",
+            "/23/present/red",
+            "/23/ornament/on/1",
+            "/23/lockfile",
+        ],
+    },
+    Day {
+        day: 24,
+        paths: &["/24/enqueue", "/24/dequeue", "/24/peek"],
+    },
+];
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DayProgress {
+    pub day: i8,
+    /// At least one task is routed
+    pub mounted: bool,
+    /// Every task is routed
+    pub complete: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct Counts {
+    quotes: i64,
+    games: i64,
+}
+
+#[derive(Debug, Serialize)]
+struct Progress {
+    days: Vec<DayProgress>,
+    #[serde(with = "crate::conventions::datetime")]
+    started_at: DateTime<Utc>,
+    uptime_seconds: i64,
+    version: &'static str,
+    commit: &'static str,
+    counts: Counts,
+}
+
+#[derive(Clone)]
+pub struct ProgressState {
+    pub days: Arc<Vec<DayProgress>>,
+    pub started_at: DateTime<Utc>,
+    pub quotes: Arc<dyn QuoteRepository>,
+    pub games: Arc<dyn GameResultRepository>,
+}
+
+/// Whether the router has a route for `path`. The method matches no route so no handler
+/// runs, a routed path answers 405 rather than 404.
+async fn routed(router: &Router, path: &str) -> bool {
+    let request = Request::builder()
+        .method(Method::from_bytes(b"PROBE").unwrap())
+        .uri(path)
+        .body(Body::empty())
+        .unwrap();
+    match router.clone().oneshot(request).await {
+        Ok(response) => response.status() != StatusCode::NOT_FOUND,
+        Err(e) => match e {},
+    }
+}
+
+/// Progress of every day in `days`, as routed by `router`
+pub async fn probe(router: &Router, days: &[Day]) -> Vec<DayProgress> {
+    let mut progress = Vec::new();
+    for day in days {
+        let mut routed_paths = 0;
+        for path in day.paths {
+            if routed(router, path).await {
+                routed_paths += 1;
+            }
+        }
+        progress.push(DayProgress {
+            day: day.day,
+            mounted: routed_paths > 0,
+            complete: routed_paths == day.paths.len(),
+        });
+    }
+    progress
+}
+
+pub async fn progress(State(state): State<ProgressState>) -> impl IntoResponse {
+    let quotes = state.quotes.count_matching(Default::default()).await;
+    let games = state.games.count().await;
+    let (Ok(quotes), Ok(games)) = (quotes, games) else {
+        return Err((StatusCode::INTERNAL_SERVER_ERROR, "".to_string()));
+    };
+
+    Ok(Json(Progress {
+        days: state.days.to_vec(),
+        started_at: state.started_at,
+        uptime_seconds: (Utc::now() - state.started_at).num_seconds(),
+        version: env!("CARGO_PKG_VERSION"),
+        commit: env!("GIT_COMMIT"),
+        counts: Counts { quotes, games },
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::box_future;
+    use crate::{day_12::MockGameResultRepository, day_19::MockQuoteRepository};
+    use axum::routing::{get, post};
+    use http_body_util::BodyExt;
+    use serde_json::Value;
+
+    const TEST_DAYS: &[Day] = &[
+        Day {
+            day: 2,
+            paths: &["/2/dest", "/2/key"],
+        },
+        Day {
+            day: 12,
+            paths: &["/12/board", "/12/place/cookie/1"],
+        },
+        Day {
+            day: 23,
+            paths: &["/23/star"],
+        },
+    ];
+
+    fn challenge_routes() -> Router {
+        Router::new()
+            .route("/2/dest", get(|| async { "dest" }))
+            .route("/2/key", get(|| async { "key" }))
+            .route("/12/place/:team/:column", post(|| async { "placed" }))
+    }
+
+    #[tokio::test]
+    async fn test_probe() {
+        let days = probe(&challenge_routes(), TEST_DAYS).await;
+        let summary = days
+            .iter()
+            .map(|d| (d.day, d.mounted, d.complete))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            [(2, true, true), (12, true, false), (23, false, false)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_progress() {
+        let mut quotes = MockQuoteRepository::new();
+        quotes
+            .expect_count_matching()
+            .returning(|_| box_future(Ok(3)));
+        let mut games = MockGameResultRepository::new();
+        games.expect_count().returning(|| box_future(Ok(2)));
+        let state = ProgressState {
+            days: Arc::new(probe(&challenge_routes(), TEST_DAYS).await),
+            started_at: Utc::now(),
+            quotes: Arc::new(quotes),
+            games: Arc::new(games),
+        };
+
+        let response = progress(State(state)).await.into_response();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let progress = serde_json::from_slice::<Value>(&body).unwrap();
+        assert_eq!(progress["days"][0]["complete"], true);
+        assert_eq!(progress["counts"]["quotes"], 3);
+        assert_eq!(progress["counts"]["games"], 2);
+        assert_eq!(progress["commit"], env!("GIT_COMMIT"));
+    }
+}
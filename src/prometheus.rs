@@ -0,0 +1,142 @@
+//! Metrics recorded with the `metrics` macros, served in the Prometheus text format at `/metrics`
+
+use std::{
+    sync::OnceLock,
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{MatchedPath, Request, State},
+    http::header,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use metrics_exporter_prometheus::{
+    Matcher, PrometheusBuilder, PrometheusHandle, PrometheusRecorder,
+};
+
+pub const HTTP_REQUESTS: &str = "http_requests_total";
+pub const HTTP_REQUEST_DURATION: &str = "http_request_duration_seconds";
+/// `/9/milk` requests turned down by the rate limiter
+pub const MILK_REJECTIONS: &str = "milk_rejections_total";
+/// Calls of the day 19 repository, by operation
+pub const DB_QUERIES: &str = "db_queries_total";
+pub const DB_QUERY_DURATION: &str = "db_query_duration_seconds";
+/// Requests slower than the latency budget of their route
+pub const SLO_BREACHES: &str = "slo_breaches_total";
+
+/// Buckets of every `_seconds` histogram, from a cached read to a slow upload
+const DURATION_BUCKETS: [f64; 11] = [
+    0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5,
+];
+/// Histograms are compacted this often, whether or not they are scraped
+const UPKEEP_INTERVAL: Duration = Duration::from_secs(5);
+const UNMATCHED: &str = "unmatched";
+
+static INSTALLED: OnceLock<Result<PrometheusHandle, String>> = OnceLock::new();
+
+pub fn recorder() -> PrometheusRecorder {
+    PrometheusBuilder::new()
+        .set_buckets_for_metric(Matcher::Suffix("_seconds".to_string()), &DURATION_BUCKETS)
+        .expect("non-empty buckets")
+        .build_recorder()
+}
+
+/// Makes the recorder the one of the whole process, its handle renders what it records.
+/// Later calls, e.g. from services built by several tests, share the first handle.
+pub fn install() -> Result<PrometheusHandle, String> {
+    INSTALLED
+        .get_or_init(|| {
+            let recorder = recorder();
+            let handle = recorder.handle();
+            metrics::set_global_recorder(recorder).map_err(|e| e.to_string())?;
+            Ok(handle)
+        })
+        .clone()
+}
+
+pub async fn upkeep(handle: PrometheusHandle) {
+    let mut interval = tokio::time::interval(UPKEEP_INTERVAL);
+    loop {
+        interval.tick().await;
+        handle.run_upkeep();
+    }
+}
+
+/// Counts the requests and times them, by route template rather than path
+pub async fn track_requests(request: Request, next: Next) -> Response {
+    let method = request.method().to_string();
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or(UNMATCHED.to_string(), |p| p.as_str().to_string());
+    let start = Instant::now();
+
+    let response = next.run(request).await;
+
+    let status = response.status().as_u16().to_string();
+    metrics::histogram!(HTTP_REQUEST_DURATION, "method" => method.clone(), "route" => route.clone())
+        .record(start.elapsed());
+    metrics::counter!(HTTP_REQUESTS, "method" => method, "route" => route, "status" => status)
+        .increment(1);
+    response
+}
+
+pub async fn render(State(handle): State<PrometheusHandle>) -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        handle.render(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, http::StatusCode, middleware, routing::get, Router};
+    use http_body_util::BodyExt;
+    use tower::ServiceExt;
+
+    async fn get_status(app: &Router, uri: &str) -> StatusCode {
+        app.clone()
+            .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+            .await
+            .unwrap()
+            .status()
+    }
+
+    #[tokio::test]
+    async fn test_metrics() {
+        let recorder = recorder();
+        let handle = recorder.handle();
+        // the test runtime is single threaded, every request is recorded by this recorder
+        let _guard = metrics::set_default_local_recorder(&recorder);
+
+        let app = Router::new()
+            .route("/2/dest", get(|| async { "10.0.0.1" }))
+            .layer(middleware::from_fn(track_requests))
+            .route("/metrics", get(render))
+            .with_state(handle);
+        assert_eq!(get_status(&app, "/2/dest?from=1").await, StatusCode::OK);
+        assert_eq!(get_status(&app, "/2/dest?from=2").await, StatusCode::OK);
+        metrics::counter!(MILK_REJECTIONS).increment(3);
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/metrics")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(
+            body.contains("http_requests_total{method=\"GET\",route=\"/2/dest\",status=\"200\"} 2")
+        );
+        assert!(body.contains(
+            "http_request_duration_seconds_bucket{method=\"GET\",route=\"/2/dest\",le=\"+Inf\"} 2"
+        ));
+        assert!(body.contains("milk_rejections_total 3"));
+    }
+}
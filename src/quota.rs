@@ -0,0 +1,298 @@
+use std::{sync::Arc, time::Duration};
+
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderMap, HeaderName, HeaderValue},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use chrono::{Days, NaiveDate, Utc};
+#[cfg(test)]
+use mockall::automock;
+use sqlx::{query, query_scalar, PgPool};
+
+use crate::{
+    app_error::AppError,
+    auth::Auth,
+    client_ip::ClientIp,
+    rate_limit::client_key,
+    settings::{QUOTES_DAILY_QUOTA, SETTINGS},
+};
+
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+const QUOTA_LIMIT: HeaderName = HeaderName::from_static("x-quota-limit");
+const QUOTA_REMAINING: HeaderName = HeaderName::from_static("x-quota-remaining");
+const QUOTA_RESET: HeaderName = HeaderName::from_static("x-quota-reset");
+
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+#[async_trait::async_trait]
+#[cfg_attr(test, automock)]
+pub trait QuotaRepository: Send + Sync + 'static {
+    /// Counts one more creation for the key, `None` when the quota is already used up
+    async fn consume(
+        &self,
+        key: String,
+        day: NaiveDate,
+        limit: i64,
+    ) -> Result<Option<i64>, sqlx::Error>;
+    /// Gives back a creation that did not happen
+    async fn refund(&self, key: String, day: NaiveDate) -> Result<(), sqlx::Error>;
+    /// Drops the counters of the days before the given one
+    async fn purge(&self, before: NaiveDate) -> Result<u64, sqlx::Error>;
+}
+
+pub struct PostgresQuotaRepository {
+    pool: PgPool,
+}
+
+impl PostgresQuotaRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl QuotaRepository for PostgresQuotaRepository {
+    async fn consume(
+        &self,
+        key: String,
+        day: NaiveDate,
+        limit: i64,
+    ) -> Result<Option<i64>, sqlx::Error> {
+        // the WHERE clause leaves the row untouched, and returns nothing, once the limit is hit
+        query_scalar::<_, i64>(
+            "INSERT INTO quota_counters (api_key, day, created) VALUES ($1, $2, 1)
+             ON CONFLICT (api_key, day) DO UPDATE SET created = quota_counters.created + 1
+             WHERE quota_counters.created < $3
+             RETURNING created",
+        )
+        .bind(key)
+        .bind(day)
+        .bind(limit)
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    async fn refund(&self, key: String, day: NaiveDate) -> Result<(), sqlx::Error> {
+        query(
+            "UPDATE quota_counters SET created = created - 1
+             WHERE api_key = $1 AND day = $2 AND created > 0",
+        )
+        .bind(key)
+        .bind(day)
+        .execute(&self.pool)
+        .await
+        .map(|_| ())
+    }
+
+    async fn purge(&self, before: NaiveDate) -> Result<u64, sqlx::Error> {
+        query("DELETE FROM quota_counters WHERE day < $1")
+            .bind(before)
+            .execute(&self.pool)
+            .await
+            .map(|r| r.rows_affected())
+    }
+}
+
+#[derive(Clone)]
+pub struct QuotaState {
+    pub repository: Arc<dyn QuotaRepository>,
+    pub daily_limit: i64,
+    /// Tells the configured API keys from made up ones
+    pub auth: Auth,
+}
+
+pub fn state_quota_repository(pool: PgPool) -> Arc<dyn QuotaRepository> {
+    Arc::new(PostgresQuotaRepository::new(pool))
+}
+
+/// Limits the quotes each API key can create per day. Requests without a configured key are
+/// counted against their client address, a new made up key per request would escape the quota.
+pub async fn enforce_quote_quota(
+    State(state): State<QuotaState>,
+    ClientIp(ip): ClientIp,
+    request: Request,
+    next: Next,
+) -> Response {
+    let key = state
+        .auth
+        .known_api_key(request.headers())
+        .unwrap_or_else(|| format!("ip:{}", client_key(ip)));
+
+    // the configured quota can be overridden at runtime
+    let daily_limit = SETTINGS.get_or(QUOTES_DAILY_QUOTA, state.daily_limit);
+    let now = Utc::now();
+    let today = now.date_naive();
+    let reset = today
+        .checked_add_days(Days::new(1))
+        .and_then(|d| d.and_hms_opt(0, 0, 0))
+        .map(|midnight| (midnight - now.naive_utc()).num_seconds())
+        .unwrap_or(0);
+
+    let used = match state
+        .repository
+        .consume(key.clone(), today, daily_limit)
+        .await
+    {
+        Ok(Some(used)) => used,
+        Ok(None) => {
+            let mut response = AppError::RateLimited.into_response();
+            quota_headers(response.headers_mut(), daily_limit, 0, reset);
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(reset));
+            return response;
+        }
+        // a soft quota shouldn't take quote creation down with it
+        Err(e) => {
+            tracing::warn!("quota check failed: {}", e);
+            return next.run(request).await;
+        }
+    };
+
+    let mut response = next.run(request).await;
+    if !response.status().is_success() {
+        if let Err(e) = state.repository.refund(key, today).await {
+            tracing::warn!("quota refund failed: {}", e);
+        }
+        return response;
+    }
+
+    let remaining = (daily_limit - used).max(0);
+    quota_headers(response.headers_mut(), daily_limit, remaining, reset);
+    response
+}
+
+fn quota_headers(headers: &mut HeaderMap, limit: i64, remaining: i64, reset: i64) {
+    headers.insert(QUOTA_LIMIT, HeaderValue::from(limit));
+    headers.insert(QUOTA_REMAINING, HeaderValue::from(remaining));
+    headers.insert(QUOTA_RESET, HeaderValue::from(reset));
+}
+
+/// Periodically drops the counters of past days, quotas start over every day
+pub async fn cleanup(repository: Arc<dyn QuotaRepository>) {
+    let mut interval = tokio::time::interval(CLEANUP_INTERVAL);
+    loop {
+        interval.tick().await;
+        if let Err(e) = repository.purge(Utc::now().date_naive()).await {
+            tracing::warn!("quota cleanup failed: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::box_future;
+    use crate::{auth::Role, config::Config};
+    use axum::{
+        body::Body, extract::connect_info::MockConnectInfo, http::StatusCode, middleware,
+        routing::post, Router,
+    };
+    use mockall::predicate::eq;
+    use std::{collections::HashMap, net::SocketAddr};
+    use tower::ServiceExt;
+
+    fn create_test_app(repository: MockQuotaRepository, status: StatusCode) -> Router {
+        let config = Config {
+            api_keys: HashMap::from([("santa".to_string(), Role::Editor)]),
+            ..Config::default()
+        };
+        let state = QuotaState {
+            repository: Arc::new(repository),
+            daily_limit: 5,
+            auth: Auth::new(&config),
+        };
+        Router::new()
+            .route("/19/draft", post(move || async move { status }))
+            .route_layer(middleware::from_fn_with_state(state, enforce_quote_quota))
+            .layer(MockConnectInfo(SocketAddr::from(([10, 0, 0, 1], 4000))))
+    }
+
+    fn draft(key: Option<&str>) -> axum::http::Request<Body> {
+        let mut builder = axum::http::Request::builder()
+            .method("POST")
+            .uri("/19/draft");
+        if let Some(k) = key {
+            builder = builder.header(API_KEY_HEADER, k);
+        }
+        builder.body(Body::empty()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_without_key_counts_the_address() {
+        let mut mock = MockQuotaRepository::new();
+        mock.expect_consume()
+            .withf(|key, _, _| key == "ip:10.0.0.1")
+            .returning(|_, _, _| box_future(Ok(Some(1))));
+
+        let app = create_test_app(mock, StatusCode::CREATED);
+
+        let response = app.oneshot(draft(None)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        assert_eq!(response.headers()[QUOTA_REMAINING], "4");
+    }
+
+    #[tokio::test]
+    async fn test_unknown_key_counts_the_address() {
+        let mut mock = MockQuotaRepository::new();
+        mock.expect_consume()
+            .withf(|key, _, _| key == "ip:10.0.0.1")
+            .times(1)
+            .returning(|_, _, _| box_future(Ok(None)));
+
+        let app = create_test_app(mock, StatusCode::CREATED);
+
+        let response = app.oneshot(draft(Some("made-up"))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[tokio::test]
+    async fn test_within_quota() {
+        let mut mock = MockQuotaRepository::new();
+        mock.expect_consume()
+            .withf(|key, _, limit| key == "santa" && *limit == 5)
+            .returning(|_, _, _| box_future(Ok(Some(2))));
+
+        let app = create_test_app(mock, StatusCode::CREATED);
+
+        let response = app.oneshot(draft(Some("santa"))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        assert_eq!(response.headers()[QUOTA_LIMIT], "5");
+        assert_eq!(response.headers()[QUOTA_REMAINING], "3");
+        assert!(response.headers().contains_key(QUOTA_RESET));
+    }
+
+    #[tokio::test]
+    async fn test_quota_exceeded() {
+        let mut mock = MockQuotaRepository::new();
+        mock.expect_consume()
+            .returning(|_, _, _| box_future(Ok(None)));
+
+        let app = create_test_app(mock, StatusCode::CREATED);
+
+        let response = app.oneshot(draft(Some("santa"))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[QUOTA_REMAINING], "0");
+        assert!(response.headers().contains_key(header::RETRY_AFTER));
+    }
+
+    #[tokio::test]
+    async fn test_failed_creation_is_refunded() {
+        let mut mock = MockQuotaRepository::new();
+        mock.expect_consume()
+            .returning(|_, _, _| box_future(Ok(Some(1))));
+        mock.expect_refund()
+            .with(eq("santa".to_string()), mockall::predicate::always())
+            .times(1)
+            .returning(|_, _| box_future(Ok(())));
+
+        let app = create_test_app(mock, StatusCode::NOT_FOUND);
+
+        let response = app.oneshot(draft(Some("santa"))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert!(response.headers().get(QUOTA_LIMIT).is_none());
+    }
+}
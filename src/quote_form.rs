@@ -0,0 +1,241 @@
+//! Form of the day 23 page submitting quotes, rendered server-side and driven by htmx
+
+use axum::{
+    extract::State,
+    response::{Html, IntoResponse},
+    Form,
+};
+use serde::Deserialize;
+
+use crate::{
+    day_19::{create_quote, DbState, DraftError, NewQuote, QuoteStatus},
+    day_23::escape_string,
+};
+
+/// Fields as typed in, missing ones are reported like empty ones
+#[derive(Debug, Default, Deserialize)]
+pub struct QuoteForm {
+    #[serde(default)]
+    author: String,
+    #[serde(default)]
+    quote: String,
+}
+
+/// Messages shown under the fields, or above the form for the whole submission
+#[derive(Debug, Default, PartialEq)]
+struct Errors {
+    author: Option<String>,
+    quote: Option<String>,
+    form: Option<String>,
+}
+
+impl Errors {
+    fn check(form: &QuoteForm) -> Self {
+        let required = |value: &str, field: &str| {
+            value
+                .trim()
+                .is_empty()
+                .then(|| format!("{} is required", field))
+        };
+        Self {
+            author: required(&form.author, "Author"),
+            quote: required(&form.quote, "Quote"),
+            form: None,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+fn field(name: &str, label: &str, value: &str, error: Option<&str>) -> String {
+    let error = match error {
+        Some(e) => format!("<span class=\"error\">{}</span>", escape_string(e)),
+        None => "".to_string(),
+    };
+    format!(
+        "<label>{label} <input name=\"{name}\" value=\"{value}\"{invalid}></label>{error}",
+        label = label,
+        name = name,
+        value = escape_string(value),
+        invalid = if error.is_empty() {
+            ""
+        } else {
+            " aria-invalid=\"true\""
+        },
+        error = error,
+    )
+}
+
+/// The form, replacing itself with whatever the submission gives back
+fn render(form: &QuoteForm, errors: &Errors, notice: Option<&str>) -> String {
+    let banner = match (&errors.form, notice) {
+        (Some(e), _) => format!("<p class=\"error\">{}</p>", escape_string(e)),
+        (None, Some(n)) => format!("<p class=\"notice\">{}</p>", escape_string(n)),
+        _ => "".to_string(),
+    };
+    format!(
+        "<form id=\"quote-form\" hx-post=\"/23/quote-form\" hx-swap=\"outerHTML\">{}{}{}<button type=\"submit\">Submit quote</button></form>",
+        banner,
+        field("author", "Author", &form.author, errors.author.as_deref()),
+        field("quote", "Quote", &form.quote, errors.quote.as_deref()),
+    )
+}
+
+pub async fn quote_form() -> impl IntoResponse {
+    Html(render(&QuoteForm::default(), &Errors::default(), None))
+}
+
+/// Creates the quote through moderation like `/19/draft`. Errors are answered with a 200 as well,
+/// since htmx doesn't swap in error responses.
+pub async fn submit_quote(
+    State(state): State<DbState>,
+    Form(form): Form<QuoteForm>,
+) -> impl IntoResponse {
+    let mut errors = Errors::check(&form);
+    if !errors.is_empty() {
+        return Html(render(&form, &errors, None));
+    }
+
+    let new_quote = NewQuote {
+        author: form.author.trim().to_string(),
+        quote: form.quote.trim().to_string(),
+        publish_at: None,
+    };
+    match create_quote(&state, new_quote).await {
+        Ok(quote) => {
+            let notice = match quote.status {
+                QuoteStatus::PendingReview => "Thanks, your quote will show once reviewed",
+                _ => "Thanks, your quote was added",
+            };
+            Html(render(&QuoteForm::default(), &errors, Some(notice)))
+        }
+        Err(DraftError::Rejected(reason)) => {
+            errors.form = Some(format!("The quote was refused: {}", reason));
+            Html(render(&form, &errors, None))
+        }
+        Err(DraftError::Failed(e)) => {
+            tracing::warn!("failed to create a quote from the form: {}", e);
+            errors.form = Some("The quote couldn't be saved, please try again".to_string());
+            Html(render(&form, &errors, None))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{
+        day_19::{state_tokens, MockQuoteRepository, Quote, PAGE_SIZE},
+        moderation::WordListModerator,
+        players::MockPlayerRepository,
+        test_support::box_future,
+    };
+    use axum::{
+        body::Body,
+        http::{header, Request, StatusCode},
+        routing::get,
+        Router,
+    };
+    use chrono::Utc;
+    use http_body_util::BodyExt;
+    use mockall::predicate::eq;
+    use tower::ServiceExt;
+    use uuid::Uuid;
+
+    fn create_test_app(repository: MockQuoteRepository) -> Router {
+        Router::new()
+            .route("/23/quote-form", get(quote_form).post(submit_quote))
+            .with_state(DbState {
+                repository: Arc::new(repository),
+                tokens: state_tokens(),
+                moderator: Arc::new(WordListModerator::new(&["grinch".to_string()])),
+                page_size: PAGE_SIZE,
+                players: Arc::new(MockPlayerRepository::new()),
+            })
+    }
+
+    async fn submit(repository: MockQuoteRepository, form: &str) -> String {
+        let response = create_test_app(repository)
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/23/quote-form")
+                    .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+                    .body(Body::from(form.to_string()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        String::from_utf8(bytes.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_empty_form() {
+        let response = create_test_app(MockQuoteRepository::new())
+            .oneshot(
+                Request::builder()
+                    .uri("/23/quote-form")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        let html = String::from_utf8(bytes.to_vec()).unwrap();
+        assert!(html.contains("hx-post=\"/23/quote-form\""));
+        assert!(!html.contains("class=\"error\""));
+    }
+
+    #[tokio::test]
+    async fn test_required_fields() {
+        // the mock has no expectations, nothing may be created
+        let html = submit(MockQuoteRepository::new(), "author=%3Cb%3ESanta&quote=+").await;
+        assert!(html.contains("Quote is required"));
+        assert!(!html.contains("Author is required"));
+        // what was typed is kept, escaped
+        assert!(html.contains("value=\"&lt;b&gt;Santa\""));
+        assert!(html.contains("aria-invalid=\"true\""));
+    }
+
+    #[tokio::test]
+    async fn test_quote_created() {
+        let mut mock = MockQuoteRepository::new();
+        mock.expect_create()
+            .with(eq(NewQuote {
+                author: "Santa".to_string(),
+                quote: "Ho ho ho".to_string(),
+                publish_at: None,
+            }))
+            .times(1)
+            .returning(|q| {
+                box_future(Ok(Quote {
+                    id: Uuid::new_v4(),
+                    author: q.author,
+                    quote: q.quote,
+                    created_at: Utc::now(),
+                    version: 1,
+                    likes: 0,
+                    publish_at: None,
+                    status: QuoteStatus::Published,
+                }))
+            });
+
+        let html = submit(mock, "author=+Santa+&quote=Ho+ho+ho").await;
+        assert!(html.contains("Thanks, your quote was added"));
+        // cleared for the next quote
+        assert!(html.contains("name=\"author\" value=\"\""));
+    }
+
+    #[tokio::test]
+    async fn test_quote_refused() {
+        let html = submit(MockQuoteRepository::new(), "author=Grinch&quote=Bah").await;
+        assert!(html.contains("The quote was refused: contains the denied word grinch"));
+        assert!(html.contains("value=\"Grinch\""));
+    }
+}
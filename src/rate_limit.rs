@@ -0,0 +1,310 @@
+//! Rate limits per client address, router-wide so that a single client can't starve the others,
+//! or on a route where a script could flood shared state. Each address gets a token bucket,
+//! refilled continuously up to its burst. An IPv6 client gets one bucket for its whole /64, the
+//! block a single host is usually handed.
+
+use std::{
+    collections::HashMap,
+    net::{IpAddr, Ipv6Addr},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{FromRequestParts, Request, State},
+    http::{header, HeaderValue},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::{app_error::AppError, client_ip::ClientIp, versioning::unversioned};
+
+/// Addresses tracked at most, new ones wait for a sweep past this
+const MAX_CLIENTS: usize = 100_000;
+/// How often the addresses whose bucket is full again are forgotten
+const SWEEP_INTERVAL: Duration = Duration::from_secs(30);
+/// Probes of the platform, answered whatever the rate of their address
+const EXEMPT: &[&str] = &["/health", "/ready", "/metrics"];
+
+/// Requests allowed per address
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ClientQuota {
+    /// Sustained rate
+    pub per_second: u32,
+    /// Requests allowed at once after a quiet period
+    pub burst: u32,
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+#[derive(Clone)]
+pub struct ClientRateLimit {
+    /// Tokens added per second
+    rate: f64,
+    burst: f64,
+    capacity: usize,
+    buckets: Arc<Mutex<HashMap<IpAddr, Bucket>>>,
+}
+
+/// Address the limits of a client are kept under, clients of an IPv6 /64 counting as one
+pub fn client_key(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(v6) => match v6.to_ipv4_mapped() {
+            Some(v4) => IpAddr::V4(v4),
+            None => IpAddr::V6(Ipv6Addr::from(u128::from(v6) & !(u128::MAX >> 64))),
+        },
+        v4 => v4,
+    }
+}
+
+impl ClientRateLimit {
+    pub fn new(quota: ClientQuota) -> Self {
+        Self::with_rate(f64::from(quota.per_second), f64::from(quota.burst))
+    }
+
+    /// One request per `interval` and client, without any burst
+    pub fn every(interval: Duration) -> Self {
+        Self::with_rate(1.0 / interval.as_secs_f64(), 1.0)
+    }
+
+    fn with_rate(rate: f64, burst: f64) -> Self {
+        Self {
+            rate,
+            burst,
+            capacity: MAX_CLIENTS,
+            buckets: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    fn refilled(&self, bucket: &Bucket, now: Instant) -> f64 {
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        (bucket.tokens + elapsed * self.rate).min(self.burst)
+    }
+
+    /// Time to wait before the client may try again, `None` when the request is allowed
+    fn check(&self, client: IpAddr, now: Instant) -> Option<Duration> {
+        let client = client_key(client);
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() >= self.capacity && !buckets.contains_key(&client) {
+            return Some(SWEEP_INTERVAL);
+        }
+
+        let bucket = buckets.entry(client).or_insert(Bucket {
+            tokens: self.burst,
+            updated: now,
+        });
+        bucket.tokens = self.refilled(bucket, now);
+        bucket.updated = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            return None;
+        }
+        let missing = 1.0 - bucket.tokens;
+        Some(Duration::from_secs_f64(missing / self.rate))
+    }
+
+    /// Forgets the clients whose bucket is full again, they'd get a full one anyway
+    fn sweep_at(&self, now: Instant) {
+        let mut buckets = self.buckets.lock().unwrap();
+        buckets.retain(|_, bucket| self.refilled(bucket, now) < self.burst);
+    }
+
+    /// Sweeps the buckets periodically, off the request path
+    pub async fn sweep(self) {
+        let mut interval = tokio::time::interval(SWEEP_INTERVAL);
+        loop {
+            interval.tick().await;
+            self.sweep_at(Instant::now());
+        }
+    }
+}
+
+fn exempt(path: &str) -> bool {
+    EXEMPT.contains(&unversioned(path))
+}
+
+/// Answers a 429 with `Retry-After` to the clients over their quota. Requests whose address is
+/// unknown are let through rather than all sharing a bucket.
+pub async fn limit_clients(
+    State(limit): State<ClientRateLimit>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if exempt(request.uri().path()) {
+        return next.run(request).await;
+    }
+    let (mut parts, body) = request.into_parts();
+    let client = ClientIp::from_request_parts(&mut parts, &()).await;
+    let request = Request::from_parts(parts, body);
+    let Ok(ClientIp(client)) = client else {
+        return next.run(request).await;
+    };
+
+    match limit.check(client, Instant::now()) {
+        None => next.run(request).await,
+        Some(wait) => {
+            let mut response = AppError::RateLimited.into_response();
+            // Retry-After counts whole seconds, rounding down would invite an early retry
+            let seconds = wait.as_secs() + u64::from(wait.subsec_nanos() > 0);
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(seconds));
+            response
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use axum::{
+        body::Body, extract::connect_info::MockConnectInfo, http::StatusCode, middleware,
+        routing::get, Extension, Router,
+    };
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::client_ip::TrustedProxies;
+
+    const QUOTA: ClientQuota = ClientQuota {
+        per_second: 2,
+        burst: 3,
+    };
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_client_key() {
+        assert_eq!(client_key(ip("10.0.0.1")), ip("10.0.0.1"));
+        assert_eq!(client_key(ip("::ffff:10.0.0.1")), ip("10.0.0.1"));
+        assert_eq!(client_key(ip("2001:db8:1:2:aaaa::1")), ip("2001:db8:1:2::"));
+    }
+
+    #[test]
+    fn test_ipv6_prefix_shares_a_bucket() {
+        let limit = ClientRateLimit::new(ClientQuota {
+            per_second: 1,
+            burst: 1,
+        });
+        let start = Instant::now();
+        assert_eq!(limit.check(ip("2001:db8:1:2::1"), start), None);
+        assert!(limit.check(ip("2001:db8:1:2::ffff"), start).is_some());
+        assert_eq!(limit.check(ip("2001:db8:1:3::1"), start), None);
+    }
+
+    #[test]
+    fn test_capacity_and_sweep() {
+        let limit = ClientRateLimit {
+            capacity: 2,
+            ..ClientRateLimit::new(QUOTA)
+        };
+        let start = Instant::now();
+        assert_eq!(limit.check(ip("10.0.0.1"), start), None);
+        assert_eq!(limit.check(ip("10.0.0.2"), start), None);
+        assert_eq!(limit.check(ip("10.0.0.3"), start), Some(SWEEP_INTERVAL));
+        // clients already tracked carry on
+        assert_eq!(limit.check(ip("10.0.0.1"), start), None);
+
+        // a token spent two seconds ago is back, the bucket is full again
+        limit.sweep_at(start + Duration::from_secs(2));
+        assert_eq!(limit.buckets.lock().unwrap().len(), 0);
+        assert_eq!(
+            limit.check(ip("10.0.0.3"), start + Duration::from_secs(2)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_every() {
+        let limit = ClientRateLimit::every(Duration::from_secs(2));
+        let start = Instant::now();
+        assert_eq!(limit.check(ip("10.0.0.1"), start), None);
+        assert_eq!(
+            limit.check(ip("10.0.0.1"), start + Duration::from_secs(1)),
+            Some(Duration::from_secs(1))
+        );
+        assert_eq!(
+            limit.check(ip("10.0.0.1"), start + Duration::from_secs(2)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_check() {
+        let limit = ClientRateLimit::new(QUOTA);
+        let start = Instant::now();
+
+        for _ in 0..3 {
+            assert_eq!(limit.check(ip("10.0.0.1"), start), None);
+        }
+        assert_eq!(
+            limit.check(ip("10.0.0.1"), start),
+            Some(Duration::from_millis(500))
+        );
+        assert_eq!(limit.check(ip("10.0.0.2"), start), None);
+        // half a second refills a token at two per second
+        assert_eq!(
+            limit.check(ip("10.0.0.1"), start + Duration::from_millis(500)),
+            None
+        );
+        assert!(limit
+            .check(ip("10.0.0.1"), start + Duration::from_millis(500))
+            .is_some());
+    }
+
+    #[tokio::test]
+    async fn test_limit_layer() {
+        let app = Router::new()
+            .route("/9/milk", get(|| async { "Milk withdrawn\n" }))
+            .route("/health", get(|| async { "ok" }))
+            .layer(middleware::from_fn_with_state(
+                ClientRateLimit::new(ClientQuota {
+                    per_second: 1,
+                    burst: 1,
+                }),
+                limit_clients,
+            ))
+            .layer(Extension(TrustedProxies::new(vec!["10.0.0.0/8"
+                .parse()
+                .unwrap()])))
+            .layer(MockConnectInfo(SocketAddr::from(([10, 0, 0, 1], 8000))));
+        let request = |uri: &str, client: &str| {
+            Request::builder()
+                .uri(uri)
+                .header("x-forwarded-for", client)
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let response = app
+            .clone()
+            .oneshot(request("/9/milk", "1.2.3.4"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = app
+            .clone()
+            .oneshot(request("/9/milk", "1.2.3.4"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "1");
+
+        let response = app
+            .clone()
+            .oneshot(request("/9/milk", "5.6.7.8"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = app.oneshot(request("/health", "1.2.3.4")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,292 @@
+//! Bird feed: chat rooms over WebSocket, every message is broadcast to the whole room, sender included
+
+use std::{collections::BTreeMap, sync::Arc};
+
+use axum::{
+    extract::{
+        ws::{close_code, CloseFrame, Message, WebSocket, WebSocketUpgrade},
+        Path, Query, State,
+    },
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use tokio::sync::{
+    broadcast::{self, error::RecvError},
+    Mutex,
+};
+
+const ROOM_CAPACITY: usize = 64;
+const ANONYMOUS: &str = "anonymous";
+/// Longest chat message, a larger frame closes the socket
+const MAX_MESSAGE_SIZE: usize = 4 * 1024;
+/// Longest display name, in characters
+const MAX_USER_LENGTH: usize = 32;
+/// Most connections a room takes, a name joining from two sockets counts twice
+const MAX_MEMBERS: usize = 100;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    Join {
+        user: String,
+        participants: Vec<String>,
+    },
+    Leave {
+        user: String,
+        participants: Vec<String>,
+    },
+    Message {
+        user: String,
+        text: String,
+    },
+}
+
+struct Room {
+    events: broadcast::Sender<Event>,
+    /// Connections per user, the same name can join from several sockets
+    participants: BTreeMap<String, usize>,
+}
+
+impl Room {
+    fn participants(&self) -> Vec<String> {
+        self.participants.keys().cloned().collect()
+    }
+
+    fn is_full(&self) -> bool {
+        self.participants.values().sum::<usize>() >= MAX_MEMBERS
+    }
+}
+
+/// Open rooms by name, a room is dropped when its last participant leaves
+#[derive(Clone, Default)]
+pub struct RoomRegistry {
+    rooms: Arc<Mutex<BTreeMap<String, Room>>>,
+}
+
+#[derive(Deserialize)]
+pub struct JoinQuery {
+    user: Option<String>,
+}
+
+impl RoomRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Subscribes to the room before announcing the user, so that the join is its first event.
+    /// `None` when the room is full.
+    pub async fn join(
+        &self,
+        room: &str,
+        user: &str,
+    ) -> Option<(broadcast::Receiver<Event>, broadcast::Sender<Event>)> {
+        let mut rooms = self.rooms.lock().await;
+        let room = rooms.entry(room.to_string()).or_insert_with(|| Room {
+            events: broadcast::channel(ROOM_CAPACITY).0,
+            participants: BTreeMap::new(),
+        });
+        if room.is_full() {
+            return None;
+        }
+
+        let receiver = room.events.subscribe();
+        *room.participants.entry(user.to_string()).or_default() += 1;
+        let _ = room.events.send(Event::Join {
+            user: user.to_string(),
+            participants: room.participants(),
+        });
+
+        Some((receiver, room.events.clone()))
+    }
+
+    /// Whether a connection would be turned away, checked before upgrading it
+    pub async fn is_full(&self, room: &str) -> bool {
+        self.rooms.lock().await.get(room).is_some_and(Room::is_full)
+    }
+
+    pub async fn leave(&self, room_name: &str, user: &str) {
+        let mut rooms = self.rooms.lock().await;
+        let Some(room) = rooms.get_mut(room_name) else {
+            return;
+        };
+
+        if let Some(connections) = room.participants.get_mut(user) {
+            *connections -= 1;
+            if *connections == 0 {
+                room.participants.remove(user);
+            }
+        }
+
+        if room.participants.is_empty() {
+            rooms.remove(room_name);
+            return;
+        }
+        let _ = room.events.send(Event::Leave {
+            user: user.to_string(),
+            participants: room.participants(),
+        });
+    }
+
+    pub async fn participants(&self, room: &str) -> Vec<String> {
+        self.rooms
+            .lock()
+            .await
+            .get(room)
+            .map(Room::participants)
+            .unwrap_or_default()
+    }
+}
+
+pub async fn join(
+    ws: WebSocketUpgrade,
+    Path(room): Path<String>,
+    Query(query): Query<JoinQuery>,
+    State(registry): State<RoomRegistry>,
+) -> Response {
+    let user = query
+        .user
+        .filter(|u| !u.trim().is_empty())
+        .unwrap_or_else(|| ANONYMOUS.to_string());
+    if user.chars().count() > MAX_USER_LENGTH {
+        return (StatusCode::BAD_REQUEST, "".to_string()).into_response();
+    }
+    if registry.is_full(&room).await {
+        return (StatusCode::CONFLICT, "".to_string()).into_response();
+    }
+
+    ws.max_message_size(MAX_MESSAGE_SIZE)
+        .max_frame_size(MAX_MESSAGE_SIZE)
+        .on_upgrade(move |socket| session(socket, registry, room, user))
+}
+
+pub async fn participants(
+    Path(room): Path<String>,
+    State(registry): State<RoomRegistry>,
+) -> impl IntoResponse {
+    Json(registry.participants(&room).await)
+}
+
+async fn session(mut socket: WebSocket, registry: RoomRegistry, room: String, user: String) {
+    // the room may have filled up since the upgrade was accepted
+    let Some((mut events, sender)) = registry.join(&room, &user).await else {
+        let _ = socket
+            .send(Message::Close(Some(CloseFrame {
+                code: close_code::AGAIN,
+                reason: "room is full".into(),
+            })))
+            .await;
+        return;
+    };
+
+    loop {
+        tokio::select! {
+            incoming = socket.recv() => match incoming {
+                Some(Ok(Message::Text(text))) => {
+                    let _ = sender.send(Event::Message { user: user.clone(), text });
+                }
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                // pings are answered by axum, binary frames are not part of the protocol
+                _ => {}
+            },
+            event = events.recv() => match event {
+                Ok(event) => {
+                    let frame = serde_json::to_string(&event).unwrap();
+                    if socket.send(Message::Text(frame)).await.is_err() {
+                        break;
+                    }
+                }
+                // a slow reader misses some messages rather than stalling the room
+                Err(RecvError::Lagged(skipped)) => {
+                    tracing::warn!("{} missed {} events in room {}", user, skipped, room);
+                }
+                Err(RecvError::Closed) => break,
+            },
+        }
+    }
+
+    registry.leave(&room, &user).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_join_and_broadcast() {
+        let registry = RoomRegistry::new();
+        let (mut robin, sender) = registry.join("nest", "robin").await.unwrap();
+        let (mut wren, _) = registry.join("nest", "wren").await.unwrap();
+
+        assert_eq!(
+            robin.recv().await.unwrap(),
+            Event::Join {
+                user: "robin".to_string(),
+                participants: vec!["robin".to_string()],
+            }
+        );
+        assert_eq!(
+            robin.recv().await.unwrap(),
+            Event::Join {
+                user: "wren".to_string(),
+                participants: vec!["robin".to_string(), "wren".to_string()],
+            }
+        );
+
+        sender
+            .send(Event::Message {
+                user: "robin".to_string(),
+                text: "tweet".to_string(),
+            })
+            .unwrap();
+        assert!(matches!(wren.recv().await.unwrap(), Event::Join { .. }));
+        assert!(
+            matches!(wren.recv().await.unwrap(), Event::Message { text, .. } if text == "tweet")
+        );
+        assert!(matches!(robin.recv().await.unwrap(), Event::Message { .. }));
+    }
+
+    #[tokio::test]
+    async fn test_leave() {
+        let registry = RoomRegistry::new();
+        let (_robin, _) = registry.join("nest", "robin").await.unwrap();
+        let (mut wren, _) = registry.join("nest", "wren").await.unwrap();
+        let _ = registry.join("nest", "wren").await.unwrap();
+
+        registry.leave("nest", "wren").await;
+        assert_eq!(registry.participants("nest").await, vec!["robin", "wren"]);
+
+        registry.leave("nest", "robin").await;
+        let mut last = None;
+        while let Ok(event) = wren.try_recv() {
+            last = Some(event);
+        }
+        assert_eq!(
+            last,
+            Some(Event::Leave {
+                user: "robin".to_string(),
+                participants: vec!["wren".to_string()],
+            })
+        );
+
+        registry.leave("nest", "wren").await;
+        assert!(registry.participants("nest").await.is_empty());
+        assert!(registry.rooms.lock().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_full_room() {
+        let registry = RoomRegistry::new();
+        let mut joined = Vec::new();
+        for n in 0..MAX_MEMBERS {
+            joined.push(registry.join("nest", &format!("bird-{}", n)).await.unwrap());
+        }
+        assert!(registry.is_full("nest").await);
+        assert!(registry.join("nest", "robin").await.is_none());
+        assert!(!registry.is_full("perch").await);
+
+        registry.leave("nest", "bird-0").await;
+        assert!(registry.join("nest", "robin").await.is_some());
+    }
+}
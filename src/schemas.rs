@@ -0,0 +1,102 @@
+//! JSON Schemas of the public payloads, generated from their types so that they can't drift
+
+use axum::{extract::Path, http::StatusCode, Json};
+use schemars::{schema::RootSchema, schema_for};
+
+use crate::{
+    day_12::BoardView,
+    day_19::{NewQuote, Quote},
+    day_9::Milk,
+    validation::ValidationErrors,
+};
+
+/// Names served at `/schemas/:name.json`
+pub const NAMES: [&str; 5] = ["quote", "new_quote", "milk", "board", "errors"];
+
+pub fn schema(name: &str) -> Option<RootSchema> {
+    match name {
+        "quote" => Some(schema_for!(Quote)),
+        "new_quote" => Some(schema_for!(NewQuote)),
+        "milk" => Some(schema_for!(Milk)),
+        "board" => Some(schema_for!(BoardView)),
+        "errors" => Some(schema_for!(ValidationErrors)),
+        _ => None,
+    }
+}
+
+pub async fn serve(Path(file): Path<String>) -> Result<Json<RootSchema>, (StatusCode, String)> {
+    file.strip_suffix(".json")
+        .and_then(schema)
+        .map(Json)
+        .ok_or((StatusCode::NOT_FOUND, "".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, http::Request, routing::get, Router};
+    use http_body_util::BodyExt;
+    use serde_json::{json, Value};
+    use tower::ServiceExt;
+
+    async fn get_schema(uri: &str) -> (StatusCode, Option<Value>) {
+        let app = Router::new().route("/schemas/:file", get(serve));
+        let response = app
+            .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let status = response.status();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        (status, serde_json::from_slice(&body).ok())
+    }
+
+    #[tokio::test]
+    async fn test_serve() {
+        for name in NAMES {
+            let (status, schema) = get_schema(&format!("/schemas/{}.json", name)).await;
+            assert_eq!(status, StatusCode::OK, "{}", name);
+            assert!(schema.unwrap()["$schema"].is_string());
+        }
+
+        let (_, schema) = get_schema("/schemas/new_quote.json").await;
+        let schema = schema.unwrap();
+        assert_eq!(schema["required"], json!(["author", "quote"]));
+        assert!(schema["properties"]["publish_at"].is_object());
+
+        assert_eq!(get_schema("/schemas/milk").await.0, StatusCode::NOT_FOUND);
+        assert_eq!(
+            get_schema("/schemas/secrets.json").await.0,
+            StatusCode::NOT_FOUND
+        );
+    }
+
+    #[test]
+    fn test_schemas_accept_the_payloads() {
+        let validate = |name, instance: Value| {
+            let schema = serde_json::to_value(schema(name).unwrap()).unwrap();
+            jsonschema::validator_for(&schema)
+                .unwrap()
+                .is_valid(&instance)
+        };
+        assert!(validate("milk", json!({ "liters": 5.0 })));
+        assert!(!validate("milk", json!({ "cups": 5.0 })));
+        assert!(validate(
+            "quote",
+            serde_json::to_value(Quote {
+                id: uuid::Uuid::nil(),
+                author: "Santa".to_string(),
+                quote: "Ho ho ho".to_string(),
+                created_at: chrono::Utc::now(),
+                version: 1,
+                likes: 0,
+                publish_at: None,
+                status: Default::default(),
+            })
+            .unwrap()
+        ));
+        assert!(validate(
+            "errors",
+            json!({ "errors": [{ "path": "/quote", "message": "missing" }] })
+        ));
+    }
+}
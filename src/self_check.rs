@@ -0,0 +1,242 @@
+use core::fmt;
+use std::collections::HashMap;
+
+use jsonwebtoken::DecodingKey;
+use sqlx::{
+    migrate::{AppliedMigration, Migrate, Migrator},
+    query_scalar, PgPool,
+};
+
+use crate::{
+    config::Config,
+    day_16::{RSA_PEM, SUPER_SECRET},
+    keys::{self, KeyBackend},
+    migrations::MigrationMode,
+};
+
+/// Inconsistency found at boot, each one explains how to fix it
+#[derive(Debug, PartialEq)]
+pub enum CheckError {
+    Database(String),
+    DirtyMigration(i64),
+    MissingMigration(i64, String),
+    ChecksumMismatch(i64, String),
+    InvalidRsaKey(String),
+    EmptySecret,
+    DefaultSecret,
+    SigningKeys(String),
+}
+
+impl fmt::Display for CheckError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CheckError::Database(e) => write!(f, "cannot inspect the database: {}", e),
+            CheckError::DirtyMigration(v) => write!(
+                f,
+                "migration {} was left partially applied, fix the schema and delete its row from _sqlx_migrations",
+                v
+            ),
+            CheckError::MissingMigration(v, d) => {
+                write!(f, "migration {} ({}) is not applied, check the migration logs", v, d)
+            }
+            CheckError::ChecksumMismatch(v, d) => write!(
+                f,
+                "migration {} ({}) was edited after being applied, add a new migration instead",
+                v, d
+            ),
+            CheckError::InvalidRsaKey(e) => {
+                write!(f, "src/day_16/rsa.pem is not a valid RSA public key: {}", e)
+            }
+            CheckError::EmptySecret => write!(f, "GIFT_SECRET must not be empty"),
+            CheckError::DefaultSecret => write!(
+                f,
+                "GIFT_SECRET still has its default value, set it in Secrets.toml before deploying"
+            ),
+            CheckError::SigningKeys(e) => write!(f, "cannot load the day 16 keys: {}", e),
+        }
+    }
+}
+
+/// Runs every check, reporting all the problems at once rather than the first one
+pub async fn run(
+    pool: &PgPool,
+    migrator: &Migrator,
+    config: &Config,
+) -> Result<(), Vec<CheckError>> {
+    // migrations that don't run at startup may still be pending, they are reported by
+    // `/admin/migrations` instead
+    let expect_applied = config.migration_mode == MigrationMode::Startup;
+    let mut errors = check_database(pool, migrator, expect_applied).await;
+    errors.extend(check_rsa_key(RSA_PEM));
+    errors.extend(check_config(config));
+    errors.extend(
+        keys::state_keys(config)
+            .err()
+            .map(|e| CheckError::SigningKeys(e.to_string())),
+    );
+
+    match errors.is_empty() {
+        true => Ok(()),
+        false => Err(errors),
+    }
+}
+
+async fn check_database(
+    pool: &PgPool,
+    migrator: &Migrator,
+    expect_applied: bool,
+) -> Vec<CheckError> {
+    let mut conn = match pool.acquire().await {
+        Ok(c) => c,
+        Err(e) => return vec![CheckError::Database(e.to_string())],
+    };
+
+    // the table comes with the first run, which waits on a fresh database outside of startup mode
+    let has_table = query_scalar::<_, bool>("SELECT to_regclass('_sqlx_migrations') IS NOT NULL")
+        .fetch_one(&mut *conn)
+        .await;
+    match has_table {
+        Ok(true) => {}
+        Ok(false) => return check_applied(migrator, &[], expect_applied),
+        Err(e) => return vec![CheckError::Database(e.to_string())],
+    }
+
+    let mut errors = match conn.dirty_version().await {
+        Ok(Some(v)) => vec![CheckError::DirtyMigration(v)],
+        Ok(None) => vec![],
+        Err(e) => return vec![CheckError::Database(e.to_string())],
+    };
+
+    match conn.list_applied_migrations().await {
+        Ok(applied) => errors.extend(check_applied(migrator, &applied, expect_applied)),
+        Err(e) => errors.push(CheckError::Database(e.to_string())),
+    }
+
+    errors
+}
+
+/// Migrations left to run are only an error when they should have run already
+fn check_applied(
+    migrator: &Migrator,
+    applied: &[AppliedMigration],
+    expect_applied: bool,
+) -> Vec<CheckError> {
+    check_migrations(migrator, applied)
+        .into_iter()
+        .filter(|e| expect_applied || !matches!(e, CheckError::MissingMigration(..)))
+        .collect()
+}
+
+fn check_migrations(migrator: &Migrator, applied: &[AppliedMigration]) -> Vec<CheckError> {
+    let applied: HashMap<i64, &AppliedMigration> = applied.iter().map(|m| (m.version, m)).collect();
+
+    migrator
+        .iter()
+        .filter(|m| !m.migration_type.is_down_migration())
+        .filter_map(|m| match applied.get(&m.version) {
+            None => Some(CheckError::MissingMigration(
+                m.version,
+                m.description.to_string(),
+            )),
+            Some(a) if a.checksum != m.checksum => Some(CheckError::ChecksumMismatch(
+                m.version,
+                m.description.to_string(),
+            )),
+            _ => None,
+        })
+        .collect()
+}
+
+fn check_rsa_key(pem: &str) -> Option<CheckError> {
+    DecodingKey::from_rsa_pem(pem.as_bytes())
+        .err()
+        .map(|e| CheckError::InvalidRsaKey(e.to_string()))
+}
+
+fn check_config(config: &Config) -> Option<CheckError> {
+    // the other backends don't use GIFT_SECRET
+    if config.key_backend != KeyBackend::Memory {
+        return None;
+    }
+
+    if config.gift_secret.is_empty() {
+        return Some(CheckError::EmptySecret);
+    }
+
+    // the default secret is public, fine for local runs only
+    if config.production && config.gift_secret == SUPER_SECRET {
+        return Some(CheckError::DefaultSecret);
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use super::*;
+    use crate::MIGRATOR;
+
+    #[test]
+    fn test_migrations_all_applied() {
+        let applied: Vec<AppliedMigration> = MIGRATOR
+            .iter()
+            .map(|m| AppliedMigration {
+                version: m.version,
+                checksum: m.checksum.clone(),
+            })
+            .collect();
+
+        assert!(check_migrations(&MIGRATOR, &applied).is_empty());
+    }
+
+    #[test]
+    fn test_migrations_missing_and_edited() {
+        let applied = vec![AppliedMigration {
+            version: 1,
+            checksum: Cow::Owned(vec![0]),
+        }];
+
+        let errors = check_migrations(&MIGRATOR, &applied);
+        assert_eq!(errors.len(), MIGRATOR.iter().count());
+        assert_eq!(
+            errors[0],
+            CheckError::ChecksumMismatch(1, "init".to_string())
+        );
+        assert!(matches!(errors[1], CheckError::MissingMigration(2, _)));
+    }
+
+    #[test]
+    fn test_rsa_key() {
+        assert_eq!(check_rsa_key(RSA_PEM), None);
+        assert!(matches!(
+            check_rsa_key("not a key"),
+            Some(CheckError::InvalidRsaKey(_))
+        ));
+    }
+
+    #[test]
+    fn test_config_secret() {
+        assert_eq!(check_config(&Config::default()), None);
+
+        let production = Config {
+            production: true,
+            ..Config::default()
+        };
+        assert_eq!(check_config(&production), Some(CheckError::DefaultSecret));
+
+        let empty = Config {
+            gift_secret: "".to_string(),
+            ..Config::default()
+        };
+        assert_eq!(check_config(&empty), Some(CheckError::EmptySecret));
+
+        let external = Config {
+            production: true,
+            key_backend: KeyBackend::Kms,
+            ..Config::default()
+        };
+        assert_eq!(check_config(&external), None);
+    }
+}
@@ -0,0 +1,426 @@
+//! Runtime-tunable settings, versioned in Postgres and cached in memory
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, LazyLock},
+    time::Duration,
+};
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    middleware,
+    response::IntoResponse,
+    routing::get,
+    Json, Router,
+};
+use chrono::{DateTime, Utc};
+#[cfg(test)]
+use mockall::automock;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::{
+    postgres::PgListener, query, query_as, types::Json as Jsonb, FromRow, PgConnection, PgPool,
+};
+use tokio::sync::watch;
+
+use crate::{
+    auth::{require_admin, Auth},
+    theme::Theme,
+};
+
+pub const QUOTES_PAGE_SIZE: &str = "quotes.page_size";
+pub const QUOTES_DAILY_QUOTA: &str = "quotes.daily_quota";
+pub const MILK_MAX_TOKENS: &str = "milk.max_tokens";
+pub const MILK_REFILL_AMOUNT: &str = "milk.refill_amount";
+pub const MILK_REFILL_INTERVAL_SECS: &str = "milk.refill_interval_secs";
+/// Name of the theme used when a request doesn't pick one
+pub const THEME: &str = "theme";
+/// Prefix of the boolean feature flags
+pub const FLAG_PREFIX: &str = "flags.";
+
+const NUMERIC: [&str; 5] = [
+    QUOTES_PAGE_SIZE,
+    QUOTES_DAILY_QUOTA,
+    MILK_MAX_TOKENS,
+    MILK_REFILL_AMOUNT,
+    MILK_REFILL_INTERVAL_SECS,
+];
+const CHANNEL: &str = "settings";
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// Current settings of the process, kept in sync with the `settings` table
+pub static SETTINGS: LazyLock<Settings> = LazyLock::new(Settings::new);
+
+pub type Values = Arc<HashMap<String, Value>>;
+
+/// In-memory copy of the settings, subscribers are told about every reload
+#[derive(Clone)]
+pub struct Settings {
+    values: Arc<watch::Sender<Values>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Setting {
+    pub key: String,
+    pub version: i32,
+    pub value: Jsonb<Value>,
+    pub updated_at: DateTime<Utc>,
+    pub deleted_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Deserialize)]
+pub struct NewValue {
+    value: Value,
+}
+
+#[derive(Clone)]
+pub struct SettingsState {
+    pub repository: Arc<dyn SettingsRepository>,
+    pub cache: Settings,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Settings {
+    pub fn new() -> Self {
+        Self {
+            values: Arc::new(watch::channel(Values::default()).0),
+        }
+    }
+
+    pub fn replace(&self, settings: Vec<Setting>) {
+        let values = settings.into_iter().map(|s| (s.key, s.value.0)).collect();
+        self.values.send_replace(Arc::new(values));
+    }
+
+    pub fn get<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+        let value = self.values.borrow().get(key).cloned()?;
+        serde_json::from_value(value).ok()
+    }
+
+    /// Value of the setting, or the built-in default when it isn't set
+    pub fn get_or<T: DeserializeOwned>(&self, key: &str, default: T) -> T {
+        self.get(key).unwrap_or(default)
+    }
+
+    pub fn flag(&self, name: &str) -> bool {
+        self.get_or(&format!("{}{}", FLAG_PREFIX, name), false)
+    }
+
+    pub fn subscribe(&self) -> watch::Receiver<Values> {
+        self.values.subscribe()
+    }
+}
+
+/// Rejects unknown keys and values of the wrong type
+pub fn validate(key: &str, value: &Value) -> Result<(), String> {
+    if NUMERIC.contains(&key) {
+        return match value.as_u64() {
+            Some(n) if n > 0 => Ok(()),
+            _ => Err(format!("{} must be a positive integer", key)),
+        };
+    }
+
+    if key == THEME {
+        return match value.as_str().and_then(Theme::by_name) {
+            Some(_) => Ok(()),
+            _ => Err(format!("{} must name a known theme", key)),
+        };
+    }
+
+    match key.strip_prefix(FLAG_PREFIX) {
+        Some(name) if !name.is_empty() && value.is_boolean() => Ok(()),
+        Some(name) if !name.is_empty() => Err(format!("{} must be a boolean", key)),
+        _ => Err(format!("unknown setting {}", key)),
+    }
+}
+
+#[async_trait::async_trait]
+#[cfg_attr(test, automock)]
+pub trait SettingsRepository: Send + Sync + 'static {
+    /// Latest version of every setting that isn't deleted
+    async fn current(&self) -> Result<Vec<Setting>, sqlx::Error>;
+    /// Every version of a setting, newest first
+    async fn history(&self, key: String) -> Result<Vec<Setting>, sqlx::Error>;
+    async fn put(&self, key: String, value: Value) -> Result<Setting, sqlx::Error>;
+    async fn delete(&self, key: String) -> Result<Setting, sqlx::Error>;
+}
+
+/// Serializes the changes of a key until the transaction ends, two puts reading the same latest
+/// version would otherwise both write the next one
+async fn lock_key(conn: &mut PgConnection, key: &str) -> Result<(), sqlx::Error> {
+    query("SELECT pg_advisory_xact_lock(hashtext('settings:' || $1))")
+        .bind(key)
+        .execute(conn)
+        .await
+        .map(|_| ())
+}
+
+pub struct PostgresSettingsRepository {
+    pool: PgPool,
+}
+
+impl PostgresSettingsRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl SettingsRepository for PostgresSettingsRepository {
+    async fn current(&self) -> Result<Vec<Setting>, sqlx::Error> {
+        query_as::<_, Setting>(
+            "SELECT * FROM (
+                SELECT DISTINCT ON (key) * FROM settings ORDER BY key, version DESC
+             ) latest WHERE deleted_at IS NULL",
+        )
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    async fn history(&self, key: String) -> Result<Vec<Setting>, sqlx::Error> {
+        query_as::<_, Setting>("SELECT * FROM settings WHERE key = $1 ORDER BY version DESC")
+            .bind(key)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn put(&self, key: String, value: Value) -> Result<Setting, sqlx::Error> {
+        // other instances reload their cache when the transaction commits
+        let mut tx = self.pool.begin().await?;
+        lock_key(&mut tx, &key).await?;
+        let setting = query_as::<_, Setting>(
+            "INSERT INTO settings (key, version, value)
+             SELECT $1, COALESCE(MAX(version), 0) + 1, $2 FROM settings WHERE key = $1
+             RETURNING *",
+        )
+        .bind(&key)
+        .bind(Jsonb(value))
+        .fetch_one(&mut *tx)
+        .await?;
+
+        query("SELECT pg_notify($1, $2)")
+            .bind(CHANNEL)
+            .bind(&key)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(setting)
+    }
+
+    async fn delete(&self, key: String) -> Result<Setting, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        lock_key(&mut tx, &key).await?;
+        let setting = query_as::<_, Setting>(
+            "UPDATE settings SET deleted_at = now()
+             WHERE key = $1 AND deleted_at IS NULL
+               AND version = (SELECT MAX(version) FROM settings WHERE key = $1)
+             RETURNING *",
+        )
+        .bind(&key)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        query("SELECT pg_notify($1, $2)")
+            .bind(CHANNEL)
+            .bind(&key)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(setting)
+    }
+}
+
+pub async fn reload(repository: &dyn SettingsRepository, cache: &Settings) {
+    match repository.current().await {
+        Ok(settings) => cache.replace(settings),
+        Err(e) => tracing::warn!("settings reload failed: {}", e),
+    }
+}
+
+/// Reloads the cache whenever any instance changes a setting
+pub async fn listen(pool: PgPool, repository: Arc<dyn SettingsRepository>, cache: Settings) {
+    loop {
+        let mut listener = match PgListener::connect_with(&pool).await {
+            Ok(l) => l,
+            Err(e) => {
+                tracing::warn!("settings listener failed to connect: {}", e);
+                tokio::time::sleep(RECONNECT_DELAY).await;
+                continue;
+            }
+        };
+        if let Err(e) = listener.listen(CHANNEL).await {
+            tracing::warn!("settings listener failed to subscribe: {}", e);
+            tokio::time::sleep(RECONNECT_DELAY).await;
+            continue;
+        }
+
+        // changes made while disconnected were not notified
+        reload(repository.as_ref(), &cache).await;
+        while listener.recv().await.is_ok() {
+            reload(repository.as_ref(), &cache).await;
+        }
+        tracing::warn!("settings listener disconnected");
+    }
+}
+
+/// Routes of the settings API, meant to be nested under `/admin/settings`
+pub fn settings_router(state: SettingsState, auth: Auth) -> Router {
+    Router::new()
+        .route("/", get(current))
+        .route("/:key", get(history).put(put).delete(remove))
+        .with_state(state)
+        .layer(middleware::from_fn_with_state(auth, require_admin))
+}
+
+pub async fn current(State(state): State<SettingsState>) -> impl IntoResponse {
+    match state.repository.current().await {
+        Ok(settings) => Ok(Json(settings)),
+        _ => Err((StatusCode::INTERNAL_SERVER_ERROR, "".to_string())),
+    }
+}
+
+pub async fn history(
+    Path(key): Path<String>,
+    State(state): State<SettingsState>,
+) -> impl IntoResponse {
+    match state.repository.history(key).await {
+        Ok(versions) if versions.is_empty() => Err((StatusCode::NOT_FOUND, "".to_string())),
+        Ok(versions) => Ok(Json(versions)),
+        _ => Err((StatusCode::INTERNAL_SERVER_ERROR, "".to_string())),
+    }
+}
+
+pub async fn put(
+    Path(key): Path<String>,
+    State(state): State<SettingsState>,
+    Json(new_value): Json<NewValue>,
+) -> impl IntoResponse {
+    if let Err(e) = validate(&key, &new_value.value) {
+        return Err((StatusCode::UNPROCESSABLE_ENTITY, e));
+    }
+
+    match state.repository.put(key, new_value.value).await {
+        Ok(setting) => {
+            reload(state.repository.as_ref(), &state.cache).await;
+            Ok(Json(setting))
+        }
+        _ => Err((StatusCode::INTERNAL_SERVER_ERROR, "".to_string())),
+    }
+}
+
+pub async fn remove(
+    Path(key): Path<String>,
+    State(state): State<SettingsState>,
+) -> impl IntoResponse {
+    match state.repository.delete(key).await {
+        Ok(setting) => {
+            reload(state.repository.as_ref(), &state.cache).await;
+            Ok(Json(setting))
+        }
+        _ => Err((StatusCode::NOT_FOUND, "".to_string())),
+    }
+}
+
+pub fn state_settings_repository(pool: PgPool) -> Arc<dyn SettingsRepository> {
+    Arc::new(PostgresSettingsRepository::new(pool))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::Config, test_support::box_future};
+    use axum::{body::Body, http::Request};
+    use http_body_util::BodyExt;
+    use serde_json::json;
+    use tower::ServiceExt;
+
+    fn setting(key: &str, version: i32, value: Value) -> Setting {
+        Setting {
+            key: key.to_string(),
+            version,
+            value: Jsonb(value),
+            updated_at: Utc::now(),
+            deleted_at: None,
+        }
+    }
+
+    fn put_request(key: &str, body: Value) -> Request<Body> {
+        Request::builder()
+            .method("PUT")
+            .uri(format!("/{}", key))
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(validate(QUOTES_PAGE_SIZE, &json!(5)).is_ok());
+        assert!(validate(QUOTES_PAGE_SIZE, &json!(0)).is_err());
+        assert!(validate(MILK_MAX_TOKENS, &json!("5")).is_err());
+        assert!(validate("flags.dark_mode", &json!(true)).is_ok());
+        assert!(validate("flags.dark_mode", &json!(1)).is_err());
+        assert!(validate("flags.", &json!(true)).is_err());
+        assert!(validate("colors.cycle", &json!([])).is_err());
+        assert!(validate(THEME, &json!("winter")).is_ok());
+        assert!(validate(THEME, &json!("neon")).is_err());
+    }
+
+    #[test]
+    fn test_cache() {
+        let cache = Settings::new();
+        let changes = cache.subscribe();
+        assert_eq!(cache.get_or(QUOTES_PAGE_SIZE, 3), 3);
+
+        cache.replace(vec![
+            setting(QUOTES_PAGE_SIZE, 2, json!(10)),
+            setting("flags.dark_mode", 1, json!(true)),
+        ]);
+        assert!(changes.has_changed().unwrap());
+        assert_eq!(cache.get_or(QUOTES_PAGE_SIZE, 3), 10);
+        assert!(cache.flag("dark_mode"));
+        assert!(!cache.flag("other"));
+    }
+
+    #[tokio::test]
+    async fn test_put_reloads_cache() {
+        let mut mock = MockSettingsRepository::new();
+        mock.expect_put()
+            .returning(|key, value| box_future(Ok(setting(&key, 1, value))));
+        mock.expect_current()
+            .returning(|| box_future(Ok(vec![setting(MILK_MAX_TOKENS, 1, json!(8))])));
+
+        let cache = Settings::new();
+        let app = settings_router(
+            SettingsState {
+                repository: Arc::new(mock),
+                cache: cache.clone(),
+            },
+            Auth::new(&Config::default()),
+        );
+
+        let response = app
+            .clone()
+            .oneshot(put_request(MILK_MAX_TOKENS, json!({ "value": 8 })))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(cache.get::<u64>(MILK_MAX_TOKENS), Some(8));
+
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let saved: Setting = serde_json::from_slice(&body).unwrap();
+        assert_eq!(saved.version, 1);
+
+        let response = app
+            .oneshot(put_request(MILK_MAX_TOKENS, json!({ "value": -1 })))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+}
@@ -0,0 +1,238 @@
+use core::{net::SocketAddr, time::Duration};
+use std::{io, sync::LazyLock};
+
+use axum::Router;
+use shuttle_runtime::{CustomError, Error};
+use tokio::{net::TcpListener, sync::watch, time::timeout};
+use tokio_stream::{wrappers::WatchStream, Stream, StreamExt};
+
+/// Time a subsystem is given to shut down unless it asks for another
+const DEFAULT_GRACE: Duration = Duration::from_secs(5);
+
+/// Set once shutdown is requested, streams that never end on their own watch it
+static DRAINING: LazyLock<watch::Sender<bool>> = LazyLock::new(|| watch::channel(false).0);
+
+/// Subsystem with state to wind down once requests are drained, rather than on drop
+#[async_trait::async_trait]
+pub trait Shutdown: Send + 'static {
+    /// Used in the logs
+    fn name(&self) -> &'static str;
+
+    /// Past this, the subsystem is left as is and the next one is shut down
+    fn grace(&self) -> Duration {
+        DEFAULT_GRACE
+    }
+
+    async fn shutdown(self: Box<Self>);
+}
+
+/// Axum service that drains in-flight requests on shutdown, then shuts the registered
+/// subsystems down
+pub struct GracefulService {
+    router: Router,
+    subsystems: Vec<Box<dyn Shutdown>>,
+}
+
+pub type ShuttleGraceful = Result<GracefulService, Error>;
+
+impl GracefulService {
+    pub fn new(router: Router) -> Self {
+        Self {
+            router,
+            subsystems: Vec::new(),
+        }
+    }
+
+    /// Router served, e.g. for tests sending it requests without binding a port
+    pub fn router(&self) -> &Router {
+        &self.router
+    }
+
+    /// Registers a subsystem, they are shut down in registration order
+    pub fn on_shutdown(mut self, subsystem: impl Shutdown) -> Self {
+        self.subsystems.push(Box::new(subsystem));
+        self
+    }
+
+    /// Serves the router until shutdown is requested, then runs the hooks. Outside of Shuttle,
+    /// this is what runs the service.
+    pub async fn serve(mut self, listener: TcpListener) -> io::Result<()> {
+        let router = core::mem::take(&mut self.router);
+
+        // the peer address is what `ClientIp` starts from
+        axum::serve(
+            listener,
+            router.into_make_service_with_connect_info::<SocketAddr>(),
+        )
+        .with_graceful_shutdown(shutdown_signal())
+        .await?;
+
+        self.run_hooks().await;
+        Ok(())
+    }
+
+    /// Same as `serve`, terminating TLS with the given certificate
+    #[cfg(feature = "standalone")]
+    pub async fn serve_tls(
+        mut self,
+        addr: SocketAddr,
+        tls: axum_server::tls_rustls::RustlsConfig,
+    ) -> io::Result<()> {
+        let router = core::mem::take(&mut self.router);
+        let handle = axum_server::Handle::new();
+        tokio::spawn({
+            let handle = handle.clone();
+            async move {
+                shutdown_signal().await;
+                // like `axum::serve`, in-flight requests are waited for however long they take
+                handle.graceful_shutdown(None);
+            }
+        });
+
+        axum_server::bind_rustls(addr, tls)
+            .handle(handle)
+            .serve(router.into_make_service_with_connect_info::<SocketAddr>())
+            .await?;
+
+        self.run_hooks().await;
+        Ok(())
+    }
+
+    async fn run_hooks(self) {
+        for subsystem in self.subsystems {
+            let (name, grace) = (subsystem.name(), subsystem.grace());
+            if timeout(grace, subsystem.shutdown()).await.is_err() {
+                tracing::warn!("{} did not shut down in {:?}", name, grace);
+            }
+        }
+    }
+}
+
+/// Ends `stream` once shutdown is requested, so that long-lived responses such as
+/// Server-Sent Events don't hold the draining of requests
+pub fn until_draining<S>(stream: S) -> impl Stream<Item = S::Item>
+where
+    S: Stream,
+{
+    until(stream, DRAINING.subscribe())
+}
+
+/// Turns `true` once shutdown is requested, for whoever should stop taking work before then
+pub fn draining() -> watch::Receiver<bool> {
+    DRAINING.subscribe()
+}
+
+fn until<S>(stream: S, draining: watch::Receiver<bool>) -> impl Stream<Item = S::Item>
+where
+    S: Stream,
+{
+    let draining = WatchStream::new(draining)
+        .filter(|draining| *draining)
+        .map(|_| None);
+    // `None` ends the stream, whichever of the two produces it first
+    stream
+        .map(Some)
+        .chain(tokio_stream::once(None))
+        .merge(draining)
+        .map_while(|item| item)
+}
+
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            tracing::warn!("failed to listen for ctrl-c: {}", e);
+            core::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut s) => {
+                s.recv().await;
+            }
+            Err(e) => {
+                tracing::warn!("failed to listen for SIGTERM: {}", e);
+                core::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = core::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+    tracing::info!("shutdown requested, draining requests");
+    DRAINING.send_replace(true);
+}
+
+#[shuttle_runtime::async_trait]
+impl shuttle_runtime::Service for GracefulService {
+    async fn bind(self, addr: SocketAddr) -> Result<(), Error> {
+        let listener = TcpListener::bind(addr).await.map_err(CustomError::new)?;
+        self.serve(listener).await.map_err(CustomError::new)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+
+    struct Recorder {
+        id: u8,
+        delay: Duration,
+        calls: Arc<Mutex<Vec<u8>>>,
+    }
+
+    #[async_trait::async_trait]
+    impl Shutdown for Recorder {
+        fn name(&self) -> &'static str {
+            "recorder"
+        }
+
+        fn grace(&self) -> Duration {
+            Duration::from_millis(50)
+        }
+
+        async fn shutdown(self: Box<Self>) {
+            tokio::time::sleep(self.delay).await;
+            self.calls.lock().unwrap().push(self.id);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_hooks_run_in_order() {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let recorder = |id, delay| Recorder {
+            id,
+            delay,
+            calls: calls.clone(),
+        };
+
+        GracefulService::new(Router::new())
+            .on_shutdown(recorder(1, Duration::ZERO))
+            // past its grace, the next subsystem is shut down anyway
+            .on_shutdown(recorder(2, Duration::from_secs(3600)))
+            .on_shutdown(recorder(3, Duration::ZERO))
+            .run_hooks()
+            .await;
+
+        assert_eq!(*calls.lock().unwrap(), vec![1, 3]);
+    }
+
+    #[tokio::test]
+    async fn test_streams_end_when_draining() {
+        let (sender, receiver) = watch::channel(false);
+        let finite = until(tokio_stream::iter([1, 2]), receiver.clone());
+        assert_eq!(finite.collect::<Vec<_>>().await, [1, 2]);
+
+        let mut endless = Box::pin(until(tokio_stream::pending::<u8>(), receiver));
+        sender.send_replace(true);
+        assert_eq!(endless.next().await, None);
+    }
+}
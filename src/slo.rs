@@ -0,0 +1,262 @@
+//! Latency of every route against its budget, summarized over a rolling window at `/admin/slo`
+
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{MatchedPath, Request, State},
+    http::{HeaderName, HeaderValue},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{prometheus, versioning::unversioned};
+
+pub const SERVER_TIMING: HeaderName = HeaderName::from_static("server-timing");
+/// Latest samples kept per route, older ones are dropped even within the window
+const MAX_SAMPLES: usize = 4096;
+
+struct Sample {
+    at: Instant,
+    latency: Duration,
+}
+
+#[derive(Clone)]
+pub struct SloTracker {
+    /// Budgets by route template, e.g. `/19/cite/:id`
+    budgets: Arc<HashMap<String, Duration>>,
+    default_budget: Duration,
+    window: Duration,
+    samples: Arc<Mutex<BTreeMap<String, VecDeque<Sample>>>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RouteSlo {
+    pub route: String,
+    pub budget_ms: u64,
+    pub requests: usize,
+    pub breaches: usize,
+    pub breach_rate: f64,
+    pub p50_ms: f64,
+    pub p95_ms: f64,
+    pub p99_ms: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SloReport {
+    pub window_seconds: u64,
+    pub routes: Vec<RouteSlo>,
+}
+
+impl SloTracker {
+    pub fn new(
+        budgets: HashMap<String, Duration>,
+        default_budget: Duration,
+        window: Duration,
+    ) -> Self {
+        Self {
+            budgets: Arc::new(budgets),
+            default_budget,
+            window,
+            samples: Arc::default(),
+        }
+    }
+
+    fn budget(&self, route: &str) -> Duration {
+        self.budgets
+            .get(route)
+            .copied()
+            .unwrap_or(self.default_budget)
+    }
+
+    /// Whether the latency is over the budget of the route
+    fn record(&self, route: &str, latency: Duration, at: Instant) -> bool {
+        let mut samples = self.samples.lock().unwrap();
+        let route_samples = samples.entry(route.to_string()).or_default();
+        while route_samples
+            .front()
+            .is_some_and(|s| at.duration_since(s.at) > self.window)
+        {
+            route_samples.pop_front();
+        }
+        if route_samples.len() == MAX_SAMPLES {
+            route_samples.pop_front();
+        }
+        route_samples.push_back(Sample { at, latency });
+        latency > self.budget(route)
+    }
+
+    pub fn report(&self, now: Instant) -> SloReport {
+        let samples = self.samples.lock().unwrap();
+        let routes = samples
+            .iter()
+            .filter_map(|(route, samples)| {
+                let mut latencies = samples
+                    .iter()
+                    .filter(|s| now.duration_since(s.at) <= self.window)
+                    .map(|s| s.latency)
+                    .collect::<Vec<_>>();
+                if latencies.is_empty() {
+                    return None;
+                }
+                latencies.sort();
+                let budget = self.budget(route);
+                let breaches = latencies.iter().filter(|l| **l > budget).count();
+                Some(RouteSlo {
+                    route: route.clone(),
+                    budget_ms: budget.as_millis() as u64,
+                    requests: latencies.len(),
+                    breaches,
+                    breach_rate: breaches as f64 / latencies.len() as f64,
+                    p50_ms: millis(percentile(&latencies, 50)),
+                    p95_ms: millis(percentile(&latencies, 95)),
+                    p99_ms: millis(percentile(&latencies, 99)),
+                })
+            })
+            .collect();
+        SloReport {
+            window_seconds: self.window.as_secs(),
+            routes,
+        }
+    }
+}
+
+/// Nearest-rank percentile of sorted, non-empty latencies
+fn percentile(sorted: &[Duration], p: usize) -> Duration {
+    let rank = (sorted.len() * p).div_ceil(100).max(1);
+    sorted[rank - 1]
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+/// Times the handler and the layers inside this one, reporting it in `Server-Timing`.
+/// Requests no route took are left out of the report.
+pub async fn track_latency(
+    State(tracker): State<SloTracker>,
+    path: Option<MatchedPath>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let start = Instant::now();
+    let mut response = next.run(request).await;
+    let latency = start.elapsed();
+
+    let timing = format!("handler;dur={:.1}", millis(latency));
+    if let Ok(timing) = HeaderValue::from_str(&timing) {
+        response.headers_mut().append(SERVER_TIMING, timing);
+    }
+    if let Some(path) = path {
+        let route = unversioned(path.as_str());
+        if tracker.record(route, latency, Instant::now()) {
+            metrics::counter!(prometheus::SLO_BREACHES, "route" => route.to_string()).increment(1);
+        }
+    }
+    response
+}
+
+pub async fn report(State(tracker): State<SloTracker>) -> impl IntoResponse {
+    Json(tracker.report(Instant::now()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, middleware, routing::get, Router};
+    use http_body_util::BodyExt;
+    use tower::ServiceExt;
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    fn tracker() -> SloTracker {
+        SloTracker::new(
+            HashMap::from([("/23/lockfile".to_string(), ms(2000))]),
+            ms(100),
+            Duration::from_secs(60),
+        )
+    }
+
+    #[test]
+    fn test_percentile() {
+        let latencies = (1..=100).map(ms).collect::<Vec<_>>();
+        assert_eq!(percentile(&latencies, 50), ms(50));
+        assert_eq!(percentile(&latencies, 99), ms(99));
+        assert_eq!(percentile(&[ms(7)], 95), ms(7));
+    }
+
+    #[test]
+    fn test_report() {
+        let tracker = tracker();
+        let start = Instant::now();
+        assert!(tracker.record("/19/list", ms(500), start));
+        // past the window by the time of the report
+        for i in 1..=20 {
+            assert!(!tracker.record("/19/list", ms(i), start + Duration::from_secs(30)));
+        }
+        assert!(!tracker.record("/23/lockfile", ms(1500), start + Duration::from_secs(30)));
+
+        let report = tracker.report(start + Duration::from_secs(61));
+        assert_eq!(report.window_seconds, 60);
+        let list = &report.routes[0];
+        assert_eq!(list.route, "/19/list");
+        assert_eq!((list.requests, list.breaches), (20, 0));
+        assert_eq!((list.p50_ms, list.p95_ms), (10.0, 19.0));
+        assert_eq!(report.routes[1].budget_ms, 2000);
+
+        assert!(tracker.record("/19/list", ms(150), start + Duration::from_secs(62)));
+        let list = &tracker.report(start + Duration::from_secs(62)).routes[0];
+        assert_eq!((list.requests, list.breaches), (21, 1));
+        assert!((list.breach_rate - 1.0 / 21.0).abs() < 1e-9);
+
+        assert!(tracker
+            .report(start + Duration::from_secs(600))
+            .routes
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_track_latency() {
+        let tracker = tracker();
+        let app = Router::new()
+            .route("/19/cite/:id", get(|| async { "cited" }))
+            .layer(middleware::from_fn_with_state(
+                tracker.clone(),
+                track_latency,
+            ));
+        for uri in ["/19/cite/1", "/19/cite/2", "/nowhere"] {
+            let response = app
+                .clone()
+                .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            let timing = response.headers()[SERVER_TIMING].to_str().unwrap();
+            assert!(timing.starts_with("handler;dur="));
+        }
+
+        let routes = Router::new()
+            .route("/admin/slo", get(super::report))
+            .with_state(tracker);
+        let response = routes
+            .oneshot(
+                Request::builder()
+                    .uri("/admin/slo")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let report = serde_json::from_slice::<SloReport>(&body).unwrap();
+        assert_eq!(report.routes.len(), 1);
+        assert_eq!(report.routes[0].route, "/19/cite/:id");
+        assert_eq!(report.routes[0].requests, 2);
+    }
+}
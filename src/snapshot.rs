@@ -0,0 +1,175 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use sqlx::{query, query_scalar, types::Json, PgPool};
+
+use crate::{
+    day_12::{Board, BoardState},
+    day_19::DbState,
+    day_9::RateLimiterState,
+    shutdown::Shutdown,
+};
+
+const SNAPSHOT_KEY: &str = "volatile";
+
+/// In-memory state worth keeping across redeploys.
+/// The random board is left out, it's rebuilt from its fixed seed anyway.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Snapshot {
+    #[serde(default)]
+    board: Option<Board>,
+    #[serde(default)]
+    milk: Option<usize>,
+    #[serde(default)]
+    tokens: HashMap<String, i64>,
+}
+
+#[derive(Clone)]
+pub struct VolatileState {
+    pub games: BoardState,
+    pub milk: RateLimiterState,
+    pub quotes: DbState,
+}
+
+impl VolatileState {
+    pub async fn capture(&self) -> Snapshot {
+        Snapshot {
+            board: Some(self.games.board.lock().await.clone()),
+            milk: Some(self.milk.limiter.lock().await.balance()),
+            tokens: self.quotes.tokens.export().await,
+        }
+    }
+
+    pub async fn restore(&self, snapshot: Snapshot) {
+        match snapshot.board {
+            Some(board) if board.has_current_shape() => *self.games.board.lock().await = board,
+            Some(_) => tracing::warn!("ignoring the snapshot board, its shape differs"),
+            None => {}
+        }
+        if let Some(milk) = snapshot.milk {
+            *self.milk.limiter.lock().await = self.milk.bucket.limiter(milk);
+        }
+        self.quotes.tokens.import(snapshot.tokens).await;
+    }
+
+    /// Empty boards, a full milk bucket and no list tokens, as after a fresh start
+    pub async fn reset(&self) {
+        self.games.clear().await;
+        *self.milk.limiter.lock().await = self.milk.bucket.limiter(self.milk.bucket.initial);
+        self.quotes.tokens.clear().await;
+    }
+}
+
+/// Saves the volatile state for the next instance to restore
+pub struct SnapshotSaver {
+    pub pool: PgPool,
+    pub state: VolatileState,
+}
+
+#[async_trait::async_trait]
+impl Shutdown for SnapshotSaver {
+    fn name(&self) -> &'static str {
+        "state snapshot"
+    }
+
+    async fn shutdown(self: Box<Self>) {
+        let snapshot = self.state.capture().await;
+        if let Err(e) = save(&self.pool, &snapshot).await {
+            tracing::warn!("failed to save the state snapshot: {}", e);
+        }
+    }
+}
+
+pub async fn save(pool: &PgPool, snapshot: &Snapshot) -> Result<(), sqlx::Error> {
+    query(
+        "INSERT INTO app_state (key, value) VALUES ($1, $2)
+         ON CONFLICT (key) DO UPDATE SET value = EXCLUDED.value, saved_at = now()",
+    )
+    .bind(SNAPSHOT_KEY)
+    .bind(Json(snapshot))
+    .execute(pool)
+    .await
+    .map(|_| ())
+}
+
+/// Loads the saved snapshot and removes it, so that it's never applied twice,
+/// e.g. after a crash that skipped the shutdown hook
+pub async fn take(pool: &PgPool) -> Result<Option<Snapshot>, sqlx::Error> {
+    query_scalar::<_, Json<Snapshot>>("DELETE FROM app_state WHERE key = $1 RETURNING value")
+        .bind(SNAPSHOT_KEY)
+        .fetch_optional(pool)
+        .await
+        .map(|s| s.map(|s| s.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{
+        board_feed::BoardFeed,
+        day_12::{arc_board, arc_random_board, MockGameResultRepository},
+        day_19::{state_tokens, MockQuoteRepository, PAGE_SIZE},
+        moderation::WordListModerator,
+        players::MockPlayerRepository,
+    };
+
+    fn volatile_state() -> VolatileState {
+        VolatileState {
+            games: BoardState {
+                board: arc_board(),
+                random_board: arc_random_board(),
+                results: Arc::new(MockGameResultRepository::new()),
+                feed: BoardFeed::default(),
+                players: Arc::new(MockPlayerRepository::new()),
+            },
+            milk: RateLimiterState::default(),
+            quotes: DbState {
+                repository: Arc::new(MockQuoteRepository::new()),
+                tokens: state_tokens(),
+                moderator: Arc::new(WordListModerator::default()),
+                page_size: PAGE_SIZE,
+                players: Arc::new(MockPlayerRepository::new()),
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn test_round_trip() {
+        let before = volatile_state();
+        assert!(before.milk.limiter.lock().await.try_acquire(2));
+        let token = before.quotes.tokens.issue(2).await.unwrap();
+        let board = before.games.board.lock().await.to_string();
+
+        let json = serde_json::to_string(&before.capture().await).unwrap();
+
+        let after = volatile_state();
+        after.restore(serde_json::from_str(&json).unwrap()).await;
+
+        assert_eq!(after.milk.limiter.lock().await.balance(), 3);
+        assert_eq!(after.quotes.tokens.resolve(token).await.unwrap(), Some(2));
+        assert_eq!(after.games.board.lock().await.to_string(), board);
+    }
+
+    #[tokio::test]
+    async fn test_reset() {
+        let state = volatile_state();
+        assert!(state.milk.limiter.lock().await.try_acquire(4));
+        let token = state.quotes.tokens.issue(2).await.unwrap();
+
+        state.reset().await;
+
+        assert_eq!(state.milk.limiter.lock().await.balance(), 5);
+        assert_eq!(state.quotes.tokens.resolve(token).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_partial_snapshot() {
+        let state = volatile_state();
+        state.restore(serde_json::from_str("{}").unwrap()).await;
+
+        assert_eq!(state.milk.limiter.lock().await.balance(), 5);
+        assert!(state.quotes.tokens.export().await.is_empty());
+    }
+}
@@ -0,0 +1,359 @@
+use core::sync::atomic::{AtomicU64, Ordering};
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use axum::{
+    extract::{MatchedPath, Query, Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{Days, NaiveDate, Utc};
+#[cfg(test)]
+use mockall::automock;
+use serde::{Deserialize, Serialize};
+use sqlx::{query, query_as, types::Json as Jsonb, FromRow, PgPool};
+
+use crate::{days::day_of, shutdown::Shutdown};
+
+const ROLLUP_INTERVAL: Duration = Duration::from_secs(60);
+const DEFAULT_RANGE_DAYS: u64 = 30;
+
+/// Usage counters accumulated since the last rollup
+pub static STATS: Stats = Stats::new();
+
+pub struct Stats {
+    requests: AtomicU64,
+    /// Requests by challenge day of their route, the other routes only count in `requests`
+    day_requests: Mutex<BTreeMap<i8, u64>>,
+    quotes_created: AtomicU64,
+    games_finished: AtomicU64,
+    milk_withdrawn: AtomicU64,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Snapshot {
+    pub requests: i64,
+    pub day_requests: BTreeMap<i8, i64>,
+    pub quotes_created: i64,
+    pub games_finished: i64,
+    pub milk_withdrawn: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, FromRow)]
+pub struct DailyStats {
+    day: NaiveDate,
+    requests: i64,
+    /// Keyed by challenge day, e.g. `"19"`
+    day_requests: Jsonb<BTreeMap<i8, i64>>,
+    quotes_created: i64,
+    games_finished: i64,
+    milk_withdrawn: i64,
+}
+
+#[derive(Deserialize)]
+pub struct Range {
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+}
+
+#[derive(Clone)]
+pub struct StatsState {
+    pub repository: Arc<dyn StatsRepository>,
+}
+
+/// Writes the counters of the last minute
+#[async_trait::async_trait]
+impl Shutdown for StatsState {
+    fn name(&self) -> &'static str {
+        "stats"
+    }
+
+    async fn shutdown(self: Box<Self>) {
+        flush(self.repository.as_ref()).await
+    }
+}
+
+impl Stats {
+    const fn new() -> Self {
+        Self {
+            requests: AtomicU64::new(0),
+            day_requests: Mutex::new(BTreeMap::new()),
+            quotes_created: AtomicU64::new(0),
+            games_finished: AtomicU64::new(0),
+            milk_withdrawn: AtomicU64::new(0),
+        }
+    }
+
+    pub fn request(&self, day: Option<i8>) {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        if let Some(day) = day {
+            *self.day_requests.lock().unwrap().entry(day).or_default() += 1;
+        }
+    }
+
+    pub fn quote_created(&self) {
+        self.quotes_created.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn game_finished(&self) {
+        self.games_finished.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn milk_withdrawn(&self) {
+        self.milk_withdrawn.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Returns the current counters and starts over from zero
+    fn take(&self) -> Snapshot {
+        Snapshot {
+            requests: self.requests.swap(0, Ordering::Relaxed) as i64,
+            day_requests: core::mem::take(&mut *self.day_requests.lock().unwrap())
+                .into_iter()
+                .map(|(day, n)| (day, n as i64))
+                .collect(),
+            quotes_created: self.quotes_created.swap(0, Ordering::Relaxed) as i64,
+            games_finished: self.games_finished.swap(0, Ordering::Relaxed) as i64,
+            milk_withdrawn: self.milk_withdrawn.swap(0, Ordering::Relaxed) as i64,
+        }
+    }
+
+    /// Puts back counts that could not be persisted
+    fn restore(&self, snapshot: Snapshot) {
+        self.requests
+            .fetch_add(snapshot.requests as u64, Ordering::Relaxed);
+        let mut day_requests = self.day_requests.lock().unwrap();
+        for (day, n) in snapshot.day_requests {
+            *day_requests.entry(day).or_default() += n as u64;
+        }
+        self.quotes_created
+            .fetch_add(snapshot.quotes_created as u64, Ordering::Relaxed);
+        self.games_finished
+            .fetch_add(snapshot.games_finished as u64, Ordering::Relaxed);
+        self.milk_withdrawn
+            .fetch_add(snapshot.milk_withdrawn as u64, Ordering::Relaxed);
+    }
+}
+
+#[async_trait::async_trait]
+#[cfg_attr(test, automock)]
+pub trait StatsRepository: Send + Sync + 'static {
+    async fn add(&self, day: NaiveDate, snapshot: Snapshot) -> Result<(), sqlx::Error>;
+    async fn range(&self, from: NaiveDate, to: NaiveDate) -> Result<Vec<DailyStats>, sqlx::Error>;
+}
+
+pub struct PostgresStatsRepository {
+    pool: PgPool,
+}
+
+impl PostgresStatsRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl StatsRepository for PostgresStatsRepository {
+    async fn add(&self, day: NaiveDate, snapshot: Snapshot) -> Result<(), sqlx::Error> {
+        // both tables move together, a failed rollup is put back whole
+        let mut tx = self.pool.begin().await?;
+        query(
+            "INSERT INTO daily_stats (day, requests, quotes_created, games_finished, milk_withdrawn)
+             VALUES ($1, $2, $3, $4, $5)
+             ON CONFLICT (day) DO UPDATE SET
+                requests = daily_stats.requests + EXCLUDED.requests,
+                quotes_created = daily_stats.quotes_created + EXCLUDED.quotes_created,
+                games_finished = daily_stats.games_finished + EXCLUDED.games_finished,
+                milk_withdrawn = daily_stats.milk_withdrawn + EXCLUDED.milk_withdrawn",
+        )
+        .bind(day)
+        .bind(snapshot.requests)
+        .bind(snapshot.quotes_created)
+        .bind(snapshot.games_finished)
+        .bind(snapshot.milk_withdrawn)
+        .execute(&mut *tx)
+        .await?;
+
+        let (days, requests): (Vec<_>, Vec<_>) = snapshot
+            .day_requests
+            .into_iter()
+            .map(|(d, n)| (d as i16, n))
+            .unzip();
+        query(
+            "INSERT INTO daily_day_stats (day, challenge_day, requests)
+             SELECT $1, * FROM UNNEST($2::smallint[], $3::bigint[])
+             ON CONFLICT (day, challenge_day) DO UPDATE SET
+                requests = daily_day_stats.requests + EXCLUDED.requests",
+        )
+        .bind(day)
+        .bind(days)
+        .bind(requests)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await
+    }
+
+    async fn range(&self, from: NaiveDate, to: NaiveDate) -> Result<Vec<DailyStats>, sqlx::Error> {
+        query_as::<_, DailyStats>(
+            "SELECT s.*, COALESCE(
+                (SELECT jsonb_object_agg(d.challenge_day, d.requests)
+                 FROM daily_day_stats d WHERE d.day = s.day),
+                '{}'
+             ) AS day_requests
+             FROM daily_stats s WHERE s.day BETWEEN $1 AND $2 ORDER BY s.day",
+        )
+        .bind(from)
+        .bind(to)
+        .fetch_all(&self.pool)
+        .await
+    }
+}
+
+/// Counts every request served by the router, by the challenge day of its route as well
+pub async fn count_requests(request: Request, next: Next) -> Response {
+    let day = request
+        .extensions()
+        .get::<MatchedPath>()
+        .and_then(|p| day_of(p.as_str()));
+    STATS.request(day);
+    next.run(request).await
+}
+
+/// Periodically moves the in-memory counters to the row of the current day
+pub async fn rollup(repository: Arc<dyn StatsRepository>) {
+    let mut interval = tokio::time::interval(ROLLUP_INTERVAL);
+    loop {
+        interval.tick().await;
+        flush(repository.as_ref()).await;
+    }
+}
+
+/// Writes the pending counters, also called on shutdown so that the last minute isn't lost
+pub async fn flush(repository: &dyn StatsRepository) {
+    let snapshot = STATS.take();
+    if snapshot == Snapshot::default() {
+        return;
+    }
+
+    if let Err(e) = repository
+        .add(Utc::now().date_naive(), snapshot.clone())
+        .await
+    {
+        tracing::warn!("stats rollup failed: {}", e);
+        STATS.restore(snapshot);
+    }
+}
+
+pub async fn daily(
+    State(state): State<StatsState>,
+    Query(range): Query<Range>,
+) -> impl IntoResponse {
+    let to = range.to.unwrap_or_else(|| Utc::now().date_naive());
+    let from = range
+        .from
+        .or_else(|| to.checked_sub_days(Days::new(DEFAULT_RANGE_DAYS)))
+        .unwrap_or(to);
+
+    if from > to {
+        return Err((StatusCode::BAD_REQUEST, "".to_string()));
+    }
+
+    match state.repository.range(from, to).await {
+        Ok(stats) => Ok((StatusCode::OK, Json(stats))),
+        _ => Err((StatusCode::INTERNAL_SERVER_ERROR, "".to_string())),
+    }
+}
+
+pub fn state_stats_repository(pool: PgPool) -> Arc<dyn StatsRepository> {
+    Arc::new(PostgresStatsRepository::new(pool))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::box_future;
+    use axum::{body::Body, http::Request, routing::get, Router};
+    use http_body_util::BodyExt;
+    use mockall::predicate::eq;
+    use tower::ServiceExt;
+
+    fn create_test_app(repository: Arc<dyn StatsRepository>) -> Router {
+        Router::new()
+            .route("/stats/daily", get(daily))
+            .with_state(StatsState { repository })
+    }
+
+    #[test]
+    fn test_take_resets_counters() {
+        let stats = Stats::new();
+        stats.request(Some(19));
+        stats.request(Some(19));
+        stats.request(None);
+        stats.milk_withdrawn();
+
+        let snapshot = stats.take();
+        assert_eq!(snapshot.requests, 3);
+        assert_eq!(snapshot.day_requests, BTreeMap::from([(19, 2)]));
+        assert_eq!(snapshot.milk_withdrawn, 1);
+        assert_eq!(stats.take(), Snapshot::default());
+
+        stats.restore(snapshot.clone());
+        assert_eq!(stats.take(), snapshot);
+    }
+
+    #[tokio::test]
+    async fn test_daily_range() {
+        let mut mock = MockStatsRepository::new();
+        let from = NaiveDate::from_ymd_opt(2024, 12, 1).unwrap();
+        let to = NaiveDate::from_ymd_opt(2024, 12, 2).unwrap();
+
+        mock.expect_range()
+            .with(eq(from), eq(to))
+            .returning(move |from, _| {
+                box_future(Ok(vec![DailyStats {
+                    day: from,
+                    requests: 10,
+                    day_requests: Jsonb(BTreeMap::from([(9, 4), (19, 6)])),
+                    quotes_created: 1,
+                    games_finished: 2,
+                    milk_withdrawn: 3,
+                }]))
+            });
+
+        let response = create_test_app(Arc::new(mock))
+            .oneshot(
+                Request::builder()
+                    .uri("/stats/daily?from=2024-12-01&to=2024-12-02")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let stats: Vec<DailyStats> = serde_json::from_slice(&body).unwrap();
+        assert_eq!(stats.len(), 1);
+        assert_eq!(stats[0].day, from);
+        assert_eq!(stats[0].requests, 10);
+        assert_eq!(stats[0].day_requests.0[&19], 6);
+    }
+
+    #[tokio::test]
+    async fn test_daily_inverted_range() {
+        let response = create_test_app(Arc::new(MockStatsRepository::new()))
+            .oneshot(
+                Request::builder()
+                    .uri("/stats/daily?from=2024-12-02&to=2024-12-01")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+}
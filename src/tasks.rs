@@ -0,0 +1,179 @@
+use core::{future::Future, time::Duration};
+
+use tokio::{
+    sync::watch,
+    task::{JoinError, JoinSet},
+    time::{sleep, timeout, Instant},
+};
+
+use crate::shutdown::Shutdown;
+
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+/// A task running this long before failing starts over from the initial backoff
+const STABLE_AFTER: Duration = Duration::from_secs(60);
+/// Time the tasks are given to wind down on shutdown, before they are aborted
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(10);
+
+/// Owns the background tasks of the service: panics are logged and the task is restarted
+/// with exponential backoff, until the supervisor shuts everything down
+pub struct Supervisor {
+    tasks: JoinSet<()>,
+    stop: watch::Sender<bool>,
+    backoff: Duration,
+}
+
+impl Default for Supervisor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn panic_message(error: JoinError) -> String {
+    let panic = error.into_panic();
+    panic
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| panic.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "<no panic message>".to_string())
+}
+
+impl Supervisor {
+    pub fn new() -> Self {
+        Self {
+            tasks: JoinSet::new(),
+            stop: watch::channel(false).0,
+            backoff: INITIAL_BACKOFF,
+        }
+    }
+
+    /// Runs the future built by `factory`, building a new one whenever the previous one panics.
+    /// A task that returns on its own is considered done.
+    pub fn spawn<F, Fut>(&mut self, name: &'static str, factory: F)
+    where
+        F: Fn() -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let mut stop = self.stop.subscribe();
+        let initial_backoff = self.backoff;
+
+        self.tasks.spawn(async move {
+            let mut backoff = initial_backoff;
+            loop {
+                let started = Instant::now();
+                let mut task = tokio::spawn(factory());
+
+                tokio::select! {
+                    res = &mut task => match res {
+                        Ok(()) => {
+                            tracing::info!("task {} finished", name);
+                            return;
+                        }
+                        Err(e) if e.is_panic() => {
+                            tracing::error!("task {} panicked: {}", name, panic_message(e));
+                        }
+                        Err(_) => return,
+                    },
+                    _ = stop.changed() => {
+                        task.abort();
+                        return;
+                    }
+                }
+
+                if started.elapsed() >= STABLE_AFTER {
+                    backoff = initial_backoff;
+                }
+                tracing::info!("restarting task {} in {:?}", name, backoff);
+
+                tokio::select! {
+                    _ = sleep(backoff) => {}
+                    _ = stop.changed() => return,
+                }
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+            }
+        });
+    }
+
+    /// Stops every task, waiting at most `grace` for them to wind down
+    pub async fn shutdown(mut self, grace: Duration) {
+        let _ = self.stop.send(true);
+
+        let drained = timeout(grace, async {
+            while self.tasks.join_next().await.is_some() {}
+        })
+        .await;
+
+        if drained.is_err() {
+            tracing::warn!("background tasks did not stop in {:?}, aborting", grace);
+            self.tasks.shutdown().await;
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Shutdown for Supervisor {
+    fn name(&self) -> &'static str {
+        "background tasks"
+    }
+
+    /// Leaves the time to abort the tasks that outlive their grace
+    fn grace(&self) -> Duration {
+        SHUTDOWN_GRACE + Duration::from_secs(1)
+    }
+
+    async fn shutdown(self: Box<Self>) {
+        Supervisor::shutdown(*self, SHUTDOWN_GRACE).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_restarts_after_panic() {
+        let runs = Arc::new(AtomicUsize::new(0));
+        let mut supervisor = Supervisor::new();
+        supervisor.backoff = Duration::from_millis(1);
+
+        let counter = runs.clone();
+        supervisor.spawn("flaky", move || {
+            let counter = counter.clone();
+            async move {
+                if counter.fetch_add(1, Ordering::SeqCst) < 2 {
+                    panic!("boom");
+                }
+            }
+        });
+
+        timeout(Duration::from_secs(5), async {
+            while supervisor.tasks.join_next().await.is_some() {}
+        })
+        .await
+        .unwrap();
+        assert_eq!(runs.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_stops_running_tasks() {
+        let mut supervisor = Supervisor::new();
+        supervisor.spawn("forever", core::future::pending::<()>);
+        supervisor.spawn("looping", || async {
+            loop {
+                sleep(Duration::from_millis(1)).await;
+            }
+        });
+
+        timeout(
+            Duration::from_secs(5),
+            supervisor.shutdown(Duration::from_secs(1)),
+        )
+        .await
+        .unwrap();
+    }
+}
@@ -0,0 +1,14 @@
+//! Helpers shared by the unit tests of the modules
+
+use core::{
+    future::{ready, Future},
+    pin::Pin,
+};
+
+/// Future already resolved to `value`, as the mocks of the async repositories return
+pub fn box_future<T>(value: T) -> Pin<Box<dyn Future<Output = T> + Send>>
+where
+    T: Send + 'static,
+{
+    Box::pin(ready(value))
+}
@@ -0,0 +1,148 @@
+//! Symbols of the day 12 board and CSS classes of the day 23 tree, picked per request
+
+use core::convert::Infallible;
+
+use axum::{
+    async_trait,
+    extract::{FromRequestParts, Query},
+    http::request::Parts,
+};
+use serde::Deserialize;
+
+use crate::settings::{SETTINGS, THEME};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Theme {
+    pub name: &'static str,
+    pub cookie: &'static str,
+    pub milk: &'static str,
+    pub empty: &'static str,
+    pub wall: &'static str,
+    /// Class of the star once lit
+    pub star_lit: &'static str,
+    pub ornament_on: &'static str,
+    /// Classes of the blue, purple and red presents
+    pub presents: [&'static str; 3],
+}
+
+/// The challenge's own output, used unless another theme is asked for
+pub const CLASSIC: Theme = Theme {
+    name: "classic",
+    cookie: "🍪",
+    milk: "🥛",
+    empty: "⬛",
+    wall: "⬜",
+    star_lit: "lit",
+    ornament_on: "on",
+    presents: ["blue", "purple", "red"],
+};
+
+/// For terminals without emoji
+pub const ASCII: Theme = Theme {
+    name: "ascii",
+    cookie: "C",
+    milk: "M",
+    empty: ".",
+    wall: "#",
+    ..CLASSIC
+};
+
+pub const WINTER: Theme = Theme {
+    name: "winter",
+    cookie: "🍪",
+    milk: "🥛",
+    empty: "🌑",
+    wall: "🧊",
+    star_lit: "lit frosty",
+    ornament_on: "on frosty",
+    presents: ["ice", "lilac", "berry"],
+};
+
+pub const THEMES: [Theme; 3] = [CLASSIC, ASCII, WINTER];
+
+#[derive(Deserialize)]
+struct ThemeQuery {
+    theme: Option<String>,
+    /// `ascii` for terminals where emoji widths break the board's alignment
+    render: Option<String>,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        CLASSIC
+    }
+}
+
+impl Theme {
+    pub fn by_name(name: &str) -> Option<Self> {
+        THEMES.into_iter().find(|t| t.name == name)
+    }
+
+    /// Theme of the `theme` setting, the classic one when unset
+    pub fn configured() -> Self {
+        SETTINGS
+            .get::<String>(THEME)
+            .and_then(|name| Self::by_name(&name))
+            .unwrap_or_default()
+    }
+
+    /// Class of a present, by its color in the challenge's URLs
+    pub fn present(&self, color: &str) -> &'static str {
+        match color {
+            "blue" => self.presents[0],
+            "purple" => self.presents[1],
+            _ => self.presents[2],
+        }
+    }
+}
+
+/// `?render=ascii` wins over `?theme=`, which wins over the setting, unknown values are ignored
+#[async_trait]
+impl<S> FromRequestParts<S> for Theme
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let Ok(Query(query)) = Query::<ThemeQuery>::try_from_uri(&parts.uri) else {
+            return Ok(Self::configured());
+        };
+        if query.render.as_deref() == Some("ascii") {
+            return Ok(ASCII);
+        }
+        Ok(query
+            .theme
+            .and_then(|name| Self::by_name(&name))
+            .unwrap_or_else(Self::configured))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::Request;
+
+    async fn extract(uri: &str) -> Theme {
+        let (mut parts, _) = Request::builder().uri(uri).body(()).unwrap().into_parts();
+        Theme::from_request_parts(&mut parts, &()).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_query() {
+        assert_eq!(extract("/12/board").await, CLASSIC);
+        assert_eq!(extract("/12/board?theme=ascii").await, ASCII);
+        assert_eq!(extract("/23/star?x=1&theme=winter").await, WINTER);
+        assert_eq!(extract("/12/board?theme=neon").await, CLASSIC);
+        assert_eq!(extract("/12/board?render=ascii").await, ASCII);
+        assert_eq!(extract("/12/board?theme=winter&render=ascii").await, ASCII);
+        assert_eq!(extract("/12/board?render=emoji").await, CLASSIC);
+    }
+
+    #[test]
+    fn test_present_classes() {
+        assert_eq!(CLASSIC.present("purple"), "purple");
+        assert_eq!(WINTER.present("blue"), "ice");
+        assert_eq!(ASCII.star_lit, CLASSIC.star_lit);
+    }
+}
@@ -0,0 +1,166 @@
+//! Counters of the tokens issued and verified, per algorithm and key id. The key id comes from
+//! the unverified header, so only the ids of the configured keys are counted by name.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::{LazyLock, Mutex, RwLock},
+};
+
+use axum::{response::IntoResponse, Json};
+use jsonwebtoken::Header;
+use serde::{Deserialize, Serialize};
+
+/// Token counters since startup
+pub static TOKENS: LazyLock<TokenMetrics> = LazyLock::new(TokenMetrics::default);
+
+/// Label of tokens without a `kid` header
+const NO_KID: &str = "none";
+/// Label of tokens naming a key that isn't configured
+const UNKNOWN_KID: &str = "unknown";
+/// Label sets counted, past this the key id of new ones is counted as unknown
+const MAX_LABELS: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenEvent {
+    Issued,
+    Verified,
+    Expired,
+    SignatureFailed,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct TokenLabels {
+    pub event: TokenEvent,
+    /// Algorithm of the token header, e.g. `HS256`
+    pub algorithm: String,
+    pub kid: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TokenCount {
+    #[serde(flatten)]
+    pub labels: TokenLabels,
+    pub count: u64,
+}
+
+#[derive(Default)]
+pub struct TokenMetrics {
+    counts: Mutex<BTreeMap<TokenLabels, u64>>,
+    known_kids: RwLock<BTreeSet<String>>,
+}
+
+impl TokenLabels {
+    pub fn new(event: TokenEvent, header: &Header, kid: &str) -> Self {
+        Self {
+            event,
+            algorithm: format!("{:?}", header.alg),
+            kid: kid.to_string(),
+        }
+    }
+}
+
+impl TokenMetrics {
+    /// Key ids counted by name, those of the configured keys
+    pub fn register_kids(&self, kids: impl IntoIterator<Item = String>) {
+        self.known_kids.write().unwrap().extend(kids);
+    }
+
+    fn kid<'a>(&self, header: &'a Header) -> &'a str {
+        match header.kid.as_deref() {
+            None => NO_KID,
+            Some(kid) if self.known_kids.read().unwrap().contains(kid) => kid,
+            Some(_) => UNKNOWN_KID,
+        }
+    }
+
+    pub fn record(&self, event: TokenEvent, header: &Header) {
+        let mut labels = TokenLabels::new(event, header, self.kid(header));
+        let mut counts = self.counts.lock().unwrap();
+        // events and algorithms are bounded, so is the map once key ids are
+        if counts.len() >= MAX_LABELS && !counts.contains_key(&labels) {
+            labels.kid = UNKNOWN_KID.to_string();
+        }
+        *counts.entry(labels).or_default() += 1;
+    }
+
+    pub fn snapshot(&self) -> Vec<TokenCount> {
+        self.counts
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(labels, count)| TokenCount {
+                labels: labels.clone(),
+                count: *count,
+            })
+            .collect()
+    }
+}
+
+pub async fn counts() -> impl IntoResponse {
+    Json(TOKENS.snapshot())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jsonwebtoken::Algorithm;
+
+    fn header(algorithm: Algorithm, kid: &str) -> Header {
+        Header {
+            kid: Some(kid.to_string()),
+            ..Header::new(algorithm)
+        }
+    }
+
+    #[test]
+    fn test_record() {
+        let metrics = TokenMetrics::default();
+        metrics.register_kids(["2024".to_string()]);
+        metrics.record(TokenEvent::Verified, &Header::default());
+        metrics.record(TokenEvent::Verified, &Header::default());
+        metrics.record(
+            TokenEvent::SignatureFailed,
+            &header(Algorithm::RS256, "2024"),
+        );
+
+        let counts = serde_json::to_value(metrics.snapshot()).unwrap();
+        assert_eq!(
+            counts,
+            serde_json::json!([
+                {"event": "verified", "algorithm": "HS256", "kid": "none", "count": 2},
+                {"event": "signature_failed", "algorithm": "RS256", "kid": "2024", "count": 1},
+            ])
+        );
+    }
+
+    #[test]
+    fn test_unknown_kids_share_a_label() {
+        let metrics = TokenMetrics::default();
+        for n in 0..1000 {
+            let header = header(Algorithm::HS256, &format!("random-{}", n));
+            metrics.record(TokenEvent::SignatureFailed, &header);
+        }
+
+        let counts = serde_json::to_value(metrics.snapshot()).unwrap();
+        assert_eq!(
+            counts,
+            serde_json::json!([
+                {"event": "signature_failed", "algorithm": "HS256", "kid": "unknown", "count": 1000},
+            ])
+        );
+    }
+
+    #[test]
+    fn test_labels_capped() {
+        let metrics = TokenMetrics::default();
+        metrics.register_kids((0..MAX_LABELS + 10).map(|n| n.to_string()));
+        for n in 0..MAX_LABELS + 10 {
+            metrics.record(
+                TokenEvent::Verified,
+                &header(Algorithm::HS256, &n.to_string()),
+            );
+        }
+        assert_eq!(metrics.snapshot().len(), MAX_LABELS + 1);
+    }
+}
@@ -0,0 +1,176 @@
+//! Continuation tokens of `/19/list`, each pointing to a page of quotes
+
+use std::{collections::HashMap, str::FromStr, sync::Arc, time::Duration};
+
+#[cfg(test)]
+use mockall::automock;
+use rand::distributions::DistString;
+use sqlx::{query, query_scalar, PgPool};
+use tokio::sync::Mutex;
+
+const TOKEN_LENGTH: usize = 16;
+/// Lifetime of the tokens stored in Postgres
+const TOKEN_TTL_SECS: i64 = 24 * 60 * 60;
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Where tokens are kept, from the `PAGINATION_TOKENS` setting
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum TokenBackend {
+    /// Lost on restart unless state snapshots are on, local to the instance
+    #[default]
+    Memory,
+    /// Shared by every instance, tokens expire after a day
+    Postgres,
+}
+
+impl FromStr for TokenBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "memory" => Ok(Self::Memory),
+            "postgres" => Ok(Self::Postgres),
+            _ => Err(format!("unknown token backend {}", s)),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+#[cfg_attr(test, automock)]
+pub trait TokenStore: Send + Sync + 'static {
+    /// New token pointing to the given page
+    async fn issue(&self, page: i64) -> Result<String, sqlx::Error>;
+    /// Page of the token, `None` when it's unknown or expired
+    async fn resolve(&self, token: String) -> Result<Option<i64>, sqlx::Error>;
+    /// Tokens worth a state snapshot, durable stores have none
+    async fn export(&self) -> HashMap<String, i64> {
+        HashMap::new()
+    }
+    async fn import(&self, _tokens: HashMap<String, i64>) {}
+    /// Forgets every token, durable stores keep theirs until they expire
+    async fn clear(&self) {}
+    /// Drops expired tokens, returning how many
+    async fn purge(&self) -> Result<u64, sqlx::Error> {
+        Ok(0)
+    }
+}
+
+fn new_token() -> String {
+    rand::distributions::Alphanumeric.sample_string(&mut rand::thread_rng(), TOKEN_LENGTH)
+}
+
+#[derive(Default)]
+pub struct MemoryTokenStore {
+    tokens: Mutex<HashMap<String, i64>>,
+}
+
+#[async_trait::async_trait]
+impl TokenStore for MemoryTokenStore {
+    async fn issue(&self, page: i64) -> Result<String, sqlx::Error> {
+        let token = new_token();
+        self.tokens.lock().await.insert(token.clone(), page);
+        Ok(token)
+    }
+
+    async fn resolve(&self, token: String) -> Result<Option<i64>, sqlx::Error> {
+        Ok(self.tokens.lock().await.get(&token).copied())
+    }
+
+    async fn export(&self) -> HashMap<String, i64> {
+        self.tokens.lock().await.clone()
+    }
+
+    async fn import(&self, tokens: HashMap<String, i64>) {
+        self.tokens.lock().await.extend(tokens);
+    }
+
+    async fn clear(&self) {
+        self.tokens.lock().await.clear();
+    }
+}
+
+pub struct PostgresTokenStore {
+    pool: PgPool,
+}
+
+impl PostgresTokenStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl TokenStore for PostgresTokenStore {
+    async fn issue(&self, page: i64) -> Result<String, sqlx::Error> {
+        let token = new_token();
+        query(
+            "INSERT INTO pagination_tokens (token, page, expires_at)
+             VALUES ($1, $2, now() + make_interval(secs => $3))",
+        )
+        .bind(&token)
+        .bind(page)
+        .bind(TOKEN_TTL_SECS as f64)
+        .execute(&self.pool)
+        .await?;
+        Ok(token)
+    }
+
+    async fn resolve(&self, token: String) -> Result<Option<i64>, sqlx::Error> {
+        query_scalar::<_, i64>(
+            "SELECT page FROM pagination_tokens WHERE token = $1 AND expires_at > now()",
+        )
+        .bind(token)
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    async fn purge(&self) -> Result<u64, sqlx::Error> {
+        query("DELETE FROM pagination_tokens WHERE expires_at <= now()")
+            .execute(&self.pool)
+            .await
+            .map(|r| r.rows_affected())
+    }
+}
+
+/// Periodically drops the expired tokens
+pub async fn cleanup(store: Arc<dyn TokenStore>) {
+    let mut interval = tokio::time::interval(CLEANUP_INTERVAL);
+    loop {
+        interval.tick().await;
+        if let Err(e) = store.purge().await {
+            tracing::warn!("token cleanup failed: {}", e);
+        }
+    }
+}
+
+pub fn state_token_store(backend: TokenBackend, pool: PgPool) -> Arc<dyn TokenStore> {
+    match backend {
+        TokenBackend::Memory => Arc::new(MemoryTokenStore::default()),
+        TokenBackend::Postgres => Arc::new(PostgresTokenStore::new(pool)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_memory_store() {
+        let store = MemoryTokenStore::default();
+        let token = store.issue(3).await.unwrap();
+        assert_eq!(token.len(), TOKEN_LENGTH);
+        assert_eq!(store.resolve(token.clone()).await.unwrap(), Some(3));
+        assert_eq!(store.resolve("unknown".to_string()).await.unwrap(), None);
+
+        let copy = MemoryTokenStore::default();
+        copy.import(store.export().await).await;
+        assert_eq!(copy.resolve(token).await.unwrap(), Some(3));
+    }
+
+    #[test]
+    fn test_backend() {
+        assert_eq!("postgres".parse(), Ok(TokenBackend::Postgres));
+        assert_eq!("memory".parse(), Ok(TokenBackend::Memory));
+        assert!("redis".parse::<TokenBackend>().is_err());
+    }
+}
@@ -0,0 +1,134 @@
+//! A span per request, closed by a structured log line with its status and latency
+
+use std::time::Duration;
+
+use axum::{
+    extract::MatchedPath,
+    http::{Request, Response},
+};
+use tower_http::{
+    classify::{ServerErrorsAsFailures, SharedClassifier},
+    trace::{MakeSpan, OnResponse, TraceLayer},
+};
+use tracing::{field, Span};
+
+#[derive(Debug, Clone, Copy)]
+pub struct RequestSpan;
+
+impl<B> MakeSpan<B> for RequestSpan {
+    fn make_span(&mut self, request: &Request<B>) -> Span {
+        // the query string is left out, it may carry tokens
+        tracing::info_span!(
+            "http",
+            method = %request.method(),
+            path = request.uri().path(),
+            route = route(request),
+            status = field::Empty,
+            latency_ms = field::Empty,
+        )
+    }
+}
+
+/// Route template of the request, `unmatched` when no route took it
+fn route<B>(request: &Request<B>) -> &str {
+    request
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or("unmatched", |p| p.as_str())
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct LogResponse;
+
+impl<B> OnResponse<B> for LogResponse {
+    fn on_response(self, response: &Response<B>, latency: Duration, span: &Span) {
+        let status = response.status().as_u16();
+        let latency_ms = latency.as_millis() as u64;
+        span.record("status", status);
+        span.record("latency_ms", latency_ms);
+        if response.status().is_server_error() {
+            tracing::error!(status, latency_ms, "request failed");
+        } else {
+            tracing::info!(status, latency_ms, "request finished");
+        }
+    }
+}
+
+pub type RequestTraceLayer =
+    TraceLayer<SharedClassifier<ServerErrorsAsFailures>, RequestSpan, (), LogResponse, (), (), ()>;
+
+/// Logs every response once, failures included, nothing is logged as the request starts
+pub fn trace_layer() -> RequestTraceLayer {
+    TraceLayer::new_for_http()
+        .make_span_with(RequestSpan)
+        .on_request(())
+        .on_response(LogResponse)
+        .on_body_chunk(())
+        .on_eos(())
+        .on_failure(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{
+        body::Body,
+        http::StatusCode,
+        middleware::{self, Next},
+        routing::get,
+        Router,
+    };
+    use http_body_util::BodyExt;
+    use tower::ServiceExt;
+
+    async fn echo_route(request: axum::extract::Request, next: Next) -> String {
+        let route = route(&request).to_string();
+        next.run(request).await;
+        route
+    }
+
+    #[tokio::test]
+    async fn test_route() {
+        let app = Router::new()
+            .route("/19/cite/:id", get(|| async {}))
+            .layer(middleware::from_fn(echo_route));
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/19/cite/1?format=apa")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, "/19/cite/:id");
+
+        let request = Request::builder().uri("/nowhere").body(()).unwrap();
+        assert_eq!(route(&request), "unmatched");
+    }
+
+    #[tokio::test]
+    async fn test_responses_are_untouched() {
+        let app = Router::new()
+            .route("/", get(|| async { "Hello, bird!" }))
+            .layer(trace_layer());
+        let response = app
+            .clone()
+            .oneshot(Request::builder().uri("/").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/nowhere")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
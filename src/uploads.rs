@@ -0,0 +1,130 @@
+//! Uploaded files kept as Postgres large objects, so they can be served again later
+
+use std::{pin::Pin, sync::Arc, time::Duration};
+
+use axum::body::Bytes;
+use chrono::{DateTime, Utc};
+#[cfg(test)]
+use mockall::automock;
+use sqlx::{postgres::types::Oid, query, query_scalar, PgPool};
+use tokio_stream::{Stream, StreamExt};
+use uuid::Uuid;
+
+/// Header carrying the id of a stored upload
+pub const UPLOAD_ID_HEADER: &str = "x-upload-id";
+
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(10 * 60);
+/// Largest piece of an upload written to its large object at once
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Content of an upload, written to storage a chunk at a time as it comes
+pub type Chunks = Pin<Box<dyn Stream<Item = Bytes> + Send>>;
+
+/// Chunks of a content read before being stored, e.g. a lockfile that had to render first
+pub fn chunks(content: Bytes) -> Chunks {
+    let pieces = (0..content.len())
+        .step_by(CHUNK_SIZE)
+        .map(move |start| content.slice(start..(start + CHUNK_SIZE).min(content.len())))
+        .collect::<Vec<_>>();
+    Box::pin(tokio_stream::iter(pieces))
+}
+
+#[async_trait::async_trait]
+#[cfg_attr(test, automock)]
+pub trait UploadRepository: Send + Sync + 'static {
+    /// Keeps the content, `kind` telling apart the routes it came from. `None` when the
+    /// uploads already take up their quota.
+    async fn store(&self, kind: &str, content: Chunks) -> Result<Option<Uuid>, sqlx::Error>;
+    async fn load(&self, kind: &str, id: Uuid) -> Result<Option<Vec<u8>>, sqlx::Error>;
+    /// Drops the uploads made before the given time, large objects included
+    async fn purge(&self, before: DateTime<Utc>) -> Result<u64, sqlx::Error>;
+}
+
+pub struct PostgresUploadRepository {
+    pool: PgPool,
+    /// Bytes all the uploads together may take up
+    quota: i64,
+}
+
+impl PostgresUploadRepository {
+    pub fn new(pool: PgPool, quota: i64) -> Self {
+        Self { pool, quota }
+    }
+}
+
+#[async_trait::async_trait]
+impl UploadRepository for PostgresUploadRepository {
+    async fn store(&self, kind: &str, mut content: Chunks) -> Result<Option<Uuid>, sqlx::Error> {
+        // the large object goes away with the transaction when the quota has no room for it
+        let mut tx = self.pool.begin().await?;
+        let object = query_scalar::<_, Oid>("SELECT lo_create(0)")
+            .fetch_one(&mut *tx)
+            .await?;
+        let mut size = 0;
+        while let Some(chunk) = content.next().await {
+            query("SELECT lo_put($1, $2, $3)")
+                .bind(object)
+                .bind(size)
+                .bind(chunk.as_ref())
+                .execute(&mut *tx)
+                .await?;
+            size += chunk.len() as i64;
+        }
+
+        // uploads are counted one after the other, concurrent ones could each fit the room left
+        query("SELECT pg_advisory_xact_lock(hashtext('uploads'))")
+            .execute(&mut *tx)
+            .await?;
+        let id = query_scalar(
+            "INSERT INTO uploads (id, kind, object, size) SELECT $1, $2, $3, $4 \
+             WHERE (SELECT COALESCE(SUM(size), 0) FROM uploads) + $4 <= $5 \
+             RETURNING id",
+        )
+        .bind(Uuid::new_v4())
+        .bind(kind)
+        .bind(object)
+        .bind(size)
+        .bind(self.quota)
+        .fetch_optional(&mut *tx)
+        .await?;
+        if id.is_some() {
+            tx.commit().await?;
+        }
+        Ok(id)
+    }
+
+    async fn load(&self, kind: &str, id: Uuid) -> Result<Option<Vec<u8>>, sqlx::Error> {
+        query_scalar("SELECT lo_get(object) FROM uploads WHERE id = $1 AND kind = $2")
+            .bind(id)
+            .bind(kind)
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    async fn purge(&self, before: DateTime<Utc>) -> Result<u64, sqlx::Error> {
+        query_scalar::<_, i64>(
+            "WITH gone AS (DELETE FROM uploads WHERE uploaded_at < $1 RETURNING object) \
+             SELECT COUNT(lo_unlink(object)) FROM gone",
+        )
+        .bind(before)
+        .fetch_one(&self.pool)
+        .await
+        .map(|n| n as u64)
+    }
+}
+
+pub fn state_upload_repository(pool: PgPool, quota: i64) -> Arc<dyn UploadRepository> {
+    Arc::new(PostgresUploadRepository::new(pool, quota))
+}
+
+/// Periodically drops the uploads older than `retention`, freeing their room in the quota
+pub async fn cleanup(repository: Arc<dyn UploadRepository>, retention: Duration) {
+    let mut interval = tokio::time::interval(CLEANUP_INTERVAL);
+    loop {
+        interval.tick().await;
+        let before = Utc::now() - chrono::Duration::from_std(retention).unwrap_or_default();
+        if let Err(e) = repository.purge(before).await {
+            tracing::warn!("upload cleanup failed: {}", e);
+        }
+    }
+}
@@ -0,0 +1,441 @@
+use core::{fmt::Display, str::FromStr};
+use std::{collections::HashMap, sync::Arc};
+
+use axum::{
+    async_trait,
+    body::{to_bytes, Body},
+    extract::{FromRequestParts, MatchedPath, Query, Request, State},
+    http::{header, request::Parts, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use jsonschema::Validator;
+use schemars::JsonSchema;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::versioning::unversioned;
+
+const MAX_BODY_SIZE: usize = 2 * 1024 * 1024;
+
+/// JSON Schema that the body of a route must satisfy
+pub struct RouteSchema {
+    method: Method,
+    path: &'static str,
+    schema: Value,
+    rejection: StatusCode,
+}
+
+impl RouteSchema {
+    pub fn new(method: Method, path: &'static str, schema: Value) -> Self {
+        Self {
+            method,
+            path,
+            schema,
+            rejection: StatusCode::UNPROCESSABLE_ENTITY,
+        }
+    }
+
+    /// Overrides the status of invalid requests, for routes with an established contract
+    pub fn rejection(mut self, status: StatusCode) -> Self {
+        self.rejection = status;
+        self
+    }
+}
+
+struct Entry {
+    validator: Validator,
+    rejection: StatusCode,
+}
+
+/// Compiled schemas, looked up by method and route path
+#[derive(Clone, Default)]
+pub struct SchemaRegistry {
+    entries: Arc<HashMap<(Method, String), Entry>>,
+}
+
+impl SchemaRegistry {
+    pub fn new(routes: impl IntoIterator<Item = RouteSchema>) -> Result<Self, String> {
+        let entries = routes
+            .into_iter()
+            .map(|r| {
+                let validator = jsonschema::validator_for(&r.schema)
+                    .map_err(|e| format!("invalid schema for {} {}: {}", r.method, r.path, e))?;
+                Ok((
+                    (r.method, r.path.to_string()),
+                    Entry {
+                        validator,
+                        rejection: r.rejection,
+                    },
+                ))
+            })
+            .collect::<Result<_, String>>()?;
+
+        Ok(Self {
+            entries: Arc::new(entries),
+        })
+    }
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ValidationError {
+    /// JSON pointer to the offending value, empty for the whole body, or the name of the
+    /// offending query parameter
+    path: String,
+    message: String,
+}
+
+/// Body of the responses rejected by validation
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ValidationErrors {
+    errors: Vec<ValidationError>,
+}
+
+impl ValidationErrors {
+    /// A single error about the request as a whole
+    pub fn whole(message: impl Into<String>) -> Self {
+        Self {
+            errors: vec![ValidationError {
+                path: String::new(),
+                message: message.into(),
+            }],
+        }
+    }
+}
+
+fn reject(status: StatusCode, errors: Vec<ValidationError>) -> Response {
+    (status, Json(ValidationErrors { errors })).into_response()
+}
+
+fn is_json(request: &Request) -> bool {
+    request
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|ct| ct.to_str().ok())
+        .is_some_and(|ct| ct.trim_start().starts_with("application/json"))
+}
+
+/// Checks JSON bodies against the schema registered for the matched route.
+/// Bodies of other content types are left to the handler.
+pub async fn validate_json(
+    State(registry): State<SchemaRegistry>,
+    path: Option<MatchedPath>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let entry = match path.and_then(|p| {
+        registry.entries.get(&(
+            request.method().clone(),
+            unversioned(p.as_str()).to_string(),
+        ))
+    }) {
+        Some(entry) if is_json(&request) => entry,
+        _ => return next.run(request).await,
+    };
+
+    let (parts, body) = request.into_parts();
+    let bytes = match to_bytes(body, MAX_BODY_SIZE).await {
+        Ok(b) => b,
+        _ => return (StatusCode::PAYLOAD_TOO_LARGE, "".to_string()).into_response(),
+    };
+
+    let instance = match serde_json::from_slice::<Value>(&bytes) {
+        Ok(v) => v,
+        Err(e) => {
+            return reject(
+                entry.rejection,
+                vec![ValidationError {
+                    path: "".to_string(),
+                    message: e.to_string(),
+                }],
+            )
+        }
+    };
+
+    let errors = entry
+        .validator
+        .iter_errors(&instance)
+        .map(|e| ValidationError {
+            path: e.instance_path.as_str().to_string(),
+            message: e.to_string(),
+        })
+        .collect::<Vec<_>>();
+    if !errors.is_empty() {
+        return reject(entry.rejection, errors);
+    }
+
+    next.run(Request::from_parts(parts, Body::from(bytes)))
+        .await
+}
+
+/// Query parameters being read, every failure is recorded rather than only the first one
+pub struct Params {
+    values: HashMap<String, String>,
+    errors: Vec<ValidationError>,
+}
+
+impl Params {
+    pub fn required<T>(&mut self, name: &str) -> Option<T>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        if !self.values.contains_key(name) {
+            self.invalid(name, "missing");
+        }
+        self.optional(name)
+    }
+
+    /// `None` when absent or invalid, the latter being recorded
+    pub fn optional<T>(&mut self, name: &str) -> Option<T>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        match self.values.get(name)?.parse() {
+            Ok(value) => Some(value),
+            Err(e) => {
+                self.invalid(name, e.to_string());
+                None
+            }
+        }
+    }
+
+    /// Records a parameter that parsed but isn't acceptable
+    pub fn invalid(&mut self, name: &str, message: impl Into<String>) {
+        self.errors.push(ValidationError {
+            path: name.to_string(),
+            message: message.into(),
+        });
+    }
+}
+
+/// Query string read parameter by parameter with [`Params`]
+pub trait FromParams: Sized {
+    /// `None` only once a failure was recorded
+    fn from_params(params: &mut Params) -> Option<Self>;
+}
+
+/// Query extractor answering a 400 that lists every bad parameter, in the shape of the
+/// JSON body errors
+pub struct ValidatedQuery<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequestParts<S> for ValidatedQuery<T>
+where
+    T: FromParams,
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let values = match Query::<HashMap<String, String>>::try_from_uri(&parts.uri) {
+            Ok(Query(values)) => values,
+            Err(e) => {
+                return Err(reject(
+                    StatusCode::BAD_REQUEST,
+                    vec![ValidationError {
+                        path: "".to_string(),
+                        message: e.body_text(),
+                    }],
+                ))
+            }
+        };
+
+        let mut params = Params {
+            values,
+            errors: vec![],
+        };
+        match T::from_params(&mut params) {
+            Some(value) if params.errors.is_empty() => Ok(Self(value)),
+            _ => Err(reject(StatusCode::BAD_REQUEST, params.errors)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{
+        middleware,
+        routing::{get, post},
+        Router,
+    };
+    use http_body_util::BodyExt;
+    use serde_json::json;
+    use tower::ServiceExt;
+
+    fn create_test_app() -> Router {
+        let registry = SchemaRegistry::new([
+            RouteSchema::new(
+                Method::POST,
+                "/strict/:id",
+                json!({
+                    "type": "object",
+                    "required": ["name"],
+                    "properties": { "name": { "type": "string" } }
+                }),
+            ),
+            RouteSchema::new(Method::POST, "/legacy", json!({ "type": "object" }))
+                .rejection(StatusCode::BAD_REQUEST),
+        ])
+        .unwrap();
+
+        Router::new()
+            .route("/strict/:id", post(|body: String| async move { body }))
+            .route("/legacy", post(|| async { StatusCode::OK }))
+            .route("/free", post(|| async { StatusCode::OK }))
+            .layer(middleware::from_fn_with_state(registry, validate_json))
+    }
+
+    struct Window {
+        start: u8,
+        end: Option<u8>,
+    }
+
+    impl FromParams for Window {
+        fn from_params(params: &mut Params) -> Option<Self> {
+            let start = params.required("start");
+            let end = params.optional("end");
+            if let (Some(start), Some(end)) = (start, end) {
+                if end < start {
+                    params.invalid("end", "before start");
+                }
+            }
+            Some(Self { start: start?, end })
+        }
+    }
+
+    async fn window(ValidatedQuery(window): ValidatedQuery<Window>) -> String {
+        format!("{}..{:?}", window.start, window.end)
+    }
+
+    async fn get_window(query: &str) -> (StatusCode, String) {
+        let response = Router::new()
+            .route("/window", get(window))
+            .oneshot(
+                Request::builder()
+                    .uri(format!("/window?{}", query))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let status = response.status();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    fn json_request(uri: &str, body: &str) -> Request {
+        Request::builder()
+            .method("POST")
+            .uri(uri)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    }
+
+    async fn errors(response: Response) -> Value {
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[test]
+    fn test_invalid_schema() {
+        let registry = SchemaRegistry::new([RouteSchema::new(
+            Method::POST,
+            "/broken",
+            json!({ "type": 42 }),
+        )]);
+        assert!(registry.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_valid_body_reaches_handler() {
+        let response = create_test_app()
+            .oneshot(json_request("/strict/1", r#"{"name":"Santa"}"#))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(&body[..], br#"{"name":"Santa"}"#);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_body() {
+        let response = create_test_app()
+            .oneshot(json_request("/strict/1", r#"{"name":42}"#))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        let errors = errors(response).await;
+        assert_eq!(errors["errors"][0]["path"], "/name");
+    }
+
+    #[tokio::test]
+    async fn test_malformed_body() {
+        let response = create_test_app()
+            .oneshot(json_request("/strict/1", "{"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        let errors = errors(response).await;
+        assert_eq!(errors["errors"][0]["path"], "");
+    }
+
+    #[tokio::test]
+    async fn test_custom_rejection() {
+        let response = create_test_app()
+            .oneshot(json_request("/legacy", "[]"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_validated_query() {
+        assert_eq!(
+            get_window("start=1&end=3").await,
+            (StatusCode::OK, "1..Some(3)".to_string())
+        );
+        assert_eq!(
+            get_window("start=2").await,
+            (StatusCode::OK, "2..None".to_string())
+        );
+        assert_eq!(get_window("start=4&end=3").await.0, StatusCode::BAD_REQUEST);
+
+        // every bad parameter is listed
+        let (status, body) = get_window("end=300").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let errors = serde_json::from_str::<Value>(&body).unwrap()["errors"].clone();
+        assert_eq!(errors[0]["path"], "start");
+        assert_eq!(errors[0]["message"], "missing");
+        assert_eq!(errors[1]["path"], "end");
+        assert_eq!(errors.as_array().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_skipped_without_schema_or_json() {
+        let response = create_test_app()
+            .oneshot(json_request("/free", "{"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = create_test_app()
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/strict/1")
+                    .body(Body::from("plain"))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,74 @@
+//! Versioned tree of the routes. Every route is served under `/v1`, and at its unversioned path
+//! as well for the challenge validator and the clients predating the prefix. A `/v2` would be
+//! merged next to it with the routes whose formats changed, the aliases staying on `/v1`.
+
+use axum::Router;
+
+pub const CURRENT: &str = "/v1";
+const VERSIONS: &[&str] = &[CURRENT];
+
+/// Serves `routes` under the current version and at their own paths
+pub fn versioned(routes: Router) -> Router {
+    Router::new().nest(CURRENT, routes.clone()).merge(routes)
+}
+
+/// Route template without its version prefix, `/19/cite/:id` for `/v1/19/cite/:id`, so that
+/// budgets, schemas and latency objectives apply to every version of a route
+pub fn unversioned(path: &str) -> &str {
+    VERSIONS
+        .iter()
+        .find_map(|version| match path.strip_prefix(version)? {
+            "" => Some("/"),
+            rest if rest.starts_with('/') => Some(rest),
+            _ => None,
+        })
+        .unwrap_or(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{
+        body::Body,
+        extract::MatchedPath,
+        http::{Request, StatusCode},
+        routing::get,
+    };
+    use http_body_util::BodyExt;
+    use tower::ServiceExt;
+
+    #[test]
+    fn test_unversioned() {
+        assert_eq!(unversioned("/v1/19/cite/:id"), "/19/cite/:id");
+        assert_eq!(unversioned("/v1"), "/");
+        assert_eq!(unversioned("/19/cite/:id"), "/19/cite/:id");
+        assert_eq!(unversioned("/v12/cite"), "/v12/cite");
+    }
+
+    #[tokio::test]
+    async fn test_versioned() {
+        let app = versioned(
+            Router::new()
+                .route("/", get(|| async { "Hello, bird!" }))
+                .route(
+                    "/19/cite/:id",
+                    get(|path: MatchedPath| async move { unversioned(path.as_str()).to_string() }),
+                ),
+        );
+        for (uri, expected) in [
+            ("/v1/19/cite/1", "/19/cite/:id"),
+            ("/19/cite/1", "/19/cite/:id"),
+            ("/v1", "Hello, bird!"),
+            ("/", "Hello, bird!"),
+        ] {
+            let response = app
+                .clone()
+                .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK, "{}", uri);
+            let body = response.into_body().collect().await.unwrap().to_bytes();
+            assert_eq!(body, expected, "{}", uri);
+        }
+    }
+}